@@ -0,0 +1,30 @@
+//! How long applying a template takes with the parsed templates cached,
+//! against parsing it each time. Run with `cargo bench`.
+use std::collections::HashMap;
+use std::hint::black_box;
+
+use apictl::{Applicator, Response, Template};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+const URL: &str = "${base_url}/users/${user_id}/posts?page=${page}";
+
+fn apply(c: &mut Criterion) {
+    let context = [
+        ("base_url", "https://api.example.com"),
+        ("user_id", "42"),
+        ("page", "2"),
+    ]
+    .into_iter()
+    .map(|(k, v)| (k.to_string(), v.to_string()))
+    .collect::<HashMap<_, _>>();
+    let app = Applicator::new(context.clone(), HashMap::<String, Response>::new());
+
+    c.bench_function("apply cached", |b| b.iter(|| app.apply(black_box(URL))));
+    c.bench_function("parse and render", |b| {
+        b.iter(|| Template::parse(black_box(URL)).render(|name| context.get(name).cloned()))
+    });
+}
+
+criterion_group!(benches, apply);
+criterion_main!(benches);
@@ -1,106 +1,260 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::sync::Arc;
 
-use crate::Response;
+use crate::template::Segment;
+use crate::{Response, Scope, Templates};
 
-use std::sync::OnceLock;
-
-use regex::Regex;
-
-static VARIABLE: OnceLock<Regex> = OnceLock::new();
+/// The deepest variables can refer to other variables.
+const MAX_DEPTH: usize = 16;
 
+/// Applicator replaces `${name}` templates with context variables,
+/// `${response.NAME.PATH}` with values from this run's responses, and
+/// `${cached.NAME.PATH}` with values from responses cached by any run.
+/// It's the only template engine: requests, body files, asserts,
+/// conditions, mocks and notifications all render through it, so
+/// functions, expressions and nested variables work the same in each.
 #[derive(Default)]
 pub struct Applicator {
     context: HashMap<String, String>,
-    responses: HashMap<String, Response>,
+    responses: Arc<HashMap<String, Response>>,
+    cached: Arc<HashMap<String, Response>>,
+    templates: Templates,
+    types: Arc<HashMap<String, serde_json::Value>>,
+    scopes: Arc<Vec<Scope>>,
+    strict: bool,
+    keep_unresolved: bool,
 }
 
 impl Applicator {
-    pub fn new(context: HashMap<String, String>, responses: HashMap<String, Response>) -> Self {
-        Self { context, responses }
+    /// Create an applicator for the context and responses. Responses
+    /// can be shared with an Arc and are only copied when one is added.
+    pub fn new(
+        context: HashMap<String, String>,
+        responses: impl Into<Arc<HashMap<String, Response>>>,
+    ) -> Self {
+        Self {
+            context,
+            responses: responses.into(),
+            cached: Arc::default(),
+            templates: Templates::default(),
+            types: Arc::default(),
+            scopes: Arc::default(),
+            strict: false,
+            keep_unresolved: false,
+        }
+    }
+
+    /// Use the cached responses for `${cached.*}` templates.
+    pub fn with_cached(mut self, cached: impl Into<Arc<HashMap<String, Response>>>) -> Self {
+        self.cached = cached.into();
+        self
+    }
+
+    /// Share the parsed templates so each string is only parsed once.
+    pub fn with_templates(mut self, templates: Templates) -> Self {
+        self.templates = templates;
+        self
+    }
+
+    /// Use the typed values of variables when applying to JSON values.
+    pub fn with_types(mut self, types: HashMap<String, serde_json::Value>) -> Self {
+        self.types = Arc::new(types);
+        self
+    }
+
+    /// Use the scopes to override variables for the requests they match.
+    pub fn with_scopes(mut self, scopes: Vec<Scope>) -> Self {
+        self.scopes = Arc::new(scopes);
+        self
+    }
+
+    /// Make unresolved variables an error when requests are applied
+    /// instead of replacing them with nothing.
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Keep the templates of variables that can't be resolved as
+    /// written instead of replacing them with nothing.
+    pub fn with_unresolved_kept(mut self) -> Self {
+        self.keep_unresolved = true;
+        self
+    }
+
+    /// Whether unresolved variables are an error.
+    pub fn is_strict(&self) -> bool {
+        self.strict
     }
 
     pub fn add_response(&mut self, name: String, response: Response) {
-        self.responses.insert(name, response);
+        Arc::make_mut(&mut self.responses).insert(name, response);
     }
 
+    /// Get a context variable.
+    pub fn get(&self, name: &str) -> Option<&String> {
+        self.context.get(name)
+    }
+
+    /// Create an applicator that uses the defaults for variables that
+    /// aren't in the context.
+    pub fn with_defaults(&self, defaults: &HashMap<String, String>) -> Self {
+        let mut context = defaults.clone();
+        context.extend(self.context.clone());
+        self.with_context(context)
+    }
+
+    /// Create an applicator with the variables of the scopes that match
+    /// the tags and URL, if any do. The URL is matched once applied.
+    pub fn scoped(&self, tags: &[String], url: &str) -> Option<Self> {
+        if self.scopes.is_empty() {
+            return None;
+        }
+        let url = self.apply(url);
+        let mut scopes = self
+            .scopes
+            .iter()
+            .filter(|s| s.matches(tags, &url))
+            .peekable();
+        scopes.peek()?;
+        let mut context = self.context.clone();
+        let mut types = (*self.types).clone();
+        for scope in scopes {
+            for (name, value) in &scope.variables {
+                types.remove(name);
+                context.insert(name.clone(), value.clone());
+            }
+        }
+        let mut app = self.with_context(context);
+        app.types = Arc::new(types);
+        Some(app)
+    }
+
+    fn with_context(&self, context: HashMap<String, String>) -> Self {
+        Self {
+            context,
+            responses: self.responses.clone(),
+            cached: self.cached.clone(),
+            templates: self.templates.clone(),
+            types: self.types.clone(),
+            scopes: self.scopes.clone(),
+            strict: self.strict,
+            keep_unresolved: self.keep_unresolved,
+        }
+    }
+
+    /// Set a context variable, replacing any existing value and its
+    /// type.
+    pub fn set(&mut self, name: &str, value: String) {
+        if self.types.contains_key(name) {
+            Arc::make_mut(&mut self.types).remove(name);
+        }
+        self.context.insert(name.to_string(), value);
+    }
+
+    /// Replace the templates in the string. The string is parsed the
+    /// first time it's seen and the parsed template is reused after.
+    /// Context variables whose values have templates are resolved too,
+    /// like `api_url: ${scheme}://${host}`.
     pub fn apply(&self, s: &str) -> String {
-        let mut output = String::new();
-        let mut last = 0;
-
-        let re = VARIABLE.get_or_init(|| Regex::new(r"\$\{\s*([-.\w]+)\s*\}").unwrap());
-
-        for capture in re.captures_iter(s) {
-            let r = capture.get(0).unwrap().range();
-            let name = capture.get(1).unwrap().as_str();
-            output.push_str(&s[last..r.start]);
-            let replacement = match name.starts_with("response.") {
-                true => match self.find_response_data(&name[9..]) {
-                    Some(v) => v,
-                    None => "".to_string(),
-                },
-                false => match self.context.get(name) {
-                    Some(v) => v.clone(),
-                    None => "".to_string(),
-                },
-            };
-
-            output.push_str(&replacement);
-
-            last = r.end;
+        self.apply_nested(s, &[], &RefCell::default())
+    }
+
+    /// Apply the context to all of the strings within a JSON value. A
+    /// string that is only a variable with a typed value, like
+    /// `"${limit}"`, is replaced with the value, so it stays a number,
+    /// boolean, map or list.
+    pub fn apply_value(&self, value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::String(s) => {
+                let typed = match self.templates.get(s).segments() {
+                    [Segment::Variable(name)] => self.types.get(name),
+                    _ => None,
+                };
+                match typed {
+                    Some(typed) => *value = typed.clone(),
+                    None => *s = self.apply(s),
+                }
+            }
+            serde_json::Value::Array(a) => a.iter_mut().for_each(|v| self.apply_value(v)),
+            serde_json::Value::Object(o) => o.values_mut().for_each(|v| self.apply_value(v)),
+            _ => {}
         }
+    }
 
-        output.push_str(&s[last..]);
-        output
+    /// The variables in the string that can't be resolved, including
+    /// those in the values of context variables it uses.
+    pub fn unresolved(&self, s: &str) -> Vec<String> {
+        let missing = RefCell::default();
+        self.apply_nested(s, &[], &missing);
+        let mut missing = missing.into_inner();
+        missing.sort();
+        missing.dedup();
+        missing
+    }
+
+    /// Apply the templates in a value of the variables in the chain,
+    /// which are being resolved. Variables without a value are added to
+    /// missing.
+    fn apply_nested(&self, s: &str, chain: &[&str], missing: &RefCell<Vec<String>>) -> String {
+        if !s.contains("${") {
+            return s.to_string();
+        }
+        self.templates
+            .get(s)
+            .render(|name| match self.lookup(name, chain, missing) {
+                Some(value) => Some(value),
+                None => {
+                    missing.borrow_mut().push(name.to_string());
+                    self.keep_unresolved.then(|| format!("${{{}}}", name))
+                }
+            })
     }
 
-    fn find_response_data(&self, name: &str) -> Option<String> {
-        // Split the request name and the path.
-        let tokens = name.splitn(2, '.').collect::<Vec<_>>();
-        if tokens.len() != 2 {
+    fn lookup(&self, name: &str, chain: &[&str], missing: &RefCell<Vec<String>>) -> Option<String> {
+        if let Some(path) = name.strip_prefix("response.") {
+            return find_response_data(&self.responses, path);
+        } else if let Some(path) = name.strip_prefix("cached.") {
+            return find_response_data(&self.cached, path);
+        }
+        let value = self.context.get(name)?;
+        if !value.contains("${") {
+            return Some(value.clone());
+        }
+        if chain.contains(&name) {
+            tracing::warn!(variable = %name, chain = %chain.join(" -> "), "variable refers to itself");
+            return None;
+        }
+        if chain.len() >= MAX_DEPTH {
+            tracing::warn!(variable = %name, depth = MAX_DEPTH, "variables nested too deeply");
             return None;
         }
-        // Get the response and try to find the path.
-        let response = self.responses.get(tokens[0])?;
-        response.find_path_in_body(tokens[1])
+        let mut chain = chain.to_vec();
+        chain.push(name);
+        Some(self.apply_nested(value, &chain, missing))
     }
 }
 
+fn find_response_data(responses: &HashMap<String, Response>, name: &str) -> Option<String> {
+    // Split the request name and the path.
+    let tokens = name.splitn(2, '.').collect::<Vec<_>>();
+    if tokens.len() != 2 {
+        return None;
+    }
+    // Get the response and try to find the path.
+    // The body is checked first so that bodies with a status_code or
+    // headers field still work.
+    let response = responses.get(tokens[0])?;
+    response
+        .find_path_in_body(tokens[1])
+        .or_else(|| response.find_field(tokens[1]))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    #[test]
-    fn variable_regex() {
-        let tests = vec![
-            ("Hello, ${name}", vec!["name"]),
-            ("Hello, ${   name  }! how are you?", vec!["name"]),
-            (
-                "Hello, ${ name }! How are you, ${    name}?",
-                vec!["name", "name"],
-            ),
-            (
-                "Hello, ${name }! How are you, ${name    }?",
-                vec!["name", "name"],
-            ),
-            (
-                "Hello, ${ cheese_and_toast }${toast_and_cheese}",
-                vec!["cheese_and_toast", "toast_and_cheese"],
-            ),
-            ("howdy, ${ responses.get.name }", vec!["responses.get.name"]),
-        ];
-
-        let re = VARIABLE.get_or_init(|| Regex::new(r"\$\{\s*([-.\w]+)\s*\}").unwrap());
-
-        for (input, expected) in tests {
-            let mut actual = vec![];
-
-            for capture in re.captures_iter(input) {
-                actual.push(capture.get(1).unwrap().as_str());
-            }
-            assert_eq!(actual, expected);
-        }
-    }
-
     #[test]
     fn test_apply() {
         use crate::Response;
@@ -117,15 +271,89 @@ mod tests {
                 version: "HTTP/1.1".to_string(),
                 headers: HashMap::new(),
                 body: "{ \"name\": \"Galaxy\", \"age\": \"13.61 Billion\" }".to_string(),
+                ..Default::default()
             },
         );
 
-        let app = Applicator { context, responses };
+        let app = Applicator::new(context, responses.clone()).with_cached(responses);
 
         let s = app.apply("Hello, ${name}! You are ${age} years old. My name is ${response.hello.name}. I am ${response.hello.age} years old.${response.hello.some.bad.one}${response.}");
         assert_eq!(
             s,
             "Hello, World! You are 4.543 Billion years old. My name is Galaxy. I am 13.61 Billion years old."
         );
+        assert_eq!(app.apply("${response.hello.status_code}"), "200");
+        assert_eq!(app.apply("${cached.hello.body.name}"), "Galaxy");
+    }
+
+    #[test]
+    fn shared_responses() {
+        let response = |body: &str| Response {
+            body: body.to_string(),
+            ..Default::default()
+        };
+        let responses = Arc::new(HashMap::from([(
+            "get".to_string(),
+            response(r#"{"id": 1}"#),
+        )]));
+        let mut first = Applicator::new(HashMap::new(), responses.clone());
+        let second = Applicator::new(HashMap::new(), responses.clone());
+        assert!(Arc::ptr_eq(&first.responses, &second.responses));
+
+        // Adding a response copies them for just that applicator.
+        first.add_response("post".into(), response(r#"{"id": 2}"#));
+        assert_eq!(first.apply("${response.post.id}"), "2");
+        assert_eq!(second.apply("${response.post.id}"), "");
+        assert_eq!(second.apply("${response.get.id}"), "1");
+        assert_eq!(responses.len(), 1);
+    }
+
+    #[test]
+    fn nested_variables() {
+        let mut context = HashMap::new();
+        for (k, v) in [
+            ("scheme", "https"),
+            ("host", "${sub}.example.com"),
+            ("sub", "api"),
+            ("api_url", "${scheme}://${host}"),
+            ("a", "x${b}"),
+            ("b", "y${a}"),
+        ] {
+            context.insert(k.to_string(), v.to_string());
+        }
+        for i in 0..20 {
+            context.insert(format!("d{}", i), format!("${{d{}}}", i + 1));
+        }
+        context.insert("d20".to_string(), "bottom".to_string());
+
+        let app = Applicator::new(context, HashMap::new());
+        assert_eq!(
+            app.apply("${api_url}/users"),
+            "https://api.example.com/users"
+        );
+        assert_eq!(app.apply("${a}"), "xy");
+        assert_eq!(app.apply("${d10}"), "bottom");
+        assert_eq!(app.apply("[${d0}]"), "[]");
+        assert_eq!(app.unresolved("${api_url}/${id}"), vec!["id"]);
+        assert_eq!(app.unresolved("${a}"), vec!["a"]);
+    }
+
+    #[test]
+    fn apply_value() {
+        let context = HashMap::from([
+            ("limit".to_string(), "10".to_string()),
+            ("name".to_string(), "bob".to_string()),
+        ]);
+        let types = HashMap::from([("limit".to_string(), serde_json::json!(10))]);
+        let mut app = Applicator::new(context, HashMap::new()).with_types(types);
+
+        let mut value = serde_json::json!({"limit": "${limit}", "q": ["${name}", "${limit}s"]});
+        app.apply_value(&mut value);
+        assert_eq!(value, serde_json::json!({"limit": 10, "q": ["bob", "10s"]}));
+
+        app.set("limit", "5".into());
+        let mut value = serde_json::json!("${limit}");
+        app.apply_value(&mut value);
+        assert_eq!(value, serde_json::json!("5"));
     }
 }
@@ -0,0 +1,1034 @@
+/// Auth adds credentials to requests once they're rendered, for schemes
+/// that can't be written as a header, like signatures over the whole
+/// request, and for secrets that should be masked wherever they're
+/// printed, like API keys.
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use crate::redact::MASK;
+use crate::request::percent_encode;
+use crate::Applicator;
+
+#[cfg(feature = "ntlm")]
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hmac::{Hmac, KeyInit, Mac};
+use reqwest::header::{HeaderName, HeaderValue, AUTHORIZATION, COOKIE, WWW_AUTHENTICATE};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+/// AuthError is the error type for auth.
+#[derive(Error, Debug)]
+pub enum AuthError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("credentials error: {0}")]
+    Credentials(String),
+
+    #[error("invalid header: {0}")]
+    Header(String),
+
+    #[error("jwt error: {0}")]
+    Jwt(String),
+
+    #[error("invalid challenge: {0}")]
+    Challenge(String),
+
+    #[error("{0} auth needs apictl built with the '{1}' feature")]
+    Feature(&'static str, &'static str),
+}
+
+/// Result is the result type for auth.
+pub type Result<T> = std::result::Result<T, AuthError>;
+
+/// Auth is how a request is authenticated.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Auth {
+    /// Sign the request with AWS Signature Version 4, for AWS and APIs
+    /// compatible with it, like OpenSearch.
+    AwsSigv4 {
+        /// The service the request is signed for, like `es` or
+        /// `execute-api`.
+        service: String,
+        region: String,
+        #[serde(default)]
+        credentials: AwsCredentials,
+        /// The profile to read credentials from. Defaults to
+        /// `AWS_PROFILE` or `default`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        profile: Option<String>,
+    },
+    /// Mint a JWT signed with the key for each request and send it as a
+    /// bearer token, for APIs that use private key JWT auth.
+    Jwt {
+        /// The claims, which are templates. `iat` and `exp` are added
+        /// when they're missing.
+        #[serde(default)]
+        claims: serde_json::Map<String, serde_json::Value>,
+        /// The file with the PEM private key or, for the HS algorithms,
+        /// the secret.
+        key: String,
+        #[serde(default)]
+        alg: JwtAlgorithm,
+        /// The key's id, added to the header as `kid`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        kid: Option<String>,
+        /// How long the token is valid when the claims don't have
+        /// `exp`.
+        #[serde(default = "default_expires_in", with = "humantime_serde")]
+        #[schemars(with = "String")]
+        expires_in: Duration,
+    },
+    /// Answer the server's HTTP Digest challenge with the username and
+    /// password.
+    Digest { username: String, password: String },
+    /// Authenticate with NTLM over the `NTLM` or `Negotiate` scheme. It
+    /// needs the `ntlm` feature.
+    Ntlm {
+        /// The user, which can include the domain like `CORP\alice`.
+        username: String,
+        password: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        domain: Option<String>,
+        #[serde(default)]
+        scheme: NtlmScheme,
+    },
+    /// Send an API key in a header, query parameter or cookie.
+    ApiKey {
+        /// The header, parameter or cookie the key is sent in, like
+        /// `X-API-Key`.
+        name: String,
+        value: String,
+        #[serde(default, rename = "in")]
+        location: ApiKeyLocation,
+    },
+}
+
+/// ApiKeyLocation is where an API key is sent.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiKeyLocation {
+    #[default]
+    Header,
+    Query,
+    Cookie,
+}
+
+/// NtlmScheme is the HTTP auth scheme NTLM messages are sent with.
+/// Negotiate only carries NTLM, not Kerberos.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum NtlmScheme {
+    #[default]
+    Ntlm,
+    Negotiate,
+}
+
+impl std::fmt::Display for NtlmScheme {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NtlmScheme::Ntlm => write!(f, "NTLM"),
+            NtlmScheme::Negotiate => write!(f, "Negotiate"),
+        }
+    }
+}
+
+fn default_expires_in() -> Duration {
+    Duration::from_secs(300)
+}
+
+/// JwtAlgorithm is how a JWT is signed.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, JsonSchema)]
+pub enum JwtAlgorithm {
+    HS256,
+    HS384,
+    HS512,
+    #[default]
+    RS256,
+    RS384,
+    RS512,
+    PS256,
+    PS384,
+    PS512,
+    ES256,
+    ES384,
+    EdDSA,
+}
+
+impl From<JwtAlgorithm> for jsonwebtoken::Algorithm {
+    fn from(alg: JwtAlgorithm) -> Self {
+        match alg {
+            JwtAlgorithm::HS256 => Self::HS256,
+            JwtAlgorithm::HS384 => Self::HS384,
+            JwtAlgorithm::HS512 => Self::HS512,
+            JwtAlgorithm::RS256 => Self::RS256,
+            JwtAlgorithm::RS384 => Self::RS384,
+            JwtAlgorithm::RS512 => Self::RS512,
+            JwtAlgorithm::PS256 => Self::PS256,
+            JwtAlgorithm::PS384 => Self::PS384,
+            JwtAlgorithm::PS512 => Self::PS512,
+            JwtAlgorithm::ES256 => Self::ES256,
+            JwtAlgorithm::ES384 => Self::ES384,
+            JwtAlgorithm::EdDSA => Self::EdDSA,
+        }
+    }
+}
+
+/// AwsCredentials is where the keys to sign with are read from.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AwsCredentials {
+    /// `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and
+    /// `AWS_SESSION_TOKEN`.
+    #[default]
+    Env,
+    /// A profile in `~/.aws/credentials` or `~/.aws/config`.
+    Profile,
+}
+
+/// Keys are AWS credentials.
+#[derive(Clone, Debug, PartialEq)]
+struct Keys {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+}
+
+/// Headers that proxies and clients change, so they're never signed.
+const UNSIGNED_HEADERS: [&str; 3] = ["authorization", "user-agent", "expect"];
+
+impl Auth {
+    /// The fields that are templates and where they are, like
+    /// `auth region`.
+    pub fn templates(&self) -> Vec<(String, String)> {
+        match self {
+            Auth::AwsSigv4 {
+                service,
+                region,
+                profile,
+                ..
+            } => {
+                let mut templates = vec![
+                    ("auth service".to_string(), service.clone()),
+                    ("auth region".to_string(), region.clone()),
+                ];
+                if let Some(profile) = profile {
+                    templates.push(("auth profile".to_string(), profile.clone()));
+                }
+                templates
+            }
+            Auth::Jwt {
+                claims, key, kid, ..
+            } => {
+                let mut templates = vec![
+                    ("auth key".to_string(), key.clone()),
+                    (
+                        "auth claims".to_string(),
+                        serde_json::Value::Object(claims.clone()).to_string(),
+                    ),
+                ];
+                if let Some(kid) = kid {
+                    templates.push(("auth kid".to_string(), kid.clone()));
+                }
+                templates
+            }
+            Auth::Digest { username, password } => vec![
+                ("auth username".to_string(), username.clone()),
+                ("auth password".to_string(), password.clone()),
+            ],
+            Auth::Ntlm {
+                username,
+                password,
+                domain,
+                ..
+            } => {
+                let mut templates = vec![
+                    ("auth username".to_string(), username.clone()),
+                    ("auth password".to_string(), password.clone()),
+                ];
+                if let Some(domain) = domain {
+                    templates.push(("auth domain".to_string(), domain.clone()));
+                }
+                templates
+            }
+            Auth::ApiKey { name, value, .. } => vec![
+                ("auth name".to_string(), name.clone()),
+                ("auth value".to_string(), value.clone()),
+            ],
+        }
+    }
+
+    /// The fields that are secrets, like passwords, so they can be
+    /// masked.
+    pub fn secrets(&self) -> Vec<&String> {
+        match self {
+            Auth::AwsSigv4 { .. } | Auth::Jwt { .. } => vec![],
+            Auth::Digest { password, .. } | Auth::Ntlm { password, .. } => vec![password],
+            Auth::ApiKey { value, .. } => vec![value],
+        }
+    }
+
+    /// Mask the API key in the URL, like the one in an error that's
+    /// printed.
+    pub fn mask(&self, url: &mut reqwest::Url) {
+        let Auth::ApiKey {
+            name,
+            location: ApiKeyLocation::Query,
+            ..
+        } = self
+        else {
+            return;
+        };
+        let pairs = url
+            .query_pairs()
+            .into_owned()
+            .map(|(k, v)| match &k == name {
+                true => (k, MASK.to_string()),
+                false => (k, v),
+            })
+            .collect::<Vec<_>>();
+        url.query_pairs_mut().clear().extend_pairs(pairs);
+    }
+
+    /// The fields that are secrets, to mask them.
+    pub fn secrets_mut(&mut self) -> Vec<&mut String> {
+        match self {
+            Auth::AwsSigv4 { .. } | Auth::Jwt { .. } => vec![],
+            Auth::Digest { password, .. } | Auth::Ntlm { password, .. } => vec![password],
+            Auth::ApiKey { value, .. } => vec![value],
+        }
+    }
+
+    /// Apply the context to the fields.
+    pub fn apply(&mut self, app: &Applicator) {
+        match self {
+            Auth::AwsSigv4 {
+                service,
+                region,
+                profile,
+                ..
+            } => {
+                *service = app.apply(service);
+                *region = app.apply(region);
+                if let Some(profile) = profile {
+                    *profile = app.apply(profile);
+                }
+            }
+            Auth::Jwt {
+                claims, key, kid, ..
+            } => {
+                claims.values_mut().for_each(|v| app.apply_value(v));
+                *key = app.apply(key);
+                if let Some(kid) = kid {
+                    *kid = app.apply(kid);
+                }
+            }
+            Auth::Digest { username, password } => {
+                *username = app.apply(username);
+                *password = app.apply(password);
+            }
+            Auth::Ntlm {
+                username,
+                password,
+                domain,
+                ..
+            } => {
+                *username = app.apply(username);
+                *password = app.apply(password);
+                if let Some(domain) = domain {
+                    *domain = app.apply(domain);
+                }
+            }
+            Auth::ApiKey { name, value, .. } => {
+                *name = app.apply(name);
+                *value = app.apply(value);
+            }
+        }
+    }
+
+    /// Add the credentials to the request, which is sent as it is after.
+    pub fn authorize(&self, request: &mut reqwest::Request) -> Result<()> {
+        match self {
+            Auth::AwsSigv4 {
+                service,
+                region,
+                credentials,
+                profile,
+            } => {
+                let keys = match credentials {
+                    AwsCredentials::Env => env_keys()?,
+                    AwsCredentials::Profile => profile_keys(profile.as_deref())?,
+                };
+                sigv4(request, &keys, service, region, SystemTime::now())
+            }
+            Auth::Jwt {
+                claims,
+                key,
+                alg,
+                kid,
+                expires_in,
+            } => {
+                let pem = std::fs::read(key)
+                    .map_err(|e| AuthError::Jwt(format!("can't read {}: {}", key, e)))?;
+                let token = jwt(
+                    claims,
+                    &pem,
+                    *alg,
+                    kid.clone(),
+                    *expires_in,
+                    SystemTime::now(),
+                )?;
+                let bearer = header(format!("Bearer {}", token))?;
+                request.headers_mut().insert(AUTHORIZATION, bearer);
+                Ok(())
+            }
+            // Digest waits for the server's challenge.
+            Auth::Digest { .. } => Ok(()),
+            #[cfg(feature = "ntlm")]
+            Auth::Ntlm { scheme, .. } => {
+                let negotiate = STANDARD.encode(crate::ntlm::negotiate());
+                let negotiate = header(format!("{} {}", scheme, negotiate))?;
+                request.headers_mut().insert(AUTHORIZATION, negotiate);
+                Ok(())
+            }
+            #[cfg(not(feature = "ntlm"))]
+            Auth::Ntlm { .. } => Err(AuthError::Feature("ntlm", "ntlm")),
+            Auth::ApiKey {
+                name,
+                value,
+                location,
+            } => {
+                match location {
+                    ApiKeyLocation::Header => {
+                        let key = HeaderName::from_bytes(name.as_bytes())
+                            .map_err(|e| AuthError::Header(format!("{}: {}", name, e)))?;
+                        let value = HeaderValue::from_str(value)
+                            .map_err(|e| AuthError::Header(format!("{}: {}", name, e)))?;
+                        request.headers_mut().insert(key, value);
+                    }
+                    ApiKeyLocation::Query => {
+                        request.url_mut().query_pairs_mut().append_pair(name, value);
+                    }
+                    // The key is added to the cookies the request has.
+                    ApiKeyLocation::Cookie => {
+                        let cookie = match request.headers().get(COOKIE) {
+                            Some(cookies) => format!(
+                                "{}; {}={}",
+                                cookies.to_str().unwrap_or_default(),
+                                name,
+                                value
+                            ),
+                            None => format!("{}={}", name, value),
+                        };
+                        let cookie = HeaderValue::from_str(&cookie)
+                            .map_err(|e| AuthError::Header(format!("cookie: {}", e)))?;
+                        request.headers_mut().insert(COOKIE, cookie);
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// The Authorization header that answers the server's challenge in
+    /// the response to the request, for challenge-response schemes. The
+    /// request is sent again with it.
+    pub fn respond(
+        &self,
+        method: &reqwest::Method,
+        url: &reqwest::Url,
+        response: &reqwest::Response,
+    ) -> Result<Option<HeaderValue>> {
+        if response.status() != reqwest::StatusCode::UNAUTHORIZED {
+            return Ok(None);
+        }
+        let challenge = |scheme: &str| {
+            response
+                .headers()
+                .get_all(WWW_AUTHENTICATE)
+                .iter()
+                .filter_map(|v| v.to_str().ok())
+                .find_map(|v| {
+                    let (name, rest) = v.trim().split_once(' ')?;
+                    name.eq_ignore_ascii_case(scheme)
+                        .then(|| rest.trim().to_string())
+                })
+        };
+        match self {
+            Auth::Digest { username, password } => {
+                let Some(challenge) = challenge("Digest") else {
+                    return Ok(None);
+                };
+                let uri = match url.query() {
+                    Some(query) => format!("{}?{}", url.path(), query),
+                    None => url.path().to_string(),
+                };
+                let cnonce = format!("{:016x}", rand::random::<u64>());
+                let answer = digest(
+                    &challenge,
+                    method.as_str(),
+                    &uri,
+                    username,
+                    password,
+                    &cnonce,
+                )?;
+                Ok(Some(header(answer)?))
+            }
+            #[cfg(feature = "ntlm")]
+            Auth::Ntlm {
+                username,
+                password,
+                domain,
+                scheme,
+            } => {
+                let Some(challenge) = challenge(&scheme.to_string()) else {
+                    return Ok(None);
+                };
+                let message = STANDARD
+                    .decode(challenge)
+                    .map_err(|e| AuthError::Challenge(e.to_string()))?;
+                let challenge = crate::ntlm::parse_challenge(&message)
+                    .map_err(|e| AuthError::Challenge(e.to_string()))?;
+                let (domain, username) = match (domain, username.split_once('\\')) {
+                    (Some(domain), _) => (domain.as_str(), username.as_str()),
+                    (None, Some((domain, username))) => (domain, username),
+                    (None, None) => ("", username.as_str()),
+                };
+                let message = crate::ntlm::authenticate(
+                    &challenge,
+                    domain,
+                    username,
+                    password,
+                    rand::random(),
+                    crate::ntlm::filetime(SystemTime::now()),
+                );
+                Ok(Some(header(format!(
+                    "{} {}",
+                    scheme,
+                    STANDARD.encode(message)
+                ))?))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+fn header(value: String) -> Result<HeaderValue> {
+    HeaderValue::from_str(&value).map_err(|e| AuthError::Header(format!("authorization: {}", e)))
+}
+
+/// The parameters of a challenge, like `realm="api", nonce="abc"`, with
+/// lowercase names.
+fn auth_params(s: &str) -> BTreeMap<String, String> {
+    let mut params = BTreeMap::new();
+    let mut chars = s.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace() || *c == ',').is_some() {}
+        let name =
+            std::iter::from_fn(|| chars.next_if(|c| *c != '=' && *c != ',')).collect::<String>();
+        if name.is_empty() {
+            return params;
+        }
+        let mut value = String::new();
+        if chars.next_if_eq(&'=').is_some() {
+            if chars.next_if_eq(&'"').is_some() {
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => value.extend(chars.next()),
+                        '"' => break,
+                        c => value.push(c),
+                    }
+                }
+            } else {
+                value = std::iter::from_fn(|| chars.next_if(|c| *c != ',')).collect();
+            }
+        }
+        params.insert(name.trim().to_lowercase(), value.trim().to_string());
+    }
+}
+
+/// The Authorization header answering the HTTP Digest challenge, the
+/// part after `Digest`, as RFC 7616 describes.
+fn digest(
+    challenge: &str,
+    method: &str,
+    uri: &str,
+    username: &str,
+    password: &str,
+    cnonce: &str,
+) -> Result<String> {
+    let params = auth_params(challenge);
+    let param = |name: &str| params.get(name).map(String::as_str).unwrap_or_default();
+    let algorithm = params
+        .get("algorithm")
+        .cloned()
+        .unwrap_or_else(|| "MD5".to_string());
+    let hash = match algorithm.to_uppercase().trim_end_matches("-SESS") {
+        "MD5" => |s: &str| hex(&md5::Md5::digest(s.as_bytes())),
+        "SHA-256" => |s: &str| hex(&Sha256::digest(s.as_bytes())),
+        _ => {
+            return Err(AuthError::Challenge(format!(
+                "unsupported algorithm: {}",
+                algorithm
+            )))
+        }
+    };
+    let (realm, nonce) = (param("realm"), param("nonce"));
+    let mut ha1 = hash(&format!("{}:{}:{}", username, realm, password));
+    if algorithm.to_uppercase().ends_with("-SESS") {
+        ha1 = hash(&format!("{}:{}:{}", ha1, nonce, cnonce));
+    }
+    let ha2 = hash(&format!("{}:{}", method, uri));
+    let qop = params
+        .get("qop")
+        .map(|q| q.split(',').any(|q| q.trim() == "auth"));
+
+    let mut answer = format!(
+        r#"Digest username="{}", realm="{}", nonce="{}", uri="{}", algorithm={}"#,
+        username, realm, nonce, uri, algorithm
+    );
+    let response = match qop {
+        Some(true) => {
+            let nc = "00000001";
+            answer.push_str(&format!(r#", qop=auth, nc={}, cnonce="{}""#, nc, cnonce));
+            hash(&format!("{}:{}:{}:{}:auth:{}", ha1, nonce, nc, cnonce, ha2))
+        }
+        Some(false) => {
+            return Err(AuthError::Challenge(format!(
+                "unsupported qop: {}",
+                param("qop")
+            )))
+        }
+        None => hash(&format!("{}:{}:{}", ha1, nonce, ha2)),
+    };
+    answer.push_str(&format!(r#", response="{}""#, response));
+    if let Some(opaque) = params.get("opaque") {
+        answer.push_str(&format!(r#", opaque="{}""#, opaque));
+    }
+    Ok(answer)
+}
+
+/// A JWT with the claims signed with the key at the time.
+fn jwt(
+    claims: &serde_json::Map<String, serde_json::Value>,
+    key: &[u8],
+    alg: JwtAlgorithm,
+    kid: Option<String>,
+    expires_in: Duration,
+    time: SystemTime,
+) -> Result<String> {
+    use jsonwebtoken::EncodingKey;
+
+    let invalid = |e: jsonwebtoken::errors::Error| AuthError::Jwt(format!("invalid key: {}", e));
+    let key = match alg {
+        JwtAlgorithm::HS256 | JwtAlgorithm::HS384 | JwtAlgorithm::HS512 => {
+            EncodingKey::from_secret(key)
+        }
+        JwtAlgorithm::ES256 | JwtAlgorithm::ES384 => {
+            EncodingKey::from_ec_pem(key).map_err(invalid)?
+        }
+        JwtAlgorithm::EdDSA => EncodingKey::from_ed_pem(key).map_err(invalid)?,
+        _ => EncodingKey::from_rsa_pem(key).map_err(invalid)?,
+    };
+    let now = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let mut claims = claims.clone();
+    claims.entry("iat").or_insert(now.into());
+    claims
+        .entry("exp")
+        .or_insert((now + expires_in.as_secs()).into());
+
+    let mut header = jsonwebtoken::Header::new(alg.into());
+    header.kid = kid;
+    jsonwebtoken::encode(&header, &claims, &key).map_err(|e| AuthError::Jwt(e.to_string()))
+}
+
+fn env_keys() -> Result<Keys> {
+    let var = |name: &str| {
+        std::env::var(name)
+            .ok()
+            .filter(|v| !v.is_empty())
+            .ok_or_else(|| AuthError::Credentials(format!("{} isn't set", name)))
+    };
+    Ok(Keys {
+        access_key_id: var("AWS_ACCESS_KEY_ID")?,
+        secret_access_key: var("AWS_SECRET_ACCESS_KEY")?,
+        session_token: var("AWS_SESSION_TOKEN").ok(),
+    })
+}
+
+/// Read the profile's keys from the shared credentials file or, if
+/// they aren't there, the config file, like the AWS CLI does.
+fn profile_keys(profile: Option<&str>) -> Result<Keys> {
+    let profile = profile
+        .map(String::from)
+        .or_else(|| std::env::var("AWS_PROFILE").ok())
+        .unwrap_or_else(|| "default".to_string());
+    let aws = |var: &str, file: &str| {
+        std::env::var_os(var).map(PathBuf::from).or_else(|| {
+            Some(
+                PathBuf::from(std::env::var_os("HOME")?)
+                    .join(".aws")
+                    .join(file),
+            )
+        })
+    };
+    let sources = [
+        (
+            aws("AWS_SHARED_CREDENTIALS_FILE", "credentials"),
+            profile.clone(),
+        ),
+        (
+            aws("AWS_CONFIG_FILE", "config"),
+            format!("profile {}", profile),
+        ),
+        (aws("AWS_CONFIG_FILE", "config"), profile.clone()),
+    ];
+    for (path, section) in sources {
+        let Some(path) = path else {
+            continue;
+        };
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let values = ini_section(&contents, &section);
+        if let (Some(id), Some(secret)) = (
+            values.get("aws_access_key_id"),
+            values.get("aws_secret_access_key"),
+        ) {
+            return Ok(Keys {
+                access_key_id: id.clone(),
+                secret_access_key: secret.clone(),
+                session_token: values.get("aws_session_token").cloned(),
+            });
+        }
+    }
+    Err(AuthError::Credentials(format!(
+        "no keys for profile '{}' in ~/.aws/credentials or ~/.aws/config",
+        profile
+    )))
+}
+
+/// The keys and values in the section of an INI file.
+fn ini_section(contents: &str, section: &str) -> BTreeMap<String, String> {
+    let mut values = BTreeMap::new();
+    let mut inside = false;
+    for line in contents.lines().map(str::trim) {
+        if line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            inside = name.trim() == section;
+        } else if let (true, Some((key, value))) = (inside, line.split_once('=')) {
+            values.insert(key.trim().to_string(), value.trim().to_string());
+        }
+    }
+    values
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn hmac(key: &[u8], message: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac takes any key");
+    mac.update(message.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Sign the request with AWS Signature Version 4 at the time. The body
+/// is hashed when it's in memory; streamed bodies, like multi-part
+/// forms, are sent unsigned.
+fn sigv4(
+    request: &mut reqwest::Request,
+    keys: &Keys,
+    service: &str,
+    region: &str,
+    time: SystemTime,
+) -> Result<()> {
+    // 2015-08-30T12:36:00Z becomes 20150830T123600Z.
+    let amz_date = humantime::format_rfc3339_seconds(time)
+        .to_string()
+        .replace(['-', ':'], "");
+    let date = &amz_date[..8];
+    let payload = match request.body() {
+        None => hex(&Sha256::digest(b"")),
+        Some(body) => match body.as_bytes() {
+            Some(bytes) => hex(&Sha256::digest(bytes)),
+            None => "UNSIGNED-PAYLOAD".to_string(),
+        },
+    };
+
+    let mut add = |name: &'static str, value: &str| -> Result<()> {
+        let value = reqwest::header::HeaderValue::from_str(value)
+            .map_err(|e| AuthError::Header(format!("{}: {}", name, e)))?;
+        request.headers_mut().insert(name, value);
+        Ok(())
+    };
+    add("x-amz-date", &amz_date)?;
+    if service == "s3" {
+        add("x-amz-content-sha256", &payload)?;
+    }
+    if let Some(token) = &keys.session_token {
+        add("x-amz-security-token", token)?;
+    }
+
+    // The query is sent as it's signed, sorted and percent-encoded.
+    let mut query = request
+        .url()
+        .query_pairs()
+        .map(|(k, v)| (percent_encode(&k), percent_encode(&v)))
+        .collect::<Vec<_>>();
+    query.sort();
+    let query = query
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join("&");
+    if request.url().query().is_some() {
+        let canonical = Some(query.as_str()).filter(|q| !q.is_empty());
+        request.url_mut().set_query(canonical);
+    }
+
+    // Services other than S3 sign the path encoded a second time.
+    let url = request.url();
+    let path = match url.path() {
+        "" => "/".to_string(),
+        path if service == "s3" => path.to_string(),
+        path => path
+            .split('/')
+            .map(percent_encode)
+            .collect::<Vec<_>>()
+            .join("/"),
+    };
+    let mut headers = BTreeMap::new();
+    let host = match url.port() {
+        Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+        None => url.host_str().unwrap_or_default().to_string(),
+    };
+    headers.insert("host".to_string(), vec![host]);
+    for (name, value) in request.headers() {
+        if UNSIGNED_HEADERS.contains(&name.as_str()) {
+            continue;
+        }
+        let value = String::from_utf8_lossy(value.as_bytes());
+        headers
+            .entry(name.as_str().to_string())
+            .or_insert_with(Vec::new)
+            .push(value.split_whitespace().collect::<Vec<_>>().join(" "));
+    }
+    let canonical_headers = headers
+        .iter()
+        .map(|(name, values)| format!("{}:{}\n", name, values.join(",")))
+        .collect::<String>();
+    let signed_headers = headers.keys().cloned().collect::<Vec<_>>().join(";");
+
+    let canonical = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        request.method(),
+        path,
+        query,
+        canonical_headers,
+        signed_headers,
+        payload
+    );
+    let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+    let to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        hex(&Sha256::digest(canonical.as_bytes()))
+    );
+    let key = [date, region, service, "aws4_request"].iter().fold(
+        format!("AWS4{}", keys.secret_access_key).into_bytes(),
+        |k, m| hmac(&k, m),
+    );
+    let authorization = format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        keys.access_key_id,
+        scope,
+        signed_headers,
+        hex(&hmac(&key, &to_sign))
+    );
+    let authorization = reqwest::header::HeaderValue::from_str(&authorization)
+        .map_err(|e| AuthError::Header(format!("authorization: {}", e)))?;
+    request
+        .headers_mut()
+        .insert(reqwest::header::AUTHORIZATION, authorization);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn sigv4() {
+        // From AWS's Signature Version 4 test suite.
+        let keys = Keys {
+            access_key_id: "AKIDEXAMPLE".into(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".into(),
+            session_token: None,
+        };
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1440938160);
+        let mut request = reqwest::Request::new(
+            reqwest::Method::GET,
+            "https://example.amazonaws.com/".parse().unwrap(),
+        );
+        super::sigv4(&mut request, &keys, "service", "us-east-1", time).unwrap();
+        assert_eq!(request.headers()["x-amz-date"], "20150830T123600Z");
+        assert_eq!(
+            request.headers()["authorization"],
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=host;x-amz-date, \
+             Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+
+        let section = ini_section(
+            "[default]\naws_access_key_id = a\n\n[profile dev]\n# keys\naws_access_key_id=b\n",
+            "profile dev",
+        );
+        assert_eq!(
+            section.get("aws_access_key_id").map(String::as_str),
+            Some("b")
+        );
+    }
+
+    #[test]
+    fn jwt() {
+        let claims = serde_json::json!({"sub": "billing", "aud": "https://api.example.com"});
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let token = super::jwt(
+            claims.as_object().unwrap(),
+            b"secret",
+            JwtAlgorithm::HS256,
+            Some("key-1".into()),
+            Duration::from_secs(60),
+            time,
+        )
+        .unwrap();
+
+        let header = jsonwebtoken::decode_header(&token).unwrap();
+        assert_eq!(header.alg, jsonwebtoken::Algorithm::HS256);
+        assert_eq!(header.kid.as_deref(), Some("key-1"));
+        let mut validation = jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::HS256);
+        validation.validate_exp = false;
+        validation.set_audience(&["https://api.example.com"]);
+        let decoded = jsonwebtoken::decode::<serde_json::Value>(
+            &token,
+            &jsonwebtoken::DecodingKey::from_secret(b"secret"),
+            &validation,
+        )
+        .unwrap();
+        assert_eq!(decoded.claims["sub"], "billing");
+        assert_eq!(decoded.claims["iat"], 1_700_000_000);
+        assert_eq!(decoded.claims["exp"], 1_700_000_060);
+
+        assert!(matches!(
+            super::jwt(
+                claims.as_object().unwrap(),
+                b"not a key",
+                JwtAlgorithm::RS256,
+                None,
+                Duration::from_secs(60),
+                time,
+            ),
+            Err(AuthError::Jwt(_))
+        ));
+    }
+
+    #[test]
+    fn digest() {
+        // From RFC 2617.
+        let challenge = r#"realm="testrealm@host.com", qop="auth,auth-int", nonce="dcd98b7102dd2f0e8b11d0f600bfb0c093", opaque="5ccc069c403ebaf9f0171e9517f40e41""#;
+        let params = auth_params(challenge);
+        assert_eq!(params["realm"], "testrealm@host.com");
+        assert_eq!(params["qop"], "auth,auth-int");
+        let answer = super::digest(
+            challenge,
+            "GET",
+            "/dir/index.html",
+            "Mufasa",
+            "Circle Of Life",
+            "0a4f113b",
+        )
+        .unwrap();
+        assert!(answer.starts_with(r#"Digest username="Mufasa", realm="testrealm@host.com""#));
+        assert!(answer.contains(r#", qop=auth, nc=00000001, cnonce="0a4f113b""#));
+        assert!(answer.contains(r#"response="6629fae49393a05397450978507c4ef1""#));
+        assert!(answer.ends_with(r#"opaque="5ccc069c403ebaf9f0171e9517f40e41""#));
+
+        // From RFC 7616.
+        let answer = super::digest(
+            r#"realm="http-auth@example.org", qop="auth, auth-int", algorithm=SHA-256, nonce="7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v", opaque="FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS""#,
+            "GET",
+            "/dir/index.html",
+            "Mufasa",
+            "Circle of Life",
+            "f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ",
+        )
+        .unwrap();
+        assert!(answer.contains(
+            r#"response="753927fa0e85d155564e2e272a28d1802ca10daf4496794697cf8db5856cb6c1""#
+        ));
+
+        assert!(matches!(
+            super::digest(
+                r#"nonce="a", algorithm=SHA-512-256"#,
+                "GET",
+                "/",
+                "u",
+                "p",
+                "c"
+            ),
+            Err(AuthError::Challenge(_))
+        ));
+    }
+
+    #[test]
+    fn api_key() {
+        let request = |location| {
+            let auth = Auth::ApiKey {
+                name: "api_key".into(),
+                value: "k3y value".into(),
+                location,
+            };
+            let mut request = reqwest::Client::new()
+                .get("https://api.example.com/items?page=2")
+                .header(COOKIE, "session=1")
+                .build()
+                .unwrap();
+            auth.authorize(&mut request).unwrap();
+            request
+        };
+        assert_eq!(
+            request(ApiKeyLocation::Header).headers()["api_key"],
+            "k3y value"
+        );
+        assert_eq!(
+            request(ApiKeyLocation::Query).url().as_str(),
+            "https://api.example.com/items?page=2&api_key=k3y+value"
+        );
+        let mut url = request(ApiKeyLocation::Query).url().clone();
+        Auth::ApiKey {
+            name: "api_key".into(),
+            value: "k3y value".into(),
+            location: ApiKeyLocation::Query,
+        }
+        .mask(&mut url);
+        assert_eq!(url.query(), Some("page=2&api_key=********"));
+        assert_eq!(
+            request(ApiKeyLocation::Cookie).headers()[COOKIE],
+            "session=1; api_key=k3y value"
+        );
+    }
+}
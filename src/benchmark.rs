@@ -0,0 +1,437 @@
+/// Benchmark is used to define a mix of requests to benchmark, like the
+/// traffic an API gets, and to summarize how each request did.
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use crate::{List, Request, Response};
+
+use rand::distr::weighted::WeightedIndex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// BenchmarkError is the error type for benchmarks.
+#[derive(Error, Debug)]
+pub enum BenchmarkError {
+    #[error("weights error: {0}")]
+    Weights(#[from] rand::distr::weighted::Error),
+
+    #[error("data error: {0}")]
+    Data(#[from] csv::Error),
+
+    #[error("not enough data: {0} unique rows for {1} iterations")]
+    NotEnoughData(usize, usize),
+
+    #[error("request error: {0}")]
+    Request(#[from] crate::RequestError),
+}
+
+/// Result is the result type for benchmarks.
+pub type Result<T> = std::result::Result<T, BenchmarkError>;
+
+/// Weighted is a request in a scenario and how often it's made compared
+/// to the others.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
+pub struct Weighted {
+    pub request: String,
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+/// Scenario is a named mix of requests to benchmark. Each iteration
+/// makes one of the requests, picked at random by weight, so a scenario
+/// with weights of 80, 15 and 5 makes the first request about 80% of
+/// the time.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, JsonSchema)]
+pub struct Scenario {
+    #[serde(default)]
+    pub description: String,
+    pub requests: Vec<Weighted>,
+    /// Rows of data for the iterations to use.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Data>,
+}
+
+/// DataMode is how the rows of a data file are handed out.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DataMode {
+    /// Each iteration uses the next row, starting over after the last.
+    #[default]
+    RoundRobin,
+    /// Each row is used by one iteration at most, for requests that
+    /// create resources the API won't allow twice.
+    Unique,
+}
+
+/// Data is a CSV file with a header row whose rows are handed out to
+/// the iterations of a benchmark. The columns are available to the
+/// requests as `${data.COLUMN}`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
+pub struct Data {
+    pub file: PathBuf,
+    #[serde(default)]
+    pub mode: DataMode,
+}
+
+/// Pool is the loaded rows of a data file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Pool {
+    columns: Vec<String>,
+    rows: Vec<Vec<String>>,
+    mode: DataMode,
+}
+
+impl Pool {
+    /// Load the rows of the data file. With unique rows, there must be
+    /// one for each of the iterations.
+    pub fn load(data: &Data, iterations: usize) -> Result<Self> {
+        let mut reader = csv::Reader::from_path(&data.file)?;
+        let columns = reader.headers()?.iter().map(String::from).collect();
+        let rows = reader
+            .records()
+            .map(|r| Ok(r?.iter().map(String::from).collect()))
+            .collect::<Result<Vec<_>>>()?;
+        let min = match data.mode {
+            DataMode::RoundRobin => 1,
+            DataMode::Unique => iterations,
+        };
+        if rows.len() < min {
+            return Err(BenchmarkError::NotEnoughData(rows.len(), iterations));
+        }
+        Ok(Self {
+            columns,
+            rows,
+            mode: data.mode,
+        })
+    }
+
+    /// The variables of the row for the iteration.
+    pub fn variables(&self, iteration: usize) -> Vec<(String, String)> {
+        let row = match self.mode {
+            DataMode::RoundRobin => self.rows.get(iteration % self.rows.len().max(1)),
+            DataMode::Unique => self.rows.get(iteration),
+        };
+        self.columns
+            .iter()
+            .zip(row.into_iter().flatten())
+            .map(|(c, v)| (format!("data.{}", c), v.clone()))
+            .collect()
+    }
+}
+
+impl Scenario {
+    /// The names of the requests in the order they are listed.
+    pub fn names(&self) -> Vec<String> {
+        self.requests.iter().map(|w| w.request.clone()).collect()
+    }
+
+    /// The distribution of the indexes of the requests by weight.
+    pub fn weights(&self) -> Result<WeightedIndex<u32>> {
+        Ok(WeightedIndex::new(self.requests.iter().map(|w| w.weight))?)
+    }
+}
+
+/// Connections are the connections a benchmark makes its requests on.
+/// Each request gets its own set of at most `max` connections, no matter
+/// how many requests are made at once, and a request waits for one of
+/// them to be free. The cap is per request, since each is sent with its
+/// own client settings, so k requests use up to k × `max` connections.
+/// Without keep-alive, every request opens a new one.
+#[derive(Debug)]
+pub struct Connections {
+    max: usize,
+    keepalive: bool,
+    slots: Mutex<HashMap<String, Arc<Vec<Slot>>>>,
+    next: AtomicUsize,
+    opened: Arc<AtomicUsize>,
+}
+
+/// Slot is a client that makes one request at a time, so it never
+/// holds more than one connection to a host.
+#[derive(Debug, Default)]
+struct Slot {
+    client: tokio::sync::OnceCell<reqwest::Client>,
+    lock: tokio::sync::Mutex<()>,
+}
+
+impl Connections {
+    pub fn new(max: usize, keepalive: bool) -> Self {
+        Self {
+            max: max.max(1),
+            keepalive,
+            slots: Mutex::default(),
+            next: AtomicUsize::new(0),
+            opened: Arc::default(),
+        }
+    }
+
+    /// How many connections have been opened, or tried to be.
+    pub fn opened(&self) -> usize {
+        self.opened.load(Ordering::SeqCst)
+    }
+
+    /// Make the request, once its templates are applied, on one of the
+    /// connections for the name.
+    pub async fn request(&self, name: &str, mut request: Request) -> Result<Response> {
+        let slots = self
+            .slots
+            .lock()
+            .unwrap()
+            .entry(name.to_string())
+            .or_insert_with(|| Arc::new((0..self.max).map(|_| Slot::default()).collect()))
+            .clone();
+        let start = self.next.fetch_add(1, Ordering::SeqCst);
+        let free = (0..slots.len())
+            .map(|i| &slots[(start + i) % slots.len()])
+            .find_map(|s| Some((s, s.lock.try_lock().ok()?)));
+        let (slot, _guard) = match free {
+            Some(free) => free,
+            None => {
+                let slot = &slots[start % slots.len()];
+                (slot, slot.lock.lock().await)
+            }
+        };
+        // The client is built for the first request on the connection,
+        // once the URL it connects to is known.
+        let client = slot
+            .client
+            .get_or_try_init(|| async {
+                let idle = match self.keepalive {
+                    true => 1,
+                    false => 0,
+                };
+                request
+                    .client_builder()?
+                    .pool_max_idle_per_host(idle)
+                    .connector_layer(CountConnections(self.opened.clone()))
+                    .build()
+                    .map_err(crate::RequestError::Http)
+            })
+            .await?;
+        request.client = Some(client.clone());
+        Ok(request.request().await?)
+    }
+}
+
+/// CountConnections is a layer on a client's connector that counts the
+/// connections it opens.
+#[derive(Clone)]
+struct CountConnections(Arc<AtomicUsize>);
+
+impl<S> tower_layer::Layer<S> for CountConnections {
+    type Service = Counted<S>;
+
+    fn layer(&self, inner: S) -> Counted<S> {
+        Counted {
+            inner,
+            opened: self.0.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Counted<S> {
+    inner: S,
+    opened: Arc<AtomicUsize>,
+}
+
+impl<S: tower_service::Service<R>, R> tower_service::Service<R> for Counted<S> {
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<std::result::Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: R) -> Self::Future {
+        self.opened.fetch_add(1, Ordering::SeqCst);
+        self.inner.call(request)
+    }
+}
+
+/// Stats are the statistics of the requests made in a benchmark.
+#[derive(Clone, Debug, Default, Serialize, PartialEq)]
+pub struct Stats {
+    pub requests: usize,
+    /// The requests that failed without a response.
+    pub failed: usize,
+    pub mean: Duration,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    pub fastest: Duration,
+    pub slowest: Duration,
+}
+
+impl Stats {
+    /// The statistics of the durations of the requests that got a
+    /// response and the number that failed.
+    pub fn new(durations: &[Duration], failed: usize) -> Self {
+        let mut sorted = durations.to_vec();
+        sorted.sort();
+        let percentile = |p: usize| match sorted.is_empty() {
+            true => Duration::ZERO,
+            false => sorted[(sorted.len() * p / 100).min(sorted.len() - 1)],
+        };
+        Self {
+            requests: durations.len() + failed,
+            failed,
+            mean: durations.iter().sum::<Duration>() / (durations.len().max(1) as u32),
+            p50: percentile(50),
+            p95: percentile(95),
+            p99: percentile(99),
+            fastest: sorted.first().copied().unwrap_or_default(),
+            slowest: sorted.last().copied().unwrap_or_default(),
+        }
+    }
+}
+
+/// Implement List for the statistics of each request.
+impl List for HashMap<String, Stats> {
+    fn headers(&self) -> Vec<String> {
+        [
+            "Request", "Requests", "Failed", "Mean", "P50", "P95", "P99", "Fastest", "Slowest",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect()
+    }
+
+    fn values(&self) -> Vec<Vec<String>> {
+        self.iter()
+            .map(|(n, s)| {
+                vec![
+                    n.clone(),
+                    s.requests.to_string(),
+                    s.failed.to_string(),
+                    format!("{:?}", s.mean),
+                    format!("{:?}", s.p50),
+                    format!("{:?}", s.p95),
+                    format!("{:?}", s.p99),
+                    format!("{:?}", s.fastest),
+                    format!("{:?}", s.slowest),
+                ]
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::distr::Distribution;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn weights() {
+        let scenario: Scenario = serde_yaml::from_str(
+            "{requests: [{request: get, weight: 80}, {request: create, weight: 20}, {request: list}]}",
+        )
+        .unwrap();
+        assert_eq!(scenario.names(), vec!["get", "create", "list"]);
+        let weights = scenario.weights().unwrap();
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut counts = [0; 3];
+        for _ in 0..10_000 {
+            counts[weights.sample(&mut rng)] += 1;
+        }
+        assert!(counts[0] > 7_500 && counts[0] < 8_500, "{:?}", counts);
+        assert!(counts[2] > 0 && counts[2] < 200, "{:?}", counts);
+
+        let empty = Scenario::default();
+        assert!(empty.weights().is_err());
+
+        let ms = Duration::from_millis;
+        let stats = Stats::new(&[ms(3), ms(1), ms(2), ms(6)], 1);
+        assert_eq!(stats.requests, 5);
+        assert_eq!(stats.mean, ms(3));
+        assert_eq!(
+            (stats.fastest, stats.p50, stats.slowest),
+            (ms(1), ms(3), ms(6))
+        );
+        assert_eq!(Stats::new(&[], 2).slowest, Duration::ZERO);
+    }
+
+    #[test]
+    fn pool() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("pool.csv");
+        std::fs::write(&file, "email,name\na@example.com,A\nb@example.com,B\n").unwrap();
+        let mut data = Data {
+            file: file.clone(),
+            mode: DataMode::RoundRobin,
+        };
+        let pool = Pool::load(&data, 3).unwrap();
+        assert_eq!(
+            pool.variables(2),
+            vec![
+                ("data.email".to_string(), "a@example.com".to_string()),
+                ("data.name".to_string(), "A".to_string()),
+            ]
+        );
+
+        data.mode = DataMode::Unique;
+        assert!(matches!(
+            Pool::load(&data, 3),
+            Err(BenchmarkError::NotEnoughData(2, 3))
+        ));
+        let pool = Pool::load(&data, 2).unwrap();
+        assert_eq!(pool.variables(1)[1].1, "B");
+        assert!(pool.variables(2).is_empty());
+    }
+
+    #[tokio::test]
+    async fn connections() {
+        use crate::{Mock, MockServer};
+
+        let mocks = [("get", "/get"), ("list", "/list")]
+            .into_iter()
+            .map(|(name, path)| {
+                let mock = Mock {
+                    method: None,
+                    path: path.into(),
+                    status: 200,
+                    headers: HashMap::new(),
+                    body: "ok".into(),
+                };
+                (name.to_string(), mock)
+            })
+            .collect();
+        let server = MockServer::new(&mocks, HashMap::new(), None).unwrap();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(server.serve_listener(listener));
+        let request = |path: &str| Request {
+            url: format!("http://{}{}", addr, path),
+            ..Default::default()
+        };
+
+        // Kept alive, each request opens at most its own two.
+        let connections = Connections::new(2, true);
+        for _ in 0..5 {
+            let response = connections.request("get", request("/get")).await.unwrap();
+            assert_eq!(response.status_code, 200);
+        }
+        assert_eq!(connections.opened(), 2);
+        connections.request("list", request("/list")).await.unwrap();
+        assert_eq!(connections.opened(), 3);
+
+        let connections = Connections::new(2, false);
+        for _ in 0..5 {
+            connections.request("get", request("/get")).await.unwrap();
+        }
+        assert_eq!(connections.opened(), 5);
+    }
+}
@@ -1,14 +1,33 @@
-use std::collections::HashMap;
-use std::io::stdout;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{stdout, IsTerminal};
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Arc, Mutex};
-use std::time::{Duration, Instant};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime};
 
-use apictl::{Applicator, Config, List, OutputFormat, Request, Response, Results, State};
+use apictl::events::millis;
+use apictl::history::Run;
+use apictl::request::{Body, RawBody};
+use apictl::suite::Order;
+use apictl::test::{Assert, Step};
+use apictl::{
+    benchmark, changed, condition, diagnostic, history, hurl, notify, scaffold, Applicator, Bundle,
+    Check, Config, Correlation, Diagnostic, Docs, DocsFormat, Event, Events, Graph, GraphFormat,
+    History, List, MockServer, Monitor, OpenApi, OutputFormat, Profile, ProfileError, Recorder,
+    Redactor, Request, RequestError, Response, Results, Schema, Severity, State, Suite, Test,
+    TestError, TestOptions, UserConfig, Verbosity, WriteBehind,
+};
 
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand};
+use clap_complete::engine::{ArgValueCandidates, CompletionCandidate};
+use clap_complete::env::Shells;
+use clap_complete::{CompleteEnv, Shell};
+use crossterm::{cursor, terminal, ExecutableCommand};
+use futures_util::StreamExt;
+use rand::distr::Distribution;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::util::SubscriberInitExt;
 
 #[derive(Parser)]
 #[command(name = "apictl")]
@@ -20,12 +39,58 @@ use clap::{Parser, Subcommand};
 #[command(long_about = None)]
 struct Args {
     /// The file or folder containing the configuration and cache files.
-    #[arg(short, long, value_name = "CONFIG", default_value = ".apictl.yaml")]
+    #[arg(
+        short,
+        long,
+        value_name = "CONFIG",
+        default_value = ".apictl.yaml",
+        env = "APICTL_CONFIG"
+    )]
     config: PathBuf,
 
-    /// The folder used to store responses.
-    #[arg(long, value_name = "CACHE", default_value = ".apictl")]
-    cache: PathBuf,
+    /// The folder used to store responses. Defaults to the profile's
+    /// cache or .apictl.
+    #[arg(long, value_name = "CACHE")]
+    cache: Option<PathBuf>,
+
+    /// The profile from the user configuration to use instead of its
+    /// default profile.
+    #[arg(long, value_name = "PROFILE", env = "APICTL_PROFILE")]
+    profile: Option<String>,
+
+    /// The user configuration with the profiles. Defaults to
+    /// ~/.config/apictl/config.yaml.
+    #[arg(long, value_name = "FILE", env = "APICTL_USER_CONFIG")]
+    user_config: Option<PathBuf>,
+
+    /// The level of logs to write: off, error, warn, info, debug, or
+    /// trace.
+    #[arg(long, value_name = "LEVEL", default_value = "warn")]
+    log_level: LevelFilter,
+
+    /// The name of this run. Responses are cached under it and only
+    /// responses from the same run are used in templates. Defaults to
+    /// a new name for every run.
+    #[arg(long, value_name = "RUN_ID", env = "APICTL_RUN_ID")]
+    run_id: Option<String>,
+
+    /// Use the latest cached responses from any run in templates.
+    #[arg(long)]
+    any_run: bool,
+
+    /// Write logs as JSON lines to this file instead of stderr.
+    #[arg(long, value_name = "FILE")]
+    log_file: Option<PathBuf>,
+
+    /// Fail when a variable in a request can't be resolved instead of
+    /// replacing it with nothing.
+    #[arg(long)]
+    strict_templates: bool,
+
+    /// Send this header, like X-Request-Id, with every request, set to
+    /// the run ID so the requests can be found in the server's logs.
+    #[arg(long, value_name = "HEADER", env = "APICTL_CORRELATION_HEADER")]
+    correlation_header: Option<String>,
 
     #[command(subcommand)]
     command: Command,
@@ -33,6 +98,24 @@ struct Args {
 
 #[derive(Subcommand)]
 enum Command {
+    /// Print the script that enables shell completions. For example,
+    /// add `source <(apictl completions bash)` to your .bashrc.
+    Completions {
+        /// The shell to print the script for.
+        shell: Shell,
+    },
+
+    /// Create a starter configuration.
+    New {
+        /// The file to write the configuration to.
+        #[arg(value_name = "PATH", default_value = ".apictl.yaml")]
+        path: PathBuf,
+
+        /// Overwrite the file if it already exists.
+        #[arg(short, long)]
+        force: bool,
+    },
+
     /// Manage requests.
     #[command(subcommand)]
     Requests(Requests),
@@ -49,12 +132,178 @@ enum Command {
     #[command(subcommand)]
     Tests(Tests),
 
+    /// Manage suites of tests.
+    #[command(subcommand)]
+    Suites(Suites),
+
+    /// Look at past test runs and benchmarks.
+    #[command(subcommand)]
+    History(Runs),
+
+    /// Inspect the configuration.
+    #[command(subcommand)]
+    Config(Configuration),
+
+    /// Print the JSON Schema of configuration files, for editors to
+    /// validate and complete them.
+    Schema,
+
+    /// Check the configuration for problems, with the file and line of
+    /// each, for editors and other tools.
+    Check {
+        /// The contexts that will be used.
+        #[arg(short, long, value_name = "CONTEXT", add = ArgValueCandidates::new(context_names))]
+        contexts: Vec<String>,
+
+        /// Check again whenever a file changes, until stopped.
+        #[arg(short, long)]
+        watch: bool,
+
+        /// Output the problems of each check as a line of JSON.
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Manage flows.
+    #[command(subcommand)]
+    Flows(Flows),
+
+    /// Generate documentation from the requests.
+    #[command(subcommand)]
+    Docs(Documentation),
+
+    /// Convert the requests to other formats.
+    #[command(subcommand)]
+    Export(Export),
+
+    /// Add requests and tests from other formats.
+    #[command(subcommand)]
+    Import(Import),
+
+    /// Serve stubbed responses.
+    #[command(subcommand)]
+    Mock(Mocks),
+
+    /// Record traffic through a local proxy as requests and responses.
+    Record {
+        /// The port to listen on.
+        #[arg(short, long, default_value = "8888")]
+        port: u16,
+
+        /// The address to listen on.
+        #[arg(long, default_value = "127.0.0.1")]
+        host: std::net::IpAddr,
+
+        /// The file to write the recorded requests to.
+        #[arg(short, long, value_name = "FILE", default_value = "recorded.yaml")]
+        output: PathBuf,
+
+        /// Forward to this base URL instead of acting as an HTTP proxy.
+        #[arg(short, long, value_name = "URL")]
+        target: Option<reqwest::Url>,
+    },
+
+    /// Work with GraphQL endpoints.
+    #[command(subcommand)]
+    Graphql(GraphQL),
+
+    /// Make a request from the command line without defining it.
+    Call {
+        /// The URL to request. Relative URLs use the context's base_url.
+        url: String,
+
+        /// The method to use. Defaults to POST when there is data and GET
+        /// otherwise.
+        #[arg(short = 'X', long, value_name = "METHOD")]
+        method: Option<String>,
+
+        /// Headers to set as 'name: value'.
+        #[arg(short = 'H', long = "header", value_name = "HEADER")]
+        headers: Vec<String>,
+
+        /// The body to send. Use @path to send a file.
+        #[arg(short, long, value_name = "DATA")]
+        data: Option<String>,
+
+        /// The contexts to use.
+        #[arg(short, long, value_name = "CONTEXT", add = ArgValueCandidates::new(context_names))]
+        contexts: Vec<String>,
+
+        /// Set a variable, overriding the contexts.
+        #[arg(long = "var", value_name = "KEY=VALUE")]
+        vars: Vec<String>,
+
+        /// The name to cache the response as.
+        #[arg(short, long, value_name = "NAME", default_value = "call")]
+        save_as: String,
+
+        /// Include response and header values before the body.
+        #[arg(short, long)]
+        verbose: bool,
+    },
+
+    /// Print a graph of which tests make which requests and which
+    /// responses and variables each request uses.
+    Graph {
+        /// The format of the graph, dot or mermaid.
+        #[arg(short, long, value_name = "FORMAT", default_value = "dot")]
+        output: GraphFormat,
+    },
+
+    /// Run tests and requests over and over, showing their status and
+    /// reporting when it changes.
+    Monitor {
+        /// The contexts to use.
+        #[arg(short, long, value_name = "CONTEXT", add = ArgValueCandidates::new(context_names))]
+        contexts: Vec<String>,
+
+        /// Set a variable, overriding the contexts.
+        #[arg(long = "var", value_name = "KEY=VALUE")]
+        vars: Vec<String>,
+
+        /// How long to wait between runs (e.g. 30s).
+        #[arg(long, value_parser = humantime::parse_duration, default_value = "30s")]
+        interval: Duration,
+
+        /// The tests to run.
+        #[arg(long, value_delimiter = ',', add = ArgValueCandidates::new(test_names))]
+        tests: Vec<String>,
+
+        /// The requests to run. They pass when they get a response below
+        /// 400 that meets their expect.
+        #[arg(long, value_delimiter = ',', add = ArgValueCandidates::new(request_names))]
+        requests: Vec<String>,
+
+        /// Append each check to this file as a JSON line.
+        #[arg(long, value_name = "FILE")]
+        log: Option<PathBuf>,
+
+        /// POST each check that changes a status to this URL as JSON.
+        #[arg(long, value_name = "URL")]
+        webhook: Option<String>,
+
+        /// Stop after this many runs instead of running until
+        /// interrupted.
+        #[arg(long)]
+        count: Option<usize>,
+
+        /// Serve Prometheus metrics at /metrics on this address, like
+        /// 127.0.0.1:9090, while it runs.
+        #[cfg(feature = "metrics")]
+        #[arg(long, value_name = "ADDR")]
+        metrics: Option<std::net::SocketAddr>,
+    },
+
     /// benchmark an API.
     Benchmark {
         /// The contexts to use.
-        #[arg(short, long, value_name = "CONTEXT")]
+        #[arg(short, long, value_name = "CONTEXT", add = ArgValueCandidates::new(context_names))]
         contexts: Vec<String>,
 
+        /// Set a variable, overriding the contexts.
+        #[arg(long = "var", value_name = "KEY=VALUE")]
+        vars: Vec<String>,
+
         /// The number of times to run the requests.
         #[arg(short, value_name = "NUMBER", default_value = "100")]
         number: usize,
@@ -63,7 +312,49 @@ enum Command {
         #[arg(short, value_name = "PARALLEL", default_value = "8")]
         parallel: usize,
 
+        /// The most connections each request uses, so a scenario of
+        /// several requests opens up to this many for each of them.
+        /// Defaults to the number of concurrent requests.
+        #[arg(long, value_name = "N")]
+        connections: Option<usize>,
+
+        /// Open a new connection for every request instead of reusing
+        /// them, to include connecting in the durations.
+        #[arg(long)]
+        no_keepalive: bool,
+
+        /// Save the last response of each request to the cache when
+        /// the benchmark finishes.
+        #[arg(long)]
+        save_responses: bool,
+
+        /// Serve Prometheus metrics at /metrics on this address, like
+        /// 127.0.0.1:9090, while it runs.
+        #[cfg(feature = "metrics")]
+        #[arg(long, value_name = "ADDR")]
+        metrics: Option<std::net::SocketAddr>,
+
+        /// Run the scenario from the config instead of the requests.
+        #[arg(
+            short,
+            long,
+            conflicts_with = "benchmarks",
+            add = ArgValueCandidates::new(scenario_names)
+        )]
+        scenario: Option<String>,
+
+        /// A CSV file with a header row whose rows the iterations use
+        /// in turn as `${data.COLUMN}`, instead of the scenario's.
+        #[arg(long, value_name = "FILE")]
+        data: Option<PathBuf>,
+
+        /// Use each row of the data once, for requests that create
+        /// resources that must be unique.
+        #[arg(long, requires = "data")]
+        unique_data: bool,
+
         /// The requests to run.
+        #[arg(add = ArgValueCandidates::new(request_names))]
         benchmarks: Vec<String>,
     },
 }
@@ -75,15 +366,36 @@ enum Requests {
         /// The format in which to display the requests.
         #[arg(short, long, value_name = "OUTPUT", default_value = "table")]
         output: OutputFormat,
+
+        /// Only list the requests with one of these tags.
+        #[arg(short, long)]
+        tag: Vec<String>,
+
+        /// Group the requests by tag, method or file.
+        #[arg(short, long, value_name = "FIELD", conflicts_with = "summary")]
+        group_by: Option<String>,
+
+        /// The column to sort by, like name or method.
+        #[arg(short, long, default_value = "name")]
+        sort: String,
+
+        /// Output the number of entries in total and in each group instead.
+        #[arg(long)]
+        summary: bool,
     },
 
     /// Run the given requests.
     Run {
         /// The contexts to use.
-        #[arg(short, long, value_name = "CONTEXT")]
+        #[arg(short, long, value_name = "CONTEXT", add = ArgValueCandidates::new(context_names))]
         contexts: Vec<String>,
 
+        /// Set a variable, overriding the contexts.
+        #[arg(long = "var", value_name = "KEY=VALUE")]
+        vars: Vec<String>,
+
         /// The requests to run.
+        #[arg(add = ArgValueCandidates::new(request_names))]
         requests: Vec<String>,
 
         /// Include response and header values before the body.
@@ -93,6 +405,417 @@ enum Requests {
         /// Only output errors.
         #[arg(short, long)]
         quiet: bool,
+
+        /// How long to wait between requests (e.g. 200ms).
+        #[arg(long, value_parser = humantime::parse_duration)]
+        delay: Option<Duration>,
+
+        /// Run the requests this many times.
+        #[arg(long, value_name = "N")]
+        repeat: Option<usize>,
+
+        /// How long to wait between repeats (e.g. 5s).
+        #[arg(long, value_parser = humantime::parse_duration, default_value = "1s")]
+        interval: Duration,
+
+        /// Stop repeating once the condition is true, like
+        /// '${response.job.status} == done'. Repeats until then when
+        /// --repeat isn't given.
+        #[arg(long, value_name = "CONDITION")]
+        until: Option<String>,
+
+        /// Fail when a response's status code isn't this.
+        #[arg(long, value_name = "CODE")]
+        expect_status: Option<u16>,
+
+        /// Fail when a response's body doesn't contain the text. Can be
+        /// given more than once.
+        #[arg(long, value_name = "TEXT")]
+        expect_contains: Vec<String>,
+
+        /// Write what happens during the run as JSON lines to this file,
+        /// or a file descriptor like /dev/fd/3, as it happens.
+        #[arg(long, value_name = "FILE")]
+        events: Option<PathBuf>,
+    },
+
+    /// Open the file defining the request in your editor.
+    Edit {
+        /// The request to edit.
+        #[arg(add = ArgValueCandidates::new(request_names))]
+        name: String,
+    },
+
+    /// Search the request definitions with a regex.
+    Search {
+        /// The regex to search names, URLs, descriptions, headers, and
+        /// bodies for.
+        pattern: String,
+    },
+
+    /// Add a new request to a configuration file.
+    Add {
+        /// The name of the request.
+        name: String,
+
+        /// The URL of the request.
+        #[arg(short, long)]
+        url: String,
+
+        /// The HTTP method of the request.
+        #[arg(short = 'X', long, default_value = "GET")]
+        method: String,
+
+        /// A description of the request.
+        #[arg(short, long, default_value = "")]
+        description: String,
+
+        /// Tags for the request.
+        #[arg(short, long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
+
+        /// Headers for the request in the form 'key: value'.
+        #[arg(short = 'H', long = "header", value_name = "HEADER")]
+        headers: Vec<String>,
+
+        /// Query parameters for the request in the form 'key=value'.
+        #[arg(short, long = "query", value_name = "QUERY")]
+        query_parameters: Vec<String>,
+
+        /// The file to add the request to. Defaults to the config file.
+        #[arg(short, long, value_name = "FILE")]
+        file: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum Configuration {
+    /// Check the configuration for problems.
+    Validate {
+        /// The contexts that will be used.
+        #[arg(short, long, value_name = "CONTEXT", add = ArgValueCandidates::new(context_names))]
+        contexts: Vec<String>,
+    },
+
+    /// Find requests, context variables and cached responses that
+    /// aren't used.
+    Lint,
+
+    /// Show how two configurations differ: requests and other
+    /// definitions added, removed or changed, and changed asserts.
+    Diff {
+        /// The old configuration file or folder.
+        old: PathBuf,
+
+        /// The new configuration file or folder.
+        new: PathBuf,
+
+        /// Output the differences as yaml or json instead of text.
+        #[arg(short, long, value_name = "OUTPUT")]
+        output: Option<OutputFormat>,
+
+        /// Exit with an error when there are differences.
+        #[arg(long)]
+        exit_code: bool,
+    },
+
+    /// Print the configuration with the contexts applied to every
+    /// request. Secrets are masked.
+    Render {
+        /// The contexts to use.
+        #[arg(short, long, value_name = "CONTEXT", add = ArgValueCandidates::new(context_names))]
+        contexts: Vec<String>,
+
+        /// Set a variable, overriding the contexts.
+        #[arg(long = "var", value_name = "KEY=VALUE")]
+        vars: Vec<String>,
+
+        /// The format to print, yaml or json.
+        #[arg(short, long, value_name = "OUTPUT", default_value = "yaml")]
+        output: OutputFormat,
+    },
+}
+
+#[derive(Subcommand)]
+enum Documentation {
+    /// Write a page for each tag with its requests and link the cached
+    /// responses as examples.
+    Generate {
+        /// The folder to write the pages to.
+        #[arg(short, long, value_name = "DIR", default_value = "docs")]
+        output: PathBuf,
+
+        /// The format of the pages, markdown or html.
+        #[arg(short, long, default_value = "markdown")]
+        format: DocsFormat,
+    },
+}
+
+#[derive(Subcommand)]
+enum Export {
+    /// Write an OpenAPI 3 document inferred from the requests and their
+    /// cached responses.
+    Openapi {
+        /// The file to write, as JSON if it ends in .json and YAML
+        /// otherwise. Defaults to stdout.
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+
+        /// The title of the API.
+        #[arg(long, default_value = "API")]
+        title: String,
+    },
+
+    /// Write tests or requests as a Hurl file. Asserts without a Hurl
+    /// equivalent are written as comments.
+    Hurl {
+        /// The tests or requests to write, in order.
+        #[arg(required = true, add = ArgValueCandidates::new(test_names))]
+        names: Vec<String>,
+
+        /// The file to write. Defaults to stdout.
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum Import {
+    /// Add a request for each entry in a Hurl file and a test that
+    /// runs them in order with its asserts.
+    Hurl {
+        /// The Hurl file to import.
+        path: PathBuf,
+
+        /// The name of the test. The requests are named NAME-1, NAME-2
+        /// and so on. Defaults to the name of the file.
+        #[arg(short, long)]
+        name: Option<String>,
+
+        /// The file to add them to. Defaults to the config.
+        #[arg(short, long)]
+        file: Option<PathBuf>,
+    },
+}
+
+/// The options for running tests, shared by tests and suites.
+#[derive(clap::Args)]
+struct TestRun {
+    /// The contexts to use.
+    #[arg(short, long, value_name = "CONTEXT", add = ArgValueCandidates::new(context_names))]
+    contexts: Vec<String>,
+
+    /// Set a variable, overriding the contexts.
+    #[arg(long = "var", value_name = "KEY=VALUE")]
+    vars: Vec<String>,
+
+    /// Use the cached responses instead of making requests.
+    #[arg(long)]
+    offline: bool,
+
+    /// Replace stored snapshots with the current responses.
+    #[arg(long)]
+    update_snapshots: bool,
+
+    /// How long to wait between steps (e.g. 200ms).
+    #[arg(long, value_parser = humantime::parse_duration)]
+    delay: Option<Duration>,
+
+    /// Write a .tar.gz with what's needed to reproduce the run when it
+    /// fails.
+    #[arg(long, value_name = "FILE")]
+    bundle_on_failure: Option<PathBuf>,
+
+    /// Run the tests in a random order to find tests that depend on
+    /// each other. The seed is printed so the order can be repeated.
+    #[arg(long)]
+    shuffle: bool,
+
+    /// The seed to shuffle with, to repeat an order.
+    #[arg(long, requires = "shuffle")]
+    seed: Option<u64>,
+
+    /// Only run the tests whose definitions or requests changed since
+    /// the git revision. All of them run when that can't be told.
+    #[arg(long, value_name = "REVISION")]
+    changed_since: Option<String>,
+
+    /// Stop the run when it takes longer than this (e.g. 10m). Requests
+    /// in flight are cancelled and the tests that didn't finish are
+    /// marked as timed out. The after requests are still made.
+    #[arg(long, value_parser = humantime::parse_duration)]
+    deadline: Option<Duration>,
+
+    /// How much of the results to show: tests, auto (collapse what
+    /// passed) or all. Failures are always shown.
+    #[arg(long, value_name = "VERBOSITY", default_value = "auto")]
+    results_verbosity: Verbosity,
+
+    /// Write what happens during the run as JSON lines to this file, or
+    /// a file descriptor like /dev/fd/3, as it happens.
+    #[arg(long, value_name = "FILE")]
+    events: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Runs {
+    /// List the past test runs and benchmarks.
+    List {
+        /// The format in which to display the runs.
+        #[arg(short, long, value_name = "OUTPUT", default_value = "table")]
+        output: OutputFormat,
+
+        /// The column to sort by, descending when it starts with '-'.
+        #[arg(short, long, default_value = "run", allow_hyphen_values = true)]
+        sort: String,
+    },
+
+    /// Show the results of a run, by its number or run ID.
+    Show {
+        /// The run to show.
+        run: String,
+
+        /// Output the run as yaml or json instead of text.
+        #[arg(short, long, value_name = "OUTPUT")]
+        output: Option<OutputFormat>,
+    },
+
+    /// Show how a test, or a request in benchmarks, did in each run.
+    Trend {
+        /// The test or request.
+        name: String,
+
+        /// The format in which to display the trend.
+        #[arg(short, long, value_name = "OUTPUT", default_value = "table")]
+        output: OutputFormat,
+
+        /// The column to sort by, descending when it starts with '-'.
+        #[arg(short, long, default_value = "run", allow_hyphen_values = true)]
+        sort: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum Suites {
+    /// List all the suites.
+    List {
+        /// The format in which to display the suites.
+        #[arg(short, long, value_name = "OUTPUT", default_value = "table")]
+        output: OutputFormat,
+
+        /// The column to sort by: name, tests or description.
+        #[arg(short, long, default_value = "name")]
+        sort: String,
+
+        /// Output the number of entries in total and in each group instead.
+        #[arg(long)]
+        summary: bool,
+    },
+
+    /// Run the tests of a suite in its contexts.
+    Run {
+        /// The suite to run.
+        #[arg(add = ArgValueCandidates::new(suite_names))]
+        suite: String,
+
+        #[command(flatten)]
+        run: TestRun,
+    },
+}
+
+#[derive(Subcommand)]
+enum Flows {
+    /// List all the flows.
+    List {
+        /// The format in which to display the flows.
+        #[arg(short, long, value_name = "OUTPUT", default_value = "table")]
+        output: OutputFormat,
+
+        /// The column to sort by: name, steps or description.
+        #[arg(short, long, default_value = "name")]
+        sort: String,
+
+        /// Output the number of entries in total and in each group instead.
+        #[arg(long)]
+        summary: bool,
+    },
+
+    /// Run the given flows.
+    Run {
+        /// The contexts to use.
+        #[arg(short, long, value_name = "CONTEXT", add = ArgValueCandidates::new(context_names))]
+        contexts: Vec<String>,
+
+        /// Set a variable, overriding the contexts.
+        #[arg(long = "var", value_name = "KEY=VALUE")]
+        vars: Vec<String>,
+
+        /// The flows to run.
+        #[arg(add = ArgValueCandidates::new(flow_names))]
+        flows: Vec<String>,
+
+        /// Include response and header values before the body.
+        #[arg(short, long)]
+        verbose: bool,
+
+        /// Only output errors.
+        #[arg(short, long)]
+        quiet: bool,
+
+        /// How long to wait between requests (e.g. 200ms).
+        #[arg(long, value_parser = humantime::parse_duration)]
+        delay: Option<Duration>,
+    },
+}
+
+#[derive(Subcommand)]
+enum Mocks {
+    /// List all the mocks.
+    List {
+        /// The format in which to display the mocks.
+        #[arg(short, long, value_name = "OUTPUT", default_value = "table")]
+        output: OutputFormat,
+
+        /// The column to sort by: name, method, path or status.
+        #[arg(short, long, default_value = "name")]
+        sort: String,
+
+        /// Output the number of entries in total and in each group instead.
+        #[arg(long)]
+        summary: bool,
+    },
+
+    /// Serve the mocks.
+    Serve {
+        /// The contexts to use.
+        #[arg(short, long, value_name = "CONTEXT", add = ArgValueCandidates::new(context_names))]
+        contexts: Vec<String>,
+
+        /// The port to listen on.
+        #[arg(short, long, default_value = "8080")]
+        port: u16,
+
+        /// The address to listen on.
+        #[arg(long, default_value = "127.0.0.1")]
+        host: std::net::IpAddr,
+
+        /// Append each request received to this file as JSON lines.
+        #[arg(short, long, value_name = "FILE")]
+        record: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum GraphQL {
+    /// Fetch and cache the schema of the request's GraphQL endpoint.
+    Introspect {
+        /// The contexts to use.
+        #[arg(short, long, value_name = "CONTEXT", add = ArgValueCandidates::new(context_names))]
+        contexts: Vec<String>,
+
+        /// The request whose endpoint should be introspected.
+        #[arg(add = ArgValueCandidates::new(request_names))]
+        request: String,
     },
 }
 
@@ -103,6 +826,14 @@ enum Contexts {
         /// The format in which to output the contexts.
         #[arg(short, long, value_name = "OUTPUT", default_value = "tsv")]
         output: OutputFormat,
+
+        /// The column to sort by. Contexts only have a name.
+        #[arg(short, long, default_value = "name")]
+        sort: String,
+
+        /// Output the number of entries in total and in each group instead.
+        #[arg(long)]
+        summary: bool,
     },
 }
 
@@ -113,6 +844,14 @@ enum Responses {
         /// The format in which to output the responses.
         #[arg(short, long, value_name = "OUTPUT", default_value = "tsv")]
         output: OutputFormat,
+
+        /// The column to sort by: name, content-type or status.
+        #[arg(short, long, default_value = "name")]
+        sort: String,
+
+        /// Output the number of entries in total and in each group instead.
+        #[arg(long)]
+        summary: bool,
     },
 }
 
@@ -123,151 +862,1226 @@ enum Tests {
         /// The format in which to display the requests.
         #[arg(short, long, value_name = "OUTPUT", default_value = "table")]
         output: OutputFormat,
+
+        /// The column to sort by: name, steps or description.
+        #[arg(short, long, default_value = "name")]
+        sort: String,
+
+        /// Output the number of entries in total and in each group instead.
+        #[arg(long)]
+        summary: bool,
     },
 
     /// Describe the given tests.
     Describe {
         /// The tests to describe.
+        #[arg(add = ArgValueCandidates::new(test_names))]
         tests: Vec<String>,
+
+        /// Output the full definitions as yaml or json instead of text.
+        #[arg(short, long, value_name = "OUTPUT")]
+        output: Option<OutputFormat>,
+
+        /// Include the definition of each step's request in the output.
+        #[arg(long, requires = "output")]
+        resolve: bool,
     },
 
     /// Run the given tests.
     Run {
-        /// The contexts to use.
-        #[arg(short, long, value_name = "CONTEXT")]
-        contexts: Vec<String>,
-
         /// The tests to run.
+        #[arg(add = ArgValueCandidates::new(test_names))]
         tests: Vec<String>,
+
+        #[command(flatten)]
+        run: TestRun,
+    },
+
+    /// List the tests that were flaky or failed in past runs.
+    FlakyReport {
+        /// The format in which to display the report.
+        #[arg(short, long, value_name = "OUTPUT", default_value = "table")]
+        output: OutputFormat,
+
+        /// The column to sort by, descending when it starts with '-'.
+        #[arg(short, long, default_value = "-flaky", allow_hyphen_values = true)]
+        sort: String,
+    },
+
+    /// Add a new test to a configuration file.
+    Add {
+        /// The name of the test.
+        name: String,
+
+        /// A description of the test.
+        #[arg(short, long, default_value = "")]
+        description: String,
+
+        /// The requests to run as steps, in order.
+        #[arg(
+            short,
+            long = "request",
+            value_name = "REQUEST",
+            add = ArgValueCandidates::new(request_names)
+        )]
+        requests: Vec<String>,
+
+        /// The status code each step should assert.
+        #[arg(short, long, default_value = "200")]
+        status_code: u16,
+
+        /// The file to add the test to. Defaults to the config file.
+        #[arg(short, long, value_name = "FILE")]
+        file: Option<PathBuf>,
     },
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    let args = Args::parse();
+async fn main() {
+    // Respond to the shell when it's asking for completions.
+    CompleteEnv::with_factory(Args::command).complete();
+    // Usage errors have their own exit code, but help and the version
+    // still exit successfully.
+    let args = Args::try_parse().unwrap_or_else(|e| match e.use_stderr() {
+        true => {
+            let _ = e.print();
+            std::process::exit(EXIT_USAGE);
+        }
+        false => e.exit(),
+    });
+    let result = match init_logging(args.log_level, args.log_file.as_deref()) {
+        Ok(()) => run(args).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        eprintln!("Error: {:?}", e);
+        std::process::exit(exit_code(&e));
+    }
+}
+
+async fn run(args: Args) -> Result<()> {
+    // Creating a new configuration doesn't require an existing one.
+    if let Command::New { path, force } = &args.command {
+        scaffold::new(path, *force)?;
+        println!("created {}", path.display());
+        return Ok(());
+    }
+    if let Command::Schema = &args.command {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&apictl::config::json_schema())?
+        );
+        return Ok(());
+    }
+    if let Command::Completions { shell } = &args.command {
+        let shells = Shells::builtins();
+        let completer = shells
+            .completer(&shell.to_string())
+            .ok_or_else(|| anyhow::anyhow!("unsupported shell: {}", shell))?;
+        completer.write_registration("COMPLETE", "apictl", "apictl", "apictl", &mut stdout())?;
+        return Ok(());
+    }
+    // Comparing configurations uses their paths instead of the config.
+    if let Command::Config(Configuration::Diff {
+        old,
+        new,
+        output,
+        exit_code,
+    }) = &args.command
+    {
+        let differences =
+            changed::differences(&Config::new_from_path(old)?, &Config::new_from_path(new)?)?;
+        match output {
+            Some(OutputFormat::Json) => {
+                println!("{}", serde_json::to_string_pretty(&differences)?)
+            }
+            Some(_) => print!("{}", serde_yaml::to_string(&differences)?),
+            None => {
+                for difference in &differences {
+                    println!("{}", difference);
+                }
+            }
+        }
+        if *exit_code && !differences.is_empty() {
+            return Err(anyhow::anyhow!("{} differences found", differences.len()));
+        }
+        return Ok(());
+    }
+
+    // The user's profile is merged beneath the project's configuration.
+    let user = match args.user_config.clone().or_else(UserConfig::default_path) {
+        Some(path) => UserConfig::load(&path)?,
+        None => UserConfig::default(),
+    };
+    let profile = user
+        .profile(args.profile.as_deref())?
+        .cloned()
+        .unwrap_or_default();
+    let cache = args
+        .cache
+        .clone()
+        .or_else(|| profile.cache.clone())
+        .unwrap_or_else(|| PathBuf::from(".apictl"));
+
+    // Checking reports configurations that don't load as problems.
+    if let Command::Check {
+        contexts,
+        watch,
+        json,
+    } = &args.command
+    {
+        return check(&args, &cache, &profile, contexts, *watch, *json).await;
+    }
 
     // Make sure our cache dir exists
-    let response_dir = args.cache.clone().join("responses");
-    std::fs::create_dir_all(&response_dir)?;
+    let responses_dir = cache.join("responses");
+    let run_id = args.run_id.clone().unwrap_or_else(new_run_id);
+    let response_dir = responses_dir.join(&run_id);
 
     // Parse our config.
     let mut cfg = Config::new_from_path(&args.config)?;
-    cfg.load_responses(&response_dir)?;
+    cfg.add_profile(&profile);
+    cfg.strict_templates |= args.strict_templates;
+    if let Some(header) = &args.correlation_header {
+        cfg.add_correlation(Correlation {
+            header: header.clone(),
+            id: run_id.clone(),
+        });
+    }
+    // Only the latest runs are kept.
+    apictl::config::prune_runs(&responses_dir, KEEP_RUNS - 1, &response_dir)?;
+    // Listing responses without a run shows the latest of every run,
+    // and offline tests answer from the latest run.
+    let any_run =
+        args.any_run || (args.run_id.is_none() && matches!(args.command, Command::Responses(_)));
+    let offline = matches!(
+        &args.command,
+        Command::Tests(Tests::Run { run, .. }) | Command::Suites(Suites::Run { run, .. })
+            if run.offline
+    );
+    match (any_run, offline && args.run_id.is_none()) {
+        (true, _) => cfg.load_responses(&responses_dir)?,
+        (false, true) => {
+            if let Some(latest) = apictl::config::runs(&responses_dir)?.last() {
+                cfg.load_responses(latest)?;
+            }
+        }
+        (false, false) => cfg.load_responses(&response_dir)?,
+    }
+    cfg.load_cached(&responses_dir)?;
+    tracing::debug!(run_id = %run_id, "caching responses in {}", response_dir.display());
+    // Finding the commit runs git, so it's only done for commands that
+    // are kept in the history.
+    let history = || History::new(&cache).with_run(&run_id, history::git_revision(&args.config));
 
     // Execute the command.
     match args.command {
         Command::Responses(responses) => match responses {
-            Responses::List { output } => {
-                cfg.responses.output(output)?;
+            Responses::List {
+                output,
+                sort,
+                summary,
+            } => {
+                output_list(
+                    &*cfg.responses,
+                    "responses",
+                    output,
+                    &sort,
+                    summary,
+                    None,
+                    false,
+                )?;
             }
         },
         Command::Contexts(contexts) => match contexts {
-            Contexts::List { output } => {
-                cfg.contexts.output(output)?;
+            Contexts::List {
+                output,
+                sort,
+                summary,
+            } => {
+                output_list(
+                    &cfg.contexts,
+                    "contexts",
+                    output,
+                    &sort,
+                    summary,
+                    None,
+                    false,
+                )?;
             }
         },
         Command::Requests(requests) => match requests {
-            Requests::List { output } => {
-                cfg.requests.output(output)?;
+            Requests::List {
+                output,
+                sort,
+                summary,
+                tag,
+                group_by,
+            } => {
+                let requests = cfg
+                    .requests
+                    .iter()
+                    .filter(|(_, r)| tag.is_empty() || r.tags.iter().any(|t| tag.contains(t)))
+                    .map(|(n, r)| (n.clone(), r.clone()))
+                    .collect::<HashMap<_, _>>();
+                output_list(
+                    &requests,
+                    "requests",
+                    output,
+                    &sort,
+                    summary,
+                    group_by.as_deref(),
+                    !tag.is_empty(),
+                )?;
             }
             Requests::Run {
                 contexts,
+                vars,
                 requests,
                 verbose,
                 quiet,
+                delay,
+                repeat,
+                interval,
+                until,
+                expect_status,
+                expect_contains,
+                events,
             } => {
-                let context = cfg.merge_contexts(&contexts)?;
-                let mut app = Applicator::new(context, cfg.responses);
+                let mut context = cfg.merge_contexts(&contexts)?;
+                context.extend(parse_pairs(&vars, '=')?);
+                cfg.add_default_headers(&contexts)?;
+                cfg.add_scopes(&contexts)?;
+                let redactor = Redactor::new(&cfg.redact, &context)?
+                    .with_scopes(&cfg.scopes)
+                    .with_requests(&cfg.requests, &context);
+                let mut app = cfg.applicator(context);
+                let checks = expect_status
+                    .map(|value| Assert::StatusCode { value })
+                    .into_iter()
+                    .chain(
+                        expect_contains
+                            .into_iter()
+                            .map(|value| Assert::BodyContains { value }),
+                    )
+                    .collect::<Vec<_>>();
+                let options = TestOptions {
+                    redactor: redactor.clone(),
+                    events: events
+                        .map(|e| Events::create(&e))
+                        .transpose()?
+                        .map(Arc::new),
+                    ..Default::default()
+                };
+                options.send(Event::RunStarted {
+                    source: "requests run".into(),
+                });
+                let now = Instant::now();
+                let mut failed = 0;
+                let mut met = false;
 
-                for r in requests {
-                    // Get the request by name and apply the context.
-                    let mut request: Request = match cfg.requests.get(&r) {
-                        Some(r) => r.clone(),
-                        None => {
-                            return Err(anyhow::anyhow!("Request not found: {}", r));
+                // Without --repeat, run once or, with --until, until the
+                // condition is met.
+                let repeat = match (repeat, &until) {
+                    (Some(n), _) => n,
+                    (None, Some(_)) => usize::MAX,
+                    (None, None) => 1,
+                };
+                let run = async {
+                    for round in 0..repeat {
+                        if round > 0 {
+                            tokio::time::sleep(interval).await;
                         }
-                    };
-                    request.apply(&app);
+                        let mut outcomes = vec![];
+                        for (i, r) in requests.iter().cloned().enumerate() {
+                            if let (Some(delay), true) = (delay, i > 0) {
+                                tokio::time::sleep(delay).await;
+                            }
+
+                            // Get the request by name and apply the context.
+                            let mut request: Request = match cfg.requests.get(&r) {
+                                Some(r) => r.clone(),
+                                None => {
+                                    return Err(Failure::Config(format!(
+                                        "Request not found: {}",
+                                        r
+                                    ))
+                                    .into());
+                                }
+                            };
+                            request.try_apply(&app)?;
+
+                            // Make the requests.
+                            options.send(Event::RequestSent {
+                                request: r.clone(),
+                                method: request.method.clone(),
+                                url: redactor.text(&request.url),
+                                test: None,
+                            });
+                            let request_now = Instant::now();
+                            let resp = request.request().await?;
+                            options.send(Event::ResponseReceived {
+                                request: r.clone(),
+                                status_code: resp.status_code,
+                                duration_ms: millis(request_now.elapsed()),
+                                test: None,
+                            });
+
+                            // Report when the response isn't what the request expects.
+                            if let Some(expect) = &request.expect {
+                                for violation in expect.check(&resp) {
+                                    eprintln!("{}: expectation failed: {}", r, violation);
+                                }
+                            }
+
+                            // The checks from the flags fail the command.
+                            for check in &checks {
+                                let mut check = check.clone();
+                                check.apply(&app);
+                                let result = check
+                                    .execute(&resp, &options)
+                                    .map_err(|e| redactor.text(&e.to_string()));
+                                outcomes.push((r.clone(), check.to_string(), result));
+                            }
 
-                    // Make the requests.
-                    let resp = request.request().await?;
+                            // TODO: (?) stream to both places
 
-                    // TODO: (?) stream to both places
+                            // We want to save the response to our cache and
+                            // then print it out, without any secrets.
+                            let redacted = redactor.response(&resp);
+                            redacted
+                                .sanitize(&request.sanitize)
+                                .save(&response_dir, &r)?;
+                            if verbose && !quiet {
+                                println!("{}", redacted);
+                            } else if !quiet {
+                                println!("{}", redacted.body);
+                            }
+
+                            // Save the response incase it is used by a later request.
+                            app.add_response(r, resp);
+                        }
 
-                    // We want to save the response to our cache and
-                    // then print it out.
-                    resp.save(&response_dir, &r)?;
-                    if verbose && !quiet {
-                        println!("{}", resp);
-                    } else if !quiet {
-                        println!("{}", resp.body);
+                        if let Some(until) = &until {
+                            let until = app.apply(until);
+                            met = condition::evaluate(&until)
+                                .map_err(|e| Failure::Usage(format!("--until: {}", e)))?;
+                            if !met {
+                                tracing::info!(round, until = %until, "condition not met");
+                            }
+                        }
+
+                        // Only the last round, or the one that met the
+                        // condition, is checked, so polling can fail first.
+                        if met || round + 1 == repeat {
+                            for (request, assert, result) in outcomes {
+                                match result {
+                                    Ok(_) => options.send(Event::AssertPassed {
+                                        request,
+                                        assert,
+                                        test: None,
+                                        step: None,
+                                    }),
+                                    Err(error) => {
+                                        eprintln!("{}: {} failed: {}", request, assert, error);
+                                        options.send(Event::AssertFailed {
+                                            request,
+                                            assert,
+                                            error,
+                                            test: None,
+                                            step: None,
+                                        });
+                                        failed += 1;
+                                    }
+                                }
+                            }
+                        }
+                        if met {
+                            break;
+                        }
+                    }
+                    Ok::<_, anyhow::Error>(())
+                };
+                let result = run.await;
+                options.send(Event::RunFinished {
+                    passed: result.is_ok() && failed == 0 && (until.is_none() || met),
+                    duration_ms: millis(now.elapsed()),
+                    counts: None,
+                });
+                result?;
+                if failed > 0 {
+                    return Err(Failure::Failed(format!("{} checks failed", failed)).into());
+                }
+                if let (Some(until), false) = (&until, met) {
+                    return Err(Failure::Failed(format!(
+                        "condition '{}' not met after {} runs",
+                        until, repeat
+                    ))
+                    .into());
+                }
+            }
+            Requests::Edit { name } => {
+                let path = cfg
+                    .requests
+                    .get(&name)
+                    .ok_or_else(|| Failure::Config(format!("Request not found: {}", name)))?
+                    .source
+                    .clone()
+                    .ok_or_else(|| anyhow::anyhow!("request has no source file: {}", name))?;
+                let line = apictl::config::find_definition(&path, "requests", &name)?;
+                edit(&path, line)?;
+
+                // Make sure the changes still produce a valid configuration.
+                if let Err(e) = Config::new_from_path(&args.config) {
+                    return Err(anyhow::anyhow!(
+                        "{} is no longer valid: {}",
+                        path.display(),
+                        e
+                    ));
+                }
+            }
+            Requests::Search { pattern } => {
+                let re = regex::Regex::new(&pattern)?;
+                let mut names = cfg.requests.keys().collect::<Vec<_>>();
+                names.sort();
+                let mut found = false;
+                for name in names {
+                    let request = &cfg.requests[name];
+                    let mut matches = request.search(&re);
+                    if re.is_match(name) {
+                        matches.insert(0, format!("name: {}", name));
+                    }
+                    let source = request
+                        .source
+                        .as_ref()
+                        .map(|s| s.display().to_string())
+                        .unwrap_or_default();
+                    found |= !matches.is_empty();
+                    for m in matches {
+                        println!("{}: {}: {}", source, name, m);
+                    }
+                }
+                if !found {
+                    let message = format!("no requests match '{}'", pattern);
+                    return Err(Failure::NoMatches(message).into());
+                }
+            }
+            Requests::Add {
+                name,
+                url,
+                method,
+                description,
+                tags,
+                headers,
+                query_parameters,
+                file,
+            } => {
+                let request = Request {
+                    description,
+                    tags,
+                    url,
+                    method: method.to_uppercase(),
+                    headers: parse_pairs(&headers, ':')?,
+                    query_parameters: parse_pairs(&query_parameters, '=')?,
+                    ..Default::default()
+                };
+                let file = target_file(&args.config, file)?;
+                scaffold::add_request(&file, &name, &request)?;
+                println!("added request {} to {}", name, file.display());
+            }
+        },
+        Command::Tests(tests) => match tests {
+            Tests::List {
+                output,
+                sort,
+                summary,
+            } => {
+                output_list(&cfg.tests, "tests", output, &sort, summary, None, false)?;
+            }
+            Tests::Describe {
+                tests,
+                output,
+                resolve,
+            } => {
+                let mut definitions = serde_yaml::Mapping::new();
+                for t in tests {
+                    let test = cfg
+                        .tests
+                        .get(&t)
+                        .ok_or_else(|| anyhow::anyhow!("test not found: {}", t))?;
+                    match output {
+                        None => {
+                            println!("test: {}", t);
+                            println!("{}", test);
+                        }
+                        Some(_) => {
+                            let requests = resolve.then_some(&cfg.requests);
+                            definitions.insert(t.into(), test.definition(requests)?);
+                        }
+                    }
+                }
+                match output {
+                    None => {}
+                    Some(OutputFormat::Yaml) => print!("{}", serde_yaml::to_string(&definitions)?),
+                    Some(OutputFormat::Json) => {
+                        println!("{}", serde_json::to_string_pretty(&definitions)?)
+                    }
+                    Some(_) => {
+                        return Err(anyhow::anyhow!(
+                            "tests describe can only output yaml or json"
+                        ))
+                    }
+                }
+            }
+            Tests::Run { tests, run } => {
+                // Without names, the changed tests are picked from all of them.
+                let tests = match (tests.is_empty(), &run.changed_since) {
+                    (true, Some(_)) => cfg.tests.keys().cloned().collect(),
+                    _ => tests,
+                };
+                let tests = changed_tests(&cfg, &args.config, tests, &run);
+                let suite = Suite::new(tests);
+                run_tests(&mut cfg, &cache, &history(), &suite, "tests run", run).await?;
+            }
+            Tests::FlakyReport { output, sort } => {
+                let runs = History::new(&cache).runs()?;
+                let report = history::flakiness(&runs);
+                output_list(&report, "flaky tests", output, &sort, false, None, false)?;
+            }
+            Tests::Add {
+                name,
+                description,
+                requests,
+                status_code,
+                file,
+            } => {
+                let test = Test {
+                    description,
+                    steps: requests
+                        .into_iter()
+                        .map(|r| Step {
+                            name: r.clone(),
+                            request: r,
+                            when: None,
+                            foreach: None,
+                            transforms: vec![],
+                            asserts: vec![Assert::StatusCode { value: status_code }],
+                            max_duration: None,
+                        })
+                        .collect(),
+                    ..Default::default()
+                };
+                let file = target_file(&args.config, file)?;
+                scaffold::add_test(&file, &name, &test)?;
+                println!("added test {} to {}", name, file.display());
+            }
+        },
+        Command::History(runs) => {
+            let history = History::new(&cache).runs()?;
+            match runs {
+                Runs::List { output, sort } => {
+                    let runs = history::numbered(history);
+                    output_list(&runs, "runs", output, &sort, false, None, false)?;
+                }
+                Runs::Show { run, output } => {
+                    let run = history::find(&history, &run)?;
+                    match output {
+                        Some(OutputFormat::Json) => {
+                            println!("{}", serde_json::to_string_pretty(run)?)
+                        }
+                        Some(_) => println!("{}", serde_yaml::to_string(run)?),
+                        None => print_run(run)?,
+                    }
+                }
+                Runs::Trend { name, output, sort } => {
+                    let trend = history::trend(&history, &name);
+                    output_list(&trend, "runs", output, &sort, false, None, false)?;
+                }
+            }
+        }
+        Command::Suites(suites) => match suites {
+            Suites::List {
+                output,
+                sort,
+                summary,
+            } => {
+                output_list(&cfg.suites, "suites", output, &sort, summary, None, false)?;
+            }
+            Suites::Run { suite, run } => {
+                let mut definition = cfg
+                    .suites
+                    .get(&suite)
+                    .ok_or_else(|| Failure::Config(format!("suite not found: {}", suite)))?
+                    .clone();
+                definition.tests = changed_tests(&cfg, &args.config, definition.tests, &run);
+                let source = format!("suites run {}", suite);
+                run_tests(&mut cfg, &cache, &history(), &definition, &source, run).await?;
+            }
+        },
+        Command::Config(config) => match config {
+            Configuration::Validate { contexts } => {
+                let context = match contexts.is_empty() && cfg.default_contexts.is_empty() {
+                    true => None,
+                    false => Some(cfg.merge_contexts(&contexts)?),
+                };
+                let problems = cfg.validate(&cache, context.as_ref());
+                for problem in &problems {
+                    println!("{}", problem);
+                }
+                if !problems.is_empty() {
+                    return Err(anyhow::anyhow!("{} problems found", problems.len()));
+                }
+                println!("configuration is valid");
+            }
+            Configuration::Lint => {
+                let problems = cfg.lint();
+                for problem in &problems {
+                    println!("{}", problem);
+                }
+                if !problems.is_empty() {
+                    return Err(anyhow::anyhow!(
+                        "{} unused definitions found",
+                        problems.len()
+                    ));
+                }
+                println!("nothing unused found");
+            }
+            Configuration::Diff { .. } => unreachable!("handled before loading the config"),
+            Configuration::Render {
+                contexts,
+                vars,
+                output,
+            } => {
+                let mut context = cfg.merge_contexts(&contexts)?;
+                context.extend(parse_pairs(&vars, '=')?);
+                let mut rendered = cfg.render(&contexts, context.clone())?;
+                let redactor = Redactor::new(&cfg.redact, &context)?
+                    .with_scopes(&rendered.scopes)
+                    .with_requests(&rendered.requests, &context);
+                redactor.config(&mut rendered);
+                let text = match output {
+                    OutputFormat::Yaml => serde_yaml::to_string(&rendered)?,
+                    OutputFormat::Json => serde_json::to_string_pretty(&rendered)? + "\n",
+                    _ => {
+                        return Err(anyhow::anyhow!(
+                            "config render can only output yaml or json"
+                        ))
+                    }
+                };
+                print!("{}", redactor.text(&text));
+            }
+        },
+        Command::Docs(docs) => match docs {
+            Documentation::Generate { output, format } => {
+                let paths = Docs::new(&cfg.requests, &cfg.cached, format).write(&output)?;
+                println!("wrote {} files to {}", paths.len(), output.display());
+            }
+        },
+        Command::Graph { output } => {
+            print!("{}", Graph::from_config(&cfg).render(output));
+        }
+        Command::Export(export) => match export {
+            Export::Openapi { output, title } => {
+                let spec =
+                    OpenApi::from_requests(&title, &cfg.requests, &cfg.cached, &cfg.contexts);
+                match output {
+                    None => print!("{}", serde_yaml::to_string(&spec)?),
+                    Some(path) => {
+                        let contents = match path.extension().is_some_and(|e| e == "json") {
+                            true => serde_json::to_string_pretty(&spec)?,
+                            false => serde_yaml::to_string(&spec)?,
+                        };
+                        std::fs::write(&path, contents)?;
+                        println!("wrote {}", path.display());
+                    }
+                }
+            }
+            Export::Hurl { names, output } => {
+                let mut parts = vec![];
+                for name in &names {
+                    let part = match (cfg.tests.get(name), cfg.requests.get(name)) {
+                        (Some(test), _) => hurl::export_test(test, &cfg.requests)?,
+                        (None, Some(request)) => hurl::export(&[(name, request, &[])]),
+                        (None, None) => {
+                            return Err(anyhow::anyhow!("test or request not found: {}", name))
+                        }
+                    };
+                    parts.push(part);
+                }
+                let contents = parts.join("\n");
+                match output {
+                    None => print!("{}", contents),
+                    Some(path) => {
+                        std::fs::write(&path, contents)?;
+                        println!("wrote {}", path.display());
+                    }
+                }
+            }
+        },
+        Command::Import(import) => match import {
+            Import::Hurl { path, name, file } => {
+                let name = name.unwrap_or_else(|| {
+                    path.file_stem()
+                        .map(|s| s.to_string_lossy().to_string())
+                        .unwrap_or_else(|| "hurl".into())
+                });
+                let entries = hurl::parse(&std::fs::read_to_string(&path)?)?;
+                let (requests, test) = hurl::import(&name, entries);
+                let file = target_file(&args.config, file)?;
+                for (request_name, request) in &requests {
+                    scaffold::add_request(&file, request_name, request)?;
+                }
+                scaffold::add_test(&file, &name, &test)?;
+                println!(
+                    "added {} requests and test {} to {}",
+                    requests.len(),
+                    name,
+                    file.display()
+                );
+            }
+        },
+        Command::Flows(flows) => match flows {
+            Flows::List {
+                output,
+                sort,
+                summary,
+            } => {
+                output_list(&cfg.flows, "flows", output, &sort, summary, None, false)?;
+            }
+            Flows::Run {
+                contexts,
+                vars,
+                flows,
+                verbose,
+                quiet,
+                delay,
+            } => {
+                let mut context = cfg.merge_contexts(&contexts)?;
+                context.extend(parse_pairs(&vars, '=')?);
+                cfg.add_default_headers(&contexts)?;
+                cfg.add_scopes(&contexts)?;
+                let redactor = Redactor::new(&cfg.redact, &context)?
+                    .with_scopes(&cfg.scopes)
+                    .with_requests(&cfg.requests, &context);
+                let mut app = cfg.applicator(context);
+                for f in flows {
+                    let flow = cfg
+                        .flows
+                        .get(&f)
+                        .ok_or_else(|| anyhow::anyhow!("Flow not found: {}", f))?;
+                    let mut saved = Ok(());
+                    flow.execute(&cfg, &mut app, delay, |step, resp| {
+                        let resp = redactor.response(resp);
+                        if saved.is_ok() {
+                            saved = match cfg.requests.get(&step.request) {
+                                Some(request) => resp.sanitize(&request.sanitize),
+                                None => resp.clone(),
+                            }
+                            .save(&response_dir, &step.request);
+                        }
+                        if verbose && !quiet {
+                            println!("{}", resp);
+                        } else if !quiet {
+                            println!("{}", resp.body);
+                        }
+                    })
+                    .await?;
+                    saved?;
+                }
+            }
+        },
+        Command::Mock(mocks) => match mocks {
+            Mocks::List {
+                output,
+                sort,
+                summary,
+            } => {
+                output_list(&cfg.mocks, "mocks", output, &sort, summary, None, false)?;
+            }
+            Mocks::Serve {
+                contexts,
+                port,
+                host,
+                record,
+            } => {
+                let context = cfg.merge_contexts(&contexts)?;
+                let addr = std::net::SocketAddr::new(host, port);
+                println!("serving {} mocks on http://{}", cfg.mocks.len(), addr);
+                MockServer::new(&cfg.mocks, context, record)?
+                    .serve(addr)
+                    .await?;
+            }
+        },
+        Command::Record {
+            port,
+            host,
+            output,
+            target,
+        } => {
+            let addr = std::net::SocketAddr::new(host, port);
+            println!(
+                "recording on http://{} to {}, press Ctrl-C to stop",
+                addr,
+                output.display()
+            );
+            Recorder::new(output, response_dir, target)
+                .serve(addr)
+                .await?;
+        }
+        Command::Graphql(graphql) => match graphql {
+            GraphQL::Introspect { contexts, request } => {
+                let context = cfg.merge_contexts(&contexts)?;
+                cfg.add_default_headers(&contexts)?;
+                cfg.add_scopes(&contexts)?;
+                let app = cfg.applicator(context);
+                let mut r: Request = match cfg.requests.get(&request) {
+                    Some(r) => r.clone(),
+                    None => {
+                        return Err(
+                            Failure::Config(format!("Request not found: {}", request)).into()
+                        );
                     }
+                };
+                r.try_apply(&app)?;
+                r.method = "POST".into();
+                r.body = Body::GraphQL {
+                    query: apictl::graphql::INTROSPECTION_QUERY.into(),
+                    variables: None,
+                    schema: None,
+                };
 
-                    // Save the response incase it is used by a later request.
-                    app.add_response(r, resp);
+                let resp = r.request().await?;
+                if resp.status_code >= 300 {
+                    return Err(anyhow::anyhow!(
+                        "introspection failed with status {}: {}",
+                        resp.status_code,
+                        resp.body
+                    ));
                 }
+                let schema = Schema::from_introspection(&serde_json::from_str(&resp.body)?)?;
+                let path = apictl::graphql::schema_path(&cache, &request);
+                std::fs::create_dir_all(path.parent().unwrap_or(&cache))?;
+                std::fs::write(&path, &resp.body)?;
+                println!(
+                    "cached schema with {} types to {}",
+                    schema.types.len(),
+                    path.display()
+                );
             }
         },
-        Command::Tests(tests) => match tests {
-            Tests::List { output } => {
-                cfg.tests.output(output)?;
+        Command::Call {
+            url,
+            method,
+            headers,
+            data,
+            contexts,
+            vars,
+            save_as,
+            verbose,
+        } => {
+            let mut context = cfg.merge_contexts(&contexts)?;
+            context.extend(parse_pairs(&vars, '=')?);
+            let method = match (method, &data) {
+                (Some(m), _) => m.to_uppercase(),
+                (None, Some(_)) => "POST".into(),
+                (None, None) => "GET".into(),
+            };
+            let body = match data {
+                Some(d) => Body::Raw {
+                    from: match d.strip_prefix('@') {
+                        Some(path) => RawBody::File { path: path.into() },
+                        None => RawBody::Text { data: d },
+                    },
+                },
+                None => Body::None,
+            };
+            cfg.requests.insert(
+                save_as.clone(),
+                Request {
+                    url,
+                    method,
+                    headers: parse_pairs(&headers, ':')?,
+                    body,
+                    ..Default::default()
+                },
+            );
+            cfg.add_default_headers(&contexts)?;
+            cfg.add_scopes(&contexts)?;
+            let redactor = Redactor::new(&cfg.redact, &context)?
+                .with_scopes(&cfg.scopes)
+                .with_requests(&cfg.requests, &context);
+            let app = cfg.applicator(context);
+            let mut request = cfg.requests[&save_as].clone();
+            request.try_apply(&app)?;
+
+            let resp = redactor.response(&request.request().await?);
+            resp.save(&response_dir, &save_as)?;
+            if verbose {
+                println!("{}", resp);
+            } else {
+                println!("{}", resp.body);
             }
-            Tests::Describe { tests } => {
-                for t in tests {
-                    if let Some(test) = cfg.tests.get(&t) {
-                        println!("test: {}", t);
-                        println!("{}", test);
-                    } else {
-                        dbg!(&cfg.tests);
-                        return Err(anyhow::anyhow!("test not found: {}", t));
-                    }
+        }
+        Command::New { .. }
+        | Command::Completions { .. }
+        | Command::Schema
+        | Command::Check { .. } => {
+            unreachable!("handled before loading the config")
+        }
+        Command::Monitor {
+            contexts,
+            vars,
+            interval,
+            tests,
+            requests,
+            log,
+            webhook,
+            count,
+            #[cfg(feature = "metrics")]
+            metrics,
+        } => {
+            if tests.is_empty() && requests.is_empty() {
+                return Err(anyhow::anyhow!("give --tests or --requests to monitor"));
+            }
+            for t in &tests {
+                if !cfg.tests.contains_key(t) {
+                    return Err(Failure::Config(format!("Test not found: {}", t)).into());
                 }
             }
-            Tests::Run { contexts, tests } => {
-                let context = cfg.merge_contexts(&contexts)?;
-                let mut results = Results::new("test results");
-                let now = Instant::now();
-                let mut stdout = stdout();
-                for t in tests {
-                    // Get the test by name and apply the context.
-                    let test = match cfg.tests.get(&t) {
-                        Some(t) => t,
-                        None => {
-                            return Err(anyhow::anyhow!("Test not found: {}", t));
+            for r in &requests {
+                if !cfg.requests.contains_key(r) {
+                    return Err(Failure::Config(format!("Request not found: {}", r)).into());
+                }
+            }
+            let mut context = cfg.merge_contexts(&contexts)?;
+            context.extend(parse_pairs(&vars, '=')?);
+            cfg.add_default_headers(&contexts)?;
+            cfg.add_scopes(&contexts)?;
+            let options = TestOptions {
+                cache_dir: cache.clone(),
+                redactor: Redactor::new(&cfg.redact, &context)?
+                    .with_scopes(&cfg.scopes)
+                    .with_requests(&cfg.requests, &context),
+                correlation: cfg.correlation.clone(),
+                live: std::io::stdout().is_terminal(),
+                ..Default::default()
+            };
+            #[cfg(feature = "metrics")]
+            let metrics = serve_metrics(metrics);
+            let names = tests.iter().chain(&requests).cloned().collect::<Vec<_>>();
+            let mut monitor = Monitor::new(&names).with_log(log).with_webhook(webhook);
+            let mut stdout = stdout();
+            let mut printed = 0;
+            let mut run = 0;
+            loop {
+                let mut checks = vec![];
+                for t in &tests {
+                    let now = Instant::now();
+                    let time = SystemTime::now();
+                    let mut results = Results::new("monitor");
+                    let failures = match cfg.tests[t]
+                        .execute(
+                            t.clone(),
+                            &cfg,
+                            &context,
+                            &mut results,
+                            &mut stdout,
+                            &options,
+                        )
+                        .await
+                    {
+                        Ok(_) => results.children.iter().flat_map(|c| c.failures()).collect(),
+                        Err(e) => vec![e.to_string()],
+                    };
+                    results.clear(&mut stdout)?;
+                    checks.push(Check {
+                        name: t.clone(),
+                        time,
+                        duration: now.elapsed(),
+                        passed: failures.is_empty(),
+                        failures,
+                    });
+                }
+                // Requests are run in order so later ones can use the
+                // earlier responses.
+                let mut app = cfg.applicator(context.clone());
+                for r in &requests {
+                    let now = Instant::now();
+                    let time = SystemTime::now();
+                    let failures = match run_request(&cfg, &mut app, r).await {
+                        Ok(resp) => {
+                            let mut failures = match &cfg.requests[r].expect {
+                                Some(expect) => expect.check(&resp),
+                                None => vec![],
+                            };
+                            if resp.status_code >= 400 && failures.is_empty() {
+                                failures.push(format!("got status code {}", resp.status_code));
+                            }
+                            app.add_response(r.clone(), resp);
+                            failures
                         }
+                        Err(e) => vec![e.to_string()],
+                    };
+                    checks.push(Check {
+                        name: r.clone(),
+                        time,
+                        duration: now.elapsed(),
+                        passed: failures.is_empty(),
+                        failures: failures.iter().map(|f| options.redactor.text(f)).collect(),
+                    });
+                }
+                // Notify when checks start failing.
+                let mut failing = vec![];
+                for check in checks {
+                    #[cfg(feature = "metrics")]
+                    if let Some(metrics) = &metrics {
+                        metrics.record(&check.name, check.duration, !check.passed);
+                    }
+                    let was_failing = monitor
+                        .statuses
+                        .iter()
+                        .any(|s| s.name == check.name && s.passed == Some(false));
+                    if !check.passed && !was_failing {
+                        failing.extend(
+                            check
+                                .failures
+                                .iter()
+                                .map(|f| format!("{}: {}", check.name, f)),
+                        );
+                    }
+                    if let Err(e) = monitor.record(check).await {
+                        tracing::error!(error = %e, "recording check failed");
+                    }
+                }
+                if !failing.is_empty() {
+                    let summary = notify::Summary {
+                        source: "monitor".into(),
+                        total: names.len(),
+                        failed: monitor
+                            .statuses
+                            .iter()
+                            .filter(|s| s.passed == Some(false))
+                            .count(),
+                        failures: failing,
+                        ..Default::default()
                     };
+                    notify::send_all(
+                        &cfg.notifications,
+                        &cfg.applicator(context.clone()),
+                        &summary,
+                    )
+                    .await;
+                }
 
-                    test.execute(t, &cfg, &context, &mut results, &mut stdout)
-                        .await?;
-                    results.clear(&mut stdout)?;
+                // Redraw the summary in place.
+                if printed > 0 {
+                    stdout.execute(cursor::MoveUp(printed))?;
+                    stdout.execute(terminal::Clear(terminal::ClearType::FromCursorDown))?;
+                }
+                let summary = monitor.summary();
+                for line in &summary {
+                    println!("{}", line);
                 }
+                printed = summary.len() as u16;
 
-                results.state = State::Passed;
-                results.duration = now.elapsed();
-                results.output(&mut stdout, "")?;
+                run += 1;
+                if count.is_some_and(|c| run >= c) {
+                    break;
+                }
+                tokio::time::sleep(interval).await;
             }
-        },
+        }
         Command::Benchmark {
             contexts,
+            vars,
             number,
             parallel,
+            connections,
+            no_keepalive,
+            save_responses,
+            #[cfg(feature = "metrics")]
+            metrics,
+            scenario,
+            data,
+            unique_data,
             benchmarks,
         } => {
-            let context = cfg.merge_contexts(&contexts)?;
+            let mut context = cfg.merge_contexts(&contexts)?;
+            context.extend(parse_pairs(&vars, '=')?);
+            cfg.add_default_headers(&contexts)?;
+            cfg.add_scopes(&contexts)?;
+            // A scenario makes one request each iteration, picked by
+            // weight. Otherwise each iteration makes all the requests.
+            let (benchmarks, weights, scenario_data) = match &scenario {
+                Some(name) => {
+                    let scenario = cfg
+                        .benchmarks
+                        .get(name)
+                        .ok_or_else(|| Failure::Config(format!("benchmark not found: {}", name)))?;
+                    let weights = scenario.weights()?;
+                    (scenario.names(), Some(weights), scenario.data.clone())
+                }
+                None => (benchmarks, None, None),
+            };
+            let data = match data {
+                Some(file) => Some(benchmark::Data {
+                    file,
+                    mode: match unique_data {
+                        true => benchmark::DataMode::Unique,
+                        false => benchmark::DataMode::RoundRobin,
+                    },
+                }),
+                None => scenario_data,
+            };
+            let pool = match data {
+                Some(data) => Arc::new(benchmark::Pool::load(&data, number)?),
+                None => Arc::default(),
+            };
+            if benchmarks.is_empty() {
+                return Err(Failure::Usage("no requests or scenario to benchmark".into()).into());
+            }
+            let per_iteration = match weights {
+                Some(_) => 1,
+                None => benchmarks.len(),
+            };
+            #[cfg(feature = "metrics")]
+            let metrics = serve_metrics(metrics);
+            let connections = Arc::new(benchmark::Connections::new(
+                connections.unwrap_or(parallel),
+                !no_keepalive,
+            ));
             let count = Arc::new(AtomicUsize::new(0));
             let status_codes = Arc::new(Mutex::new(HashMap::new()));
-            let durations = Arc::new(Mutex::new(vec![]));
+            let durations = Arc::new(Mutex::new(HashMap::<String, Vec<Duration>>::new()));
+            let failed = Arc::new(Mutex::new(HashMap::<String, usize>::new()));
+            let sizes = Arc::new(Mutex::new((0u64, 0u64)));
+            let redactor = Redactor::new(&cfg.redact, &context)?
+                .with_scopes(&cfg.scopes)
+                .with_requests(&cfg.requests, &context);
+            let saved = Arc::new(WriteBehind::default());
             let bar = Arc::new(Mutex::new(indicatif::ProgressBar::new(number as u64)));
             let mut handles = vec![];
             let total_duration = Instant::now();
+            // Workers share the config rather than each getting a copy.
+            let cfg = Arc::new(cfg);
+            let context = Arc::new(context);
 
-            for _ in 0..parallel {
+            for worker in 0..parallel {
                 let count = count.clone();
                 let context = context.clone();
                 let cfg = cfg.clone();
                 let benchmarks = benchmarks.clone();
+                let weights = weights.clone();
+                let pool = pool.clone();
+                let connections = connections.clone();
+                #[cfg(feature = "metrics")]
+                let metrics = metrics.clone();
                 let status_codes = status_codes.clone();
                 let durations = durations.clone();
+                let failed = failed.clone();
+                let sizes = sizes.clone();
+                let redactor = redactor.clone();
+                let saved = saved.clone();
                 let bar = bar.clone();
                 let handle = tokio::spawn(async move {
                     loop {
@@ -275,19 +2089,46 @@ async fn main() -> Result<()> {
                         if i >= number {
                             return;
                         }
-                        let mut app = Applicator::new(context.clone(), cfg.responses.clone());
+                        let mut app = cfg.applicator((*context).clone());
+                        app.set("bench.iteration", i.to_string());
+                        app.set("bench.worker", worker.to_string());
+                        for (name, value) in pool.variables(i) {
+                            app.set(&name, value);
+                        }
+                        let requests = match &weights {
+                            Some(w) => vec![&benchmarks[w.sample(&mut rand::rng())]],
+                            None => benchmarks.iter().collect(),
+                        };
 
-                        for r in &benchmarks {
+                        for r in requests {
                             let now = Instant::now();
-                            match run_request(&cfg, &mut app, r).await {
-                                Ok(r) => {
+                            let result =
+                                run_benchmark_request(&cfg, &mut app, r, &connections).await;
+                            #[cfg(feature = "metrics")]
+                            if let Some(metrics) = &metrics {
+                                metrics.record(r, now.elapsed(), result.is_err());
+                            }
+                            match result {
+                                Ok(resp) => {
                                     let mut status_codes = status_codes.lock().unwrap();
-                                    *status_codes.entry(r.status_code).or_insert(0) += 1;
+                                    *status_codes.entry(resp.status_code).or_insert(0) += 1;
                                     let mut durations = durations.lock().unwrap();
-                                    durations.push(now.elapsed());
+                                    durations.entry(r.clone()).or_default().push(now.elapsed());
+                                    let mut sizes = sizes.lock().unwrap();
+                                    sizes.0 += resp.wire_size.unwrap_or(0);
+                                    sizes.1 += resp.body_size.unwrap_or(0);
+                                    if save_responses {
+                                        let resp = redactor.response(&resp);
+                                        let resp = match cfg.requests.get(r) {
+                                            Some(request) => resp.sanitize(&request.sanitize),
+                                            None => resp,
+                                        };
+                                        saved.insert(r, resp);
+                                    }
                                 }
                                 Err(e) => {
-                                    eprintln!("error: {}", e);
+                                    *failed.lock().unwrap().entry(r.clone()).or_insert(0) += 1;
+                                    tracing::error!(request = %r, error = %e, "request failed");
                                 }
                             }
                         }
@@ -297,11 +2138,22 @@ async fn main() -> Result<()> {
                 handles.push(handle);
             }
 
-            for handle in handles {
-                handle.await?;
-            }
+            // Ctrl-C stops the workers, cancelling their requests, and
+            // the statistics are for the requests that finished.
+            let aborts = handles.iter().map(|h| h.abort_handle()).collect::<Vec<_>>();
+            let interrupted = match until(None, futures_util::future::try_join_all(handles)).await {
+                Ok(result) => result.map(|_| false)?,
+                Err(_) => {
+                    aborts.iter().for_each(|a| a.abort());
+                    true
+                }
+            };
+            saved.flush(&response_dir)?;
 
-            bar.lock().unwrap().finish();
+            match interrupted {
+                true => bar.lock().unwrap().abandon(),
+                false => bar.lock().unwrap().finish(),
+            }
 
             println!("status codes:");
             let status_codes = status_codes.lock().unwrap();
@@ -309,65 +2161,888 @@ async fn main() -> Result<()> {
                 println!("  {}: {}", code, count);
             }
 
+            let durations = durations.lock().unwrap().clone();
+            let failures = failed.lock().unwrap().clone();
+            let all = durations.values().flatten().copied().collect::<Vec<_>>();
+            let failed = failures.values().sum::<usize>();
+            let blended = benchmark::Stats::new(&all, failed);
+
             println!("statistics:");
-            let total = number * benchmarks.len();
+            let total = match interrupted {
+                true => all.len() + failed,
+                false => number * per_iteration,
+            };
             println!("  total requests:     {}", total);
             println!("  total duration:     {:?}", total_duration.elapsed());
-            let mean = durations.lock().unwrap().iter().sum::<Duration>()
-                / (number * benchmarks.len()) as u32;
-
+            let mean = blended.mean;
             println!("  mean duration:      {:?}", mean);
-            let std_dev = (durations
-                .lock()
-                .unwrap()
+            let std_dev = (all
                 .iter()
                 .map(|d| (d.as_nanos() as f64 - mean.as_nanos() as f64).powi(2))
                 .sum::<f64>()
-                / (number * benchmarks.len()) as f64)
+                / all.len().max(1) as f64)
                 .sqrt();
             let std_dev = Duration::from_nanos(std_dev as u64);
             println!("  standard deviation: {:?}", std_dev);
-            println!(
-                "  fastest duration:   {:?}",
-                durations.lock().unwrap().iter().min().unwrap()
+            println!("  fastest duration:   {:?}", blended.fastest);
+            println!("  slowest duration:   {:?}", blended.slowest);
+
+            let (wire_size, body_size) = *sizes.lock().unwrap();
+            let requests = total.max(1) as u64;
+            println!("  mean wire size:     {} bytes", wire_size / requests);
+            println!("  mean body size:     {} bytes", body_size / requests);
+            println!("  connections opened: {}", connections.opened());
+
+            let summary = history::Benchmark {
+                requests: benchmarks.clone(),
+                total,
+                failed,
+                status_codes: status_codes.iter().map(|(c, n)| (*c, *n)).collect(),
+                mean,
+                fastest: blended.fastest,
+                slowest: blended.slowest,
+                connections: connections.opened(),
+            };
+            record(
+                &history(),
+                Run::new(
+                    "benchmark",
+                    total_duration.elapsed(),
+                    failed == 0 && !interrupted,
+                )
+                .with_benchmark(summary),
             );
-            println!(
-                "  slowest duration:   {:?}",
-                durations.lock().unwrap().iter().max().unwrap()
+
+            if benchmarks.len() > 1 {
+                println!("requests:");
+                let stats = benchmarks
+                    .iter()
+                    .map(|r| {
+                        let durations = durations.get(r).map(Vec::as_slice).unwrap_or_default();
+                        let failed = failures.get(r).copied().unwrap_or_default();
+                        (r.clone(), benchmark::Stats::new(durations, failed))
+                    })
+                    .collect::<HashMap<_, _>>();
+                stats.output(OutputFormat::Table, "request")?;
+            }
+
+            if !all.is_empty() {
+                print_latency(all);
+            }
+            if interrupted {
+                return Err(Failure::Interrupted.into());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Print the latency distribution and histogram of a benchmark's
+/// requests.
+fn print_latency(mut durations: Vec<Duration>) {
+    println!("latency distribution:");
+    durations.sort();
+    let pp = vec![99, 95, 90, 75, 50, 25, 10];
+    for p in pp {
+        println!("  {}%: {:?}", p, durations[durations.len() * p / 100]);
+    }
+
+    println!("latency histogram:");
+    let (buckets, values) = histogram(&durations, 10);
+    println!("  bin ranges:");
+    for (start, end) in buckets {
+        println!("  - [{:?}, {:?}]", start, end);
+    }
+    println!("  values:");
+    let max_count = values.iter().max().unwrap_or(&0);
+    let bar_scale = 50;
+    let bars = values
+        .iter()
+        .map(|count| (count.to_string(), count * bar_scale / max_count))
+        .collect::<Vec<_>>();
+    let max_bar_len = bars.iter().map(|b| b.0.len()).max().unwrap_or(0);
+    for (count, bar_len) in bars {
+        let bar: String = "█".repeat(bar_len);
+        println!("    {: >width$}: {}", count, bar, width = max_bar_len);
+    }
+}
+
+/// Determine the file new entries should be written to. If no file is
+/// given, the config is used as long as it isn't a folder.
+fn target_file(config: &Path, file: Option<PathBuf>) -> Result<PathBuf> {
+    match file {
+        Some(f) => Ok(f),
+        None if config.is_dir() => Err(anyhow::anyhow!(
+            "config is a folder, use --file to choose a file"
+        )),
+        None => Ok(config.to_path_buf()),
+    }
+}
+
+/// Open the given file in the user's editor, positioned at the given
+/// line if known. VISUAL and EDITOR are checked before falling back to
+/// vi.
+fn edit(path: &Path, line: Option<usize>) -> Result<()> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".into());
+    let mut parts = editor.split_whitespace();
+    let program = parts
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("no editor configured"))?;
+    let mut command = std::process::Command::new(program);
+    command.args(parts);
+    if let Some(line) = line {
+        command.arg(format!("+{}", line));
+    }
+    let status = command.arg(path).status()?;
+    if !status.success() {
+        return Err(anyhow::anyhow!("editor exited with {}", status));
+    }
+    Ok(())
+}
+
+/// The number of runs whose responses are kept in the cache folder.
+const KEEP_RUNS: usize = 20;
+
+/// A new run ID that sorts by when the run started.
+fn new_run_id() -> String {
+    let now = humantime::format_rfc3339_seconds(std::time::SystemTime::now()).to_string();
+    format!("{}-{}", now.replace(':', "-"), std::process::id())
+}
+
+/// Write logs to stderr or, if a file is given, as JSON lines to the
+/// file.
+fn init_logging(level: LevelFilter, file: Option<&Path>) -> Result<()> {
+    logger(level, file)?.try_init()?;
+    Ok(())
+}
+
+/// The subscriber that writes the logs at the level and above.
+fn logger(
+    level: LevelFilter,
+    file: Option<&Path>,
+) -> Result<Box<dyn tracing::Subscriber + Send + Sync>> {
+    let builder = tracing_subscriber::fmt().with_max_level(level);
+    Ok(match file {
+        Some(path) => {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?;
+            Box::new(
+                builder
+                    .json()
+                    .with_writer(Mutex::new(file))
+                    .with_ansi(false)
+                    .finish(),
+            )
+        }
+        None => Box::new(builder.with_writer(std::io::stderr).finish()),
+    })
+}
+
+/// The names in the configuration for completions. The configuration
+/// is found the same way as when running, with `APICTL_CONFIG` or the
+/// default path.
+fn config_names<F>(names: F) -> Vec<CompletionCandidate>
+where
+    F: Fn(&Config) -> Vec<&String>,
+{
+    let path = std::env::var_os("APICTL_CONFIG").unwrap_or_else(|| ".apictl.yaml".into());
+    let cfg = match Config::new_from_path(&PathBuf::from(path)) {
+        Ok(cfg) => cfg,
+        Err(_) => return vec![],
+    };
+    let mut names = names(&cfg);
+    names.sort();
+    names.into_iter().map(CompletionCandidate::new).collect()
+}
+
+fn request_names() -> Vec<CompletionCandidate> {
+    config_names(|c| c.requests.keys().collect())
+}
+
+fn context_names() -> Vec<CompletionCandidate> {
+    config_names(|c| c.contexts.keys().collect())
+}
+
+fn test_names() -> Vec<CompletionCandidate> {
+    config_names(|c| c.tests.keys().collect())
+}
+
+fn flow_names() -> Vec<CompletionCandidate> {
+    config_names(|c| c.flows.keys().collect())
+}
+
+fn suite_names() -> Vec<CompletionCandidate> {
+    config_names(|c| c.suites.keys().collect())
+}
+
+fn scenario_names() -> Vec<CompletionCandidate> {
+    config_names(|c| c.benchmarks.keys().collect())
+}
+
+/// How often `check --watch` looks for changed files.
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Check the configuration and print its problems. When watching, it's
+/// checked again whenever a file changes and a configuration that can't
+/// be read is just another problem. Otherwise errors fail the command.
+async fn check(
+    args: &Args,
+    cache: &Path,
+    profile: &Profile,
+    contexts: &[String],
+    watch: bool,
+    json: bool,
+) -> Result<()> {
+    let mut modified = None;
+    loop {
+        let current = diagnostic::modified(&args.config);
+        if modified.as_ref() != Some(&current) {
+            modified = Some(current);
+            let found = diagnostic::diagnose(
+                &args.config,
+                cache,
+                contexts,
+                args.strict_templates,
+                profile,
             );
+            let diagnostics = match (found, watch) {
+                (Ok(diagnostics), _) => diagnostics,
+                (Err(e), true) => vec![Diagnostic::new(
+                    &args.config,
+                    Severity::Error,
+                    e.to_string(),
+                )],
+                (Err(e), false) => return Err(e.into()),
+            };
+            let errors = diagnostics.iter().filter(|d| d.is_error()).count();
+            match json {
+                true => println!("{}", serde_json::to_string(&diagnostics)?),
+                false => {
+                    for diagnostic in &diagnostics {
+                        println!("{}", diagnostic);
+                    }
+                    match errors {
+                        0 => println!("configuration is valid"),
+                        n => println!("{} problems found", n),
+                    }
+                }
+            }
+            if !watch {
+                if errors > 0 {
+                    return Err(Failure::Failed(format!("{} problems found", errors)).into());
+                }
+                return Ok(());
+            }
+        }
+        tokio::time::sleep(WATCH_INTERVAL).await;
+    }
+}
 
-            println!("latency distribution:");
-            let mut durations = durations.lock().unwrap().clone();
-            durations.sort();
-            let pp = vec![99, 95, 90, 75, 50, 25, 10];
-            for p in pp {
-                println!("  {}%: {:?}", p, durations[durations.len() * p / 100]);
+/// Run the suite's tests and print their results, notifying and writing
+/// the failure bundle when they fail. The suite's before requests are
+/// made first and their responses are used by every test. Its after
+/// requests are made last, even when the tests fail.
+async fn run_tests(
+    cfg: &mut Config,
+    cache: &Path,
+    history: &History,
+    suite: &Suite,
+    source: &str,
+    run: TestRun,
+) -> Result<()> {
+    let TestRun {
+        contexts: mut extra,
+        vars,
+        offline,
+        update_snapshots,
+        delay,
+        bundle_on_failure,
+        shuffle,
+        seed,
+        changed_since: _,
+        deadline,
+        results_verbosity,
+        events,
+    } = run;
+    let events = events
+        .map(|e| Events::create(&e))
+        .transpose()?
+        .map(Arc::new);
+    if let Some(events) = &events {
+        events.send(Event::RunStarted {
+            source: source.into(),
+        });
+    }
+    let deadline = deadline.map(|d| (d, tokio::time::Instant::now() + d));
+    let mut contexts = suite.contexts.clone();
+    contexts.append(&mut extra);
+    let seed =
+        (shuffle || suite.order == Order::Shuffle).then(|| seed.unwrap_or_else(rand::random));
+    if let Some(seed) = seed {
+        println!("shuffled tests with seed {}", seed);
+    }
+    let tests = suite.ordered_tests(seed);
+    let parallel = suite.parallel;
+    let mut context = cfg.merge_contexts(&contexts)?;
+    context.extend(parse_pairs(&vars, '=')?);
+    cfg.add_default_headers(&contexts)?;
+    cfg.add_scopes(&contexts)?;
+    if let Some(t) = tests.iter().find(|t| !cfg.tests.contains_key(*t)) {
+        return Err(Failure::Config(format!("Test not found: {}", t)).into());
+    }
+    for name in &suite.before {
+        tracing::info!(request = %name, "running before request");
+        match run_request(cfg, &mut cfg.applicator(context.clone()), name).await {
+            Ok(response) => {
+                Arc::make_mut(&mut cfg.responses).insert(name.clone(), response);
+            }
+            Err(e) => {
+                // Clean up whatever the earlier requests created.
+                run_after(cfg, &context, &suite.after).await?;
+                return Err(e.context(format!("before request '{}' failed", name)));
+            }
+        }
+    }
+    let cfg = &*cfg;
+    let options = TestOptions {
+        offline,
+        cache_dir: cache.to_path_buf(),
+        update_snapshots,
+        delay,
+        redactor: Redactor::new(&cfg.redact, &context)?
+            .with_scopes(&cfg.scopes)
+            .with_requests(&cfg.requests, &context),
+        responses: bundle_on_failure
+            .as_ref()
+            .map(|_| Arc::new(WriteBehind::default())),
+        correlation: cfg.correlation.clone(),
+        events,
+        // Parallel tests are printed once they finish.
+        live: parallel <= 1 && std::io::stdout().is_terminal(),
+    };
+    let names = tests.clone();
+    let bundle = |results: &Results, error: Option<String>| -> Result<()> {
+        match (&bundle_on_failure, &options.responses) {
+            (Some(path), Some(responses)) => write_bundle(
+                path,
+                &cfg.subset(&names, &contexts),
+                &context,
+                &options.redactor,
+                results,
+                responses,
+                error,
+            ),
+            _ => Ok(()),
+        }
+    };
+    let mut results = Results::new("test results");
+    results.seed = seed;
+    results.verbosity = results_verbosity;
+    let now = Instant::now();
+    let mut stdout = stdout();
+    let total = tests.len();
+    let mut error = None;
+    let stopped = if parallel <= 1 {
+        let run = async {
+            for t in tests {
+                let name = t.clone();
+                if let Err(e) = cfg.tests[&t]
+                    .execute(t, cfg, &context, &mut results, &mut stdout, &options)
+                    .await
+                {
+                    error = Some((name, e));
+                    break;
+                }
+                results.clear(&mut stdout)?;
             }
+            Ok::<_, anyhow::Error>(())
+        };
+        match until(deadline, run).await {
+            Ok(result) => result.map(|_| None)?,
+            Err(stopped) => Some(stopped),
+        }
+    } else {
+        // Each test has its own results, which are added as they finish.
+        let (context, options) = (&context, &options);
+        let runs = futures_util::stream::iter(tests.into_iter().map(|t| async move {
+            let mut results = Results::new("test results");
+            let result = cfg.tests[&t]
+                .execute(
+                    t.clone(),
+                    cfg,
+                    context,
+                    &mut results,
+                    &mut std::io::sink(),
+                    options,
+                )
+                .await;
+            (t, results, result)
+        }))
+        .buffered(parallel);
+        let run = async {
+            futures_util::pin_mut!(runs);
+            while let Some((name, finished, result)) = runs.next().await {
+                results.children.extend(finished.children);
+                if let Err(e) = result {
+                    error = Some((name, e));
+                    break;
+                }
+            }
+        };
+        let stopped = until(deadline, run).await.err();
+        results.duration = now.elapsed();
+        if error.is_some() {
+            results.print(&mut stdout, "")?;
+        }
+        stopped
+    };
 
-            println!("latency histogram:");
-            let (buckets, values) = histogram(&durations, 10);
-            println!("  bin ranges:");
-            for (start, end) in buckets {
-                println!("  - [{:?}, {:?}]", start, end);
+    // The tests that were cancelled or never started are marked with
+    // why they stopped.
+    if let Some(stopped) = &stopped {
+        if parallel <= 1 {
+            results.clear(&mut stdout)?;
+        }
+        for name in &names {
+            if !results.children.iter().any(|c| c.name == *name) {
+                results.add_results(Results::from_test(name, &cfg.tests[name]));
             }
-            println!("  values:");
-            let max_count = values.iter().max().unwrap_or(&0);
-            let bar_scale = 50;
-            let bars = values
+        }
+        results.stop(&stopped.state());
+    }
+
+    // Another Ctrl-C stops the after requests too.
+    let after = match until(None, run_after(cfg, &context, &suite.after)).await {
+        Ok(after) => after,
+        Err(stopped) => Err(anyhow::anyhow!("after requests stopped: {}", stopped)),
+    };
+
+    results.duration = now.elapsed();
+    results.finish(match (&error, &stopped) {
+        (Some((name, _)), _) => State::Failed(format!("test '{}' failed", name)),
+        (None, Some(stopped)) => stopped.state(),
+        (None, None) => State::Passed,
+    });
+    let passed = error.is_none() && stopped.is_none() && results.failures().is_empty();
+    options.send(Event::RunFinished {
+        passed,
+        duration_ms: millis(results.duration),
+        counts: Some(results.counts()),
+    });
+    record(
+        history,
+        Run::new(source, results.duration, passed).with_results(results.clone()),
+    );
+
+    let counts = results.counts();
+    if let Some(stopped) = stopped {
+        results.print(&mut stdout, "")?;
+        println!("{}", counts);
+        if let (Stopped::Interrupted, Err(e)) = (&stopped, &after) {
+            eprintln!("{}", e);
+        }
+        let message = stopped.to_string();
+        let mut failures = results.failures();
+        failures.extend(
+            results
+                .children
                 .iter()
-                .map(|count| (count.to_string(), count * bar_scale / max_count))
+                .filter(|t| t.state == State::TimedOut)
+                .map(|t| format!("{}: timed out", t.name)),
+        );
+        let summary = notify::Summary {
+            source: source.into(),
+            total,
+            failed: counts.failed,
+            skipped: counts.skipped,
+            timed_out: counts.timed_out,
+            failures,
+        };
+        notify::send_all(
+            &cfg.notifications,
+            &cfg.applicator(context.clone()),
+            &summary,
+        )
+        .await;
+        bundle(&results, Some(message.clone()))?;
+        return Err(match stopped {
+            Stopped::Interrupted => Failure::Interrupted,
+            Stopped::Deadline(_) => Failure::Failed(message),
+        }
+        .into());
+    }
+
+    if let Some((name, e)) = error {
+        let summary = notify::Summary {
+            source: source.into(),
+            total,
+            failed: 1,
+            failures: vec![format!(
+                "{}: {}",
+                name,
+                options.redactor.text(&e.to_string())
+            )],
+            ..Default::default()
+        };
+        notify::send_all(
+            &cfg.notifications,
+            &cfg.applicator(context.clone()),
+            &summary,
+        )
+        .await;
+        bundle(&results, Some(options.redactor.text(&e.to_string())))?;
+        return Err(e.into());
+    }
+
+    match parallel <= 1 {
+        true => results.output(&mut stdout, "")?,
+        false => results.print(&mut stdout, "")?,
+    }
+    println!("{}", counts);
+    let slowest = results.slowest_steps(SLOWEST_STEPS);
+    if !slowest.is_empty() {
+        println!("slowest steps:");
+        for (name, duration) in slowest {
+            println!("  {:?} {}", duration, name);
+        }
+    }
+
+    let failures = results.failures();
+    if !failures.is_empty() {
+        let summary = notify::Summary {
+            source: source.into(),
+            total,
+            failed: counts.failed,
+            skipped: counts.skipped,
+            timed_out: counts.timed_out,
+            failures,
+        };
+        notify::send_all(
+            &cfg.notifications,
+            &cfg.applicator(context.clone()),
+            &summary,
+        )
+        .await;
+        bundle(&results, None)?;
+        after?;
+        return Err(Failure::Failed(format!("tests failed: {}", counts)).into());
+    }
+    after
+}
+
+/// The number of steps in the slowest steps after a test run.
+const SLOWEST_STEPS: usize = 10;
+
+/// Stopped is why a run stopped before it finished.
+#[derive(Debug)]
+enum Stopped {
+    /// The run took longer than the deadline.
+    Deadline(Duration),
+    /// The run was interrupted with Ctrl-C.
+    Interrupted,
+}
+
+impl Stopped {
+    /// The state of the results that didn't finish.
+    fn state(&self) -> State {
+        match self {
+            Stopped::Deadline(_) => State::TimedOut,
+            Stopped::Interrupted => State::Skipped("interrupted".into()),
+        }
+    }
+}
+
+impl std::fmt::Display for Stopped {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Stopped::Deadline(d) => write!(f, "tests didn't finish within the deadline of {:?}", d),
+            Stopped::Interrupted => write!(f, "interrupted"),
+        }
+    }
+}
+
+/// The number of times Ctrl-C was pressed. One listener is installed
+/// for the whole run the first time it's watched, and each phase waits
+/// for the next press. A press while no phase is watching exits, as it
+/// would without the listener.
+fn interrupts() -> &'static tokio::sync::watch::Sender<usize> {
+    static INTERRUPTS: OnceLock<tokio::sync::watch::Sender<usize>> = OnceLock::new();
+    INTERRUPTS.get_or_init(|| {
+        let (sender, _) = tokio::sync::watch::channel(0);
+        let listener = sender.clone();
+        tokio::spawn(async move {
+            while tokio::signal::ctrl_c().await.is_ok() {
+                if listener.receiver_count() == 0 {
+                    std::process::exit(EXIT_INTERRUPTED);
+                }
+                listener.send_modify(|n| *n += 1);
+            }
+        });
+        sender
+    })
+}
+
+/// Run the future until the deadline, if there is one, or Ctrl-C. The
+/// future is dropped when it's stopped, cancelling the requests it's
+/// making.
+async fn until<F: std::future::Future>(
+    deadline: Option<(Duration, tokio::time::Instant)>,
+    future: F,
+) -> std::result::Result<F::Output, Stopped> {
+    let deadline = async {
+        match deadline {
+            Some((d, at)) => {
+                tokio::time::sleep_until(at).await;
+                d
+            }
+            None => std::future::pending().await,
+        }
+    };
+    let mut interrupts = interrupts().subscribe();
+    tokio::select! {
+        output = future => Ok(output),
+        d = deadline => Err(Stopped::Deadline(d)),
+        _ = interrupts.changed() => Err(Stopped::Interrupted),
+    }
+}
+
+/// Print the summary of a run and its results.
+fn print_run(run: &Run) -> Result<()> {
+    println!("id:       {}", run.id);
+    println!("command:  {}", run.command);
+    println!("time:     {}", humantime::format_rfc3339_seconds(run.time));
+    if let Some(git) = &run.git {
+        println!("git:      {}", git);
+    }
+    println!("duration: {:?}", run.duration);
+    println!("passed:   {}", run.passed);
+    if let Some(results) = &run.results {
+        println!("tests:    {}", results.counts());
+        results.print(&mut stdout(), "")?;
+    }
+    if let Some(benchmark) = &run.benchmark {
+        println!("requests: {}", benchmark.requests.join(", "));
+        println!("total:    {}", benchmark.total);
+        println!("failed:   {}", benchmark.failed);
+        for (code, count) in &benchmark.status_codes {
+            println!("  {}: {}", code, count);
+        }
+        println!("mean:     {:?}", benchmark.mean);
+        println!("fastest:  {:?}", benchmark.fastest);
+        println!("slowest:  {:?}", benchmark.slowest);
+    }
+    Ok(())
+}
+
+/// Add the run to the history. The history is only used for reports,
+/// so the command doesn't fail when it can't be written.
+fn record(history: &History, run: Run) {
+    if let Err(e) = history.append(run) {
+        tracing::warn!(error = %e, "unable to add the run to the history");
+    }
+}
+
+/// Keep the tests affected by changes since the revision given with
+/// `--changed-since`, or all of them when that can't be told.
+fn changed_tests(cfg: &Config, config: &Path, tests: Vec<String>, run: &TestRun) -> Vec<String> {
+    let revision = match &run.changed_since {
+        Some(r) => r,
+        None => return tests,
+    };
+    match changed::affected_tests(cfg, config, revision) {
+        Ok(affected) => {
+            let tests = tests
+                .into_iter()
+                .filter(|t| affected.contains(t))
                 .collect::<Vec<_>>();
-            let max_bar_len = bars.iter().map(|b| b.0.len()).max().unwrap_or(0);
-            for (count, bar_len) in bars {
-                let bar: String = "█".repeat(bar_len);
-                println!("    {: >width$}: {}", count, bar, width = max_bar_len);
+            println!("running {} tests changed since {}", tests.len(), revision);
+            tests
+        }
+        Err(e) => {
+            eprintln!(
+                "running all tests, changes since {} are unknown: {}",
+                revision, e
+            );
+            tests
+        }
+    }
+}
+
+/// Make a suite's after requests. They are all made even if some fail,
+/// and the last failure is returned.
+async fn run_after(
+    cfg: &Config,
+    context: &HashMap<String, String>,
+    names: &[String],
+) -> Result<()> {
+    let mut app = cfg.applicator(context.clone());
+    let mut result = Ok(());
+    for name in names {
+        tracing::info!(request = %name, "running after request");
+        match run_request(cfg, &mut app, name).await {
+            Ok(response) => app.add_response(name.clone(), response),
+            Err(e) => {
+                tracing::warn!(request = %name, error = %e, "after request failed");
+                result = Err(e.context(format!("after request '{}' failed", name)));
             }
         }
     }
+    result
+}
+
+/// Write the failure bundle for a test run, with the secrets masked.
+fn write_bundle(
+    path: &Path,
+    cfg: &Config,
+    context: &HashMap<String, String>,
+    redactor: &Redactor,
+    results: &Results,
+    responses: &WriteBehind,
+    error: Option<String>,
+) -> Result<()> {
+    let mut bundle = Bundle::new();
+    let mut cfg = cfg.clone();
+    redactor.config(&mut cfg);
+    bundle.add_yaml("config.yaml", &cfg)?;
+    let mut context = context.clone();
+    redactor.map(&mut context);
+    bundle.add_yaml(
+        "context.yaml",
+        &context.into_iter().collect::<BTreeMap<_, _>>(),
+    )?;
+    let responses = responses.take().into_iter().collect::<BTreeMap<_, _>>();
+    for (name, response) in &responses {
+        bundle.add_yaml(&format!("responses/{}.yaml", name), response)?;
+    }
+    bundle.add_json("results.json", results)?;
+    if let Some(error) = error {
+        bundle.add("error.txt", error);
+    }
+    bundle.write(path)?;
+    eprintln!("wrote failure bundle to {}", path.display());
+    Ok(())
+}
+
+/// Failure is an error that says which exit code the command fails with.
+#[derive(Debug, thiserror::Error)]
+enum Failure {
+    /// Tests or checks failed.
+    #[error("{0}")]
+    Failed(String),
+    /// The configuration doesn't have what was asked for.
+    #[error("{0}")]
+    Config(String),
+    /// The command line asks for something that can't be done.
+    #[error("{0}")]
+    Usage(String),
+    /// The run was interrupted with Ctrl-C.
+    #[error("interrupted")]
+    Interrupted,
+    /// A filter or search matched nothing.
+    #[error("{0}")]
+    NoMatches(String),
+}
+
+/// The exit code for the error, by the first error in its chain that
+/// tells what kind of failure it was.
+fn exit_code(e: &anyhow::Error) -> i32 {
+    for cause in e.chain() {
+        if let Some(failure) = cause.downcast_ref::<Failure>() {
+            return match failure {
+                Failure::Failed(_) => EXIT_FAILED,
+                Failure::Config(_) => EXIT_CONFIG,
+                Failure::Usage(_) => EXIT_USAGE,
+                Failure::Interrupted => EXIT_INTERRUPTED,
+                Failure::NoMatches(_) => EXIT_NO_MATCHES,
+            };
+        }
+        if let Some(e) = cause.downcast_ref::<RequestError>() {
+            return match e {
+                RequestError::Http(_)
+                | RequestError::Io(_)
+                | RequestError::Parse(_)
+                | RequestError::WebSocket(_)
+                | RequestError::Grpc(_) => EXIT_NETWORK,
+                _ => EXIT_CONFIG,
+            };
+        }
+        if cause.is::<reqwest::Error>() {
+            return EXIT_NETWORK;
+        }
+        if cause.is::<apictl::config::Error>()
+            || cause.is::<serde_yaml::Error>()
+            || cause.is::<ProfileError>()
+        {
+            return EXIT_CONFIG;
+        }
+        if let Some(TestError::RequestNotFound(_)) = cause.downcast_ref::<TestError>() {
+            return EXIT_CONFIG;
+        }
+    }
+    EXIT_FAILED
+}
+
+/// The exit code when tests, checks or another part of a command fail.
+const EXIT_FAILED: i32 = 1;
+
+/// The exit code when the configuration can't be loaded or refers to
+/// something that doesn't exist.
+const EXIT_CONFIG: i32 = 2;
+
+/// The exit code when a request couldn't get a response.
+const EXIT_NETWORK: i32 = 3;
+
+/// The exit code when the command line is wrong.
+const EXIT_USAGE: i32 = 4;
+
+/// The exit code when a filter or search matches nothing.
+const EXIT_NO_MATCHES: i32 = 5;
 
+/// The exit code when a run is interrupted with Ctrl-C, after its
+/// partial results are printed.
+const EXIT_INTERRUPTED: i32 = 130;
+
+/// Output the list, its summary, or the list grouped by a field. An
+/// empty list prints a message instead, or fails with NoMatches when it
+/// was filtered.
+fn output_list<L: List>(
+    list: &L,
+    kind: &str,
+    output: OutputFormat,
+    sort: &str,
+    summary: bool,
+    group_by: Option<&str>,
+    filtered: bool,
+) -> Result<()> {
+    let empty = list.values().is_empty();
+    if empty && filtered {
+        return Err(Failure::NoMatches(format!("no {} match the filter", kind)).into());
+    }
+    match (summary, empty, group_by) {
+        (true, _, _) => list.output_summary(output)?,
+        (false, true, _) => eprintln!("no {} found", kind),
+        (false, false, Some(field)) => list.output_grouped(output, sort, field)?,
+        (false, false, None) => list.output(output, sort)?,
+    }
     Ok(())
 }
 
+/// Parse the given 'key<sep>value' strings into a map.
+fn parse_pairs(pairs: &[String], sep: char) -> Result<HashMap<String, String>> {
+    pairs
+        .iter()
+        .map(|p| match p.split_once(sep) {
+            Some((k, v)) => Ok((k.trim().to_string(), v.trim().to_string())),
+            None => Err(Failure::Usage(format!("expected 'key{}value': {}", sep, p)).into()),
+        })
+        .collect()
+}
+
 fn histogram(values: &Vec<Duration>, num_bins: usize) -> (Vec<(Duration, Duration)>, Vec<usize>) {
     let min = values.iter().min().unwrap().as_nanos();
     let max = values.iter().max().unwrap().as_nanos();
@@ -399,11 +3074,398 @@ pub async fn run_request(cfg: &Config, app: &mut Applicator, request: &str) -> R
     let mut request: Request = match cfg.requests.get(request) {
         Some(r) => r.clone(),
         None => {
-            return Err(anyhow::anyhow!("Request not found: {}", request));
+            return Err(Failure::Config(format!("Request not found: {}", request)).into());
         }
     };
-    request.apply(app);
+    request.try_apply(app)?;
 
     // Make the requests.
     Ok(request.request().await?)
 }
+
+/// Serve the metrics on the address, if one is given, while the command
+/// runs.
+#[cfg(feature = "metrics")]
+fn serve_metrics(addr: Option<std::net::SocketAddr>) -> Option<Arc<apictl::Metrics>> {
+    let addr = addr?;
+    let metrics = Arc::new(apictl::Metrics::default());
+    let served = metrics.clone();
+    tokio::spawn(async move {
+        if let Err(e) = served.serve(addr).await {
+            tracing::error!(%addr, error = %e, "serving metrics failed");
+        }
+    });
+    Some(metrics)
+}
+
+/// Make the request on one of the benchmark's connections.
+async fn run_benchmark_request(
+    cfg: &Config,
+    app: &mut Applicator,
+    name: &str,
+    connections: &benchmark::Connections,
+) -> Result<Response> {
+    let mut request = cfg
+        .requests
+        .get(name)
+        .cloned()
+        .ok_or_else(|| Failure::Config(format!("Request not found: {}", name)))?;
+    request.try_apply(app)?;
+    Ok(connections.request(name, request).await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use apictl::{Mock, MockServer};
+
+    /// Run apictl with the configuration, caching in the folder.
+    async fn apictl(dir: &Path, config: &str, args: &[&str]) -> Result<()> {
+        let path = dir.join(".apictl.yaml");
+        std::fs::write(&path, config)?;
+        let mut argv = vec![
+            "apictl".into(),
+            "-c".into(),
+            path.into_os_string(),
+            "--cache".into(),
+            dir.join("cache").into_os_string(),
+            "--user-config".into(),
+            dir.join("user.yaml").into_os_string(),
+        ];
+        argv.extend(args.iter().map(Into::into));
+        run(Args::try_parse_from(argv)?).await
+    }
+
+    /// Serve the mocks on a local port, recording the requests they
+    /// get to the file, and return the base URL.
+    async fn serve(mocks: &str, record: &Path) -> String {
+        let mocks: HashMap<String, Mock> = serde_yaml::from_str(mocks).unwrap();
+        let server = MockServer::new(&mocks, HashMap::new(), Some(record.into())).unwrap();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(server.serve_listener(listener));
+        format!("http://{}", addr)
+    }
+
+    /// The lines written to the file, parsed as JSON.
+    fn json_lines(path: &Path) -> Vec<serde_json::Value> {
+        std::fs::read_to_string(path)
+            .unwrap_or_default()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn repeat_until() {
+        let dir = tempfile::tempdir().unwrap();
+        let record = dir.path().join("record.jsonl");
+        let base_url = serve(
+            r#"
+running: { path: /jobs/1, body: '{"status": "running"}' }
+done: { path: /jobs/2, body: '{"status": "done"}' }
+"#,
+            &record,
+        )
+        .await;
+        let config = format!(
+            r#"
+contexts:
+  local: {{ base_url: "{}" }}
+requests:
+  running: {{ tags: [jobs], description: a running job, url: /jobs/1 }}
+  done: {{ tags: [jobs], description: a finished job, url: /jobs/2 }}
+"#,
+            base_url
+        );
+        let until = |name| format!("${{response.{}.status}} == done", name);
+        let requests = |from: usize| json_lines(&record).len() - from;
+
+        // Repeats wait for the interval between them.
+        let started = Instant::now();
+        let args = ["requests", "run", "-c", "local", "running", "--repeat", "3"];
+        apictl(
+            dir.path(),
+            &config,
+            &[&args[..], &["--interval", "20ms"]].concat(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(requests(0), 3);
+        assert!(started.elapsed() >= Duration::from_millis(40));
+
+        // It stops once the condition is met.
+        let args = ["requests", "run", "-c", "local", "done", "--repeat", "3"];
+        apictl(
+            dir.path(),
+            &config,
+            &[&args[..], &["--until", &until("done")]].concat(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(requests(3), 1);
+
+        // Running out of repeats fails, but only the last round's
+        // checks are reported.
+        let events = dir.path().join("events.jsonl");
+        let args = [
+            "requests",
+            "run",
+            "-c",
+            "local",
+            "running",
+            "--repeat",
+            "2",
+            "--interval",
+            "1ms",
+            "--expect-status",
+            "201",
+            "--events",
+            events.to_str().unwrap(),
+        ];
+        let e = apictl(
+            dir.path(),
+            &config,
+            &[&args[..], &["--until", &until("running")]].concat(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(exit_code(&e), EXIT_FAILED);
+        assert_eq!(requests(4), 2);
+        let failed = json_lines(&events)
+            .into_iter()
+            .filter(|e| e["event"] == "assert_failed")
+            .count();
+        assert_eq!(failed, 1);
+    }
+
+    /// An interrupt stops everything waiting on one, so the tests that
+    /// wait take turns.
+    static INTERRUPTS: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+    #[tokio::test]
+    async fn until_interrupted() {
+        let _turn = INTERRUPTS.lock().await;
+        let pending = tokio::spawn(until(None, std::future::pending::<()>()));
+        while interrupts().receiver_count() == 0 {
+            tokio::task::yield_now().await;
+        }
+        interrupts().send_modify(|n| *n += 1);
+        assert!(matches!(pending.await.unwrap(), Err(Stopped::Interrupted)));
+
+        // Later phases wait for the next interrupt.
+        assert!(matches!(until(None, async { 1 }).await, Ok(1)));
+        let at = tokio::time::Instant::now() + Duration::from_millis(10);
+        let stopped = until(
+            Some((Duration::from_millis(10), at)),
+            std::future::pending::<()>(),
+        );
+        assert!(matches!(stopped.await, Err(Stopped::Deadline(_))));
+    }
+
+    #[tokio::test]
+    async fn call() {
+        let dir = tempfile::tempdir().unwrap();
+        let record = dir.path().join("record.jsonl");
+        let base_url = serve(
+            r#"new-user: { method: POST, path: /users, status: 201, body: '{"id": 7}' }"#,
+            &record,
+        )
+        .await;
+        let config = format!(
+            "contexts:\n  local: {{ base_url: \"{}\", token: abc }}\n",
+            base_url
+        );
+        let args = [
+            "--run-id",
+            "one",
+            "call",
+            "/users",
+            "-c",
+            "local",
+            "-H",
+            "x-token: ${token}",
+            "-d",
+            r#"{"name": "${name}"}"#,
+            "--var",
+            "name=bob",
+            "--save-as",
+            "new-user",
+        ];
+        apictl(dir.path(), &config, &args).await.unwrap();
+
+        // The request is made with the context like a configured one.
+        let sent = json_lines(&record);
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0]["method"], "POST");
+        assert_eq!(sent[0]["path"], "/users");
+        assert_eq!(sent[0]["headers"]["x-token"], "abc");
+        assert_eq!(sent[0]["body"], r#"{"name": "bob"}"#);
+
+        // The response is cached under the name for later requests.
+        let saved = dir.path().join("cache/responses/one/new-user.yaml");
+        let saved = std::fs::read_to_string(saved).unwrap();
+        assert!(saved.contains("status_code: 201"), "{}", saved);
+    }
+
+    #[test]
+    fn completions() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".apictl.yaml");
+        let config = r#"
+contexts: { prod: {}, local: {} }
+requests:
+  get-users: { tags: [users], description: list the users, url: /users }
+  get-user: { tags: [users], description: get a user, url: /users/1 }
+  new-user: { tags: [users], description: add a user, url: /users, method: POST }
+"#;
+        std::fs::write(&path, config).unwrap();
+        std::env::set_var("APICTL_CONFIG", &path);
+
+        // Names are read from the configuration as they're typed.
+        let complete = |line: &str| {
+            let args = line.split(' ').map(Into::into).collect::<Vec<_>>();
+            let index = args.len() - 1;
+            clap_complete::engine::complete(&mut Args::command(), args, index, None)
+                .unwrap()
+                .into_iter()
+                .map(|c| c.get_value().to_string_lossy().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            complete("apictl requests run get-"),
+            ["get-user", "get-users"]
+        );
+        assert_eq!(complete("apictl requests run -c "), ["local", "prod"]);
+    }
+
+    #[tokio::test]
+    async fn benchmark_workers() {
+        let _turn = INTERRUPTS.lock().await;
+        let dir = tempfile::tempdir().unwrap();
+        let record = dir.path().join("record.jsonl");
+        let base_url = serve(
+            r#"item: { path: "/items/{id}", body: '{"id": 1}' }"#,
+            &record,
+        )
+        .await;
+        let config = format!(
+            r#"
+contexts:
+  local: {{ base_url: "{}" }}
+requests:
+  get-item: {{ tags: [items], description: get an item, url: "/items/${{bench.iteration}}" }}
+"#,
+            base_url
+        );
+        let args = [
+            "--run-id",
+            "bench",
+            "benchmark",
+            "-c",
+            "local",
+            "-n",
+            "8",
+            "-p",
+            "4",
+            "--save-responses",
+            "get-item",
+        ];
+        apictl(dir.path(), &config, &args).await.unwrap();
+
+        // The workers share the configuration and context but each
+        // iteration gets its own variables.
+        let mut paths = json_lines(&record)
+            .into_iter()
+            .map(|r| r["path"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>();
+        paths.sort();
+        assert_eq!(
+            paths,
+            (0..8).map(|i| format!("/items/{}", i)).collect::<Vec<_>>()
+        );
+        assert!(dir
+            .path()
+            .join("cache/responses/bench/get-item.yaml")
+            .exists());
+    }
+
+    #[test]
+    fn log_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("apictl.log");
+        std::fs::write(&path, "{}\n").unwrap();
+
+        let logger = logger(LevelFilter::INFO, Some(&path)).unwrap();
+        tracing::subscriber::with_default(logger, || {
+            tracing::info!(request = "get-job", "request sent");
+            tracing::debug!("too detailed");
+        });
+
+        // The logs are appended as JSON lines at the level and above.
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let lines = contents.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        let line: serde_json::Value = serde_json::from_str(lines[1]).unwrap();
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["fields"]["message"], "request sent");
+        assert_eq!(line["fields"]["request"], "get-job");
+    }
+
+    #[test]
+    fn empty_lists() {
+        let none = HashMap::<String, Request>::new();
+        let output = |summary, filtered| {
+            output_list(
+                &none,
+                "requests",
+                OutputFormat::Table,
+                "name",
+                summary,
+                None,
+                filtered,
+            )
+        };
+        assert!(output(false, false).is_ok());
+        assert!(output(true, false).is_ok());
+        assert_eq!(
+            exit_code(&output(false, true).unwrap_err()),
+            EXIT_NO_MATCHES
+        );
+        assert_eq!(exit_code(&output(true, true).unwrap_err()), EXIT_NO_MATCHES);
+    }
+
+    #[test]
+    fn exit_codes() {
+        let io = || std::io::Error::new(std::io::ErrorKind::ConnectionRefused, "refused");
+        let cases: Vec<(anyhow::Error, i32)> = vec![
+            (Failure::Failed("tests failed".into()).into(), EXIT_FAILED),
+            (Failure::Config("no test".into()).into(), EXIT_CONFIG),
+            (Failure::Usage("bad flag".into()).into(), EXIT_USAGE),
+            (Failure::Interrupted.into(), EXIT_INTERRUPTED),
+            (
+                Failure::NoMatches("no tests".into()).into(),
+                EXIT_NO_MATCHES,
+            ),
+            (RequestError::Io(io()).into(), EXIT_NETWORK),
+            (
+                RequestError::RelativeUrl("/users".into()).into(),
+                EXIT_CONFIG,
+            ),
+            (
+                apictl::config::Error::ContextNotFound("dev".into()).into(),
+                EXIT_CONFIG,
+            ),
+            (TestError::RequestNotFound("get".into()).into(), EXIT_CONFIG),
+            (
+                anyhow::Error::from(Failure::Usage("bad flag".into())).context("running"),
+                EXIT_USAGE,
+            ),
+            (parse_pairs(&["foo".into()], '=').unwrap_err(), EXIT_USAGE),
+            (anyhow::anyhow!("something else"), EXIT_FAILED),
+        ];
+        for (e, code) in cases {
+            assert_eq!(exit_code(&e), code, "{}", e);
+        }
+    }
+}
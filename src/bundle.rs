@@ -0,0 +1,103 @@
+/// Bundle is used to package the files that reproduce a run into a
+/// single `.tar.gz` archive to attach to bug reports.
+use std::fs::File;
+use std::path::Path;
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::Serialize;
+use thiserror::Error;
+
+/// BundleError is the error type for bundles.
+#[derive(Error, Debug)]
+pub enum BundleError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("yaml error: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// Result is the result type for bundles.
+pub type Result<T> = std::result::Result<T, BundleError>;
+
+/// Bundle collects files in memory until it's written.
+#[derive(Debug, Default)]
+pub struct Bundle {
+    files: Vec<(String, Vec<u8>)>,
+}
+
+impl Bundle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a file at the path in the archive.
+    pub fn add(&mut self, path: &str, contents: impl Into<Vec<u8>>) {
+        self.files.push((path.to_string(), contents.into()));
+    }
+
+    /// Add the value as a YAML file.
+    pub fn add_yaml<T: Serialize>(&mut self, path: &str, value: &T) -> Result<()> {
+        self.add(path, serde_yaml::to_string(value)?);
+        Ok(())
+    }
+
+    /// Add the value as a JSON file.
+    pub fn add_json<T: Serialize>(&mut self, path: &str, value: &T) -> Result<()> {
+        self.add(path, serde_json::to_string_pretty(value)?);
+        Ok(())
+    }
+
+    /// The paths of the files in the bundle.
+    pub fn paths(&self) -> Vec<&str> {
+        self.files.iter().map(|(p, _)| p.as_str()).collect()
+    }
+
+    /// Write the files as a gzipped tar archive.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let encoder = GzEncoder::new(File::create(path)?, Compression::default());
+        let mut archive = tar::Builder::new(encoder);
+        let mtime = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        for (name, contents) in &self.files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_mtime(mtime);
+            header.set_cksum();
+            archive.append_data(&mut header, name, contents.as_slice())?;
+        }
+        archive.into_inner()?.finish()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bundle.tar.gz");
+        let mut bundle = Bundle::new();
+        bundle.add("error.txt", "boom");
+        bundle.add_yaml("context.yaml", &vec![("a", "b")]).unwrap();
+        bundle.write(&path).unwrap();
+
+        let file = File::open(&path).unwrap();
+        let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(file));
+        let names = archive
+            .entries()
+            .unwrap()
+            .map(|e| e.unwrap().path().unwrap().display().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["error.txt", "context.yaml"]);
+    }
+}
@@ -0,0 +1,384 @@
+/// Changed is used to find the tests affected by changes to the
+/// configuration since a git revision, so pipelines can run only those,
+/// and to describe how two versions of a configuration differ.
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::{diff, Config, Test};
+
+use serde::Serialize;
+use serde_yaml::Value;
+use thiserror::Error;
+
+/// ChangedError is the error type for finding changes.
+#[derive(Error, Debug)]
+pub enum ChangedError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("git {0}: {1}")]
+    Git(String, String),
+
+    #[error("config error at the revision: {0}")]
+    Config(#[from] crate::config::Error),
+
+    #[error("yaml error: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+
+    #[error("{0} changed, which can affect every test")]
+    Shared(&'static str),
+}
+
+/// Result is the result type for finding changes.
+pub type Result<T> = std::result::Result<T, ChangedError>;
+
+/// The tests whose definitions, or the requests they make, changed
+/// since the revision, sorted by name. Requests are compared after
+/// `extends` is resolved, so changing a base request affects every
+/// request that extends it. Other changed files, like body templates,
+/// affect the requests that mention their file name.
+///
+/// Changes to what every request uses, like contexts or default
+/// headers, are an error because they can affect every test.
+pub fn affected_tests(cfg: &Config, config: &Path, revision: &str) -> Result<Vec<String>> {
+    let config = config.canonicalize()?;
+    let dir = match config.is_dir() {
+        true => config.as_path(),
+        false => config.parent().unwrap_or(Path::new(".")),
+    };
+    let root = PathBuf::from(git(dir, &["rev-parse", "--show-toplevel"])?.trim()).canonicalize()?;
+    let relative = config.strip_prefix(&root).unwrap_or(&config);
+    let relative = relative.to_string_lossy();
+    let pathspec = match relative.is_empty() {
+        true => ".",
+        false => &relative,
+    };
+
+    // The config as it was, from the same files at the revision.
+    let files = git(
+        &root,
+        &["ls-tree", "-r", "--name-only", revision, "--", pathspec],
+    )?;
+    let mut documents = vec![];
+    for file in files.lines().filter(|f| is_yaml(f)) {
+        let contents = git(&root, &["show", &format!("{}:{}", revision, file)])?;
+        let file = root.join(file);
+        for document in crate::config::parse_documents(&file, &contents)? {
+            documents.push((file.clone(), document));
+        }
+    }
+    let old = Config::from_documents(documents)?;
+
+    if !same(&old.contexts, &cfg.contexts) {
+        return Err(ChangedError::Shared("contexts"));
+    }
+    if !same(&old.default_headers, &cfg.default_headers) {
+        return Err(ChangedError::Shared("default headers"));
+    }
+    if !same(&old.resolve, &cfg.resolve) {
+        return Err(ChangedError::Shared("resolve"));
+    }
+
+    let files = git(&root, &["diff", "--name-only", revision])?;
+    let names = files
+        .lines()
+        .filter(|f| !is_yaml(f))
+        .filter_map(|f| Path::new(f).file_name()?.to_str())
+        .collect::<Vec<_>>();
+    let mut requests = changed(&old.requests, &cfg.requests)?;
+    for (name, request) in &cfg.requests {
+        let text = serde_yaml::to_string(request)?;
+        if names.iter().any(|n| text.contains(n)) {
+            requests.insert(name.clone());
+        }
+    }
+
+    let mut tests = changed(&old.tests, &cfg.tests)?;
+    tests.extend(
+        cfg.tests
+            .iter()
+            .filter(|(_, t)| t.steps.iter().any(|s| requests.contains(&s.request)))
+            .map(|(n, _)| n.clone()),
+    );
+    tests.retain(|t| cfg.tests.contains_key(t));
+    Ok(tests.into_iter().collect())
+}
+
+/// Run git in the folder, returning what it printed.
+fn git(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git").arg("-C").arg(dir).args(args).output()?;
+    match output.status.success() {
+        true => Ok(String::from_utf8_lossy(&output.stdout).into_owned()),
+        false => Err(ChangedError::Git(
+            args.first().copied().unwrap_or_default().into(),
+            String::from_utf8_lossy(&output.stderr).trim().into(),
+        )),
+    }
+}
+
+fn is_yaml(file: &str) -> bool {
+    file.ends_with(".yaml") || file.ends_with(".yml")
+}
+
+fn same<T: Serialize>(a: &T, b: &T) -> bool {
+    match (serde_yaml::to_value(a), serde_yaml::to_value(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// The names that were added, removed or are defined differently.
+fn changed<T: Serialize>(
+    old: &HashMap<String, T>,
+    new: &HashMap<String, T>,
+) -> Result<BTreeSet<String>> {
+    let mut names = BTreeSet::new();
+    for name in old.keys().chain(new.keys()) {
+        let old = old.get(name).map(serde_yaml::to_value).transpose()?;
+        let new = new.get(name).map(serde_yaml::to_value).transpose()?;
+        if old != new {
+            names.insert(name.clone());
+        }
+    }
+    Ok(names)
+}
+
+/// Change is whether a definition was added, removed or changed.
+#[derive(Clone, Copy, Debug, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Change {
+    Added,
+    Removed,
+    Changed,
+}
+
+/// Difference is a definition that differs between two configurations.
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct Difference {
+    /// The kind of definition, like `request` or `test`.
+    pub kind: String,
+    pub name: String,
+    pub change: Change,
+    /// What changed in a changed definition, one line each.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub details: Vec<String>,
+}
+
+impl std::fmt::Display for Difference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sign = match self.change {
+            Change::Added => "+",
+            Change::Removed => "-",
+            Change::Changed => "~",
+        };
+        write!(f, "{} {} {}", sign, self.kind, self.name)?;
+        for detail in &self.details {
+            write!(f, "\n    {}", detail)?;
+        }
+        Ok(())
+    }
+}
+
+/// The definitions that differ from the old configuration to the new
+/// one, by kind and then name. Requests are compared field by field and
+/// tests step by step, so a changed assert shows what it expected before
+/// and after.
+pub fn differences(old: &Config, new: &Config) -> Result<Vec<Difference>> {
+    let mut differences = vec![];
+    differences.extend(section("context", &old.contexts, &new.contexts, fields)?);
+    differences.extend(section("request", &old.requests, &new.requests, fields)?);
+    differences.extend(section("test", &old.tests, &new.tests, test_details)?);
+    differences.extend(section("suite", &old.suites, &new.suites, fields)?);
+    differences.extend(section("flow", &old.flows, &new.flows, fields)?);
+    differences.extend(section("mock", &old.mocks, &new.mocks, fields)?);
+    differences.extend(section(
+        "benchmark",
+        &old.benchmarks,
+        &new.benchmarks,
+        fields,
+    )?);
+    Ok(differences)
+}
+
+/// The differences between the definitions of one kind, described by
+/// details when changed.
+fn section<T: Serialize>(
+    kind: &str,
+    old: &HashMap<String, T>,
+    new: &HashMap<String, T>,
+    details: impl Fn(&T, &T) -> Result<Vec<String>>,
+) -> Result<Vec<Difference>> {
+    let mut differences = vec![];
+    for name in changed(old, new)? {
+        let (change, details) = match (old.get(&name), new.get(&name)) {
+            (Some(o), Some(n)) => (Change::Changed, details(o, n)?),
+            (Some(_), None) => (Change::Removed, vec![]),
+            _ => (Change::Added, vec![]),
+        };
+        differences.push(Difference {
+            kind: kind.into(),
+            name,
+            change,
+            details,
+        });
+    }
+    Ok(differences)
+}
+
+/// The top level fields that differ, with their old and new values.
+fn fields<T: Serialize>(old: &T, new: &T) -> Result<Vec<String>> {
+    Ok(mapping_fields(
+        &serde_yaml::to_value(old)?,
+        &serde_yaml::to_value(new)?,
+        "",
+    ))
+}
+
+fn mapping_fields(old: &Value, new: &Value, prefix: &str) -> Vec<String> {
+    let (old, new) = match (old.as_mapping(), new.as_mapping()) {
+        (Some(o), Some(n)) => (o, n),
+        _ => return vec![format!("{}{} -> {}", prefix, text(old), text(new))],
+    };
+    let keys = old
+        .keys()
+        .chain(new.keys())
+        .filter_map(|k| Some((k.as_str()?, k)))
+        .collect::<BTreeMap<_, _>>();
+    let mut details = vec![];
+    for (name, key) in keys {
+        match (old.get(key), new.get(key)) {
+            (Some(o), Some(n)) if o != n => {
+                details.push(format!("{}{}: {} -> {}", prefix, name, text(o), text(n)))
+            }
+            (Some(o), None) => details.push(format!("{}{}: removed {}", prefix, name, text(o))),
+            (None, Some(n)) => details.push(format!("{}{}: added {}", prefix, name, text(n))),
+            _ => {}
+        }
+    }
+    details
+}
+
+/// The value on one line, shortened if it's long.
+fn text(value: &Value) -> String {
+    diff::truncate(&serde_json::to_string(value).unwrap_or_default())
+}
+
+/// The fields, steps and asserts that differ between two versions of a
+/// test. Steps are matched by name and asserts by position.
+fn test_details(old: &Test, new: &Test) -> Result<Vec<String>> {
+    let without_steps = |t: &Test| Test {
+        steps: vec![],
+        ..t.clone()
+    };
+    let mut details = fields(&without_steps(old), &without_steps(new))?;
+
+    for step in &old.steps {
+        if !new.steps.iter().any(|s| s.name == step.name) {
+            details.push(format!("step '{}': removed", step.name));
+        }
+    }
+    for step in &new.steps {
+        let old = match old.steps.iter().find(|s| s.name == step.name) {
+            Some(s) => s,
+            None => {
+                details.push(format!("step '{}': added", step.name));
+                continue;
+            }
+        };
+        let prefix = format!("step '{}': ", step.name);
+        let mut o = serde_yaml::to_value(old)?;
+        let mut n = serde_yaml::to_value(step)?;
+        for value in [&mut o, &mut n] {
+            if let Value::Mapping(m) = value {
+                m.remove("asserts");
+            }
+        }
+        details.extend(mapping_fields(&o, &n, &prefix));
+
+        for i in 0..old.asserts.len().max(step.asserts.len()) {
+            let (o, n) = (old.asserts.get(i), step.asserts.get(i));
+            let same = match (o, n) {
+                (Some(o), Some(n)) => serde_yaml::to_value(o)? == serde_yaml::to_value(n)?,
+                _ => false,
+            };
+            match (o, n) {
+                _ if same => {}
+                (Some(o), Some(n)) => details.push(format!("{}assert {} -> {}", prefix, o, n)),
+                (Some(o), None) => details.push(format!("{}assert removed {}", prefix, o)),
+                (None, Some(n)) => details.push(format!("{}assert added {}", prefix, n)),
+                (None, None) => {}
+            }
+        }
+    }
+    Ok(details)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changed_definitions() {
+        let old: HashMap<String, Value> =
+            serde_yaml::from_str("{a: {url: x}, b: {url: y}, c: {url: z}}").unwrap();
+        let new: HashMap<String, Value> =
+            serde_yaml::from_str("{a: {url: x}, b: {url: changed}, d: {url: z}}").unwrap();
+        assert_eq!(
+            changed(&old, &new).unwrap().into_iter().collect::<Vec<_>>(),
+            vec!["b", "c", "d"]
+        );
+        assert!(same(&old["a"], &new["a"]));
+        assert!(is_yaml("tests/users.yml"));
+    }
+
+    #[test]
+    fn config_differences() {
+        let config = |yaml: &str| -> Config { serde_yaml::from_str(yaml).unwrap() };
+        let old = config(
+            r#"
+requests:
+  get-user: {description: "", tags: [], url: /users/1}
+  delete-user: {description: "", tags: [], url: /users/1, method: DELETE}
+tests:
+  users:
+    description: ""
+    steps:
+      - name: get
+        request: get-user
+        asserts: [{type: status_code, value: 200}]
+"#,
+        );
+        let new = config(
+            r#"
+requests:
+  get-user: {description: "", tags: [], url: /v2/users/1}
+  create-user: {description: "", tags: [], url: /users, method: POST}
+tests:
+  users:
+    description: ""
+    steps:
+      - name: get
+        request: get-user
+        asserts:
+          - {type: status_code, value: 201}
+          - {type: equals, key: $.id, value: "1"}
+"#,
+        );
+        let lines = differences(&old, &new)
+            .unwrap()
+            .iter()
+            .map(|d| d.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            vec![
+                "+ request create-user",
+                "- request delete-user",
+                "~ request get-user\n    url: \"/users/1\" -> \"/v2/users/1\"",
+                "~ test users\n    step 'get': assert status_code == 200 -> status_code == 201\n    step 'get': assert added equals($.id, 1)",
+            ]
+        );
+        assert!(differences(&new, &new).unwrap().is_empty());
+    }
+}
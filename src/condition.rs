@@ -0,0 +1,113 @@
+/// Condition is used to evaluate the simple expressions used to decide
+/// whether steps run. Templates are applied before evaluating, so
+/// expressions compare plain values like `201 == 201`.
+use thiserror::Error;
+
+/// ConditionError is the error type for conditions.
+#[derive(Error, Debug, PartialEq)]
+pub enum ConditionError {
+    #[error("missing value in condition: {0}")]
+    MissingValue(String),
+}
+
+/// Result is the result type for conditions.
+pub type Result<T> = std::result::Result<T, ConditionError>;
+
+const OPERATORS: &[&str] = &["==", "!=", "<=", ">=", "<", ">"];
+
+/// Evaluate the condition. Conditions are comparisons (`==`, `!=`,
+/// `<`, `<=`, `>`, `>=`) joined with `&&` and `||`, where `&&` binds
+/// tighter. Values are compared as numbers when both sides are numbers
+/// and as strings otherwise. A value on its own is true unless it is
+/// empty, `false`, or `0`.
+pub fn evaluate(condition: &str) -> Result<bool> {
+    for any in condition.split("||") {
+        let mut all = true;
+        for term in any.split("&&") {
+            if !term_is_true(term)? {
+                all = false;
+                break;
+            }
+        }
+        if all {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+fn term_is_true(term: &str) -> Result<bool> {
+    let term = term.trim();
+    let (term, negate) = match term.strip_prefix('!') {
+        Some(t) if !t.starts_with('=') => (t.trim(), true),
+        _ => (term, false),
+    };
+
+    for op in OPERATORS {
+        if let Some((left, right)) = term.split_once(op) {
+            let (left, right) = (unquote(left), unquote(right));
+            if left.is_empty() && right.is_empty() {
+                return Err(ConditionError::MissingValue(term.to_string()));
+            }
+            let ordering = match (left.parse::<f64>(), right.parse::<f64>()) {
+                (Ok(l), Ok(r)) => l.partial_cmp(&r),
+                _ => Some(left.cmp(right)),
+            };
+            let result = match (*op, ordering) {
+                ("==", Some(o)) => o.is_eq(),
+                ("!=", Some(o)) => o.is_ne(),
+                ("<", Some(o)) => o.is_lt(),
+                ("<=", Some(o)) => o.is_le(),
+                (">", Some(o)) => o.is_gt(),
+                (">=", Some(o)) => o.is_ge(),
+                _ => false,
+            };
+            return Ok(result != negate);
+        }
+    }
+
+    let value = unquote(term);
+    let truthy = !(value.is_empty() || value == "false" || value == "0");
+    Ok(truthy != negate)
+}
+
+fn unquote(s: &str) -> &str {
+    let s = s.trim();
+    for q in ['"', '\''] {
+        if s.len() >= 2 && s.starts_with(q) && s.ends_with(q) {
+            return &s[1..s.len() - 1];
+        }
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evaluate_conditions() {
+        let tests = vec![
+            ("201 == 201", true),
+            ("201 == 200", false),
+            ("201 != 200", true),
+            ("10 > 9", true),
+            ("10 >= 10.0", true),
+            ("'b' < 'a'", false),
+            ("\"hello world\" == hello world", true),
+            ("true", true),
+            ("false", false),
+            ("0", false),
+            ("", false),
+            ("!false", true),
+            ("!1 == 1", false),
+            ("1 == 1 && 2 == 3", false),
+            ("1 == 2 || 2 == 2", true),
+            ("1 == 2 || 2 == 2 && 3 == 4", false),
+        ];
+        for (condition, expected) in tests {
+            assert_eq!(evaluate(condition), Ok(expected), "{}", condition);
+        }
+        assert!(evaluate(" == ").is_err());
+    }
+}
@@ -1,9 +1,16 @@
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use crate::{Request, Response, Test};
+use crate::request::Body;
+use crate::{
+    Applicator, Flow, Limit, Limiter, Mock, Notification, Profile, Redaction, Request, Response,
+    Scenario, Schema, Suite, Template, Templates, Test, Tls,
+};
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
 use thiserror::Error;
 use walkdir::WalkDir;
 
@@ -21,19 +28,293 @@ pub enum Error {
 
     #[error("context not found: {0}")]
     ContextNotFound(String),
+
+    #[error("request '{0}': {1}")]
+    Request(String, serde_yaml::Error),
+
+    #[error("extends error: {0}")]
+    Extends(String),
+
+    #[error("can't decrypt {0}: {1}")]
+    Decrypt(String, crate::SopsError),
+}
+
+/// A context is the set of variables used when applying requests. It
+/// can also add default headers to every request.
+#[derive(Clone, Default, Debug, Deserialize, Serialize, PartialEq, JsonSchema)]
+#[serde(try_from = "ContextDefinition", into = "ContextDefinition")]
+pub struct Context {
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub default_headers: HashMap<String, String>,
+    /// Variables that override the context for some requests.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scopes: Vec<Scope>,
+    /// The variables as text. Maps are also added by their dotted paths,
+    /// like `service.auth.token`, and maps and lists are written as
+    /// JSON.
+    #[serde(flatten)]
+    pub variables: HashMap<String, String>,
+    /// The variables that weren't strings, so they can keep their type
+    /// in JSON.
+    #[serde(skip)]
+    pub values: HashMap<String, serde_json::Value>,
+}
+
+/// A context as it's written, where variables can be any YAML value.
+/// The settings are read as any value too, so a variable that uses a
+/// setting's name gets an error that says so.
+#[derive(Deserialize, Serialize, JsonSchema)]
+struct ContextDefinition {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<HashMap<String, String>>")]
+    default_headers: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<Vec<Scope>>")]
+    scopes: Option<Value>,
+    #[serde(flatten)]
+    #[schemars(with = "HashMap<String, serde_json::Value>")]
+    variables: HashMap<String, Value>,
+}
+
+/// Read the context setting, explaining that the name is reserved when
+/// it doesn't have the setting's shape.
+fn setting<T: serde::de::DeserializeOwned + Default>(
+    name: &str,
+    value: Option<Value>,
+) -> std::result::Result<T, String> {
+    value.map_or(Ok(T::default()), |value| {
+        serde_yaml::from_value(value).map_err(|e| {
+            format!(
+                "'{}' is a context setting and can't be a variable: {}",
+                name, e
+            )
+        })
+    })
+}
+
+impl TryFrom<ContextDefinition> for Context {
+    type Error = String;
+
+    fn try_from(definition: ContextDefinition) -> std::result::Result<Self, String> {
+        let mut context = Context {
+            default_headers: setting("default_headers", definition.default_headers)?,
+            scopes: setting("scopes", definition.scopes)?,
+            ..Default::default()
+        };
+        for (name, value) in definition.variables {
+            let value = serde_json::to_value(value)
+                .map_err(|e| format!("context variable '{}': {}", name, e))?;
+            context.add(name, value);
+        }
+        Ok(context)
+    }
+}
+
+impl From<Context> for ContextDefinition {
+    /// The context as it was written: maps are written as maps again
+    /// instead of their dotted paths, and values keep their type.
+    fn from(context: Context) -> Self {
+        let maps = context
+            .values
+            .iter()
+            .filter(|(_, v)| v.is_object())
+            .map(|(k, _)| format!("{}.", k))
+            .collect::<Vec<_>>();
+        let variables = context
+            .variables
+            .into_iter()
+            .filter(|(name, _)| !maps.iter().any(|m| name.starts_with(m.as_str())))
+            .map(|(name, text)| {
+                let value = match context.values.get(&name) {
+                    Some(value) => serde_yaml::to_value(value).unwrap_or(Value::String(text)),
+                    None => Value::String(text),
+                };
+                (name, value)
+            })
+            .collect();
+        let setting = |empty: bool, value: serde_yaml::Result<Value>| match empty {
+            true => None,
+            false => value.ok(),
+        };
+        Self {
+            default_headers: setting(
+                context.default_headers.is_empty(),
+                serde_yaml::to_value(&context.default_headers),
+            ),
+            scopes: setting(
+                context.scopes.is_empty(),
+                serde_yaml::to_value(&context.scopes),
+            ),
+            variables,
+        }
+    }
+}
+
+/// A scope overrides variables for the requests with one of its tags
+/// and whose URL matches its pattern, like a different token for one
+/// service. Either can be left out.
+#[derive(Clone, Default, Debug, Deserialize, Serialize, PartialEq, JsonSchema)]
+pub struct Scope {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// A regex matched against the URL once the context is applied.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<String>")]
+    pub url: Option<UrlPattern>,
+    pub variables: HashMap<String, String>,
+}
+
+impl Scope {
+    /// Whether the scope applies to a request with the tags and URL.
+    pub fn matches(&self, tags: &[String], url: &str) -> bool {
+        let tagged = self.tags.is_empty() || self.tags.iter().any(|t| tags.contains(t));
+        tagged
+            && match &self.url {
+                None => true,
+                Some(pattern) => pattern.0.is_match(url),
+            }
+    }
+}
+
+/// A scope's URL regex. It's compiled when the config is loaded, so an
+/// invalid pattern is a config error rather than a scope that never
+/// matches.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct UrlPattern(regex::Regex);
+
+impl UrlPattern {
+    pub fn new(pattern: &str) -> std::result::Result<Self, regex::Error> {
+        regex::Regex::new(pattern).map(Self)
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl PartialEq for UrlPattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl TryFrom<String> for UrlPattern {
+    type Error = regex::Error;
+
+    fn try_from(s: String) -> std::result::Result<Self, regex::Error> {
+        Self::new(&s)
+    }
+}
+
+impl From<UrlPattern> for String {
+    fn from(pattern: UrlPattern) -> Self {
+        pattern.as_str().to_string()
+    }
+}
+
+impl Context {
+    /// Add the variable and, for maps, each of its fields by path.
+    fn add(&mut self, name: String, value: serde_json::Value) {
+        if let serde_json::Value::Object(fields) = &value {
+            for (key, field) in fields {
+                self.add(format!("{}.{}", name, key), field.clone());
+            }
+        }
+        self.variables.insert(name.clone(), text(&value));
+        if !value.is_string() {
+            self.values.insert(name, value);
+        }
+    }
+}
+
+/// The text of a context variable's value.
+fn text(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        serde_json::Value::Null => String::new(),
+        _ => value.to_string(),
+    }
+}
+
+/// Correlation is a header sent with every request in a run and the ID
+/// it's set to, so the requests can be found in the server's logs.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Correlation {
+    pub header: String,
+    pub id: String,
 }
 
 /// The configuration for the CLI.
-#[derive(Clone, Default, Debug, Deserialize, Serialize)]
+#[derive(Clone, Default, Debug, Deserialize, Serialize, JsonSchema)]
 pub struct Config {
+    /// Headers added to every request that doesn't set them.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub default_headers: HashMap<String, String>,
     #[serde(default, skip_serializing_if = "HashMap::is_empty")]
-    pub contexts: HashMap<String, HashMap<String, String>>,
+    pub contexts: HashMap<String, Context>,
     #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub requests: HashMap<String, Request>,
+    /// The loaded responses, shared with every applicator.
     #[serde(default, skip_serializing_if = "HashMap::is_empty")]
-    pub responses: HashMap<String, Response>,
+    #[schemars(skip)]
+    pub responses: Arc<HashMap<String, Response>>,
     #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub tests: HashMap<String, Test>,
+    /// Named groups of tests that run together.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub suites: HashMap<String, Suite>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub mocks: HashMap<String, Mock>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub flows: HashMap<String, Flow>,
+    /// The most requests made to a host, by `host` or `host:port`,
+    /// shared by every request in a run.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub rate_limits: HashMap<String, Limit>,
+    /// Named mixes of requests for `benchmark`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub benchmarks: HashMap<String, Scenario>,
+    /// Addresses to use for hosts in every request, as
+    /// `host:port:addr`. Requests can override them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub resolve: Vec<String>,
+    /// Webhooks called when `tests run` or `monitor` find failures.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notifications: Vec<Notification>,
+    /// What to mask when printing and saving.
+    #[serde(default)]
+    pub redact: Redaction,
+    /// Make variables that can't be resolved an error instead of
+    /// replacing them with nothing.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub strict_templates: bool,
+    /// The contexts to use when a command isn't given any.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub default_contexts: Vec<String>,
+    /// The proxy every request is sent through, like
+    /// http://proxy:3128.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+    /// How servers' certificates are verified.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<Tls>,
+    /// The responses cached by any run.
+    #[serde(skip)]
+    pub cached: Arc<HashMap<String, Response>>,
+    /// The templates parsed so far, shared by every applicator.
+    #[serde(skip)]
+    pub templates: Templates,
+    /// The scopes of the contexts in use.
+    #[serde(skip)]
+    pub scopes: Vec<Scope>,
+    /// The variables of the contexts in use that weren't strings, so
+    /// they keep their type in JSON.
+    #[serde(skip)]
+    pub types: HashMap<String, serde_json::Value>,
+    /// The correlation header added to every request, if any.
+    #[serde(skip)]
+    pub correlation: Option<Correlation>,
 }
 
 /// Result is a convenience type for config errors.
@@ -41,75 +322,776 @@ type Result<T> = std::result::Result<T, Error>;
 
 impl Config {
     pub fn new(path: &str) -> Result<Self> {
-        let contents = std::fs::read_to_string(path)?;
-        Ok(serde_yaml::from_str(&contents)?)
-    }
-
-    pub fn new_from_path(path: &PathBuf) -> Result<Self> {
-        let mut cfg: Config = Config::default();
-        // Loop through the path and only parse yaml files.
-        for entry in WalkDir::new(path).follow_links(true) {
-            let entry = entry.map_err(|e| Error::Path(e.to_string()))?;
-            if entry.file_type().is_file() {
-                let path = entry.path();
-                if let Some(ext) = path.extension() {
-                    if ext == "yaml" || ext == "yml" {
-                        let c = Config::new(
-                            path.to_str().ok_or(Error::Path("non-ascii path".into()))?,
-                        )?;
-                        cfg.merge(c);
-                    }
-                }
+        let documents = read_documents(Path::new(path))?;
+        Config::from_documents(
+            documents
+                .into_iter()
+                .map(|d| (PathBuf::from(path), d))
+                .collect(),
+        )
+    }
+
+    pub fn new_from_path(path: &Path) -> Result<Self> {
+        let mut documents = vec![];
+        for path in yaml_files(path)? {
+            for document in read_documents(&path)? {
+                documents.push((path.clone(), document));
             }
         }
-        Ok(cfg)
+        Config::from_documents(documents)
     }
 
-    pub fn load_responses(&mut self, path: &PathBuf) -> Result<()> {
-        for entry in WalkDir::new(path).follow_links(true) {
-            let entry = entry.map_err(|e| Error::Path(e.to_string()))?;
-            if entry.file_type().is_file() {
-                let path = entry.path();
-                if let Some(ext) = path.extension() {
-                    if ext == "yaml" || ext == "yml" {
-                        // Get the basename without extension.
-                        let name = path
-                            .file_stem()
-                            .ok_or(Error::Path("non-ascii path".into()))?
-                            .to_str()
-                            .ok_or(Error::Path("non-ascii path".into()))?
-                            .to_string();
-                        let contents = std::fs::read_to_string(path)?;
-                        self.responses
-                            .insert(name, serde_yaml::from_str(&contents)?);
+    /// Merge the parsed files into a config. Requests are resolved
+    /// after all the files are merged so they can extend requests in
+    /// other files.
+    pub(crate) fn from_documents(documents: Vec<(PathBuf, Value)>) -> Result<Self> {
+        let mut cfg = Config::default();
+        let mut requests = Mapping::new();
+        let mut sources = HashMap::new();
+        for (path, mut document) in documents {
+            if let Some(Value::Mapping(r)) =
+                document.as_mapping_mut().and_then(|m| m.remove("requests"))
+            {
+                for (name, request) in r {
+                    if let Some(n) = name.as_str() {
+                        sources.insert(n.to_string(), path.clone());
                     }
+                    requests.insert(name, request);
                 }
             }
+            cfg.merge(serde_yaml::from_value(document)?);
+        }
+
+        let limiter = match cfg.rate_limits.is_empty() {
+            true => None,
+            false => Some(Arc::new(Limiter::new(cfg.rate_limits.clone()))),
+        };
+        for name in requests.keys() {
+            let name = name.as_str().ok_or_else(|| {
+                Error::Extends(format!("request name is not a string: {:?}", name))
+            })?;
+            let value = resolve_extends(&requests, name, &mut vec![])?;
+            let mut request: Request =
+                serde_yaml::from_value(value).map_err(|e| Error::Request(name.to_string(), e))?;
+            request.source = sources.get(name).cloned();
+            request.extends = requests
+                .get(name)
+                .and_then(|r| r.get("extends"))
+                .and_then(Value::as_str)
+                .map(String::from);
+            request.add_resolve(&cfg.resolve);
+            request.limiter = limiter.clone();
+            request.proxy = cfg.proxy.clone();
+            request.tls = cfg.tls.clone();
+            cfg.requests.insert(name.to_string(), request);
         }
+        Ok(cfg)
+    }
+
+    /// Load the cached responses in the path and its subfolders.
+    pub fn load_responses(&mut self, path: &Path) -> Result<()> {
+        Arc::make_mut(&mut self.responses).extend(read_responses(path)?);
         Ok(())
     }
 
+    /// Load the cached responses in the path and its subfolders to be
+    /// used by `${cached.*}` templates.
+    pub fn load_cached(&mut self, path: &Path) -> Result<()> {
+        Arc::make_mut(&mut self.cached).extend(read_responses(path)?);
+        Ok(())
+    }
+
+    /// Create an applicator for the context and the loaded responses.
+    /// The responses are shared rather than copied.
+    /// Variables that came from typed context values keep their type
+    /// in JSON unless they were overridden.
+    pub fn applicator(&self, context: HashMap<String, String>) -> Applicator {
+        // Variables set to something else, like with --var, are text.
+        let types = self
+            .types
+            .iter()
+            .filter(|(k, v)| context.get(*k) == Some(&text(v)))
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect::<HashMap<_, _>>();
+        Applicator::new(context, self.responses.clone())
+            .with_types(types)
+            .with_scopes(self.scopes.clone())
+            .with_cached(self.cached.clone())
+            .with_templates(self.templates.clone())
+            .with_strict(self.strict_templates)
+    }
+
+    /// The part of the config needed to run the tests in the contexts:
+    /// the tests, the requests they make and the contexts, along with
+    /// the settings that apply to every request.
+    pub fn subset(&self, tests: &[String], contexts: &[String]) -> Config {
+        let contexts = self.or_default(contexts);
+        let tests = self
+            .tests
+            .iter()
+            .filter(|(name, _)| tests.contains(name))
+            .map(|(name, test)| (name.clone(), test.clone()))
+            .collect::<HashMap<_, _>>();
+        let requests = self
+            .requests
+            .iter()
+            .filter(|(name, _)| {
+                tests
+                    .values()
+                    .any(|t| t.steps.iter().any(|s| &s.request == *name))
+            })
+            .map(|(name, request)| (name.clone(), request.clone()))
+            .collect();
+        Config {
+            default_headers: self.default_headers.clone(),
+            contexts: self
+                .contexts
+                .iter()
+                .filter(|(name, _)| contexts.contains(name))
+                .map(|(name, context)| (name.clone(), context.clone()))
+                .collect(),
+            requests,
+            tests,
+            resolve: self.resolve.clone(),
+            redact: self.redact.clone(),
+            strict_templates: self.strict_templates,
+            default_contexts: self.default_contexts.clone(),
+            proxy: self.proxy.clone(),
+            tls: self.tls.clone(),
+            correlation: self.correlation.clone(),
+            ..Default::default()
+        }
+    }
+
     pub fn merge(&mut self, other: Config) {
+        self.default_headers.extend(other.default_headers);
         self.contexts.extend(other.contexts);
+        self.resolve.extend(other.resolve);
+        self.notifications.extend(other.notifications);
         self.requests.extend(other.requests);
-        self.responses.extend(other.responses);
+        Arc::make_mut(&mut self.responses)
+            .extend(other.responses.iter().map(|(k, v)| (k.clone(), v.clone())));
         self.tests.extend(other.tests);
+        self.suites.extend(other.suites);
+        self.mocks.extend(other.mocks);
+        self.flows.extend(other.flows);
+        self.benchmarks.extend(other.benchmarks);
+        self.rate_limits.extend(other.rate_limits);
+        self.strict_templates |= other.strict_templates;
+        if !other.default_contexts.is_empty() {
+            self.default_contexts = other.default_contexts;
+        }
+        if other.proxy.is_some() {
+            self.proxy = other.proxy;
+        }
+        if other.tls.is_some() {
+            self.tls = other.tls;
+        }
+        if other.redact != Redaction::default() {
+            self.redact = other.redact;
+        }
+    }
+
+    /// Check the configuration for problems that would only be found
+    /// when running it. Each problem is described by a message. GraphQL
+    /// queries are validated against schemas cached in the cache dir.
+    /// Relative URLs need a base_url in the given context or, if none
+    /// is given, in at least one of the contexts. With strict_templates,
+    /// the variables requests use must be in the context, or in one of
+    /// the contexts if none is given.
+    pub fn validate(
+        &self,
+        cache_dir: &Path,
+        context: Option<&HashMap<String, String>>,
+    ) -> Vec<String> {
+        let mut problems = vec![];
+
+        let has_base_url = match context {
+            Some(c) => c.contains_key("base_url"),
+            None => self
+                .contexts
+                .values()
+                .any(|c| c.variables.contains_key("base_url")),
+        };
+        if !has_base_url {
+            for (name, request) in &self.requests {
+                if crate::request::is_relative_url(&request.url) && request.unix_socket.is_none() {
+                    problems.push(format!(
+                        "request '{}': relative url '{}' needs a base_url in the context",
+                        name, request.url
+                    ));
+                }
+            }
+        }
+
+        if self.strict_templates {
+            let variables = match context {
+                Some(c) => c.clone(),
+                None => self
+                    .contexts
+                    .values()
+                    .flat_map(|c| c.variables.clone())
+                    .collect(),
+            };
+            // Responses aren't known until the requests are run, and
+            // benchmarks set their own variables for each iteration.
+            let scopes = self
+                .contexts
+                .values()
+                .flat_map(|c| c.scopes.clone())
+                .collect();
+            let app = Applicator::new(variables, HashMap::new()).with_scopes(scopes);
+            for (name, request) in &self.requests {
+                for unresolved in request.unresolved(&app) {
+                    let later = ["response.", "cached.", "bench.", "data."];
+                    if !later.iter().any(|p| unresolved.starts_with(p)) {
+                        problems.push(format!(
+                            "request '{}': unresolved variable {}",
+                            name, unresolved
+                        ));
+                    }
+                }
+            }
+        }
+
+        for (name, test) in &self.tests {
+            for step in &test.steps {
+                if !self.requests.contains_key(&step.request) {
+                    problems.push(format!(
+                        "test '{}' step '{}': request not found: {}",
+                        name, step.name, step.request
+                    ));
+                }
+            }
+        }
+
+        for (name, suite) in &self.suites {
+            for test in suite.tests.iter().filter(|t| !self.tests.contains_key(*t)) {
+                problems.push(format!("suite '{}': test not found: {}", name, test));
+            }
+            for context in suite
+                .contexts
+                .iter()
+                .filter(|c| !self.contexts.contains_key(*c))
+            {
+                problems.push(format!("suite '{}': context not found: {}", name, context));
+            }
+            for request in suite
+                .before
+                .iter()
+                .chain(&suite.after)
+                .filter(|r| !self.requests.contains_key(*r))
+            {
+                problems.push(format!("suite '{}': request not found: {}", name, request));
+            }
+            if suite.parallel == 0 {
+                problems.push(format!("suite '{}': parallel must be at least 1", name));
+            }
+        }
+
+        for (name, flow) in &self.flows {
+            for step in &flow.steps {
+                if !self.requests.contains_key(&step.request) {
+                    problems.push(format!(
+                        "flow '{}' step '{}': request not found: {}",
+                        name,
+                        step.name(),
+                        step.request
+                    ));
+                }
+            }
+            if let Err(e) = flow.order() {
+                problems.push(format!("flow '{}': {}", name, e));
+            }
+        }
+
+        for (host, limit) in &self.rate_limits {
+            if !limit.is_valid() {
+                problems.push(format!(
+                    "rate limit '{}': requests and per must be more than 0",
+                    host
+                ));
+            }
+        }
+
+        for (name, scenario) in &self.benchmarks {
+            for weighted in &scenario.requests {
+                if !self.requests.contains_key(&weighted.request) {
+                    problems.push(format!(
+                        "benchmark '{}': request not found: {}",
+                        name, weighted.request
+                    ));
+                }
+            }
+            if let Err(e) = scenario.weights() {
+                problems.push(format!("benchmark '{}': {}", name, e));
+            }
+            if let Some(data) = scenario.data.as_ref().filter(|d| !d.file.exists()) {
+                problems.push(format!(
+                    "benchmark '{}': data file not found: {}",
+                    name,
+                    data.file.display()
+                ));
+            }
+        }
+
+        for (name, request) in &self.requests {
+            if let Body::GraphQL { query, schema, .. } = &request.body {
+                let schema_name = schema.as_deref().unwrap_or(name);
+                let path = crate::graphql::schema_path(cache_dir, schema_name);
+                if !path.exists() {
+                    continue;
+                }
+                let result = Schema::load(&path).and_then(|s| s.validate(query));
+                match result {
+                    Ok(p) => problems.extend(
+                        p.into_iter()
+                            .map(|p| format!("request '{}': graphql: {}", name, p)),
+                    ),
+                    Err(e) => problems.push(format!("request '{}': graphql: {}", name, e)),
+                }
+            }
+        }
+
+        problems.sort();
+        problems
     }
 
-    pub fn merge_contexts(&self, names: &[String]) -> Result<HashMap<String, String>> {
+    /// Find what is defined but never used: requests that no test, flow,
+    /// suite, benchmark or other request uses, context variables that no
+    /// template uses and cached responses without a request.
+    pub fn lint(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        // Everything that can have templates, as text.
+        let mut text = serde_yaml::to_string(&(
+            &self.default_headers,
+            &self.requests,
+            &self.tests,
+            &self.flows,
+            &self.mocks,
+            &self.notifications,
+        ))
+        .unwrap_or_default();
+        for context in self.contexts.values() {
+            text.push_str(
+                &serde_yaml::to_string(&(&context.default_headers, &context.scopes))
+                    .unwrap_or_default(),
+            );
+            for value in context.variables.values() {
+                text.push_str(value);
+            }
+        }
+        let template = Template::parse(&text);
+        let variables = template.variables();
+
+        let mut used = self
+            .tests
+            .values()
+            .flat_map(|t| t.steps.iter().map(|s| s.request.as_str()))
+            .chain(
+                self.flows
+                    .values()
+                    .flat_map(|f| f.steps.iter().map(|s| s.request.as_str())),
+            )
+            .chain(
+                self.suites
+                    .values()
+                    .flat_map(|s| s.before.iter().chain(&s.after).map(String::as_str)),
+            )
+            .chain(
+                self.benchmarks
+                    .values()
+                    .flat_map(|b| b.requests.iter().map(|w| w.request.as_str())),
+            )
+            .chain(self.requests.values().filter_map(|r| r.extends.as_deref()))
+            .collect::<std::collections::HashSet<_>>();
+        used.extend(variables.iter().filter_map(|v| {
+            let rest = v
+                .strip_prefix("response.")
+                .or_else(|| v.strip_prefix("cached."))?;
+            rest.split('.').next()
+        }));
+        for name in self.requests.keys().filter(|n| !used.contains(n.as_str())) {
+            problems.push(format!(
+                "request '{}': not used by any test, flow, suite, benchmark or request",
+                name
+            ));
+        }
+
+        let relative = self
+            .requests
+            .values()
+            .any(|r| crate::request::is_relative_url(&r.url) && r.unix_socket.is_none());
+        for (name, context) in &self.contexts {
+            for key in context.variables.keys() {
+                // Maps are also added by their dotted paths, so any part
+                // of the path being used counts.
+                // Relative URLs are joined to the base URL.
+                let used = (key == "base_url" && relative)
+                    || variables.iter().any(|v| {
+                        v == key
+                            || v.strip_prefix(key.as_str())
+                                .is_some_and(|r| r.starts_with('.'))
+                            || key.strip_prefix(*v).is_some_and(|r| r.starts_with('.'))
+                    });
+                if !used {
+                    problems.push(format!(
+                        "context '{}': variable '{}' is not used by any template",
+                        name, key
+                    ));
+                }
+            }
+        }
+
+        for name in self
+            .cached
+            .keys()
+            .filter(|n| !self.requests.contains_key(*n))
+        {
+            problems.push(format!("response '{}': no request has this name", name));
+        }
+
+        problems.sort();
+        problems
+    }
+
+    /// Merge the variables of the contexts, later ones winning. The
+    /// types of those that weren't strings are kept for applicators.
+    pub fn merge_contexts(&mut self, names: &[String]) -> Result<HashMap<String, String>> {
         let mut context: HashMap<String, String> = HashMap::new();
-        for n in names {
+        let mut types = HashMap::new();
+        for n in self.or_default(names) {
             match self.contexts.get(n) {
                 Some(c) => {
-                    context.extend(c.iter().map(|(k, v)| (k.clone(), v.clone())));
+                    context.extend(c.variables.iter().map(|(k, v)| (k.clone(), v.clone())));
+                    for name in c.variables.keys() {
+                        match c.values.get(name) {
+                            Some(value) => types.insert(name.clone(), value.clone()),
+                            None => types.remove(name),
+                        };
+                    }
                 }
                 None => {
                     return Err(Error::ContextNotFound(n.clone()));
                 }
             };
         }
+        self.types = types;
         Ok(context)
     }
+
+    /// The config as it runs in the contexts: the default headers and
+    /// the context are applied to every request. Responses aren't known,
+    /// so `${response.*}` and other variables that can't be resolved are
+    /// kept as written. Only the contexts used are kept, for the
+    /// templates in tests and flows.
+    pub fn render(&self, names: &[String], context: HashMap<String, String>) -> Result<Config> {
+        let mut cfg = self.clone();
+        cfg.responses = Arc::default();
+        cfg.cached = Arc::default();
+        cfg.add_default_headers(names)?;
+        cfg.add_scopes(names)?;
+        let app = cfg.applicator(context).with_unresolved_kept();
+        for request in cfg.requests.values_mut() {
+            request.apply(&app);
+        }
+        let names = self.or_default(names);
+        cfg.default_headers.clear();
+        cfg.contexts.retain(|name, _| names.contains(name));
+        Ok(cfg)
+    }
+
+    /// Use the scopes of the given contexts in applicators. Scopes of
+    /// later contexts win.
+    pub fn add_scopes(&mut self, names: &[String]) -> Result<()> {
+        self.scopes.clear();
+        for n in self.or_default(names).to_vec() {
+            match self.contexts.get(&n) {
+                Some(c) => self.scopes.extend(c.scopes.clone()),
+                None => return Err(Error::ContextNotFound(n)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Send the correlation header with every request that doesn't set
+    /// it already.
+    pub fn add_correlation(&mut self, correlation: Correlation) {
+        for request in self.requests.values_mut() {
+            let header = &correlation.header;
+            if !request
+                .headers
+                .keys()
+                .any(|k| k.eq_ignore_ascii_case(header))
+            {
+                request
+                    .headers
+                    .insert(header.clone(), correlation.id.clone());
+            }
+        }
+        self.correlation = Some(correlation);
+    }
+
+    /// Add the default headers of the config and then the given
+    /// contexts to every request. Headers set on a request win.
+    pub fn add_default_headers(&mut self, names: &[String]) -> Result<()> {
+        let mut headers = self.default_headers.clone();
+        for n in self.or_default(names) {
+            match self.contexts.get(n) {
+                Some(c) => headers.extend(c.default_headers.clone()),
+                None => return Err(Error::ContextNotFound(n.clone())),
+            }
+        }
+        for request in self.requests.values_mut() {
+            for (key, value) in &headers {
+                if !request.headers.keys().any(|k| k.eq_ignore_ascii_case(key)) {
+                    request.headers.insert(key.clone(), value.clone());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The names or, when there aren't any, the default contexts.
+    fn or_default<'a>(&'a self, names: &'a [String]) -> &'a [String] {
+        match names.is_empty() {
+            true => &self.default_contexts,
+            false => names,
+        }
+    }
+
+    /// Use the profile's settings where the configuration doesn't have
+    /// its own.
+    pub fn add_profile(&mut self, profile: &Profile) {
+        if self.default_contexts.is_empty() {
+            self.default_contexts = profile.contexts.clone();
+        }
+        if self.proxy.is_none() {
+            self.proxy = profile.proxy.clone();
+        }
+        if self.tls.is_none() {
+            self.tls = profile.tls.clone();
+        }
+        for request in self.requests.values_mut() {
+            request.proxy = self.proxy.clone();
+            request.tls = self.tls.clone();
+        }
+    }
+}
+
+/// Read the cached responses in the path and its subfolders. When a
+/// name is cached more than once, the most recently saved wins.
+fn read_responses(path: &Path) -> Result<HashMap<String, Response>> {
+    let mut responses = HashMap::new();
+    if !path.exists() {
+        return Ok(responses);
+    }
+    let mut files = vec![];
+    for entry in WalkDir::new(path).follow_links(true) {
+        let entry = entry.map_err(|e| Error::Path(e.to_string()))?;
+        if entry.file_type().is_file() {
+            let path = entry.path();
+            if let Some(ext) = path.extension() {
+                if ext == "yaml" || ext == "yml" {
+                    let modified = entry.metadata().ok().and_then(|m| m.modified().ok());
+                    files.push((modified, path.to_path_buf()));
+                }
+            }
+        }
+    }
+    files.sort();
+
+    for (_, path) in files {
+        // Get the basename without extension.
+        let name = path
+            .file_stem()
+            .ok_or(Error::Path("non-ascii path".into()))?
+            .to_str()
+            .ok_or(Error::Path("non-ascii path".into()))?
+            .to_string();
+        let contents = std::fs::read_to_string(&path)?;
+        responses.insert(name, serde_yaml::from_str(&contents)?);
+    }
+    Ok(responses)
+}
+
+/// The run folders in the responses folder, oldest first by when a
+/// response was last saved in them.
+pub fn runs(responses_dir: &Path) -> Result<Vec<PathBuf>> {
+    let entries = match std::fs::read_dir(responses_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
+    let mut runs = vec![];
+    for entry in entries {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            runs.push((metadata.modified().ok(), entry.path()));
+        }
+    }
+    runs.sort();
+    Ok(runs.into_iter().map(|(_, path)| path).collect())
+}
+
+/// Remove the oldest runs in the responses folder so that only `keep`
+/// are left along with the current one.
+pub fn prune_runs(responses_dir: &Path, keep: usize, current: &Path) -> Result<()> {
+    let mut runs = self::runs(responses_dir)?;
+    runs.retain(|r| r != current);
+    for run in &runs[..runs.len().saturating_sub(keep)] {
+        std::fs::remove_dir_all(run)?;
+    }
+    Ok(())
+}
+
+/// The yaml files in the path and its subfolders, or the path itself
+/// when it's a file.
+pub fn yaml_files(path: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for entry in WalkDir::new(path).follow_links(true) {
+        let entry = entry.map_err(|e| Error::Path(e.to_string()))?;
+        if entry.file_type().is_file() {
+            let path = entry.path();
+            if let Some(ext) = path.extension() {
+                if ext == "yaml" || ext == "yml" {
+                    files.push(path.to_path_buf());
+                }
+            }
+        }
+    }
+    Ok(files)
+}
+
+fn read_documents(path: &Path) -> Result<Vec<Value>> {
+    parse_documents(path, &std::fs::read_to_string(path)?)
+}
+
+/// Parse each document in the YAML from the file, separated by `---`,
+/// with merge keys (`<<: *anchor`) applied. Documents encrypted with
+/// SOPS are decrypted first. Empty documents are left out.
+pub fn parse_documents(file: &Path, contents: &str) -> Result<Vec<Value>> {
+    let mut documents = vec![];
+    for document in serde_yaml::Deserializer::from_str(contents) {
+        let mut value = Value::deserialize(document)?;
+        if crate::sops::is_encrypted(&value) {
+            crate::sops::decrypt(&mut value)
+                .map_err(|e| Error::Decrypt(file.display().to_string(), e))?;
+        }
+        value.apply_merge()?;
+        if !value.is_null() {
+            documents.push(value);
+        }
+    }
+    Ok(documents)
+}
+
+/// Resolve the request with the given name. A request with `extends`
+/// is the request it extends deep-merged with its own fields. Chain is
+/// the requests being resolved, used to find cycles.
+fn resolve_extends(requests: &Mapping, name: &str, chain: &mut Vec<String>) -> Result<Value> {
+    if chain.iter().any(|n| n == name) {
+        chain.push(name.to_string());
+        return Err(Error::Extends(format!("cycle: {}", chain.join(" -> "))));
+    }
+    let mut request = match requests.get(name) {
+        Some(r) => r.clone(),
+        None => {
+            return Err(Error::Extends(format!(
+                "'{}' extends unknown request '{}'",
+                chain.last().map(String::as_str).unwrap_or_default(),
+                name
+            )))
+        }
+    };
+    let parent = match request.as_mapping_mut().and_then(|m| m.remove("extends")) {
+        Some(Value::String(p)) => p,
+        Some(v) => {
+            return Err(Error::Extends(format!(
+                "'{}' extends must be a request name, got {:?}",
+                name, v
+            )))
+        }
+        None => return Ok(request),
+    };
+
+    chain.push(name.to_string());
+    let mut base = resolve_extends(requests, &parent, chain)?;
+    chain.pop();
+    deep_merge(&mut base, request);
+    Ok(base)
+}
+
+/// Merge the overrides into the base. Mappings are merged key by key
+/// and everything else is replaced.
+fn deep_merge(base: &mut Value, overrides: Value) {
+    match (base, overrides) {
+        (Value::Mapping(base), Value::Mapping(overrides)) => {
+            for (key, value) in overrides {
+                match base.get_mut(&key) {
+                    Some(b) => deep_merge(b, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overrides) => *base = overrides,
+    }
+}
+
+/// Find the line (1-based) on which the given entry of a top-level
+/// section (e.g. a request in `requests`) is defined in the file.
+pub fn find_definition(path: &Path, section: &str, name: &str) -> Result<Option<usize>> {
+    let contents = std::fs::read_to_string(path)?;
+    let mut in_section = false;
+    let mut indent = None;
+    for (i, line) in contents.lines().enumerate() {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let depth = line.len() - trimmed.len();
+        if depth == 0 {
+            in_section = trimmed.trim_end() == format!("{}:", section);
+            indent = None;
+            continue;
+        }
+        if !in_section || indent.is_some_and(|d| d != depth) {
+            continue;
+        }
+        indent = Some(depth);
+        let key = trimmed.split(':').next().unwrap_or_default();
+        if key.trim_matches(|c| c == '"' || c == '\'') == name {
+            return Ok(Some(i + 1));
+        }
+    }
+    Ok(None)
+}
+
+/// The JSON Schema of configuration files, generated from the types
+/// they're read into so it can't drift from them. Requests can extend
+/// another request, so none of their fields are required.
+pub fn json_schema() -> serde_json::Value {
+    let mut schema = schemars::schema_for!(Config).to_value();
+    if let Some(request) = schema
+        .pointer_mut("/$defs/Request")
+        .and_then(serde_json::Value::as_object_mut)
+    {
+        request.remove("required");
+        if let Some(serde_json::Value::Object(properties)) = request.get_mut("properties") {
+            properties.insert(
+                "extends".into(),
+                serde_json::json!({
+                    "description": "The request this one is deep-merged onto.",
+                    "type": "string",
+                }),
+            );
+        }
+    }
+    schema
 }
 
 impl std::fmt::Display for Config {
@@ -118,3 +1100,427 @@ impl std::fmt::Display for Config {
         write!(f, "{}", c)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_responses() {
+        let temp = tempfile::tempdir().unwrap();
+        let responses = temp.path().join("responses");
+        for (run, body) in [("first", "old"), ("second", "new")] {
+            let response = Response::new(200, HashMap::new(), body.into());
+            response.save(&responses.join(run), "get").unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+
+        // The latest response of each name wins across runs.
+        let mut cfg = Config::default();
+        cfg.load_responses(&responses).unwrap();
+        assert_eq!(cfg.responses["get"].body, "new");
+        let mut cfg = Config::default();
+        cfg.load_responses(&responses.join("first")).unwrap();
+        assert_eq!(cfg.responses["get"].body, "old");
+
+        let names = |runs: Vec<PathBuf>| {
+            runs.iter()
+                .map(|r| r.file_name().unwrap().to_string_lossy().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(runs(&responses).unwrap()), vec!["first", "second"]);
+        prune_runs(&responses, 1, &responses.join("first")).unwrap();
+        assert_eq!(names(runs(&responses).unwrap()), vec!["first", "second"]);
+        prune_runs(&responses, 1, &responses.join("third")).unwrap();
+        assert_eq!(names(runs(&responses).unwrap()), vec!["second"]);
+        assert!(runs(&temp.path().join("missing")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn find_definition_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("find.yaml");
+        std::fs::write(
+            &path,
+            r#"contexts:
+  local:
+    get-posts: not this one
+requests:
+  # a comment
+  get-posts:
+    url: http://localhost/posts
+    headers:
+      get-user: not this one either
+  "get-user":
+    url: http://localhost/user
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            find_definition(&path, "requests", "get-posts").unwrap(),
+            Some(6)
+        );
+        assert_eq!(
+            find_definition(&path, "requests", "get-user").unwrap(),
+            Some(10)
+        );
+        assert_eq!(find_definition(&path, "requests", "local").unwrap(), None);
+    }
+
+    #[test]
+    fn extends() {
+        let requests: Mapping = serde_yaml::from_str(
+            r#"
+base:
+  description: a user
+  tags: [users]
+  url: ${base_url}/users/1
+  headers:
+    Accept: application/json
+  body:
+    type: raw
+    from:
+      type: text
+      data: hello
+child:
+  extends: base
+  url: ${base_url}/users/2
+  headers:
+    X-Extra: yes
+grandchild:
+  extends: child
+  description: another user
+loop-a:
+  extends: loop-b
+loop-b:
+  extends: loop-a
+orphan:
+  extends: missing
+"#,
+        )
+        .unwrap();
+
+        let request: Request =
+            serde_yaml::from_value(resolve_extends(&requests, "grandchild", &mut vec![]).unwrap())
+                .unwrap();
+        assert_eq!(request.description, "another user");
+        assert_eq!(request.url, "${base_url}/users/2");
+        assert_eq!(request.tags, vec!["users".to_string()]);
+        assert_eq!(request.headers.len(), 2);
+        assert!(matches!(request.body, Body::Raw { .. }));
+
+        assert!(matches!(
+            resolve_extends(&requests, "loop-a", &mut vec![]),
+            Err(Error::Extends(_))
+        ));
+        assert!(matches!(
+            resolve_extends(&requests, "orphan", &mut vec![]),
+            Err(Error::Extends(_))
+        ));
+    }
+
+    #[test]
+    fn default_headers() {
+        let mut cfg: Config = serde_yaml::from_str(
+            r#"
+default_headers:
+  User-Agent: apictl
+  Accept: application/json
+contexts:
+  dev:
+    base_url: https://dev.app
+    default_headers:
+      X-Env: dev
+requests:
+  get-posts:
+    description: get the posts
+    tags: []
+    url: /posts
+    headers:
+      accept: text/plain
+"#,
+        )
+        .unwrap();
+        assert_eq!(cfg.contexts["dev"].variables.len(), 1);
+
+        cfg.add_default_headers(&["dev".into()]).unwrap();
+        let headers = &cfg.requests["get-posts"].headers;
+        assert_eq!(headers.len(), 3);
+        assert_eq!(headers["accept"], "text/plain");
+        assert_eq!(headers["User-Agent"], "apictl");
+        assert_eq!(headers["X-Env"], "dev");
+
+        assert!(cfg.add_default_headers(&["prod".into()]).is_err());
+    }
+
+    #[test]
+    fn typed_contexts() {
+        let mut cfg: Config = serde_yaml::from_str(
+            r#"
+contexts:
+  dev:
+    limit: 10
+    debug: true
+    page: "2"
+    service:
+      auth: {token: abc}
+      hosts: [a, b]
+  prod:
+    page: 2
+"#,
+        )
+        .unwrap();
+        let dev = &cfg.contexts["dev"];
+        assert_eq!(dev.variables["limit"], "10");
+        assert_eq!(dev.variables["service.auth.token"], "abc");
+        assert_eq!(dev.variables["service.hosts"], r#"["a","b"]"#);
+        assert_eq!(dev.values["debug"], serde_json::json!(true));
+        assert!(!dev.values.contains_key("service.auth.token"));
+
+        // Only the contexts in use give variables their types.
+        let mut context = cfg.merge_contexts(&["dev".into()]).unwrap();
+        context.insert("debug".into(), "false".into());
+        let app = cfg.applicator(context);
+        let mut value = serde_json::json!(["${limit}", "${debug}", "${service.auth}", "${page}"]);
+        app.apply_value(&mut value);
+        assert_eq!(
+            value,
+            serde_json::json!([10, "false", {"token": "abc"}, "2"])
+        );
+
+        // Contexts are written as they were defined.
+        let dev = &cfg.contexts["dev"];
+        let written = serde_yaml::to_string(dev).unwrap();
+        assert!(!written.contains("service.auth.token"));
+        let read: Context = serde_yaml::from_str(&written).unwrap();
+        assert_eq!(&read, dev);
+
+        // Settings can't be used as variable names.
+        let headers: Context =
+            serde_yaml::from_str("default_headers: {X-Env: dev}\ntoken: abc").unwrap();
+        assert_eq!(headers.default_headers["X-Env"], "dev");
+        let written = serde_yaml::to_string(&headers).unwrap();
+        assert_eq!(serde_yaml::from_str::<Context>(&written).unwrap(), headers);
+        for reserved in ["default_headers: abc", "scopes: abc"] {
+            let e = serde_yaml::from_str::<Context>(reserved).unwrap_err();
+            assert!(e.to_string().contains("is a context setting"), "{}", e);
+        }
+    }
+
+    #[test]
+    fn scopes() {
+        let mut cfg: Config = serde_yaml::from_str(
+            r#"
+contexts:
+  prod:
+    token: main
+    billing_url: https://billing.prod.app
+    scopes:
+      - tags: [billing]
+        variables: {token: billing}
+      - url: ^https://search\.
+        variables: {token: search}
+requests:
+  invoices:
+    description: ""
+    tags: [billing]
+    url: ${billing_url}/invoices
+    headers: {Authorization: "${token}"}
+  search:
+    description: ""
+    tags: []
+    url: https://search.prod.app/q
+    headers: {Authorization: "${token}"}
+  users:
+    description: ""
+    tags: []
+    url: https://prod.app/users
+    headers: {Authorization: "${token}"}
+"#,
+        )
+        .unwrap();
+        cfg.add_scopes(&["prod".into()]).unwrap();
+        let context = cfg.merge_contexts(&["prod".into()]).unwrap();
+        let app = cfg.applicator(context);
+        for (name, token) in [
+            ("invoices", "billing"),
+            ("search", "search"),
+            ("users", "main"),
+        ] {
+            let mut request = cfg.requests[name].clone();
+            request.apply(&app);
+            assert_eq!(request.headers["Authorization"], token, "{}", name);
+        }
+        assert!(cfg.add_scopes(&["dev".into()]).is_err());
+
+        let invalid =
+            "contexts:\n  prod:\n    scopes:\n      - url: \"(\"\n        variables: {}\n";
+        assert!(serde_yaml::from_str::<Config>(invalid).is_err());
+    }
+
+    #[test]
+    fn render() {
+        let mut cfg: Config = serde_yaml::from_str(
+            r#"
+default_headers:
+  User-Agent: apictl
+contexts:
+  dev:
+    base_url: https://dev.app
+  prod:
+    base_url: https://prod.app
+requests:
+  get-user:
+    description: get a user
+    tags: []
+    url: /users/${response.login.id}
+    headers:
+      Authorization: Bearer ${token}
+"#,
+        )
+        .unwrap();
+        let context = cfg.merge_contexts(&["dev".into()]).unwrap();
+        let rendered = cfg.render(&["dev".into()], context).unwrap();
+        let request = &rendered.requests["get-user"];
+        assert_eq!(request.url, "https://dev.app/users/${response.login.id}");
+        assert_eq!(request.headers["User-Agent"], "apictl");
+        assert_eq!(request.headers["Authorization"], "Bearer ${token}");
+        assert!(rendered.default_headers.is_empty());
+        assert_eq!(rendered.contexts.keys().collect::<Vec<_>>(), vec!["dev"]);
+    }
+
+    #[test]
+    fn lint() {
+        let mut cfg: Config = serde_yaml::from_str(
+            r#"
+contexts:
+  dev:
+    base_url: https://dev.app
+    token: abc
+    unused: x
+    service: {auth: {user: bob}, port: 1}
+requests:
+  login:
+    description: log in
+    tags: []
+    url: /login?user=${service.auth.user}
+  get-user:
+    description: get a user
+    tags: []
+    url: /users
+    headers:
+      Authorization: Bearer ${response.login.token} ${token}
+  orphan:
+    description: not used
+    tags: []
+    url: /orphan
+tests:
+  users:
+    description: ""
+    steps:
+      - {name: get, request: get-user, asserts: []}
+"#,
+        )
+        .unwrap();
+        Arc::make_mut(&mut cfg.cached).insert("deleted".into(), Response::default());
+        assert_eq!(
+            cfg.lint(),
+            vec![
+                "context 'dev': variable 'service.port' is not used by any template",
+                "context 'dev': variable 'unused' is not used by any template",
+                "request 'orphan': not used by any test, flow, suite, benchmark or request",
+                "response 'deleted': no request has this name",
+            ]
+        );
+    }
+
+    #[test]
+    fn strict_templates() {
+        let cfg: Config = serde_yaml::from_str(
+            r#"
+strict_templates: true
+contexts:
+  dev:
+    api_url: ${scheme}://dev.app
+requests:
+  get-user:
+    description: get a user
+    tags: []
+    url: ${api_url}/users/${id}
+    headers:
+      Authorization: Bearer ${response.login.token}
+    variables:
+      scheme: https
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            cfg.validate(Path::new("."), None),
+            vec!["request 'get-user': unresolved variable id in url"]
+        );
+    }
+
+    #[test]
+    fn documents() {
+        let documents = parse_documents(
+            Path::new("documents.yaml"),
+            r#"---
+contexts:
+  dev: &dev
+    base_url: http://dev
+    token: abc
+  staging:
+    <<: *dev
+    base_url: http://staging
+requests:
+  base: &base
+    description: base
+    tags: [users]
+    url: /users
+  get-user:
+    <<: *base
+    url: /users/1
+---
+tests:
+  users:
+    description: users
+    steps:
+      - name: get
+        request: get-user
+        asserts: []
+"#,
+        )
+        .unwrap();
+        assert_eq!(documents.len(), 2);
+
+        let path = PathBuf::from("config.yaml");
+        let cfg =
+            Config::from_documents(documents.into_iter().map(|d| (path.clone(), d)).collect())
+                .unwrap();
+        let staging = &cfg.contexts["staging"].variables;
+        assert_eq!(staging["base_url"], "http://staging");
+        assert_eq!(staging["token"], "abc");
+        let request = &cfg.requests["get-user"];
+        assert_eq!(request.url, "/users/1");
+        assert_eq!(request.tags, vec!["users"]);
+        assert!(cfg.tests.contains_key("users"));
+    }
+
+    #[test]
+    fn schema() {
+        let schema = json_schema();
+        for section in ["contexts", "requests", "tests", "suites", "flows"] {
+            assert!(schema["properties"][section].is_object(), "{}", section);
+        }
+        let request = &schema["$defs"]["Request"];
+        assert!(request.get("required").is_none());
+        assert_eq!(request["properties"]["extends"]["type"], "string");
+        assert_eq!(request["properties"]["url"]["type"], "string");
+        assert!(schema["$defs"]["Assert"]["oneOf"].is_array());
+
+        // Durations are written as text, like 2s.
+        let step = &schema["$defs"]["Step"]["properties"]["max_duration"];
+        assert_eq!(step["type"], serde_json::json!(["string", "null"]));
+    }
+}
@@ -0,0 +1,348 @@
+/// Diagnostics are the problems in a configuration with the file and
+/// line they're on, so editors can show them while the YAML is written.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::config::{self, find_definition, yaml_files};
+use crate::{Config, Profile};
+
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+
+/// Result is the result type for diagnostics. Only a configuration that
+/// can't be read is an error; its problems are diagnostics.
+pub type Result<T> = std::result::Result<T, config::Error>;
+
+/// Severity is how bad a problem is. Only errors fail a check.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// Diagnostic is a problem in a configuration file. Lines and columns
+/// start at 1 and are missing when the problem can't be placed.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub file: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<usize>,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(file: &Path, severity: Severity, message: String) -> Self {
+        Self {
+            file: file.to_path_buf(),
+            line: None,
+            column: None,
+            severity,
+            message,
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.file.display())?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+        }
+        if let Some(column) = self.column {
+            write!(f, ":{}", column)?;
+        }
+        write!(f, ": {}: {}", self.severity, self.message)
+    }
+}
+
+/// The sections of the configuration that problems name their
+/// definitions by, like `test 'login'`.
+const SECTIONS: [(&str, &str); 7] = [
+    ("request", "requests"),
+    ("test", "tests"),
+    ("suite", "suites"),
+    ("flow", "flows"),
+    ("benchmark", "benchmarks"),
+    ("context", "contexts"),
+    ("rate limit", "rate_limits"),
+];
+
+/// Check the configuration at the path, a file or folder, for the
+/// problems `config validate` finds, plus files that can't be parsed
+/// and definitions that don't match their schema. Unresolved variables
+/// are warnings unless templates are strict. The profile is merged
+/// beneath the configuration, like when it runs.
+pub fn diagnose(
+    path: &Path,
+    cache_dir: &Path,
+    contexts: &[String],
+    strict_templates: bool,
+    profile: &Profile,
+) -> Result<Vec<Diagnostic>> {
+    let files = yaml_files(path)?;
+    let mut diagnostics = vec![];
+
+    // Each document is parsed on its own so errors point into it.
+    // Documents with merge keys and requests that extend another are
+    // only complete once they're merged, so they're checked then.
+    for file in &files {
+        let contents = std::fs::read_to_string(file)?;
+        let documents = serde_yaml::Deserializer::from_str(&contents)
+            .map(Config::deserialize)
+            .zip(serde_yaml::Deserializer::from_str(&contents).map(Value::deserialize));
+        for (typed, value) in documents {
+            let (e, parsed) = match (typed, value) {
+                (_, Err(e)) => (e, false),
+                (Ok(_), Ok(_)) => continue,
+                (Err(e), Ok(value)) => {
+                    let extends = value
+                        .get("requests")
+                        .and_then(Value::as_mapping)
+                        .is_some_and(|r| r.values().any(|r| r.get("extends").is_some()));
+                    if has_merge_keys(&value) || (extends && e.to_string().starts_with("requests."))
+                    {
+                        continue;
+                    }
+                    (e, true)
+                }
+            };
+            let mut diagnostic = Diagnostic::new(file, Severity::Error, message(&e));
+            if let Some(location) = e.location() {
+                diagnostic.line = Some(location.line());
+                diagnostic.column = Some(location.column());
+            }
+            diagnostics.push(diagnostic);
+
+            // The rest of a file that isn't valid YAML can't be read.
+            if !parsed {
+                break;
+            }
+        }
+    }
+    if !diagnostics.is_empty() {
+        return Ok(diagnostics);
+    }
+
+    let mut cfg = match Config::new_from_path(path) {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            let message = e.to_string();
+            let mut diagnostic = Diagnostic::new(path, Severity::Error, message.clone());
+            match &e {
+                config::Error::Request(name, _) => {
+                    locate(&mut diagnostic, &files, "requests", name)
+                }
+                config::Error::Decrypt(file, _) => diagnostic.file = PathBuf::from(file),
+                _ => {}
+            }
+            return Ok(vec![diagnostic]);
+        }
+    };
+    cfg.add_profile(profile);
+    let context = match contexts.is_empty() && cfg.default_contexts.is_empty() {
+        true => None,
+        false => match cfg.merge_contexts(contexts) {
+            Ok(context) => Some(context),
+            Err(e) => return Ok(vec![Diagnostic::new(path, Severity::Error, e.to_string())]),
+        },
+    };
+
+    // Unresolved variables are always looked for, but only fail the
+    // check when templates are strict.
+    let strict = cfg.strict_templates || strict_templates;
+    cfg.strict_templates = true;
+    for problem in cfg.validate(cache_dir, context.as_ref()) {
+        let severity = match problem.contains(": unresolved variable ") && !strict {
+            true => Severity::Warning,
+            false => Severity::Error,
+        };
+        let mut diagnostic = Diagnostic::new(path, severity, problem.clone());
+        if let Some((section, name)) = definition(&problem) {
+            locate(&mut diagnostic, &files, section, name);
+        }
+        if let Some((_, unresolved)) = problem.split_once(": unresolved variable ") {
+            let variable = unresolved.split(" in ").next().unwrap_or_default();
+            refine(&mut diagnostic, &format!("${{{}", variable));
+        }
+        diagnostics.push(diagnostic);
+    }
+    Ok(diagnostics)
+}
+
+/// Whether any mapping in the value has a merge key (`<<`).
+fn has_merge_keys(value: &Value) -> bool {
+    match value {
+        Value::Mapping(m) => m
+            .iter()
+            .any(|(k, v)| k.as_str() == Some("<<") || has_merge_keys(v)),
+        Value::Sequence(s) => s.iter().any(has_merge_keys),
+        Value::Tagged(t) => has_merge_keys(&t.value),
+        _ => false,
+    }
+}
+
+/// The error's message without the location, which is kept separately.
+fn message(e: &serde_yaml::Error) -> String {
+    let message = e.to_string();
+    match message.rfind(" at line ") {
+        Some(i) if e.location().is_some() => message[..i].to_string(),
+        _ => message,
+    }
+}
+
+/// The section and name of the definition a problem is about.
+fn definition(problem: &str) -> Option<(&'static str, &str)> {
+    SECTIONS.iter().find_map(|(kind, section)| {
+        let rest = problem.strip_prefix(kind)?.strip_prefix(" '")?;
+        let (name, _) = rest.split_once('\'')?;
+        Some((*section, name))
+    })
+}
+
+/// Point the diagnostic at the first file that defines the name in the
+/// section.
+fn locate(diagnostic: &mut Diagnostic, files: &[PathBuf], section: &str, name: &str) {
+    for file in files {
+        if let Ok(Some(line)) = find_definition(file, section, name) {
+            diagnostic.file = file.clone();
+            diagnostic.line = Some(line);
+            return;
+        }
+    }
+}
+
+/// Move the diagnostic to where the text first appears at or after its
+/// line, like the variable that can't be resolved.
+fn refine(diagnostic: &mut Diagnostic, text: &str) {
+    let (Some(line), Ok(contents)) = (diagnostic.line, std::fs::read_to_string(&diagnostic.file))
+    else {
+        return;
+    };
+    let found = contents
+        .lines()
+        .enumerate()
+        .skip(line - 1)
+        .find_map(|(i, l)| l.find(text).map(|column| (i + 1, column + 1)));
+    if let Some((line, column)) = found {
+        diagnostic.line = Some(line);
+        diagnostic.column = Some(column);
+    }
+}
+
+/// When each yaml file in the path was last changed, to tell when the
+/// configuration needs to be checked again.
+pub fn modified(path: &Path) -> HashMap<PathBuf, SystemTime> {
+    yaml_files(path)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|f| {
+            let modified = std::fs::metadata(&f).and_then(|m| m.modified()).ok()?;
+            Some((f, modified))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diagnose() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let requests = dir.join("requests.yaml");
+        std::fs::write(
+            &requests,
+            r#"requests:
+  base:
+    description: base
+    tags: []
+    url: http://localhost/items
+  get-item:
+    extends: base
+    url: http://localhost/items/${id}
+"#,
+        )
+        .unwrap();
+        let tests = dir.join("tests.yaml");
+        std::fs::write(
+            &tests,
+            r#"tests:
+  items:
+    description: items
+    steps:
+      - name: get
+        request: missing
+        asserts: []
+"#,
+        )
+        .unwrap();
+
+        let found = super::diagnose(dir, dir, &[], false, &Profile::default()).unwrap();
+        assert_eq!(found.len(), 2);
+        let warning = found.iter().find(|d| !d.is_error()).unwrap();
+        assert_eq!(warning.file, requests);
+        assert_eq!((warning.line, warning.column), (Some(8), Some(33)));
+        let error = found.iter().find(|d| d.is_error()).unwrap();
+        assert_eq!(error.file, tests);
+        assert_eq!(error.line, Some(2));
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "{}:2: error: test 'items' step 'get': request not found: missing",
+                tests.display()
+            )
+        );
+
+        // Strict templates make unresolved variables errors.
+        let found = super::diagnose(dir, dir, &[], true, &Profile::default()).unwrap();
+        assert!(found.iter().all(Diagnostic::is_error));
+
+        // A schema violation points into the file.
+        std::fs::write(&tests, "tests:\n  items:\n    description: items\n").unwrap();
+        let found = super::diagnose(dir, dir, &[], false, &Profile::default()).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].file, tests);
+        assert_eq!(found[0].line, Some(3));
+        assert_eq!(found[0].message, "tests.items: missing field `steps`");
+
+        // Errors in later documents point to their line in the file, and
+        // documents with merge keys are checked once merged.
+        std::fs::write(
+            &tests,
+            r#"tests:
+  base: &base
+    description: base
+    steps: []
+  merged:
+    <<: *base
+---
+tests:
+  items:
+    description: items
+"#,
+        )
+        .unwrap();
+        let found = super::diagnose(dir, dir, &[], false, &Profile::default()).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].line, Some(10));
+    }
+}
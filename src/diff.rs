@@ -0,0 +1,244 @@
+/// Diff is used to describe how two values differ in a readable way
+/// for assert failures.
+use serde_json::Value;
+use similar::{Algorithm, DiffTag};
+
+/// Values longer than this are truncated in messages.
+const MAX_VALUE_LEN: usize = 200;
+
+/// At most this many lines of differences are shown.
+const MAX_LINES: usize = 40;
+
+/// The number of unchanged lines shown around a changed line.
+const CONTEXT_LINES: usize = 2;
+
+/// Describe how got differs from want for an assert failure. Short
+/// values are shown in full. JSON objects and arrays are compared by
+/// path and other long values line by line with some context.
+pub fn describe(want: &str, got: &str) -> String {
+    if !want.contains('\n')
+        && !got.contains('\n')
+        && want.len() <= MAX_VALUE_LEN
+        && got.len() <= MAX_VALUE_LEN
+    {
+        return format!("got '{}', want '{}'", got, want);
+    }
+    let differences = match (
+        serde_json::from_str::<Value>(want),
+        serde_json::from_str::<Value>(got),
+    ) {
+        (Ok(w), Ok(g)) if (w.is_object() || w.is_array()) && (g.is_object() || g.is_array()) => {
+            json(&w, &g, false)
+        }
+        _ => lines(want, got),
+    };
+    format!("differs from the expected value:\n{}", limit(differences))
+}
+
+/// Shorten the value to a readable length for messages.
+pub fn truncate(s: &str) -> String {
+    if s.len() <= MAX_VALUE_LEN {
+        return s.to_string();
+    }
+    let mut end = MAX_VALUE_LEN;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}... ({} more bytes)", &s[..end], s.len() - end)
+}
+
+/// Compare the text line by line. Removed lines start with `-`, added
+/// lines with `+` and unchanged context lines with a space. Skipped
+/// unchanged lines are marked with `...`.
+pub fn lines(want: &str, got: &str) -> Vec<String> {
+    let want = want.lines().collect::<Vec<_>>();
+    let got = got.lines().collect::<Vec<_>>();
+
+    // Myers' diff keeps memory linear for large bodies. Added lines are
+    // shown before the lines they replace.
+    let mut edits = vec![];
+    for op in similar::capture_diff_slices(Algorithm::Myers, &want, &got) {
+        let (_, removed, added) = op.as_tag_tuple();
+        if op.tag() == DiffTag::Equal {
+            edits.extend(want[removed].iter().map(|l| format!("  {}", l)));
+            continue;
+        }
+        edits.extend(got[added].iter().map(|l| format!("+ {}", l)));
+        edits.extend(want[removed].iter().map(|l| format!("- {}", l)));
+    }
+
+    // Only keep the unchanged lines near a change.
+    let changed = edits
+        .iter()
+        .enumerate()
+        .filter(|(_, e)| !e.starts_with("  "))
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    let mut differences = vec![];
+    let mut skipped = false;
+    for (i, edit) in edits.into_iter().enumerate() {
+        let near = changed
+            .iter()
+            .any(|c| i + CONTEXT_LINES >= *c && i <= c + CONTEXT_LINES);
+        if near {
+            differences.push(edit);
+            skipped = false;
+        } else if !skipped {
+            differences.push("...".to_string());
+            skipped = true;
+        }
+    }
+    differences
+}
+
+/// Join the differences for a message, truncating each and dropping
+/// those past the maximum.
+pub fn limit(differences: Vec<String>) -> String {
+    let mut lines = differences
+        .iter()
+        .take(MAX_LINES)
+        .map(|d| truncate(d))
+        .collect::<Vec<_>>();
+    if differences.len() > MAX_LINES {
+        lines.push(format!(
+            "... ({} more differences)",
+            differences.len() - MAX_LINES
+        ));
+    }
+    lines.join("\n")
+}
+
+/// Compare two JSON values structurally, returning a message for each
+/// difference found at its path. Object keys are compared without
+/// regard to order and numbers by their value. When ignore_array_order
+/// is set, arrays match if they have the same elements in any order.
+pub fn json(want: &Value, got: &Value, ignore_array_order: bool) -> Vec<String> {
+    let mut differences = vec![];
+    compare(want, got, "$", ignore_array_order, &mut differences);
+    differences
+}
+
+/// Whether the values are structurally equal.
+pub fn json_equal(want: &Value, got: &Value, ignore_array_order: bool) -> bool {
+    json(want, got, ignore_array_order).is_empty()
+}
+
+fn compare(
+    want: &Value,
+    got: &Value,
+    path: &str,
+    ignore_array_order: bool,
+    differences: &mut Vec<String>,
+) {
+    match (want, got) {
+        (Value::Object(w), Value::Object(g)) => {
+            let mut keys = w.keys().chain(g.keys()).collect::<Vec<_>>();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let path = format!("{}.{}", path, key);
+                match (w.get(key), g.get(key)) {
+                    (Some(w), Some(g)) => compare(w, g, &path, ignore_array_order, differences),
+                    (Some(w), None) => differences.push(format!("{}: missing, want {}", path, w)),
+                    (None, Some(g)) => differences.push(format!("{}: unexpected {}", path, g)),
+                    (None, None) => {}
+                }
+            }
+        }
+        (Value::Array(w), Value::Array(g)) if ignore_array_order => {
+            let mut unmatched = g.iter().collect::<Vec<_>>();
+            for item in w {
+                match unmatched
+                    .iter()
+                    .position(|g| json_equal(item, g, ignore_array_order))
+                {
+                    Some(i) => {
+                        unmatched.remove(i);
+                    }
+                    None => differences.push(format!("{}: missing element {}", path, item)),
+                }
+            }
+            for item in unmatched {
+                differences.push(format!("{}: unexpected element {}", path, item));
+            }
+        }
+        (Value::Array(w), Value::Array(g)) => {
+            for i in 0..w.len().max(g.len()) {
+                let path = format!("{}[{}]", path, i);
+                match (w.get(i), g.get(i)) {
+                    (Some(w), Some(g)) => compare(w, g, &path, ignore_array_order, differences),
+                    (Some(w), None) => differences.push(format!("{}: missing, want {}", path, w)),
+                    (None, Some(g)) => differences.push(format!("{}: unexpected {}", path, g)),
+                    (None, None) => {}
+                }
+            }
+        }
+        (Value::Number(w), Value::Number(g)) if w.as_f64() == g.as_f64() => {}
+        (w, g) if w == g => {}
+        (w, g) => differences.push(format!("{}: got {}, want {}", path, g, w)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_differences() {
+        let want: Value =
+            serde_json::from_str(r#"{"a": 1, "b": [1, 2, {"c": "x"}], "d": null}"#).unwrap();
+        let got: Value =
+            serde_json::from_str(r#"{"b": [2, 1, {"c": "y"}], "a": 1.0, "e": true}"#).unwrap();
+        assert_eq!(
+            json(&want, &got, false),
+            vec![
+                "$.b[0]: got 2, want 1",
+                "$.b[1]: got 1, want 2",
+                "$.b[2].c: got \"y\", want \"x\"",
+                "$.d: missing, want null",
+                "$.e: unexpected true",
+            ]
+        );
+        assert_eq!(
+            json(&want, &got, true),
+            vec![
+                "$.b: missing element {\"c\":\"x\"}",
+                "$.b: unexpected element {\"c\":\"y\"}",
+                "$.d: missing, want null",
+                "$.e: unexpected true",
+            ]
+        );
+        assert!(json_equal(&want, &want, false));
+    }
+
+    #[test]
+    fn describe_differences() {
+        assert_eq!(describe("a", "b"), "got 'b', want 'a'");
+
+        let want = (1..=10).map(|i| i.to_string()).collect::<Vec<_>>();
+        let mut got = want.clone();
+        got[5] = "x".into();
+        assert_eq!(
+            describe(&want.join("\n"), &got.join("\n")),
+            "differs from the expected value:\n...\n  4\n  5\n+ x\n- 6\n  7\n  8\n..."
+        );
+
+        // Large bodies don't need memory for every pair of lines.
+        let want = (0..20_000).map(|i| i.to_string()).collect::<Vec<_>>();
+        let mut got = want.clone();
+        got[10_000] = "x".into();
+        assert_eq!(lines(&want.join("\n"), &got.join("\n")).len(), 8);
+
+        let want = format!(r#"{{"a": "{}", "b": 1}}"#, "x".repeat(300));
+        let got = format!(r#"{{"a": "{}", "b": 2}}"#, "x".repeat(300));
+        assert_eq!(
+            describe(&want, &got),
+            "differs from the expected value:\n$.b: got 2, want 1"
+        );
+
+        assert_eq!(
+            truncate(&"é".repeat(150)).len(),
+            200 + " (100 more bytes)".len() + 3
+        );
+    }
+}
@@ -0,0 +1,440 @@
+/// Docs is used to generate API documentation from the requests, with
+/// a page for each tag and the cached responses as examples.
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use crate::request::{Body, MultiPartField, RawBody};
+use crate::{Request, Response};
+
+use thiserror::Error;
+
+/// DocsError is the error type for generating docs.
+#[derive(Error, Debug)]
+pub enum DocsError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("format error: {0}")]
+    Format(String),
+}
+
+/// Result is the result type for generating docs.
+pub type Result<T> = std::result::Result<T, DocsError>;
+
+/// The page requests without any tags are listed on.
+const UNTAGGED: &str = "untagged";
+
+/// DocsFormat is the format the pages are written in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DocsFormat {
+    Markdown,
+    Html,
+}
+
+impl std::str::FromStr for DocsFormat {
+    type Err = DocsError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "markdown" | "md" => Ok(DocsFormat::Markdown),
+            "html" => Ok(DocsFormat::Html),
+            _ => Err(DocsError::Format(format!("unknown format: {}", s))),
+        }
+    }
+}
+
+impl DocsFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            DocsFormat::Markdown => "md",
+            DocsFormat::Html => "html",
+        }
+    }
+}
+
+/// Block is a part of a page that is rendered in the format.
+#[derive(Debug, PartialEq)]
+enum Block {
+    Heading(usize, String),
+    Paragraph(String),
+    Code(&'static str, String),
+    Table(Vec<&'static str>, Vec<Vec<String>>),
+    Links(Vec<(String, String)>),
+}
+
+/// Docs renders the requests into pages.
+pub struct Docs<'a> {
+    requests: &'a HashMap<String, Request>,
+    responses: &'a HashMap<String, Response>,
+    format: DocsFormat,
+}
+
+impl<'a> Docs<'a> {
+    /// Document the requests. The responses are linked as examples for
+    /// the requests with the same name.
+    pub fn new(
+        requests: &'a HashMap<String, Request>,
+        responses: &'a HashMap<String, Response>,
+        format: DocsFormat,
+    ) -> Self {
+        Self {
+            requests,
+            responses,
+            format,
+        }
+    }
+
+    /// The names of the requests on each tag's page, sorted.
+    fn tags(&self) -> BTreeMap<String, Vec<&'a str>> {
+        let mut tags: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+        for (name, request) in self.requests {
+            match request.tags.is_empty() {
+                true => tags.entry(UNTAGGED.into()).or_default().push(name),
+                false => {
+                    for tag in &request.tags {
+                        tags.entry(tag.clone()).or_default().push(name);
+                    }
+                }
+            }
+        }
+        tags.values_mut().for_each(|names| names.sort());
+        tags
+    }
+
+    /// The file the page for the tag is written to.
+    fn page(&self, tag: &str) -> String {
+        let slug = tag
+            .to_lowercase()
+            .chars()
+            .map(|c| match c.is_ascii_alphanumeric() {
+                true => c,
+                false => '-',
+            })
+            .collect::<String>();
+        format!("{}.{}", slug, self.format.extension())
+    }
+
+    /// The example response for the request, as its file name and
+    /// contents.
+    fn example(&self, name: &str) -> Option<(String, String)> {
+        let response = self.responses.get(name)?;
+        Some(match response.json() {
+            Some(json) => (
+                format!("examples/{}.json", name),
+                serde_json::to_string_pretty(json).unwrap_or_else(|_| response.body.clone()),
+            ),
+            None => (format!("examples/{}.txt", name), response.body.clone()),
+        })
+    }
+
+    /// The files to write, as their path in the docs folder and their
+    /// contents.
+    pub fn files(&self) -> Vec<(String, String)> {
+        let tags = self.tags();
+        let mut index = vec![Block::Heading(1, "API".into())];
+        for (tag, names) in &tags {
+            index.push(Block::Heading(2, tag.clone()));
+            index.push(Block::Links(
+                names
+                    .iter()
+                    .map(|n| (n.to_string(), format!("{}#{}", self.page(tag), anchor(n))))
+                    .collect(),
+            ));
+        }
+        let mut files = vec![(
+            format!("index.{}", self.format.extension()),
+            self.render("API", &index),
+        )];
+
+        for (tag, names) in &tags {
+            let mut blocks = vec![Block::Heading(1, tag.clone())];
+            for name in names {
+                blocks.extend(self.request(name, &self.requests[*name]));
+            }
+            files.push((self.page(tag), self.render(tag, &blocks)));
+        }
+
+        let mut names = self.requests.keys().collect::<Vec<_>>();
+        names.sort();
+        files.extend(names.into_iter().filter_map(|n| self.example(n)));
+        files
+    }
+
+    /// Write the files to the folder, returning their paths.
+    pub fn write(&self, dir: &Path) -> Result<Vec<PathBuf>> {
+        let mut paths = vec![];
+        for (name, contents) in self.files() {
+            let path = dir.join(name);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&path, contents)?;
+            paths.push(path);
+        }
+        Ok(paths)
+    }
+
+    /// The blocks that document the request.
+    fn request(&self, name: &str, request: &Request) -> Vec<Block> {
+        let mut blocks = vec![Block::Heading(2, name.to_string())];
+        if !request.description.is_empty() {
+            blocks.push(Block::Paragraph(request.description.clone()));
+        }
+        blocks.push(Block::Code(
+            "",
+            format!("{} {}", request.method.to_uppercase(), request.url),
+        ));
+        for (title, values) in [
+            ("Headers", &request.headers),
+            ("Query Parameters", &request.query_parameters),
+        ] {
+            if !values.is_empty() {
+                blocks.push(Block::Heading(3, title.into()));
+                blocks.push(Block::Table(vec!["Name", "Value"], sorted(values)));
+            }
+        }
+
+        let body = match &request.body {
+            Body::None => vec![],
+            Body::Form { data } => vec![Block::Table(vec!["Field", "Value"], sorted(data))],
+            Body::Raw {
+                from: RawBody::Text { data },
+            } => {
+                let lang = match serde_json::from_str::<serde_json::Value>(data) {
+                    Ok(_) => "json",
+                    Err(_) => "",
+                };
+                vec![Block::Code(lang, data.clone())]
+            }
+            Body::Raw {
+                from: RawBody::File { path },
+            } => vec![Block::Paragraph(format!(
+                "The contents of the file {}.",
+                path
+            ))],
+            Body::Raw {
+                from: RawBody::Template { path },
+            } => vec![Block::Paragraph(format!(
+                "The contents of the file {} with its templates applied.",
+                path
+            ))],
+            Body::MultiPart { data } => {
+                let mut rows = data
+                    .iter()
+                    .flat_map(|(k, v)| v.parts().iter().map(move |p| (k, p)))
+                    .map(|(k, part)| match part {
+                        MultiPartField::Text { data, .. } => vec![k.clone(), data.clone()],
+                        MultiPartField::File {
+                            path, content_type, ..
+                        } => match content_type {
+                            Some(c) => vec![k.clone(), format!("file {} ({})", path, c)],
+                            None => vec![k.clone(), format!("file {}", path)],
+                        },
+                    })
+                    .collect::<Vec<_>>();
+                rows.sort();
+                vec![Block::Table(vec!["Field", "Value"], rows)]
+            }
+            Body::GraphQL {
+                query, variables, ..
+            } => {
+                let mut blocks = vec![Block::Code("graphql", query.clone())];
+                if let Some(variables) = variables {
+                    blocks.push(Block::Code(
+                        "json",
+                        serde_json::to_string_pretty(variables).unwrap_or_default(),
+                    ));
+                }
+                blocks
+            }
+            Body::Soap {
+                action,
+                envelope,
+                header,
+            } => vec![
+                Block::Paragraph(format!("SOAP action {}.", action)),
+                Block::Code("xml", Body::soap_envelope(envelope, header.as_deref())),
+            ],
+        };
+        if !body.is_empty() {
+            blocks.push(Block::Heading(3, "Body".into()));
+            blocks.extend(body);
+        }
+
+        if let Some((path, _)) = self.example(name) {
+            let status = self.responses[name].status_code;
+            blocks.push(Block::Links(vec![(
+                format!("Example response ({})", status),
+                path,
+            )]));
+        }
+        blocks
+    }
+
+    fn render(&self, title: &str, blocks: &[Block]) -> String {
+        match self.format {
+            DocsFormat::Markdown => blocks.iter().map(markdown).collect::<Vec<_>>().join("\n"),
+            DocsFormat::Html => format!(
+                "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n{}</body>\n</html>\n",
+                escape(title),
+                blocks.iter().map(html).collect::<String>()
+            ),
+        }
+    }
+}
+
+fn sorted(values: &HashMap<String, String>) -> Vec<Vec<String>> {
+    let mut rows = values
+        .iter()
+        .map(|(k, v)| vec![k.clone(), v.clone()])
+        .collect::<Vec<_>>();
+    rows.sort();
+    rows
+}
+
+/// The id of the heading for a request name.
+fn anchor(name: &str) -> String {
+    name.to_lowercase()
+        .chars()
+        .filter(|c| c.is_alphanumeric() || *c == '-' || *c == '_' || *c == ' ')
+        .map(|c| if c == ' ' { '-' } else { c })
+        .collect()
+}
+
+fn markdown(block: &Block) -> String {
+    let cell = |c: &str| c.replace('|', "\\|").replace('\n', " ");
+    match block {
+        Block::Heading(level, text) => format!("{} {}\n", "#".repeat(*level), text),
+        Block::Paragraph(text) => format!("{}\n", text),
+        Block::Code(lang, code) => format!("```{}\n{}\n```\n", lang, code.trim_end()),
+        Block::Table(headers, rows) => {
+            let mut table = format!(
+                "| {} |\n|{}\n",
+                headers.join(" | "),
+                " --- |".repeat(headers.len())
+            );
+            for row in rows {
+                let row = row.iter().map(|c| cell(c)).collect::<Vec<_>>();
+                table.push_str(&format!("| {} |\n", row.join(" | ")));
+            }
+            table
+        }
+        Block::Links(links) => links
+            .iter()
+            .map(|(text, href)| format!("- [{}]({})\n", text, href))
+            .collect(),
+    }
+}
+
+fn html(block: &Block) -> String {
+    match block {
+        Block::Heading(level, text) => format!(
+            "<h{level} id=\"{}\">{}</h{level}>\n",
+            anchor(text),
+            escape(text),
+            level = level
+        ),
+        Block::Paragraph(text) => format!("<p>{}</p>\n", escape(text)),
+        Block::Code(_, code) => format!("<pre><code>{}</code></pre>\n", escape(code.trim_end())),
+        Block::Table(headers, rows) => {
+            let row = |tag: &str, cells: Vec<String>| {
+                let cells = cells
+                    .iter()
+                    .map(|c| format!("<{tag}>{}</{tag}>", escape(c), tag = tag))
+                    .collect::<String>();
+                format!("<tr>{}</tr>\n", cells)
+            };
+            let mut table = String::from("<table>\n");
+            table.push_str(&row("th", headers.iter().map(|h| h.to_string()).collect()));
+            for r in rows {
+                table.push_str(&row("td", r.clone()));
+            }
+            table.push_str("</table>\n");
+            table
+        }
+        Block::Links(links) => {
+            let items = links
+                .iter()
+                .map(|(text, href)| {
+                    format!(
+                        "<li><a href=\"{}\">{}</a></li>\n",
+                        escape(href),
+                        escape(text)
+                    )
+                })
+                .collect::<String>();
+            format!("<ul>\n{}</ul>\n", items)
+        }
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn files() {
+        let requests: HashMap<String, Request> = serde_yaml::from_str(
+            r#"
+get-user:
+  description: get a user
+  tags: [users]
+  url: /users/${id}
+  headers: {accept: application/json}
+create-user:
+  description: ""
+  tags: [users, admin]
+  url: /users
+  method: post
+  body: {type: raw, from: {type: text, data: '{"name": "bob"}'}}
+health:
+  description: ""
+  tags: []
+  url: /health
+"#,
+        )
+        .unwrap();
+        let responses = vec![(
+            "get-user".to_string(),
+            Response {
+                status_code: 200,
+                body: r#"{"id": 1}"#.into(),
+                ..Default::default()
+            },
+        )]
+        .into_iter()
+        .collect();
+
+        let files = Docs::new(&requests, &responses, DocsFormat::Markdown).files();
+        let names = files.iter().map(|(n, _)| n.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                "index.md",
+                "admin.md",
+                "untagged.md",
+                "users.md",
+                "examples/get-user.json"
+            ]
+        );
+        let users = &files[3].1;
+        assert!(users.contains("## create-user\n"));
+        assert!(users.contains("```json\n{\"name\": \"bob\"}\n```"));
+        assert!(users.contains("| accept | application/json |"));
+        assert!(users.contains("- [Example response (200)](examples/get-user.json)"));
+        assert!(files[0].1.contains("- [health](untagged.md#health)"));
+
+        let files = Docs::new(&requests, &responses, DocsFormat::Html).files();
+        assert!(files[3].1.contains("<h2 id=\"get-user\">get-user</h2>"));
+        assert!(files[3]
+            .1
+            .contains("<pre><code>GET /users/${id}</code></pre>"));
+    }
+}
@@ -0,0 +1,167 @@
+/// Events are written as JSON lines while tests and requests run, so
+/// dashboards and editors can follow a run as it happens.
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use crate::{Counts, State};
+
+use serde::Serialize;
+use thiserror::Error;
+
+/// EventsError is the error type for events.
+#[derive(Error, Debug)]
+pub enum EventsError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Result is the result type for events.
+pub type Result<T> = std::result::Result<T, EventsError>;
+
+/// Event is something that happened during a run. Steps and asserts are
+/// named like they are in the results.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    RunStarted {
+        source: String,
+    },
+    RequestSent {
+        request: String,
+        method: String,
+        url: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        test: Option<String>,
+    },
+    ResponseReceived {
+        request: String,
+        status_code: u16,
+        duration_ms: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        test: Option<String>,
+    },
+    AssertPassed {
+        request: String,
+        assert: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        test: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        step: Option<String>,
+    },
+    AssertFailed {
+        request: String,
+        assert: String,
+        error: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        test: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        step: Option<String>,
+    },
+    TestFinished {
+        test: String,
+        state: State,
+        duration_ms: u64,
+    },
+    RunFinished {
+        passed: bool,
+        duration_ms: u64,
+        /// How many tests ended in each state, when tests were run.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        counts: Option<Counts>,
+    },
+}
+
+/// The milliseconds in a duration, for events.
+pub fn millis(duration: Duration) -> u64 {
+    duration.as_millis().try_into().unwrap_or(u64::MAX)
+}
+
+/// Line is an event as it's written, with when it happened.
+#[derive(Serialize)]
+struct Line<'a> {
+    time: String,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+/// Events writes each event to a file as soon as it's sent. It's shared
+/// by parallel tests, so their lines don't interleave.
+#[derive(Debug)]
+pub struct Events {
+    file: Mutex<File>,
+}
+
+impl Events {
+    /// Write the events to the file, which can be a file descriptor like
+    /// `/dev/fd/3`.
+    pub fn create(path: &Path) -> Result<Self> {
+        Ok(Self {
+            file: Mutex::new(File::create(path)?),
+        })
+    }
+
+    /// Write the event. A failed write is logged instead of failing the
+    /// run.
+    pub fn send(&self, event: Event) {
+        let line = Line {
+            time: humantime::format_rfc3339_millis(SystemTime::now()).to_string(),
+            event: &event,
+        };
+        let mut line = match serde_json::to_string(&line) {
+            Ok(line) => line,
+            Err(e) => {
+                tracing::warn!(error = %e, "failed to serialize event");
+                return;
+            }
+        };
+        line.push('\n');
+        if let Err(e) = self.file.lock().unwrap().write_all(line.as_bytes()) {
+            tracing::warn!(error = %e, "failed to write event");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn send() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.jsonl");
+        let events = Events::create(&path).unwrap();
+        events.send(Event::RunStarted {
+            source: "tests run".into(),
+        });
+        events.send(Event::AssertFailed {
+            request: "get-token".into(),
+            assert: "status_code(200)".into(),
+            error: "got 401".into(),
+            test: Some("login".into()),
+            step: None,
+        });
+        events.send(Event::TestFinished {
+            test: "login".into(),
+            state: State::Failed("1 of 1 failed".into()),
+            duration_ms: millis(Duration::from_millis(1500)),
+        });
+
+        let lines = std::fs::read_to_string(&path).unwrap();
+        let lines = lines
+            .lines()
+            .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert!(lines.iter().all(|l| l["time"].is_string()));
+        assert_eq!(lines[0]["event"], "run_started");
+        assert_eq!(lines[0]["source"], "tests run");
+        assert_eq!(lines[1]["event"], "assert_failed");
+        assert_eq!(lines[1]["error"], "got 401");
+        assert!(lines[1].get("step").is_none());
+        assert_eq!(lines[2]["state"]["Failed"], "1 of 1 failed");
+        assert_eq!(lines[2]["duration_ms"], 1500);
+    }
+}
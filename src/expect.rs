@@ -0,0 +1,179 @@
+/// Expect is used to describe the response a request should get so
+/// that it can be checked whenever the request is run.
+use std::collections::HashMap;
+
+use crate::Response;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Expect is the response a request should get. Headers are checked by
+/// the header containing the value. The schema is a subset of JSON
+/// Schema: type, enum, required, properties, and items.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, JsonSchema)]
+pub struct Expect {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub headers: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<Value>,
+}
+
+impl Expect {
+    /// Check the response, returning a message for each violation.
+    pub fn check(&self, response: &Response) -> Vec<String> {
+        let mut violations = vec![];
+        if let Some(status) = self.status {
+            if response.status_code != status {
+                violations.push(format!(
+                    "got status code {}, want {}",
+                    response.status_code, status
+                ));
+            }
+        }
+
+        let mut keys = self.headers.keys().collect::<Vec<_>>();
+        keys.sort();
+        for key in keys {
+            let value = &self.headers[key];
+            match response.headers.get(&key.to_lowercase()) {
+                Some(h) if h.contains(value.as_str()) => {}
+                Some(h) => violations.push(format!(
+                    "header '{}' got '{}', does not contain '{}'",
+                    key, h, value
+                )),
+                None => violations.push(format!("header not found: {}", key)),
+            }
+        }
+
+        if let Some(schema) = &self.schema {
+            match serde_json::from_str::<Value>(&response.body) {
+                Ok(body) => validate(schema, &body, "$", &mut violations),
+                Err(e) => violations.push(format!("body is not json: {}", e)),
+            }
+        }
+        violations
+    }
+}
+
+/// Validate the value against the schema, adding a message for each
+/// violation found.
+pub fn validate(schema: &Value, value: &Value, path: &str, violations: &mut Vec<String>) {
+    if let Some(t) = schema.get("type") {
+        let types = match t {
+            Value::Array(a) => a.iter().filter_map(Value::as_str).collect(),
+            Value::String(s) => vec![s.as_str()],
+            _ => vec![],
+        };
+        if !types.iter().any(|t| is_type(value, t)) {
+            violations.push(format!(
+                "{}: got {}, want type {}",
+                path,
+                type_name(value),
+                types.join(" or ")
+            ));
+            return;
+        }
+    }
+
+    if let Some(Value::Array(options)) = schema.get("enum") {
+        if !options.contains(value) {
+            violations.push(format!(
+                "{}: {} is not one of the allowed values",
+                path, value
+            ));
+        }
+    }
+
+    if let Value::Object(object) = value {
+        if let Some(Value::Array(required)) = schema.get("required") {
+            for key in required.iter().filter_map(Value::as_str) {
+                if !object.contains_key(key) {
+                    violations.push(format!("{}: missing required property '{}'", path, key));
+                }
+            }
+        }
+        if let Some(Value::Object(properties)) = schema.get("properties") {
+            for (key, property) in properties {
+                if let Some(v) = object.get(key) {
+                    validate(property, v, &format!("{}.{}", path, key), violations);
+                }
+            }
+        }
+    }
+
+    if let (Value::Array(items), Some(schema)) = (value, schema.get("items")) {
+        for (i, item) in items.iter().enumerate() {
+            validate(schema, item, &format!("{}[{}]", path, i), violations);
+        }
+    }
+}
+
+fn is_type(value: &Value, t: &str) -> bool {
+    match t {
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        t => type_name(value) == t,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check() {
+        let expect: Expect = serde_yaml::from_str(
+            r#"
+status: 200
+headers:
+  Content-Type: json
+schema:
+  type: object
+  required: [id, tags]
+  properties:
+    id: { type: integer }
+    state: { enum: [open, closed] }
+    tags:
+      type: array
+      items: { type: string }
+"#,
+        )
+        .unwrap();
+
+        let mut response = Response {
+            status_code: 200,
+            headers: vec![("content-type".into(), "application/json".into())]
+                .into_iter()
+                .collect(),
+            body: r#"{"id": 1, "state": "open", "tags": ["a"]}"#.into(),
+            ..Default::default()
+        };
+        assert!(expect.check(&response).is_empty());
+
+        response.status_code = 500;
+        response.body = r#"{"id": "1", "state": "new", "tags": ["a", 2]}"#.into();
+        assert_eq!(
+            expect.check(&response),
+            vec![
+                "got status code 500, want 200",
+                "$.id: got string, want type integer",
+                "$.state: \"new\" is not one of the allowed values",
+                "$.tags[1]: got number, want type string",
+            ]
+        );
+    }
+}
@@ -0,0 +1,156 @@
+/// Expression is used to evaluate the arithmetic in templates like
+/// `${ ${count} + 1 }`. Templates are applied before evaluating, so
+/// expressions only contain numbers.
+use thiserror::Error;
+
+/// ExpressionError is the error type for expressions.
+#[derive(Error, Debug, PartialEq)]
+pub enum ExpressionError {
+    #[error("invalid expression '{0}': {1}")]
+    Invalid(String, String),
+
+    #[error("division by zero in '{0}'")]
+    DivisionByZero(String),
+}
+
+/// Result is the result type for expressions.
+pub type Result<T> = std::result::Result<T, ExpressionError>;
+
+/// Whether the text is an expression once the templates in it are
+/// replaced: numbers, parentheses and at least one of `+ - * / %`.
+pub fn is_expression(s: &str) -> bool {
+    let s = s.trim();
+    s.chars().any(|c| "+-*/%".contains(c))
+        && s.chars()
+            .all(|c| c.is_ascii_digit() || c.is_whitespace() || "+-*/%().".contains(c))
+}
+
+/// Evaluate the expression. Whole results are written without a
+/// decimal point.
+pub fn evaluate(expression: &str) -> Result<String> {
+    let mut parser = Parser {
+        source: expression,
+        chars: expression.chars().filter(|c| !c.is_whitespace()).collect(),
+        position: 0,
+    };
+    let value = parser.sum()?;
+    if parser.position < parser.chars.len() {
+        return Err(parser.error(&format!("unexpected '{}'", parser.chars[parser.position])));
+    }
+    Ok(match value.fract() == 0.0 && value.abs() < 1e15 {
+        true => format!("{}", value as i64),
+        false => value.to_string(),
+    })
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> ExpressionError {
+        ExpressionError::Invalid(self.source.to_string(), message.to_string())
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    /// Terms added or subtracted.
+    fn sum(&mut self) -> Result<f64> {
+        let mut value = self.product()?;
+        while let Some(op @ ('+' | '-')) = self.peek() {
+            self.position += 1;
+            let right = self.product()?;
+            value = match op {
+                '+' => value + right,
+                _ => value - right,
+            };
+        }
+        Ok(value)
+    }
+
+    /// Factors multiplied, divided or taking the remainder.
+    fn product(&mut self) -> Result<f64> {
+        let mut value = self.factor()?;
+        while let Some(op @ ('*' | '/' | '%')) = self.peek() {
+            self.position += 1;
+            let right = self.factor()?;
+            if op != '*' && right == 0.0 {
+                return Err(ExpressionError::DivisionByZero(self.source.to_string()));
+            }
+            value = match op {
+                '*' => value * right,
+                '/' => value / right,
+                _ => value % right,
+            };
+        }
+        Ok(value)
+    }
+
+    /// A number, a negated factor or a sum in parentheses.
+    fn factor(&mut self) -> Result<f64> {
+        match self.peek() {
+            Some('-') => {
+                self.position += 1;
+                Ok(-self.factor()?)
+            }
+            Some('(') => {
+                self.position += 1;
+                let value = self.sum()?;
+                match self.peek() {
+                    Some(')') => {
+                        self.position += 1;
+                        Ok(value)
+                    }
+                    _ => Err(self.error("missing ')'")),
+                }
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let start = self.position;
+                while self.peek().is_some_and(|c| c.is_ascii_digit() || c == '.') {
+                    self.position += 1;
+                }
+                let number = self.chars[start..self.position].iter().collect::<String>();
+                number
+                    .parse()
+                    .map_err(|_| self.error(&format!("invalid number '{}'", number)))
+            }
+            Some(c) => Err(self.error(&format!("unexpected '{}'", c))),
+            None => Err(self.error("missing a value")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evaluate_expressions() {
+        let tests = vec![
+            ("5 + 1", "6"),
+            ("2 + 3 * 4", "14"),
+            ("(2 + 3) * 4", "20"),
+            ("7 / 2", "3.5"),
+            ("7 % 4 - -1", "4"),
+            ("1.5 * 2", "3"),
+        ];
+        for (expression, want) in tests {
+            assert_eq!(evaluate(expression).unwrap(), want, "{}", expression);
+        }
+        assert_eq!(
+            evaluate("1 / 0"),
+            Err(ExpressionError::DivisionByZero("1 / 0".into()))
+        );
+        assert!(matches!(
+            evaluate(" + 1"),
+            Err(ExpressionError::Invalid(..))
+        ));
+        assert!(matches!(evaluate("(1"), Err(ExpressionError::Invalid(..))));
+        assert!(is_expression("0 + 1"));
+        assert!(!is_expression("$"));
+    }
+}
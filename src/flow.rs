@@ -0,0 +1,196 @@
+/// Flow is used to run a set of requests that depend on each other.
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::{condition, Applicator, Config, List, Request, RequestError, Response};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Implement List for flows.
+impl List for HashMap<String, Flow> {
+    fn headers(&self) -> Vec<String> {
+        vec!["Name".into(), "Steps".into(), "Description".into()]
+    }
+
+    fn values(&self) -> Vec<Vec<String>> {
+        self.iter()
+            .map(|(n, f)| vec![n.clone(), f.steps.len().to_string(), f.description.clone()])
+            .collect()
+    }
+}
+
+/// FlowError is the error type for flows.
+#[derive(Error, Debug)]
+pub enum FlowError {
+    #[error("request not found: {0}")]
+    RequestNotFound(String),
+
+    #[error("request error: {0}")]
+    RequestError(#[from] RequestError),
+
+    #[error("step '{0}' depends on unknown step '{1}'")]
+    UnknownStep(String, String),
+
+    #[error("steps have a dependency cycle: {0}")]
+    Cycle(String),
+
+    #[error("condition error: {0}")]
+    Condition(#[from] crate::ConditionError),
+}
+
+/// Result is the result type for flows.
+pub type Result<T> = std::result::Result<T, FlowError>;
+
+/// Flow is a named list of requests. Steps run in the order given
+/// unless they declare the steps they must run after.
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Flow {
+    #[serde(default)]
+    pub description: String,
+    pub steps: Vec<FlowStep>,
+}
+
+/// FlowStep runs a request. The variables are applied with the
+/// responses so far and added to the context before the request is
+/// applied, which allows mapping values from earlier responses.
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct FlowStep {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub request: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub after: Vec<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub variables: HashMap<String, String>,
+    /// The step only runs if the condition is true once templates are
+    /// applied.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub when: Option<String>,
+}
+
+impl FlowStep {
+    /// The name of the step, which defaults to the request.
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.request)
+    }
+}
+
+impl Flow {
+    /// The order in which the steps should run. If no step declares
+    /// dependencies, each step runs after the previous one. Otherwise
+    /// steps are sorted by their dependencies, keeping the given order
+    /// where they are independent.
+    pub fn order(&self) -> Result<Vec<&FlowStep>> {
+        if self.steps.iter().all(|s| s.after.is_empty()) {
+            return Ok(self.steps.iter().collect());
+        }
+
+        let names = self.steps.iter().map(|s| s.name()).collect::<Vec<_>>();
+        for step in &self.steps {
+            for dep in &step.after {
+                if !names.contains(&dep.as_str()) {
+                    return Err(FlowError::UnknownStep(step.name().into(), dep.clone()));
+                }
+            }
+        }
+
+        let mut done: Vec<&str> = vec![];
+        let mut order = vec![];
+        while order.len() < self.steps.len() {
+            let next = self.steps.iter().find(|s| {
+                !done.contains(&s.name()) && s.after.iter().all(|d| done.contains(&d.as_str()))
+            });
+            match next {
+                Some(step) => {
+                    done.push(step.name());
+                    order.push(step);
+                }
+                None => {
+                    let remaining = names
+                        .iter()
+                        .filter(|n| !done.contains(n))
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    return Err(FlowError::Cycle(remaining.join(", ")));
+                }
+            }
+        }
+        Ok(order)
+    }
+
+    /// Run the steps with the applicator, calling f with each response
+    /// as it is received. Responses are added to the applicator under
+    /// their request name so later steps can refer to them. If a delay
+    /// is given, it is waited between steps.
+    pub async fn execute<F>(
+        &self,
+        cfg: &Config,
+        app: &mut Applicator,
+        delay: Option<Duration>,
+        mut f: F,
+    ) -> Result<()>
+    where
+        F: FnMut(&FlowStep, &Response),
+    {
+        for (i, step) in self.order()?.into_iter().enumerate() {
+            if let (Some(delay), true) = (delay, i > 0) {
+                tokio::time::sleep(delay).await;
+            }
+            if let Some(when) = &step.when {
+                if !condition::evaluate(&app.apply(when))? {
+                    tracing::info!(step = step.name(), when = %when, "skipping step");
+                    continue;
+                }
+            }
+            tracing::info!(step = step.name(), request = %step.request, "running step");
+            for (key, value) in &step.variables {
+                let value = app.apply(value);
+                app.set(key, value);
+            }
+            let mut request: Request = cfg
+                .requests
+                .get(&step.request)
+                .cloned()
+                .ok_or_else(|| FlowError::RequestNotFound(step.request.clone()))?;
+            request.try_apply(app)?;
+            let resp = request.request().await?;
+            f(step, &resp);
+            app.add_response(step.request.clone(), resp);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(flow: &Flow) -> Result<Vec<&str>> {
+        Ok(flow.order()?.into_iter().map(|s| s.name()).collect())
+    }
+
+    #[test]
+    fn order() {
+        let mut flow: Flow = serde_yaml::from_str(
+            r#"
+steps:
+  - request: a
+  - request: b
+  - request: c
+"#,
+        )
+        .unwrap();
+        assert_eq!(names(&flow).unwrap(), vec!["a", "b", "c"]);
+
+        flow.steps[0].after = vec!["c".into()];
+        assert_eq!(names(&flow).unwrap(), vec!["b", "c", "a"]);
+
+        flow.steps[2].after = vec!["a".into()];
+        assert!(matches!(names(&flow), Err(FlowError::Cycle(_))));
+
+        flow.steps[2].after = vec!["d".into()];
+        assert!(matches!(names(&flow), Err(FlowError::UnknownStep(_, _))));
+    }
+}
@@ -0,0 +1,302 @@
+/// Function is used to call the functions available in templates, like
+/// `${sha256(${body})}` or `${fake.email()}`.
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use hmac::{Hmac, KeyInit, Mac};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+/// FunctionError is the error type for template functions.
+#[derive(Error, Debug)]
+pub enum FunctionError {
+    #[error("unknown function: {0}")]
+    Unknown(String),
+
+    #[error("function '{0}' needs apictl built with the '{1}' feature")]
+    Feature(String, &'static str),
+
+    #[error("function '{name}' takes {want} arguments, got {got}")]
+    Arguments {
+        name: String,
+        want: String,
+        got: usize,
+    },
+
+    #[error("function '{name}': {message}")]
+    Invalid { name: String, message: String },
+}
+
+/// Result is the result type for template functions.
+pub type Result<T> = std::result::Result<T, FunctionError>;
+
+/// The function that computes the value from the arguments.
+type Call = fn(&str, &[String]) -> Result<String>;
+
+/// A registered function and the number of arguments it takes.
+struct Registered {
+    min: usize,
+    max: usize,
+    call: Call,
+}
+
+static REGISTRY: OnceLock<HashMap<&'static str, Registered>> = OnceLock::new();
+
+fn registry() -> &'static HashMap<&'static str, Registered> {
+    REGISTRY.get_or_init(|| {
+        let mut registry = HashMap::new();
+        let mut register = |name, min, max, call| {
+            registry.insert(name, Registered { min, max, call });
+        };
+        register("base64", 1, 1, base64 as Call);
+        register("urlencode", 1, 1, urlencode);
+        register("sha256", 1, 1, sha256);
+        register("hmac_sha256", 2, 2, hmac_sha256);
+        register("jwt", 2, 2, jwt);
+        register("upper", 1, 1, upper);
+        register("lower", 1, 1, lower);
+        register("trim", 1, 1, trim);
+        register("len", 1, 1, len);
+        register("substr", 2, 3, substr);
+        #[cfg(feature = "fake")]
+        {
+            register("fake.email", 0, 0, fake::email);
+            register("fake.name", 0, 0, fake::name);
+            register("fake.uuid", 0, 0, fake::uuid);
+            register("fake.lorem", 0, 1, fake::lorem);
+        }
+        registry
+    })
+}
+
+/// Call the function with the arguments.
+pub fn call(name: &str, args: &[String]) -> Result<String> {
+    let registered = match registry().get(name) {
+        Some(r) => r,
+        None if name.starts_with("fake.") && !cfg!(feature = "fake") => {
+            return Err(FunctionError::Feature(name.to_string(), "fake"))
+        }
+        None => return Err(FunctionError::Unknown(name.to_string())),
+    };
+    if args.len() < registered.min || args.len() > registered.max {
+        let want = match (registered.min, registered.max) {
+            (min, max) if min == max => min.to_string(),
+            (min, usize::MAX) => format!("at least {}", min),
+            (min, max) => format!("{} to {}", min, max),
+        };
+        return Err(FunctionError::Arguments {
+            name: name.to_string(),
+            want,
+            got: args.len(),
+        });
+    }
+    (registered.call)(name, args)
+}
+
+/// Parse the argument at the index as a number.
+fn number(name: &str, args: &[String], index: usize, default: usize) -> Result<usize> {
+    match args.get(index) {
+        None => Ok(default),
+        Some(arg) => arg.trim().parse().map_err(|_| FunctionError::Invalid {
+            name: name.to_string(),
+            message: format!("'{}' isn't a number", arg),
+        }),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn sign(key: &str, message: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("hmac takes any key");
+    mac.update(message.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Standard base64 with padding.
+fn base64(_: &str, args: &[String]) -> Result<String> {
+    Ok(STANDARD.encode(&args[0]))
+}
+
+/// Percent-encode everything but unreserved characters.
+fn urlencode(_: &str, args: &[String]) -> Result<String> {
+    Ok(crate::request::percent_encode(&args[0]))
+}
+
+/// The hex SHA-256 digest.
+fn sha256(_: &str, args: &[String]) -> Result<String> {
+    Ok(hex(&Sha256::digest(args[0].as_bytes())))
+}
+
+/// The hex HMAC-SHA256 of the message with the key.
+fn hmac_sha256(_: &str, args: &[String]) -> Result<String> {
+    Ok(hex(&sign(&args[0], &args[1])))
+}
+
+/// A JWT for the JSON claims signed with HS256 and the secret.
+fn jwt(name: &str, args: &[String]) -> Result<String> {
+    let claims: serde_json::Value =
+        serde_json::from_str(&args[0]).map_err(|e| FunctionError::Invalid {
+            name: name.to_string(),
+            message: format!("claims aren't JSON: {}", e),
+        })?;
+    let header = serde_json::json!({"alg": "HS256", "typ": "JWT"});
+    let unsigned = format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(header.to_string()),
+        URL_SAFE_NO_PAD.encode(claims.to_string())
+    );
+    let signature = URL_SAFE_NO_PAD.encode(sign(&args[1], &unsigned));
+    Ok(format!("{}.{}", unsigned, signature))
+}
+
+fn upper(_: &str, args: &[String]) -> Result<String> {
+    Ok(args[0].to_uppercase())
+}
+
+fn lower(_: &str, args: &[String]) -> Result<String> {
+    Ok(args[0].to_lowercase())
+}
+
+fn trim(_: &str, args: &[String]) -> Result<String> {
+    Ok(args[0].trim().to_string())
+}
+
+/// The number of characters.
+fn len(_: &str, args: &[String]) -> Result<String> {
+    Ok(args[0].chars().count().to_string())
+}
+
+/// The characters from the start, to the end or for the length.
+fn substr(name: &str, args: &[String]) -> Result<String> {
+    let start = number(name, args, 1, 0)?;
+    let length = number(name, args, 2, usize::MAX)?;
+    Ok(args[0].chars().skip(start).take(length).collect())
+}
+
+/// Fake data for load and data-driven tests. Each call returns a new
+/// value.
+#[cfg(feature = "fake")]
+mod fake {
+    use super::{number, Result};
+
+    use ::fake::faker::internet::en::SafeEmail;
+    use ::fake::faker::lorem::en::Words;
+    use ::fake::faker::name::en::Name;
+    use ::fake::Fake;
+
+    pub fn email(_: &str, _: &[String]) -> Result<String> {
+        Ok(SafeEmail().fake())
+    }
+
+    pub fn name(_: &str, _: &[String]) -> Result<String> {
+        Ok(Name().fake())
+    }
+
+    /// A random (version 4) UUID.
+    pub fn uuid(_: &str, _: &[String]) -> Result<String> {
+        let mut b: [u8; 16] = ::fake::rand::random();
+        b[6] = (b[6] & 0x0f) | 0x40;
+        b[8] = (b[8] & 0x3f) | 0x80;
+        let hex = b.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+        Ok(format!(
+            "{}-{}-{}-{}-{}",
+            &hex[..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..]
+        ))
+    }
+
+    /// The number of words, 10 by default.
+    pub fn lorem(name: &str, args: &[String]) -> Result<String> {
+        let count = number(name, args, 0, 10)?;
+        let words: Vec<String> = Words(count..count + 1).fake();
+        Ok(words.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors() {
+        assert!(matches!(call("nope", &[]), Err(FunctionError::Unknown(_))));
+        assert_eq!(
+            call("hmac_sha256", &["key".into()])
+                .unwrap_err()
+                .to_string(),
+            "function 'hmac_sha256' takes 2 arguments, got 1"
+        );
+        assert!(matches!(
+            call("jwt", &["not json".into(), "secret".into()]),
+            Err(FunctionError::Invalid { .. })
+        ));
+    }
+
+    #[test]
+    fn strings() {
+        let call = |name: &str, args: &[&str]| {
+            let args = args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+            call(name, &args).unwrap()
+        };
+        assert_eq!(call("upper", &["bob"]), "BOB");
+        assert_eq!(call("len", &["héllo"]), "5");
+        assert_eq!(call("substr", &["0123456789", "2", "3"]), "234");
+        assert_eq!(call("substr", &["0123456789", "8"]), "89");
+    }
+
+    #[test]
+    fn encoding() {
+        let call = |name: &str, args: &[&str]| {
+            let args = args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+            call(name, &args).unwrap()
+        };
+        assert_eq!(call("base64", &["user:pass"]), "dXNlcjpwYXNz");
+        assert_eq!(call("urlencode", &["a b&c"]), "a%20b%26c");
+        assert_eq!(
+            call("sha256", &["abc"]),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            call(
+                "hmac_sha256",
+                &["key", "The quick brown fox jumps over the lazy dog"]
+            ),
+            "f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+        assert_eq!(
+            call("jwt", &[r#"{"sub":"1234567890"}"#, "secret"]),
+            "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9.eyJzdWIiOiIxMjM0NTY3ODkwIn0.\
+             Rq8IxqeX7eA6GgYxlcHdPFVRNFFZc5rEI3MQTZZbK3I"
+        );
+    }
+
+    #[cfg(feature = "fake")]
+    #[test]
+    fn fake() {
+        let uuid = call("fake.uuid", &[]).unwrap();
+        assert_eq!(uuid.len(), 36);
+        assert_eq!(&uuid[14..15], "4");
+        assert_ne!(uuid, call("fake.uuid", &[]).unwrap());
+        assert!(call("fake.email", &[]).unwrap().contains('@'));
+        assert_eq!(
+            call("fake.lorem", &["3".into()])
+                .unwrap()
+                .split(' ')
+                .count(),
+            3
+        );
+        assert_eq!(
+            call("fake.lorem", &["1".into(), "2".into()])
+                .unwrap_err()
+                .to_string(),
+            "function 'fake.lorem' takes 0 to 1 arguments, got 2"
+        );
+    }
+}
@@ -0,0 +1,209 @@
+/// Graph is used to show how tests, requests and variables depend on
+/// each other, to see what a change to a shared request affects.
+use std::collections::BTreeSet;
+
+use crate::{Config, Template};
+
+use thiserror::Error;
+
+/// GraphError is the error type for graphs.
+#[derive(Error, Debug)]
+pub enum GraphError {
+    #[error("format error: {0}")]
+    Format(String),
+}
+
+/// Result is the result type for graphs.
+pub type Result<T> = std::result::Result<T, GraphError>;
+
+/// GraphFormat is the language the graph is written in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GraphFormat {
+    Dot,
+    Mermaid,
+}
+
+impl std::str::FromStr for GraphFormat {
+    type Err = GraphError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "dot" | "graphviz" => Ok(GraphFormat::Dot),
+            "mermaid" => Ok(GraphFormat::Mermaid),
+            _ => Err(GraphError::Format(format!("unknown format: {}", s))),
+        }
+    }
+}
+
+/// Node is a test, request or context variable.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Node {
+    Test(String),
+    Request(String),
+    Variable(String),
+}
+
+impl Node {
+    fn name(&self) -> &str {
+        match self {
+            Node::Test(n) | Node::Request(n) | Node::Variable(n) => n,
+        }
+    }
+
+    /// An ID that is unique across the kinds of nodes.
+    fn id(&self) -> String {
+        match self {
+            Node::Test(n) => format!("test:{}", n),
+            Node::Request(n) => format!("request:{}", n),
+            Node::Variable(n) => format!("variable:{}", n),
+        }
+    }
+}
+
+/// Graph is the edges from each test to the requests it makes and from
+/// each request to the responses and variables its templates use.
+#[derive(Debug, Default, PartialEq)]
+pub struct Graph {
+    pub edges: BTreeSet<(Node, Node)>,
+}
+
+impl Graph {
+    pub fn from_config(cfg: &Config) -> Self {
+        let mut edges = BTreeSet::new();
+        for (name, test) in &cfg.tests {
+            for step in &test.steps {
+                edges.insert((
+                    Node::Test(name.clone()),
+                    Node::Request(step.request.clone()),
+                ));
+            }
+        }
+        for (name, request) in &cfg.requests {
+            for (_, text) in request.templates() {
+                for variable in Template::parse(&text).variables() {
+                    edges.insert((Node::Request(name.clone()), node(variable)));
+                }
+            }
+        }
+        Self { edges }
+    }
+
+    /// The nodes in the edges, sorted and without duplicates.
+    fn nodes(&self) -> BTreeSet<&Node> {
+        self.edges.iter().flat_map(|(a, b)| [a, b]).collect()
+    }
+
+    pub fn render(&self, format: GraphFormat) -> String {
+        match format {
+            GraphFormat::Dot => self.dot(),
+            GraphFormat::Mermaid => self.mermaid(),
+        }
+    }
+
+    fn dot(&self) -> String {
+        let mut out = String::from("digraph apictl {\n  rankdir=LR;\n");
+        for node in self.nodes() {
+            let shape = match node {
+                Node::Test(_) => "box",
+                Node::Request(_) => "ellipse",
+                Node::Variable(_) => "note",
+            };
+            out.push_str(&format!(
+                "  {} [label={}, shape={}];\n",
+                quote(&node.id()),
+                quote(node.name()),
+                shape
+            ));
+        }
+        for (from, to) in &self.edges {
+            out.push_str(&format!(
+                "  {} -> {};\n",
+                quote(&from.id()),
+                quote(&to.id())
+            ));
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    fn mermaid(&self) -> String {
+        // Mermaid IDs can't have most punctuation, so nodes are numbered.
+        let nodes = self.nodes().into_iter().collect::<Vec<_>>();
+        let id = |node: &Node| format!("n{}", nodes.iter().position(|n| *n == node).unwrap_or(0));
+        let mut out = String::from("flowchart LR\n");
+        for node in &nodes {
+            let label = node.name().replace('"', "#quot;");
+            let shape = match node {
+                Node::Test(_) => format!("[\"{}\"]", label),
+                Node::Request(_) => format!("(\"{}\")", label),
+                Node::Variable(_) => format!("{{{{\"{}\"}}}}", label),
+            };
+            out.push_str(&format!("  {}{}\n", id(node), shape));
+        }
+        for (from, to) in &self.edges {
+            out.push_str(&format!("  {} --> {}\n", id(from), id(to)));
+        }
+        out
+    }
+}
+
+/// The node a template variable refers to. Responses, whether from this
+/// run or cached, are the requests that made them.
+fn node(variable: &str) -> Node {
+    let response = variable
+        .strip_prefix("response.")
+        .or_else(|| variable.strip_prefix("cached."));
+    match response {
+        Some(rest) => Node::Request(rest.split('.').next().unwrap_or(rest).to_string()),
+        None => Node::Variable(variable.to_string()),
+    }
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_config() {
+        let cfg: Config = serde_yaml::from_str(
+            r#"
+requests:
+  create-user:
+    description: ""
+    tags: []
+    url: ${base_url}/users
+  get-user:
+    description: ""
+    tags: []
+    url: ${base_url}/users/${response.create-user.id}
+tests:
+  users:
+    description: ""
+    steps:
+      - {name: create, request: create-user, asserts: []}
+      - {name: get, request: get-user, asserts: []}
+"#,
+        )
+        .unwrap();
+        let graph = Graph::from_config(&cfg);
+        let request = |n: &str| Node::Request(n.into());
+        assert!(graph
+            .edges
+            .contains(&(request("get-user"), request("create-user"))));
+        assert!(graph
+            .edges
+            .contains(&(Node::Test("users".into()), request("get-user"))));
+        assert_eq!(graph.nodes().len(), 4);
+
+        let dot = graph.render(GraphFormat::Dot);
+        assert!(dot.contains("\"request:get-user\" -> \"request:create-user\";"));
+        let mermaid = graph.render(GraphFormat::Mermaid);
+        assert!(mermaid.starts_with("flowchart LR\n  n0[\"users\"]\n"));
+        assert!(mermaid.contains("  n3{{\"base_url\"}}\n"));
+        assert!(mermaid.contains("  n2 --> n1\n"));
+    }
+}
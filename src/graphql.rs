@@ -0,0 +1,275 @@
+/// GraphQL is used to introspect GraphQL endpoints and validate the
+/// queries in requests against their schemas.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use graphql_parser::query::{
+    Definition, Document, OperationDefinition, Selection, SelectionSet, TypeCondition,
+};
+use serde_json::Value;
+use thiserror::Error;
+
+/// The query sent to an endpoint to learn its schema. Only the parts
+/// needed to validate field selections are requested.
+pub const INTROSPECTION_QUERY: &str = r#"query IntrospectionQuery {
+  __schema {
+    queryType { name }
+    mutationType { name }
+    subscriptionType { name }
+    types {
+      name
+      fields(includeDeprecated: true) {
+        name
+        type { ...TypeRef }
+      }
+    }
+  }
+}
+
+fragment TypeRef on __Type {
+  kind
+  name
+  ofType { kind name ofType { kind name ofType { kind name ofType { kind name } } } }
+}"#;
+
+/// GraphQLError is the error type for GraphQL schemas.
+#[derive(Error, Debug)]
+pub enum GraphQLError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("invalid introspection result: {0}")]
+    Introspection(String),
+
+    #[error("query parse error: {0}")]
+    Parse(String),
+}
+
+/// Result is the result type for GraphQL schemas.
+pub type Result<T> = std::result::Result<T, GraphQLError>;
+
+/// Schema is the part of an introspected schema needed to validate
+/// queries: the root types and the fields of each type.
+#[derive(Debug, Default)]
+pub struct Schema {
+    pub query: Option<String>,
+    pub mutation: Option<String>,
+    pub subscription: Option<String>,
+    /// Type name to field name to the field's (unwrapped) type name.
+    pub types: HashMap<String, HashMap<String, String>>,
+}
+
+/// The path in the cache where the named schema is stored.
+pub fn schema_path(cache_dir: &Path, name: &str) -> PathBuf {
+    cache_dir.join("graphql").join(format!("{}.json", name))
+}
+
+impl Schema {
+    /// Build a schema from an introspection response body.
+    pub fn from_introspection(body: &Value) -> Result<Self> {
+        let schema = body
+            .pointer("/data/__schema")
+            .or_else(|| body.get("__schema"))
+            .ok_or_else(|| GraphQLError::Introspection("missing __schema".into()))?;
+
+        let root = |name: &str| {
+            schema
+                .get(name)
+                .and_then(|t| t.get("name"))
+                .and_then(Value::as_str)
+                .map(String::from)
+        };
+
+        let mut types = HashMap::new();
+        for t in schema
+            .get("types")
+            .and_then(Value::as_array)
+            .ok_or_else(|| GraphQLError::Introspection("missing types".into()))?
+        {
+            let name = match t.get("name").and_then(Value::as_str) {
+                Some(n) => n.to_string(),
+                None => continue,
+            };
+            let fields = t
+                .get("fields")
+                .and_then(Value::as_array)
+                .map(|fields| {
+                    fields
+                        .iter()
+                        .filter_map(|f| {
+                            Some((
+                                f.get("name")?.as_str()?.to_string(),
+                                named_type(f.get("type")?)?,
+                            ))
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            types.insert(name, fields);
+        }
+
+        Ok(Self {
+            query: root("queryType"),
+            mutation: root("mutationType"),
+            subscription: root("subscriptionType"),
+            types,
+        })
+    }
+
+    /// Load a cached introspection response.
+    pub fn load(path: &Path) -> Result<Self> {
+        let body: Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        Self::from_introspection(&body)
+    }
+
+    /// Validate the query against the schema, returning a message for
+    /// each field that doesn't exist on its parent type.
+    pub fn validate(&self, query: &str) -> Result<Vec<String>> {
+        let doc: Document<String> =
+            graphql_parser::parse_query(query).map_err(|e| GraphQLError::Parse(e.to_string()))?;
+
+        let fragments = doc
+            .definitions
+            .iter()
+            .filter_map(|d| match d {
+                Definition::Fragment(f) => Some((f.name.clone(), f)),
+                _ => None,
+            })
+            .collect::<HashMap<_, _>>();
+
+        let mut problems = vec![];
+        for definition in &doc.definitions {
+            let (root, selection_set) = match definition {
+                Definition::Operation(OperationDefinition::SelectionSet(s)) => (&self.query, s),
+                Definition::Operation(OperationDefinition::Query(q)) => {
+                    (&self.query, &q.selection_set)
+                }
+                Definition::Operation(OperationDefinition::Mutation(m)) => {
+                    (&self.mutation, &m.selection_set)
+                }
+                Definition::Operation(OperationDefinition::Subscription(s)) => {
+                    (&self.subscription, &s.selection_set)
+                }
+                Definition::Fragment(f) => {
+                    let TypeCondition::On(t) = &f.type_condition;
+                    self.check(t, &f.selection_set, &fragments, &mut problems);
+                    continue;
+                }
+            };
+            match root {
+                Some(root) => self.check(root, selection_set, &fragments, &mut problems),
+                None => problems.push("schema doesn't support this operation type".into()),
+            }
+        }
+        Ok(problems)
+    }
+
+    fn check(
+        &self,
+        type_name: &str,
+        selection_set: &SelectionSet<String>,
+        fragments: &HashMap<String, &graphql_parser::query::FragmentDefinition<String>>,
+        problems: &mut Vec<String>,
+    ) {
+        let fields = match self.types.get(type_name) {
+            Some(f) => f,
+            None => {
+                problems.push(format!("unknown type '{}'", type_name));
+                return;
+            }
+        };
+        for selection in &selection_set.items {
+            match selection {
+                Selection::Field(field) => {
+                    if field.name.starts_with("__") {
+                        continue;
+                    }
+                    match fields.get(&field.name) {
+                        Some(t) => self.check(t, &field.selection_set, fragments, problems),
+                        None => problems.push(format!(
+                            "unknown field '{}' on type '{}'",
+                            field.name, type_name
+                        )),
+                    }
+                }
+                Selection::InlineFragment(inline) => {
+                    let t = match &inline.type_condition {
+                        Some(TypeCondition::On(t)) => t.as_str(),
+                        None => type_name,
+                    };
+                    self.check(t, &inline.selection_set, fragments, problems);
+                }
+                Selection::FragmentSpread(spread) => {
+                    if !fragments.contains_key(&spread.fragment_name) {
+                        problems.push(format!("unknown fragment '{}'", spread.fragment_name));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Unwrap NON_NULL and LIST wrappers to find the named type.
+fn named_type(t: &Value) -> Option<String> {
+    match t.get("name").and_then(Value::as_str) {
+        Some(name) => Some(name.to_string()),
+        None => named_type(t.get("ofType")?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate() {
+        let body = serde_json::json!({
+            "data": {
+                "__schema": {
+                    "queryType": { "name": "Query" },
+                    "mutationType": null,
+                    "subscriptionType": null,
+                    "types": [
+                        {
+                            "name": "Query",
+                            "fields": [
+                                { "name": "user", "type": { "kind": "OBJECT", "name": "User" } },
+                            ]
+                        },
+                        {
+                            "name": "User",
+                            "fields": [
+                                { "name": "id", "type": { "kind": "NON_NULL", "name": null, "ofType": { "kind": "SCALAR", "name": "ID" } } },
+                                { "name": "name", "type": { "kind": "SCALAR", "name": "String" } },
+                            ]
+                        },
+                        { "name": "ID", "fields": null },
+                        { "name": "String", "fields": null },
+                    ]
+                }
+            }
+        });
+        let schema = Schema::from_introspection(&body).unwrap();
+
+        assert!(schema
+            .validate("{ user { id name __typename } }")
+            .unwrap()
+            .is_empty());
+        assert!(schema
+            .validate("query { user { ...U } } fragment U on User { id }")
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            schema.validate("query { user { id nmae } }").unwrap(),
+            vec!["unknown field 'nmae' on type 'User'"]
+        );
+        assert_eq!(
+            schema.validate("mutation { user { id } }").unwrap(),
+            vec!["schema doesn't support this operation type"]
+        );
+        assert!(schema.validate("query {").is_err());
+    }
+}
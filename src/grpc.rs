@@ -0,0 +1,263 @@
+/// Grpc is used to call gRPC services over HTTP/1.1 using the Connect or
+/// gRPC-Web protocols, with JSON messages.
+use std::collections::HashMap;
+
+use crate::{Applicator, Response};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// GrpcError is the error type for gRPC requests.
+#[derive(Error, Debug)]
+pub enum GrpcError {
+    #[error("http error: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("invalid frame: {0}")]
+    Frame(String),
+}
+
+/// Result is the result type for gRPC requests.
+pub type Result<T> = std::result::Result<T, GrpcError>;
+
+/// Protocol is how the call is framed over HTTP.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Protocol {
+    /// Connect's unary protocol, where the message is the body.
+    #[default]
+    Connect,
+    /// gRPC-Web, where messages are length prefixed and the status is
+    /// sent in a trailer frame.
+    GrpcWeb,
+}
+
+/// Grpc turns a request into a unary call of the method on the service.
+/// The request's URL is the server and the call is made to
+/// `URL/SERVICE/METHOD`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
+pub struct Grpc {
+    #[serde(default)]
+    pub protocol: Protocol,
+    /// The fully qualified service, like `acme.user.v1.UserService`.
+    pub service: String,
+    pub method: String,
+    /// The JSON request message.
+    #[serde(default = "default_message")]
+    pub message: String,
+}
+
+fn default_message() -> String {
+    "{}".to_string()
+}
+
+/// The flag set on gRPC-Web frames that hold trailers.
+const TRAILER_FLAG: u8 = 0x80;
+
+/// The gRPC status codes by their Connect names.
+const CODES: [&str; 17] = [
+    "ok",
+    "canceled",
+    "unknown",
+    "invalid_argument",
+    "deadline_exceeded",
+    "not_found",
+    "already_exists",
+    "permission_denied",
+    "resource_exhausted",
+    "failed_precondition",
+    "aborted",
+    "out_of_range",
+    "unimplemented",
+    "internal",
+    "unavailable",
+    "data_loss",
+    "unauthenticated",
+];
+
+impl Grpc {
+    /// Apply the context to the names and message.
+    pub fn apply(&mut self, app: &Applicator) {
+        self.service = app.apply(&self.service);
+        self.method = app.apply(&self.method);
+        self.message = app.apply(&self.message);
+    }
+
+    /// Make the call. The response has `grpc-status` and `grpc-message`
+    /// headers for either protocol, and the body is the response message,
+    /// or a JSON array of them when the server streams more than one.
+    pub async fn request(
+        &self,
+        client: &reqwest::Client,
+        url: &str,
+        headers: &HashMap<String, String>,
+    ) -> Result<Response> {
+        let url = format!(
+            "{}/{}/{}",
+            url.trim_end_matches('/'),
+            self.service,
+            self.method
+        );
+        let mut builder = client.post(&url);
+        builder = match self.protocol {
+            Protocol::Connect => builder
+                .header("content-type", "application/json")
+                .header("connect-protocol-version", "1")
+                .body(self.message.clone()),
+            Protocol::GrpcWeb => builder
+                .header("content-type", "application/grpc-web+json")
+                .header("x-grpc-web", "1")
+                .body(frame(0, self.message.as_bytes())),
+        };
+        for (key, value) in headers.iter() {
+            builder = builder.header(key, value);
+        }
+
+        let resp = builder.send().await?;
+        let mut response = Response {
+            status_code: resp.status().as_u16(),
+            version: format!("{:?}", resp.version()),
+            headers: resp
+                .headers()
+                .iter()
+                .map(|(k, v)| {
+                    (
+                        k.to_string(),
+                        String::from_utf8_lossy(v.as_bytes()).to_string(),
+                    )
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let body = resp.bytes().await?;
+        match self.protocol {
+            Protocol::Connect => connect_response(&mut response, &body),
+            Protocol::GrpcWeb => grpc_web_response(&mut response, &body)?,
+        }
+        Ok(response)
+    }
+}
+
+/// Length prefix the message as a frame with the flags.
+fn frame(flags: u8, message: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(message.len() + 5);
+    frame.push(flags);
+    frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    frame.extend_from_slice(message);
+    frame
+}
+
+/// Split the body into its frames and their flags.
+fn frames(mut body: &[u8]) -> Result<Vec<(u8, &[u8])>> {
+    let mut frames = vec![];
+    while !body.is_empty() {
+        if body.len() < 5 {
+            return Err(GrpcError::Frame(format!(
+                "{} bytes left, want a 5 byte prefix",
+                body.len()
+            )));
+        }
+        let length = u32::from_be_bytes([body[1], body[2], body[3], body[4]]) as usize;
+        let end = 5 + length;
+        if body.len() < end {
+            return Err(GrpcError::Frame(format!(
+                "frame is {} bytes, only {} left",
+                length,
+                body.len() - 5
+            )));
+        }
+        frames.push((body[0], &body[5..end]));
+        body = &body[end..];
+    }
+    Ok(frames)
+}
+
+/// Connect errors are a JSON body with the code's name and a message.
+fn connect_response(response: &mut Response, body: &[u8]) {
+    response.set_body(String::from_utf8_lossy(body).to_string());
+    let (code, message) = match response.status_code {
+        200 => (0, String::new()),
+        _ => {
+            let error = response.json();
+            let name = error
+                .and_then(|e| e.get("code"))
+                .and_then(|c| c.as_str())
+                .unwrap_or("unknown");
+            let message = error
+                .and_then(|e| e.get("message"))
+                .and_then(|m| m.as_str())
+                .unwrap_or_default()
+                .to_string();
+            (CODES.iter().position(|c| *c == name).unwrap_or(2), message)
+        }
+    };
+    response
+        .headers
+        .insert("grpc-status".into(), code.to_string());
+    response.headers.insert("grpc-message".into(), message);
+}
+
+/// gRPC-Web trailers are in the last frame, or in the headers when the
+/// server sent no messages.
+fn grpc_web_response(response: &mut Response, body: &[u8]) -> Result<()> {
+    let mut messages = vec![];
+    for (flags, data) in frames(body)? {
+        let data = String::from_utf8_lossy(data).to_string();
+        match flags & TRAILER_FLAG {
+            0 => messages.push(data),
+            _ => {
+                for line in data.lines() {
+                    if let Some((key, value)) = line.split_once(':') {
+                        response
+                            .headers
+                            .insert(key.trim().to_lowercase(), value.trim().to_string());
+                    }
+                }
+            }
+        }
+    }
+    let body = match messages.len() {
+        1 => messages.remove(0),
+        _ => format!("[{}]", messages.join(",")),
+    };
+    response.set_body(body);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grpc_web() {
+        let mut body = frame(0, br#"{"id": "7"}"#);
+        body.extend(frame(TRAILER_FLAG, b"grpc-status: 0\r\ngrpc-message: \r\n"));
+        let mut response = Response {
+            status_code: 200,
+            ..Default::default()
+        };
+        grpc_web_response(&mut response, &body).unwrap();
+        assert_eq!(response.headers["grpc-status"], "0");
+        assert_eq!(response.find_path_in_body("id"), Some("7".into()));
+
+        assert!(matches!(
+            grpc_web_response(&mut response, &body[..8]),
+            Err(GrpcError::Frame(_))
+        ));
+    }
+
+    #[test]
+    fn connect_errors() {
+        let mut response = Response {
+            status_code: 404,
+            ..Default::default()
+        };
+        connect_response(
+            &mut response,
+            br#"{"code": "not_found", "message": "no user 7"}"#,
+        );
+        assert_eq!(response.headers["grpc-status"], "5");
+        assert_eq!(response.headers["grpc-message"], "no user 7");
+    }
+}
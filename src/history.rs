@@ -0,0 +1,455 @@
+/// History is used to keep a summary of every test run and benchmark
+/// in the cache folder, so failures and durations can be followed over
+/// time and tests that fail now and then can be found.
+use std::collections::{BTreeMap, HashMap};
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::{List, Results, State};
+
+use fs4::FileExt;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// HistoryError is the error type for the history.
+#[derive(Error, Debug)]
+pub enum HistoryError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("run not found: {0}")]
+    NotFound(String),
+}
+
+/// Result is the result type for the history.
+pub type Result<T> = std::result::Result<T, HistoryError>;
+
+/// Run is the summary of one test run or benchmark.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Run {
+    /// The run ID the responses were cached under.
+    pub id: String,
+    /// What was run, like `tests run` or `benchmark`.
+    pub command: String,
+    /// When the run finished.
+    #[serde(with = "humantime_serde")]
+    pub time: SystemTime,
+    /// The git commit of the configuration, if it's in a repository.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git: Option<String>,
+    #[serde(with = "humantime_serde")]
+    pub duration: Duration,
+    pub passed: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub results: Option<Results>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub benchmark: Option<Benchmark>,
+}
+
+impl Run {
+    /// A run of the command that finished now. The ID and commit are
+    /// set by the history when it's added.
+    pub fn new(command: &str, duration: Duration, passed: bool) -> Self {
+        Self {
+            id: String::new(),
+            command: command.into(),
+            time: SystemTime::now(),
+            git: None,
+            duration,
+            passed,
+            results: None,
+            benchmark: None,
+        }
+    }
+
+    pub fn with_results(mut self, results: Results) -> Self {
+        self.results = Some(results);
+        self
+    }
+
+    pub fn with_benchmark(mut self, benchmark: Benchmark) -> Self {
+        self.benchmark = Some(benchmark);
+        self
+    }
+}
+
+/// Benchmark is the summary of a benchmark.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Benchmark {
+    pub requests: Vec<String>,
+    pub total: usize,
+    /// The requests that failed without a response.
+    pub failed: usize,
+    pub status_codes: BTreeMap<u16, usize>,
+    #[serde(with = "humantime_serde")]
+    pub mean: Duration,
+    #[serde(with = "humantime_serde")]
+    pub fastest: Duration,
+    #[serde(with = "humantime_serde")]
+    pub slowest: Duration,
+    /// The connections opened to make the requests.
+    #[serde(default)]
+    pub connections: usize,
+}
+
+/// Implement List for the runs by their number.
+impl List for HashMap<String, Run> {
+    fn headers(&self) -> Vec<String> {
+        ["Run", "ID", "Command", "Time", "Git", "Duration", "Passed"]
+            .into_iter()
+            .map(String::from)
+            .collect()
+    }
+
+    fn values(&self) -> Vec<Vec<String>> {
+        self.iter()
+            .map(|(n, r)| {
+                vec![
+                    n.clone(),
+                    r.id.clone(),
+                    r.command.clone(),
+                    humantime::format_rfc3339_seconds(r.time).to_string(),
+                    r.git.as_deref().map(short).unwrap_or_default().to_string(),
+                    format!("{:?}", r.duration),
+                    r.passed.to_string(),
+                ]
+            })
+            .collect()
+    }
+}
+
+/// Point is a test or request in one run, for following it over time.
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct Point {
+    #[serde(with = "humantime_serde")]
+    pub time: SystemTime,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub git: Option<String>,
+    /// How long the test took, or the mean of the request in a
+    /// benchmark.
+    #[serde(with = "humantime_serde")]
+    pub duration: Duration,
+    pub passed: bool,
+}
+
+/// Implement List for the points of a trend by run number.
+impl List for HashMap<String, Point> {
+    fn headers(&self) -> Vec<String> {
+        ["Run", "Time", "Git", "Duration", "Passed"]
+            .into_iter()
+            .map(String::from)
+            .collect()
+    }
+
+    fn values(&self) -> Vec<Vec<String>> {
+        self.iter()
+            .map(|(n, p)| {
+                vec![
+                    n.clone(),
+                    humantime::format_rfc3339_seconds(p.time).to_string(),
+                    p.git.as_deref().map(short).unwrap_or_default().to_string(),
+                    format!("{:?}", p.duration),
+                    p.passed.to_string(),
+                ]
+            })
+            .collect()
+    }
+}
+
+/// The abbreviated commit.
+fn short(git: &str) -> &str {
+    &git[..git.len().min(8)]
+}
+
+/// The number of runs kept in the history by default.
+pub const MAX_RUNS: usize = 1000;
+
+/// History is the runs kept as JSON lines in a file in the cache
+/// folder. Runs are numbered from 1 in the order they were added. Only
+/// the latest runs are kept, so the numbers shift once it's full.
+#[derive(Clone, Debug)]
+pub struct History {
+    path: PathBuf,
+    id: String,
+    git: Option<String>,
+    max_runs: usize,
+}
+
+impl History {
+    pub fn new(cache_dir: &Path) -> Self {
+        Self {
+            path: cache_dir.join("history.jsonl"),
+            id: String::new(),
+            git: None,
+            max_runs: MAX_RUNS,
+        }
+    }
+
+    /// Keep only the latest runs.
+    pub fn with_max_runs(mut self, max_runs: usize) -> Self {
+        self.max_runs = max_runs;
+        self
+    }
+
+    /// Record the runs under the run ID and git commit.
+    pub fn with_run(mut self, id: &str, git: Option<String>) -> Self {
+        self.id = id.into();
+        self.git = git;
+        self
+    }
+
+    /// Append the run, dropping the oldest runs past the maximum. The
+    /// file is locked while it's written, so concurrent runs don't
+    /// interleave their lines.
+    pub fn append(&self, mut run: Run) -> Result<()> {
+        run.id = self.id.clone();
+        run.git = self.git.clone();
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&self.path)?;
+        file.lock_exclusive()?;
+        writeln!(file, "{}", serde_json::to_string(&run)?)?;
+
+        let mut contents = String::new();
+        file.seek(SeekFrom::Start(0))?;
+        file.read_to_string(&mut contents)?;
+        let lines: Vec<&str> = contents.lines().collect();
+        if lines.len() > self.max_runs {
+            file.set_len(0)?;
+            for line in &lines[lines.len() - self.max_runs..] {
+                writeln!(file, "{}", line)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The runs, oldest first. Lines that can't be read, like ones from
+    /// an older version, are skipped.
+    pub fn runs(&self) -> Result<Vec<Run>> {
+        let file = match std::fs::File::open(&self.path) {
+            Ok(f) => f,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+        FileExt::lock_shared(&file)?;
+        let mut runs = vec![];
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            match serde_json::from_str(&line?) {
+                Ok(run) => runs.push(run),
+                Err(e) => tracing::warn!(line = i + 1, error = %e, "skipping history"),
+            }
+        }
+        Ok(runs)
+    }
+}
+
+/// The commit checked out where the configuration is, if it's in a git
+/// repository.
+pub fn git_revision(config: &Path) -> Option<String> {
+    let dir = match config.is_dir() {
+        true => config,
+        false => config.parent()?,
+    };
+    let dir = match dir.as_os_str().is_empty() {
+        true => Path::new("."),
+        false => dir,
+    };
+    let output = std::process::Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// The runs by their number.
+pub fn numbered(runs: Vec<Run>) -> HashMap<String, Run> {
+    runs.into_iter()
+        .enumerate()
+        .map(|(i, r)| ((i + 1).to_string(), r))
+        .collect()
+}
+
+/// Find the run by its number or, for runs that share an ID, the latest
+/// with the ID.
+pub fn find<'a>(runs: &'a [Run], run: &str) -> Result<&'a Run> {
+    let by_number = run
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| runs.get(n.checked_sub(1)?));
+    by_number
+        .or_else(|| runs.iter().rev().find(|r| r.id == run))
+        .ok_or_else(|| HistoryError::NotFound(run.into()))
+}
+
+/// The test, or request in benchmarks, in each run it was part of, by
+/// run number.
+pub fn trend(runs: &[Run], name: &str) -> HashMap<String, Point> {
+    let mut points = HashMap::new();
+    for (i, run) in runs.iter().enumerate() {
+        let point = |duration, passed| Point {
+            time: run.time,
+            git: run.git.clone(),
+            duration,
+            passed,
+        };
+        let test = run
+            .results
+            .iter()
+            .flat_map(|r| &r.children)
+            .find(|t| t.name == name);
+        if let Some(test) = test {
+            let passed = matches!(test.state, State::Passed | State::FlakyPass(_))
+                && test.failures().is_empty();
+            points.insert((i + 1).to_string(), point(test.duration, passed));
+        }
+        if let Some(b) = run
+            .benchmark
+            .as_ref()
+            .filter(|b| b.requests.iter().any(|r| r == name))
+        {
+            points.insert((i + 1).to_string(), point(b.mean, b.failed == 0));
+        }
+    }
+    points
+}
+
+/// Flakiness is how often a test passed, passed after retries and
+/// failed in the history.
+#[derive(Clone, Debug, Default, Serialize, PartialEq)]
+pub struct Flakiness {
+    pub runs: usize,
+    pub passed: usize,
+    pub flaky: usize,
+    pub failed: usize,
+}
+
+/// Implement List for the flakiness of tests.
+impl List for HashMap<String, Flakiness> {
+    fn headers(&self) -> Vec<String> {
+        ["Name", "Runs", "Passed", "Flaky", "Failed"]
+            .into_iter()
+            .map(String::from)
+            .collect()
+    }
+
+    fn values(&self) -> Vec<Vec<String>> {
+        self.iter()
+            .map(|(n, f)| {
+                vec![
+                    n.clone(),
+                    f.runs.to_string(),
+                    f.passed.to_string(),
+                    f.flaky.to_string(),
+                    f.failed.to_string(),
+                ]
+            })
+            .collect()
+    }
+}
+
+/// The flakiness of each test that passed after retries or failed in
+/// the runs.
+pub fn flakiness(runs: &[Run]) -> HashMap<String, Flakiness> {
+    let mut tests: HashMap<String, Flakiness> = HashMap::new();
+    let results = runs.iter().filter_map(|r| r.results.as_ref());
+    for test in results.flat_map(|r| &r.children) {
+        let flakiness = tests.entry(test.name.clone()).or_default();
+        flakiness.runs += 1;
+        match (&test.state, test.failures().is_empty()) {
+            (State::FlakyPass(_), _) => flakiness.flaky += 1,
+            (State::Passed, true) => flakiness.passed += 1,
+            _ => flakiness.failed += 1,
+        }
+    }
+    tests.retain(|_, f| f.flaky > 0 || f.failed > 0);
+    tests
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let history = History::new(dir).with_run("nightly", None);
+        for state in [State::Passed, State::FlakyPass(2), State::Passed] {
+            let mut results = Results::new("test results");
+            results.add("stable");
+            results.add("flaky");
+            results.children[0].state = State::Passed;
+            results.children[1].state = state;
+            history
+                .append(Run::new("tests run", Duration::ZERO, true).with_results(results))
+                .unwrap();
+        }
+        let mut benchmark = Run::new("benchmark", Duration::ZERO, false);
+        benchmark.benchmark = Some(Benchmark {
+            requests: vec!["flaky".into()],
+            failed: 1,
+            mean: Duration::from_millis(5),
+            ..Default::default()
+        });
+        history.append(benchmark).unwrap();
+        let runs = history.runs().unwrap();
+        assert_eq!(runs.len(), 4);
+        assert_eq!(find(&runs, "4").unwrap().command, "benchmark");
+        assert!(find(&runs, "5").is_err());
+
+        let trend = trend(&runs, "flaky");
+        assert_eq!(trend.len(), 4);
+        assert!(trend["2"].passed);
+        assert!(!trend["4"].passed);
+        assert_eq!(trend["4"].duration, Duration::from_millis(5));
+
+        let flakiness = flakiness(&runs);
+        assert_eq!(flakiness.len(), 1);
+        assert_eq!(
+            flakiness["flaky"],
+            Flakiness {
+                runs: 3,
+                passed: 2,
+                flaky: 1,
+                failed: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn capped() {
+        let temp = tempfile::tempdir().unwrap();
+        let history = History::new(temp.path()).with_max_runs(5);
+        let threads: Vec<_> = (0..20)
+            .map(|i| {
+                let history = history.clone().with_run(&i.to_string(), None);
+                std::thread::spawn(move || {
+                    history
+                        .append(Run::new("tests run", Duration::ZERO, true))
+                        .unwrap()
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        let contents = std::fs::read_to_string(temp.path().join("history.jsonl")).unwrap();
+        assert_eq!(contents.lines().count(), 5);
+        assert_eq!(history.runs().unwrap().len(), 5);
+    }
+}
@@ -0,0 +1,716 @@
+/// Hurl is used to convert between Hurl files and requests and tests,
+/// so suites can be moved over, or back, a bit at a time.
+use std::collections::HashMap;
+
+use crate::request::{Body, MultiPartField, MultiPartValue, RawBody};
+use crate::template::Segment;
+use crate::test::{Assert, Step};
+use crate::{Request, Template, Test};
+
+use regex::Regex;
+use thiserror::Error;
+
+/// HurlError is the error type for Hurl files.
+#[derive(Error, Debug)]
+pub enum HurlError {
+    #[error("line {line}: {message}")]
+    Parse { line: usize, message: String },
+
+    #[error("request not found: {0}")]
+    RequestNotFound(String),
+}
+
+/// Result is the result type for Hurl files.
+pub type Result<T> = std::result::Result<T, HurlError>;
+
+/// Entry is a request in a Hurl file and what's checked and captured
+/// from its response.
+#[derive(Debug, Default, Clone)]
+pub struct Entry {
+    pub request: Request,
+    pub asserts: Vec<Assert>,
+    /// Variables set from the response, as the name and where it comes
+    /// from: a body path or `headers.NAME`.
+    pub captures: Vec<(String, String)>,
+}
+
+/// The part of an entry being parsed.
+#[derive(Clone, Copy, PartialEq)]
+enum Section {
+    Headers,
+    Query,
+    Form,
+    Multipart,
+    ResponseHeaders,
+    Captures,
+    Asserts,
+    Other,
+}
+
+/// Parse the entries in a Hurl file. Templates like `{{id}}` become
+/// `${id}`.
+pub fn parse(s: &str) -> Result<Vec<Entry>> {
+    let request_line = Regex::new(r"^([A-Z]+)\s+(\S+)\s*$").unwrap();
+    let status_line = Regex::new(r"^HTTP(?:/[\d.]+)?\s+(\d{3}|\*)\s*$").unwrap();
+
+    let lines = s.lines().collect::<Vec<_>>();
+    let mut entries: Vec<Entry> = vec![];
+    let mut section = Section::Other;
+    let mut i = 0;
+    while i < lines.len() {
+        let number = i + 1;
+        let line = lines[i].trim();
+        i += 1;
+        let error = |message: String| HurlError::Parse {
+            line: number,
+            message,
+        };
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(c) = request_line.captures(line) {
+            if &c[1] != "HTTP" {
+                entries.push(Entry {
+                    request: Request {
+                        method: c[1].to_string(),
+                        url: template(&c[2]),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                });
+                section = Section::Headers;
+                continue;
+            }
+        }
+        let entry = entries
+            .last_mut()
+            .ok_or_else(|| error(format!("expected a request, got '{}'", line)))?;
+
+        if let Some(c) = status_line.captures(line) {
+            if let Ok(value) = c[1].parse() {
+                entry.asserts.push(Assert::StatusCode { value });
+            }
+            section = Section::ResponseHeaders;
+            continue;
+        }
+        if line.starts_with('[') && line.ends_with(']') {
+            section = match &line[1..line.len() - 1] {
+                "QueryStringParams" | "Query" => Section::Query,
+                "FormParams" | "Form" => Section::Form,
+                "MultipartFormData" | "Multipart" => Section::Multipart,
+                "Captures" => Section::Captures,
+                "Asserts" => Section::Asserts,
+                _ => Section::Other,
+            };
+            continue;
+        }
+
+        // Bodies are in a code block or start like JSON.
+        if section == Section::Headers && (line.starts_with("```") || is_json_start(line)) {
+            let (body, next) = body(&lines, i - 1);
+            entry.request.body = match body.strip_prefix("file,") {
+                Some(path) => Body::Raw {
+                    from: RawBody::File {
+                        path: path.trim_end_matches(';').to_string(),
+                    },
+                },
+                None => Body::Raw {
+                    from: RawBody::Text {
+                        data: template(&body),
+                    },
+                },
+            };
+            i = next;
+            continue;
+        }
+        if section == Section::Headers && line.starts_with("file,") {
+            entry.request.body = Body::Raw {
+                from: RawBody::File {
+                    path: line["file,".len()..].trim_end_matches(';').to_string(),
+                },
+            };
+            continue;
+        }
+
+        match section {
+            Section::Asserts => entry
+                .asserts
+                .push(assert(line).ok_or_else(|| error(format!("unsupported assert '{}'", line)))?),
+            Section::Other => {}
+            _ => {
+                let (key, value) = line
+                    .split_once(':')
+                    .map(|(k, v)| (k.trim().to_string(), unquote(v.trim())))
+                    .ok_or_else(|| error(format!("expected 'name: value', got '{}'", line)))?;
+                match section {
+                    Section::Headers => {
+                        entry.request.headers.insert(key, template(&value));
+                    }
+                    Section::Query => {
+                        entry.request.query_parameters.insert(key, template(&value));
+                    }
+                    Section::Form => {
+                        form(&mut entry.request.body).insert(key, template(&value));
+                    }
+                    Section::Multipart => {
+                        let field = match value.strip_prefix("file,") {
+                            Some(file) => {
+                                let (path, content_type) =
+                                    file.split_once(';').unwrap_or((file, ""));
+                                let mut field = MultiPartField::file(path.trim());
+                                if let MultiPartField::File {
+                                    content_type: c, ..
+                                } = &mut field
+                                {
+                                    *c = Some(content_type.trim().to_string())
+                                        .filter(|c| !c.is_empty());
+                                }
+                                field
+                            }
+                            None => MultiPartField::text(&template(&value)),
+                        };
+                        // Parts with the same name are sent together.
+                        let data = multipart(&mut entry.request.body);
+                        match data.remove(&key) {
+                            None => data.insert(key, field.into()),
+                            Some(existing) => {
+                                let mut parts = existing.parts().to_vec();
+                                parts.push(field);
+                                data.insert(key, MultiPartValue::Many(parts))
+                            }
+                        };
+                    }
+                    Section::ResponseHeaders => {
+                        entry.asserts.push(Assert::HeaderEquals {
+                            key: key.to_lowercase(),
+                            value: template(&value),
+                        });
+                    }
+                    Section::Captures => {
+                        let from = query(&value)
+                            .ok_or_else(|| error(format!("unsupported capture '{}'", line)))?;
+                        entry.captures.push((key, from));
+                    }
+                    Section::Asserts | Section::Other => {}
+                }
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Convert the entries to requests named `NAME-1`, `NAME-2`, and so on
+/// and a test named NAME with a step for each. Captured variables are
+/// replaced with templates for the response they come from.
+pub fn import(name: &str, entries: Vec<Entry>) -> (Vec<(String, Request)>, Test) {
+    let mut captures: HashMap<String, String> = HashMap::new();
+    let mut requests = vec![];
+    let mut steps = vec![];
+    for (i, entry) in entries.into_iter().enumerate() {
+        let request_name = format!("{}-{}", name, i + 1);
+        let mut request = entry.request;
+        request.description = format!("imported from {}", name);
+        request.tags = vec!["imported".into()];
+        replace_captures(&mut request, &captures);
+        for (variable, from) in entry.captures {
+            captures.insert(variable, format!("response.{}.{}", request_name, from));
+        }
+        steps.push(Step {
+            name: request_name.clone(),
+            request: request_name.clone(),
+            when: None,
+            foreach: None,
+            transforms: vec![],
+            asserts: entry.asserts,
+            max_duration: None,
+        });
+        requests.push((request_name, request));
+    }
+    let test = Test {
+        description: format!("imported from {}", name),
+        steps,
+        ..Default::default()
+    };
+    (requests, test)
+}
+
+/// Write the test as a Hurl file. Templates for earlier responses
+/// become captures and asserts without a Hurl equivalent are comments.
+pub fn export_test(test: &Test, requests: &HashMap<String, Request>) -> Result<String> {
+    let entries = test
+        .steps
+        .iter()
+        .map(|s| {
+            requests
+                .get(&s.request)
+                .map(|r| (s.request.as_str(), r, s.asserts.as_slice()))
+                .ok_or_else(|| HurlError::RequestNotFound(s.request.clone()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(export(&entries))
+}
+
+/// Write the requests, with the asserts for their responses, as a Hurl
+/// file.
+pub fn export(entries: &[(&str, &Request, &[Assert])]) -> String {
+    // Find the response values used by later requests to capture them.
+    let mut captures: HashMap<&str, Vec<(String, String)>> = HashMap::new();
+    for (_, request, _) in entries {
+        for variable in variables(request) {
+            let Some(path) = variable.strip_prefix("response.") else {
+                continue;
+            };
+            let Some((name, key)) = path.split_once('.') else {
+                continue;
+            };
+            let Some(entry) = entries.iter().find(|(n, _, _)| *n == name) else {
+                continue;
+            };
+            let list = captures.entry(entry.0).or_default();
+            if !list.iter().any(|(v, _)| v == &variable) {
+                list.push((variable.clone(), key.to_string()));
+            }
+        }
+    }
+
+    let mut hurl = vec![];
+    for (name, request, asserts) in entries {
+        let mut lines = vec![];
+        match request.description.is_empty() {
+            true => lines.push(format!("# {}", name)),
+            false => lines.push(format!("# {}: {}", name, request.description)),
+        }
+        lines.push(format!(
+            "{} {}",
+            request.method.to_uppercase(),
+            hurl_template(&request.url)
+        ));
+        lines.extend(sorted(&request.headers));
+        if !request.query_parameters.is_empty() {
+            lines.push("[QueryStringParams]".into());
+            lines.extend(sorted(&request.query_parameters));
+        }
+        match &request.body {
+            Body::None => {}
+            Body::Form { data } => {
+                lines.push("[FormParams]".into());
+                lines.extend(sorted(data));
+            }
+            Body::MultiPart { data } => {
+                lines.push("[MultipartFormData]".into());
+                let mut fields = data
+                    .iter()
+                    .flat_map(|(k, v)| v.parts().iter().map(move |p| (k, p)))
+                    .map(|(k, part)| match part {
+                        MultiPartField::Text { data, .. } => {
+                            format!("{}: {}", k, hurl_template(data))
+                        }
+                        MultiPartField::File {
+                            path, content_type, ..
+                        } => format!(
+                            "{}: file,{};{}",
+                            k,
+                            path,
+                            content_type
+                                .as_ref()
+                                .map(|c| format!(" {}", c))
+                                .unwrap_or_default()
+                        ),
+                    })
+                    .collect::<Vec<_>>();
+                fields.sort();
+                lines.extend(fields);
+            }
+            Body::Raw {
+                from: RawBody::Text { data },
+            } => lines.push(format!("```\n{}\n```", hurl_template(data.trim_end()))),
+            Body::Raw {
+                from: RawBody::File { path },
+            } => lines.push(format!("file,{};", path)),
+            // Hurl only applies templates to inline bodies.
+            Body::Raw {
+                from: RawBody::Template { path },
+            } => match std::fs::read_to_string(path) {
+                Ok(data) => lines.push(format!("```\n{}\n```", hurl_template(data.trim_end()))),
+                Err(_) => lines.push(format!("# unsupported: template {}\nfile,{};", path, path)),
+            },
+            Body::GraphQL { query, .. } => lines.push(format!(
+                "```graphql\n{}\n```",
+                hurl_template(query.trim_end())
+            )),
+            Body::Soap {
+                action,
+                envelope,
+                header,
+            } => {
+                lines.push("Content-Type: text/xml; charset=utf-8".into());
+                lines.push(format!("SOAPAction: \"{}\"", hurl_template(action)));
+                let envelope = Body::soap_envelope(envelope, header.as_deref());
+                lines.push(format!("```xml\n{}```", hurl_template(&envelope)));
+            }
+        }
+
+        let status = asserts.iter().find_map(|a| match a {
+            Assert::StatusCode { value } => Some(value.to_string()),
+            _ => None,
+        });
+        lines.push(format!("HTTP {}", status.unwrap_or_else(|| "*".into())));
+        if let Some(captures) = captures.get(name) {
+            lines.push("[Captures]".into());
+            for (variable, key) in captures {
+                lines.push(format!("{}: {}", capture_name(variable), hurl_query(key)));
+            }
+        }
+        let asserts = asserts
+            .iter()
+            .filter(|a| !matches!(a, Assert::StatusCode { .. }))
+            .map(|a| hurl_assert(a).unwrap_or_else(|| format!("# unsupported: {}", a)))
+            .collect::<Vec<_>>();
+        if !asserts.is_empty() {
+            lines.push("[Asserts]".into());
+            lines.extend(asserts);
+        }
+        hurl.push(lines.join("\n"));
+    }
+    let mut hurl = hurl.join("\n\n");
+    hurl.push('\n');
+
+    // Use the captured variables in place of the response templates.
+    for list in captures.values() {
+        for (variable, _) in list {
+            hurl = hurl.replace(
+                &format!("{{{{{}}}}}", variable),
+                &format!("{{{{{}}}}}", capture_name(variable)),
+            );
+        }
+    }
+    hurl
+}
+
+fn is_json_start(line: &str) -> bool {
+    line.starts_with('{') || line.starts_with('[') && !line.ends_with(']')
+}
+
+/// Read the body starting at the line, returning it and the line after
+/// it. Code blocks end at their closing fence and JSON ends before the
+/// status line.
+fn body(lines: &[&str], start: usize) -> (String, usize) {
+    let first = lines[start].trim();
+    if let Some(rest) = first.strip_prefix("```") {
+        if rest.len() > 3 && rest.ends_with("```") {
+            return (rest.trim_end_matches("```").to_string(), start + 1);
+        }
+        let end = (start + 1..lines.len())
+            .find(|i| lines[*i].trim() == "```")
+            .unwrap_or(lines.len());
+        let body = lines[start + 1..end].join("\n");
+        return (body, end + 1);
+    }
+    let end = (start..lines.len())
+        .find(|i| lines[*i].trim_start().starts_with("HTTP"))
+        .unwrap_or(lines.len());
+    let body = lines[start..end].join("\n").trim_end().to_string();
+    (body, end)
+}
+
+/// Parse a Hurl query like `jsonpath "$.id"` or `header "Location"`
+/// into a body path or `headers.NAME`.
+fn query(s: &str) -> Option<String> {
+    let (kind, arg) = s.split_once(' ').unwrap_or((s, ""));
+    let arg = unquote(arg.trim());
+    match kind {
+        "jsonpath" => Some(
+            arg.trim_start_matches('$')
+                .trim_start_matches('.')
+                .to_string(),
+        ),
+        "header" => Some(format!("headers.{}", arg.to_lowercase())),
+        "body" => Some(String::new()),
+        _ => None,
+    }
+}
+
+/// Parse a Hurl assert like `jsonpath "$.id" == 7`.
+fn assert(line: &str) -> Option<Assert> {
+    let re = Regex::new(
+        r#"^(status|body|jsonpath\s+"[^"]*"|header\s+"[^"]*")\s+(==|!=|contains|startsWith|endsWith|matches)\s+(.+)$"#,
+    )
+    .unwrap();
+    let c = re.captures(line.trim())?;
+    let value = template(&unquote(c[3].trim()));
+    if &c[1] == "status" {
+        return match &c[2] {
+            "==" => Some(Assert::StatusCode {
+                value: value.parse().ok()?,
+            }),
+            _ => None,
+        };
+    }
+    let key = query(&c[1])?;
+    if let Some(name) = key.strip_prefix("headers.") {
+        let key = name.to_string();
+        return match &c[2] {
+            "==" => Some(Assert::HeaderEquals { key, value }),
+            "contains" => Some(Assert::HeaderContains { key, value }),
+            _ => None,
+        };
+    }
+    Some(match &c[2] {
+        "==" => Assert::Equals { key, value },
+        "!=" => Assert::NotEquals { key, value },
+        "contains" => Assert::Contains { key, value },
+        "startsWith" => Assert::HasPrefix { key, value },
+        "endsWith" => Assert::HasSuffix { key, value },
+        _ => Assert::Regex { key, value },
+    })
+}
+
+/// Write the assert in Hurl's syntax if it has one.
+fn hurl_assert(assert: &Assert) -> Option<String> {
+    let (key, op, value) = match assert {
+        Assert::HeaderEquals { key, value } => {
+            return Some(format!("header \"{}\" == {}", key, hurl_value(value)))
+        }
+        Assert::HeaderContains { key, value } => {
+            return Some(format!("header \"{}\" contains {}", key, quote(value)))
+        }
+        Assert::BodyContains { value } => return Some(format!("body contains {}", quote(value))),
+        Assert::Equals { key, value } => (key, "==", hurl_value(value)),
+        Assert::NotEquals { key, value } => (key, "!=", hurl_value(value)),
+        Assert::Contains { key, value } => (key, "contains", quote(value)),
+        Assert::HasPrefix { key, value } => (key, "startsWith", quote(value)),
+        Assert::HasSuffix { key, value } => (key, "endsWith", quote(value)),
+        Assert::Regex { key, value } => (key, "matches", quote(value)),
+        _ => return None,
+    };
+    Some(format!("{} {} {}", hurl_query(key), op, value))
+}
+
+fn hurl_query(key: &str) -> String {
+    match key.is_empty() {
+        true => "body".into(),
+        false => format!("jsonpath \"$.{}\"", key),
+    }
+}
+
+/// Numbers and booleans are written as is so Hurl compares them as
+/// values, and everything else as a string.
+fn hurl_value(value: &str) -> String {
+    match value.parse::<f64>().is_ok() || value == "true" || value == "false" {
+        true => value.to_string(),
+        false => quote(value),
+    }
+}
+
+fn quote(s: &str) -> String {
+    format!(
+        "\"{}\"",
+        hurl_template(&s.replace('\\', "\\\\").replace('"', "\\\""))
+    )
+}
+
+fn unquote(s: &str) -> String {
+    match s.len() >= 2 && s.starts_with('"') && s.ends_with('"') {
+        true => s[1..s.len() - 1]
+            .replace("\\\"", "\"")
+            .replace("\\\\", "\\"),
+        false => s.to_string(),
+    }
+}
+
+/// Convert Hurl's `{{name}}` templates to `${name}`.
+fn template(s: &str) -> String {
+    let re = Regex::new(r"\{\{\s*([-.\w]+)\s*\}\}").unwrap();
+    re.replace_all(s, "$${$1}").to_string()
+}
+
+/// Convert `${name}` templates to Hurl's `{{name}}`.
+fn hurl_template(s: &str) -> String {
+    Template::parse(s)
+        .segments()
+        .iter()
+        .map(|segment| match segment {
+            Segment::Variable(name) => format!("{{{{{}}}}}", name),
+            // Hurl's functions differ, so calls are left as they are.
+            segment => segment.to_string(),
+        })
+        .collect()
+}
+
+/// The Hurl variable for a response template.
+fn capture_name(variable: &str) -> String {
+    variable
+        .trim_start_matches("response.")
+        .chars()
+        .map(|c| match c.is_alphanumeric() || c == '_' {
+            true => c,
+            false => '_',
+        })
+        .collect()
+}
+
+/// The variables used in the request.
+fn variables(request: &Request) -> Vec<String> {
+    let mut strings = vec![request.url.clone()];
+    strings.extend(request.headers.values().cloned());
+    strings.extend(request.query_parameters.values().cloned());
+    match &request.body {
+        Body::Form { data } => strings.extend(data.values().cloned()),
+        Body::Raw {
+            from: RawBody::Text { data },
+        } => strings.push(data.clone()),
+        Body::GraphQL { query, .. } => strings.push(query.clone()),
+        Body::Soap {
+            envelope, header, ..
+        } => strings.extend(header.iter().chain([envelope]).cloned()),
+        _ => {}
+    }
+    strings
+        .iter()
+        .flat_map(|s| {
+            Template::parse(s)
+                .variables()
+                .into_iter()
+                .map(|v| v.to_string())
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Replace the captured variables in the request with the templates
+/// for the responses they come from.
+fn replace_captures(request: &mut Request, captures: &HashMap<String, String>) {
+    let replace = |s: &mut String| {
+        for (variable, template) in captures {
+            *s = s.replace(&format!("${{{}}}", variable), &format!("${{{}}}", template));
+        }
+    };
+    replace(&mut request.url);
+    request.headers.values_mut().for_each(replace);
+    request.query_parameters.values_mut().for_each(replace);
+    match &mut request.body {
+        Body::Form { data } => data.values_mut().for_each(replace),
+        Body::Raw {
+            from: RawBody::Text { data },
+        } => replace(data),
+        _ => {}
+    }
+}
+
+fn form(body: &mut Body) -> &mut HashMap<String, String> {
+    if !matches!(body, Body::Form { .. }) {
+        *body = Body::Form {
+            data: HashMap::new(),
+        };
+    }
+    match body {
+        Body::Form { data } => data,
+        _ => unreachable!(),
+    }
+}
+
+fn multipart(body: &mut Body) -> &mut HashMap<String, MultiPartValue> {
+    if !matches!(body, Body::MultiPart { .. }) {
+        *body = Body::MultiPart {
+            data: HashMap::new(),
+        };
+    }
+    match body {
+        Body::MultiPart { data } => data,
+        _ => unreachable!(),
+    }
+}
+
+fn sorted(values: &HashMap<String, String>) -> Vec<String> {
+    let mut lines = values
+        .iter()
+        .map(|(k, v)| format!("{}: {}", k, hurl_template(v)))
+        .collect::<Vec<_>>();
+    lines.sort();
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HURL: &str = r#"
+# Log in and get the user.
+POST {{base_url}}/login
+Content-Type: application/json
+{
+  "user": "bob"
+}
+HTTP 200
+[Captures]
+token: jsonpath "$.token"
+[Asserts]
+jsonpath "$.user.name" == "bob"
+header "Content-Type" contains "json"
+
+GET {{base_url}}/me
+Authorization: Bearer {{token}}
+[QueryStringParams]
+fields: name
+HTTP *
+[Asserts]
+status == 200
+body contains "bob"
+"#;
+
+    #[test]
+    fn import_entries() {
+        let (requests, test) = import("login", parse(HURL).unwrap());
+        assert_eq!(requests.len(), 2);
+        let (name, login) = &requests[0];
+        assert_eq!(name, "login-1");
+        assert_eq!(login.method, "POST");
+        assert_eq!(login.url, "${base_url}/login");
+        assert_eq!(
+            login.body,
+            Body::Raw {
+                from: RawBody::Text {
+                    data: "{\n  \"user\": \"bob\"\n}".into()
+                }
+            }
+        );
+        let me = &requests[1].1;
+        assert_eq!(
+            me.headers["Authorization"],
+            "Bearer ${response.login-1.token}"
+        );
+        assert_eq!(me.query_parameters["fields"], "name");
+
+        let asserts = test
+            .steps
+            .iter()
+            .map(|s| s.asserts.iter().map(|a| a.to_string()).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            asserts,
+            vec![
+                vec![
+                    "status_code == 200",
+                    "equals(user.name, bob)",
+                    "header_contains(content-type, json)"
+                ],
+                vec!["status_code == 200", "contains(, bob)"],
+            ]
+        );
+    }
+
+    #[test]
+    fn export_entries() {
+        let (requests, test) = import("login", parse(HURL).unwrap());
+        let requests = requests.into_iter().collect::<HashMap<_, _>>();
+        let hurl = export_test(&test, &requests).unwrap();
+        assert!(hurl.contains("POST {{base_url}}/login\nContent-Type: application/json\n```\n{"));
+        assert!(hurl.contains("HTTP 200\n[Captures]\nlogin_1_token: jsonpath \"$.token\"\n[Asserts]\njsonpath \"$.user.name\" == \"bob\""));
+        assert!(hurl.contains("Authorization: Bearer {{login_1_token}}"));
+
+        // The export imports to the same requests.
+        let (again, _) = import("login", parse(&hurl).unwrap());
+        assert_eq!(again[1].1.headers, requests["login-2"].headers);
+    }
+}
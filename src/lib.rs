@@ -1,20 +1,134 @@
+pub mod auth;
+pub use auth::{Auth, AuthError};
+
+pub mod benchmark;
+pub use benchmark::{BenchmarkError, Scenario};
+
+pub mod bundle;
+pub use bundle::Bundle;
+
+pub mod changed;
+pub use changed::{Change, ChangedError, Difference};
+
+pub mod condition;
+pub use condition::ConditionError;
+
 pub mod config;
-pub use config::Config;
+pub use config::{Config, Context, Correlation, Scope, UrlPattern};
+
+pub mod diagnostic;
+pub use diagnostic::{Diagnostic, Severity};
+
+pub mod diff;
+
+pub mod docs;
+pub use docs::{Docs, DocsError, DocsFormat};
+
+pub mod events;
+pub use events::{Event, Events, EventsError};
+
+pub mod expression;
+pub use expression::ExpressionError;
+
+pub mod function;
+pub use function::FunctionError;
+
+pub mod graph;
+pub use graph::{Graph, GraphError, GraphFormat};
+
+pub mod grpc;
+pub use grpc::{Grpc, GrpcError};
+
+pub mod history;
+pub use history::{History, HistoryError};
+
+pub mod hurl;
+pub use hurl::HurlError;
+
+pub mod expect;
+pub use expect::Expect;
 
 pub mod applicator;
 pub use applicator::Applicator;
 
+#[cfg(feature = "ntlm")]
+pub mod ntlm;
+
+pub mod notify;
+pub use notify::Notification;
+
+pub mod openapi;
+pub use openapi::OpenApi;
+
 pub mod output;
 pub use output::{List, OutputFormat};
 
 pub mod response;
-pub use response::{Response, ResponseError};
+pub use response::{BodyFile, Response, ResponseError, WriteBehind};
 
 pub mod results;
-pub use results::{Results, ResultsError, State};
+pub use results::{Counts, Results, ResultsError, State, Verbosity};
 
 pub mod request;
 pub use request::{Request, RequestError};
 
+pub mod template;
+pub use template::{Template, Templates};
+
+pub mod tls;
+pub use tls::{Certificate, Tls};
+
+pub mod transform;
+pub use transform::{Transform, TransformError};
+
 pub mod test;
-pub use test::{Test, TestError};
+pub use test::{Test, TestError, TestOptions};
+
+pub mod suite;
+pub use suite::Suite;
+
+pub mod scaffold;
+pub use scaffold::ScaffoldError;
+
+pub mod websocket;
+pub use websocket::{WebSocket, WebSocketError};
+
+pub mod flow;
+pub use flow::{Flow, FlowError};
+
+pub mod graphql;
+pub use graphql::{GraphQLError, Schema};
+
+pub mod monitor;
+pub use monitor::{Check, Monitor};
+
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "metrics")]
+pub use metrics::Metrics;
+
+pub mod limiter;
+pub use limiter::{Limit, Limiter};
+
+pub mod mock;
+pub use mock::{Mock, MockError, MockServer};
+
+pub mod snapshot;
+pub use snapshot::SnapshotError;
+
+pub mod paginate;
+pub use paginate::Paginate;
+
+pub mod record;
+pub use record::{RecordError, Recorder};
+
+pub mod profile;
+pub use profile::{Profile, ProfileError, UserConfig};
+
+pub mod redact;
+pub use redact::{Redaction, Redactor};
+
+pub mod sops;
+pub use sops::SopsError;
+
+mod server;
@@ -0,0 +1,130 @@
+/// Limiter is used to keep the requests made to a host under its rate
+/// limit, no matter how many tests or workers share it.
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Limit is the most requests made to a host in a period. Up to `burst`
+/// requests can be made at once after a quiet period.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
+pub struct Limit {
+    pub requests: u32,
+    #[serde(with = "humantime_serde")]
+    #[schemars(with = "String")]
+    pub per: Duration,
+    /// Defaults to the number of requests.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub burst: Option<u32>,
+}
+
+impl Limit {
+    /// Requests per second.
+    fn rate(&self) -> f64 {
+        self.requests as f64 / self.per.as_secs_f64()
+    }
+
+    fn capacity(&self) -> f64 {
+        self.burst.unwrap_or(self.requests).max(1) as f64
+    }
+
+    /// Whether the limit lets any requests through.
+    pub fn is_valid(&self) -> bool {
+        self.requests > 0 && !self.per.is_zero()
+    }
+}
+
+/// Bucket holds the tokens for a host. Tokens go below zero when
+/// requests are waiting, so they're let through in the order they came.
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Limiter is a token bucket for each host with a limit, shared by
+/// every request so the limit holds across parallel tests.
+#[derive(Debug, Default)]
+pub struct Limiter {
+    limits: HashMap<String, Limit>,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl Limiter {
+    /// A limiter for the limits by `host` or `host:port`.
+    pub fn new(limits: HashMap<String, Limit>) -> Self {
+        Self {
+            limits,
+            buckets: Mutex::default(),
+        }
+    }
+
+    /// Wait until a request can be made to the URL's host.
+    pub async fn acquire(&self, url: &str) {
+        if let Some(wait) = self.reserve(url, Instant::now()) {
+            tracing::debug!(%url, ?wait, "waiting for the rate limit");
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Take a token for the URL's host at the time, returning how long
+    /// to wait for it if there wasn't one.
+    fn reserve(&self, url: &str, now: Instant) -> Option<Duration> {
+        let (key, limit) = self.limit(url)?;
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry(key).or_insert(Bucket {
+            tokens: limit.capacity(),
+            updated: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * limit.rate()).min(limit.capacity());
+        bucket.updated = now;
+        bucket.tokens -= 1.0;
+        (bucket.tokens < 0.0).then(|| Duration::from_secs_f64(-bucket.tokens / limit.rate()))
+    }
+
+    /// The limit for the URL's host, by `host:port` first and then
+    /// `host`.
+    fn limit(&self, url: &str) -> Option<(String, &Limit)> {
+        let url = reqwest::Url::parse(url).ok()?;
+        let host = url.host_str()?;
+        let with_port = format!("{}:{}", host, url.port_or_known_default()?);
+        [with_port, host.to_string()]
+            .into_iter()
+            .find_map(|k| Some((k.clone(), self.limits.get(&k).filter(|l| l.is_valid())?)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reserve() {
+        let limits = serde_yaml::from_str(
+            "{api.example.com: {requests: 2, per: 1s}, 'localhost:8080': {requests: 1, per: 100ms, burst: 3}}",
+        )
+        .unwrap();
+        let limiter = Limiter::new(limits);
+        let now = Instant::now();
+        let url = "https://api.example.com/users";
+        assert_eq!(limiter.reserve(url, now), None);
+        assert_eq!(limiter.reserve(url, now), None);
+        assert_eq!(limiter.reserve(url, now), Some(Duration::from_millis(500)));
+        assert_eq!(limiter.reserve(url, now), Some(Duration::from_secs(1)));
+        let later = now + Duration::from_secs(2);
+        assert_eq!(limiter.reserve(url, later), None);
+
+        let local = "http://localhost:8080/";
+        for _ in 0..3 {
+            assert_eq!(limiter.reserve(local, now), None);
+        }
+        assert_eq!(
+            limiter.reserve(local, now),
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(limiter.reserve("http://localhost:9090/", now), None);
+    }
+}
@@ -0,0 +1,148 @@
+/// Metrics is used to expose the progress of long runs, like monitors
+/// and benchmarks, for Prometheus to scrape.
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The upper bounds of the latency histogram buckets in seconds.
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Series is what's been recorded for one test or request.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Series {
+    requests: u64,
+    errors: u64,
+    /// The count in each bucket, not including the smaller buckets.
+    buckets: [u64; BUCKETS.len()],
+    seconds: f64,
+}
+
+/// Metrics are the request counts, error counts and latency histograms
+/// of each test or request in a run.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    series: Mutex<BTreeMap<String, Series>>,
+}
+
+impl Metrics {
+    /// Record a request or test that took the duration.
+    pub fn record(&self, name: &str, duration: Duration, error: bool) {
+        let mut series = self.series.lock().unwrap();
+        let series = series.entry(name.to_string()).or_default();
+        let seconds = duration.as_secs_f64();
+        series.requests += 1;
+        series.errors += error as u64;
+        series.seconds += seconds;
+        if let Some(i) = BUCKETS.iter().position(|b| seconds <= *b) {
+            series.buckets[i] += 1;
+        }
+    }
+
+    /// The metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let series = self.series.lock().unwrap();
+        let mut out = String::new();
+        out.push_str("# HELP apictl_requests_total Requests made.\n");
+        out.push_str("# TYPE apictl_requests_total counter\n");
+        for (name, s) in series.iter() {
+            out.push_str(&format!(
+                "apictl_requests_total{{name={}}} {}\n",
+                label(name),
+                s.requests
+            ));
+        }
+        out.push_str("# HELP apictl_errors_total Requests that failed.\n");
+        out.push_str("# TYPE apictl_errors_total counter\n");
+        for (name, s) in series.iter() {
+            out.push_str(&format!(
+                "apictl_errors_total{{name={}}} {}\n",
+                label(name),
+                s.errors
+            ));
+        }
+        out.push_str("# HELP apictl_request_duration_seconds How long requests took.\n");
+        out.push_str("# TYPE apictl_request_duration_seconds histogram\n");
+        for (name, s) in series.iter() {
+            let name = label(name);
+            let mut count = 0;
+            for (bound, n) in BUCKETS.iter().zip(s.buckets) {
+                count += n;
+                out.push_str(&format!(
+                    "apictl_request_duration_seconds_bucket{{name={},le=\"{}\"}} {}\n",
+                    name, bound, count
+                ));
+            }
+            out.push_str(&format!(
+                "apictl_request_duration_seconds_bucket{{name={},le=\"+Inf\"}} {}\n",
+                name, s.requests
+            ));
+            out.push_str(&format!(
+                "apictl_request_duration_seconds_sum{{name={}}} {}\n",
+                name, s.seconds
+            ));
+            out.push_str(&format!(
+                "apictl_request_duration_seconds_count{{name={}}} {}\n",
+                name, s.requests
+            ));
+        }
+        out
+    }
+
+    /// Serve the metrics at `/metrics` on the address until the process
+    /// exits.
+    pub async fn serve(
+        self: std::sync::Arc<Self>,
+        addr: std::net::SocketAddr,
+    ) -> std::io::Result<()> {
+        crate::server::serve(addr, move |req| {
+            let metrics = self.clone();
+            async move {
+                match req.uri.path() {
+                    "/metrics" => hyper::Response::builder()
+                        .header("content-type", "text/plain; version=0.0.4")
+                        .body(http_body_util::Full::new(metrics.render().into()))
+                        .unwrap(),
+                    _ => crate::server::text(404, "not found".into()),
+                }
+            }
+        })
+        .await
+    }
+}
+
+/// The value quoted as a label value.
+fn label(value: &str) -> String {
+    format!(
+        "\"{}\"",
+        value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render() {
+        let metrics = Metrics::default();
+        metrics.record("get-user", Duration::from_millis(20), false);
+        metrics.record("get-user", Duration::from_millis(300), true);
+        metrics.record("get-user", Duration::from_secs(20), false);
+        let text = metrics.render();
+        assert!(text.contains("apictl_requests_total{name=\"get-user\"} 3\n"));
+        assert!(text.contains("apictl_errors_total{name=\"get-user\"} 1\n"));
+        assert!(text.contains(
+            "apictl_request_duration_seconds_bucket{name=\"get-user\",le=\"0.025\"} 1\n"
+        ));
+        assert!(text
+            .contains("apictl_request_duration_seconds_bucket{name=\"get-user\",le=\"0.5\"} 2\n"));
+        assert!(text
+            .contains("apictl_request_duration_seconds_bucket{name=\"get-user\",le=\"+Inf\"} 3\n"));
+        assert_eq!(label("a\"b"), "\"a\\\"b\"");
+    }
+}
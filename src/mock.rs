@@ -0,0 +1,270 @@
+/// Mock is used to serve stubbed responses defined in the
+/// configuration.
+use std::collections::HashMap;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crate::server::{text, Received};
+use crate::{Applicator, List};
+
+use http_body_util::Full;
+use hyper::body::Bytes;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Implement List for mocks.
+impl List for HashMap<String, Mock> {
+    fn headers(&self) -> Vec<String> {
+        vec![
+            "Name".into(),
+            "Method".into(),
+            "Path".into(),
+            "Status".into(),
+        ]
+    }
+
+    fn values(&self) -> Vec<Vec<String>> {
+        self.iter()
+            .map(|(n, m)| {
+                vec![
+                    n.clone(),
+                    m.method.clone().unwrap_or_else(|| "*".into()),
+                    m.path.clone(),
+                    m.status.to_string(),
+                ]
+            })
+            .collect()
+    }
+
+    fn group_fields(&self) -> Vec<String> {
+        vec!["method".into(), "status".into()]
+    }
+
+    fn groups(&self, name: &str, field: &str) -> Vec<String> {
+        match (self.get(name), field) {
+            (Some(m), "method") => m.method.iter().map(|m| m.to_uppercase()).collect(),
+            (Some(m), "status") => vec![m.status.to_string()],
+            _ => vec![],
+        }
+    }
+}
+
+/// MockError is the error type for the mock server.
+#[derive(Error, Debug)]
+pub enum MockError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Result is the result type for the mock server.
+pub type Result<T> = std::result::Result<T, MockError>;
+
+/// Mock is a stubbed response served for requests that match the
+/// method and path. Path segments of the form `{name}` match any value
+/// and are available to templates as `${path.name}`.
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Mock {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    pub path: String,
+    #[serde(default = "default_status")]
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub headers: HashMap<String, String>,
+    #[serde(default)]
+    pub body: String,
+}
+
+fn default_status() -> u16 {
+    200
+}
+
+impl Mock {
+    /// Match the request against the mock, returning the captured path
+    /// segments if it matches.
+    pub fn matches(&self, method: &str, path: &str) -> Option<HashMap<String, String>> {
+        if let Some(m) = &self.method {
+            if !m.eq_ignore_ascii_case(method) {
+                return None;
+            }
+        }
+        let pattern = self.path.trim_matches('/').split('/').collect::<Vec<_>>();
+        let actual = path.trim_matches('/').split('/').collect::<Vec<_>>();
+        if pattern.len() != actual.len() {
+            return None;
+        }
+        let mut captures = HashMap::new();
+        for (p, a) in pattern.iter().zip(actual.iter()) {
+            match p.strip_prefix('{').and_then(|p| p.strip_suffix('}')) {
+                Some(name) => {
+                    captures.insert(format!("path.{}", name), a.to_string());
+                }
+                None if p == a => {}
+                None => return None,
+            }
+        }
+        Some(captures)
+    }
+
+    /// The number of literal segments in the path. Mocks with more
+    /// literal segments are preferred when several match.
+    fn specificity(&self) -> usize {
+        self.path
+            .split('/')
+            .filter(|s| !s.is_empty() && !s.starts_with('{'))
+            .count()
+    }
+}
+
+/// RecordedRequest is a request received by the mock server.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    pub mock: Option<String>,
+    pub method: String,
+    pub path: String,
+    pub query: HashMap<String, String>,
+    pub headers: HashMap<String, String>,
+    pub body: String,
+}
+
+/// MockServer serves the mocks with the given context. If record is
+/// set, every request received is appended to it as a JSON line.
+pub struct MockServer {
+    mocks: Vec<(String, Mock)>,
+    context: HashMap<String, String>,
+    record: Option<Mutex<std::fs::File>>,
+}
+
+impl MockServer {
+    pub fn new(
+        mocks: &HashMap<String, Mock>,
+        context: HashMap<String, String>,
+        record: Option<PathBuf>,
+    ) -> Result<Self> {
+        let mut mocks = mocks
+            .iter()
+            .map(|(n, m)| (n.clone(), m.clone()))
+            .collect::<Vec<_>>();
+        mocks.sort_by(|a, b| {
+            b.1.specificity()
+                .cmp(&a.1.specificity())
+                .then_with(|| a.0.cmp(&b.0))
+        });
+        let record = match record {
+            Some(path) => Some(Mutex::new(
+                std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)?,
+            )),
+            None => None,
+        };
+        Ok(Self {
+            mocks,
+            context,
+            record,
+        })
+    }
+
+    /// Serve the mocks on the given address until the process exits.
+    pub async fn serve(self, addr: SocketAddr) -> Result<()> {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        self.serve_listener(listener).await
+    }
+
+    /// Serve the mocks on the listener until the process exits, like
+    /// one bound to port 0.
+    pub async fn serve_listener(self, listener: tokio::net::TcpListener) -> Result<()> {
+        let server = Arc::new(self);
+        crate::server::serve_listener(listener, move |req| {
+            let server = server.clone();
+            async move { server.handle(req) }
+        })
+        .await?;
+        Ok(())
+    }
+
+    fn handle(&self, req: Received) -> hyper::Response<Full<Bytes>> {
+        let path = req.uri.path().to_string();
+        let query = req.query();
+        let body = String::from_utf8_lossy(&req.body).to_string();
+
+        let matched = self
+            .mocks
+            .iter()
+            .find_map(|(n, m)| m.matches(&req.method, &path).map(|c| (n, m, c)));
+
+        if let Some(record) = &self.record {
+            let recorded = RecordedRequest {
+                mock: matched.as_ref().map(|(n, _, _)| n.to_string()),
+                method: req.method.clone(),
+                path: path.clone(),
+                query: query.clone(),
+                headers: req.headers.clone(),
+                body: body.clone(),
+            };
+            if let Ok(line) = serde_json::to_string(&recorded) {
+                let mut file = record.lock().unwrap();
+                let _ = writeln!(file, "{}", line);
+            }
+        }
+
+        tracing::debug!(
+            method = %req.method,
+            path = %path,
+            mock = matched.as_ref().map(|(n, _, _)| n.as_str()),
+            "received request"
+        );
+        let (_, mock, captures) = match matched {
+            Some(m) => m,
+            None => return text(404, format!("no mock matched {} {}", req.method, path)),
+        };
+
+        let mut context = self.context.clone();
+        context.extend(captures);
+        context.extend(query.into_iter().map(|(k, v)| (format!("query.{}", k), v)));
+        context.extend(
+            req.headers
+                .into_iter()
+                .map(|(k, v)| (format!("header.{}", k), v)),
+        );
+        context.insert("method".into(), req.method);
+        context.insert("path".into(), path);
+        context.insert("body".into(), body);
+        let app = Applicator::new(context, HashMap::new());
+
+        let mut builder = hyper::Response::builder().status(mock.status);
+        for (k, v) in &mock.headers {
+            builder = builder.header(k, app.apply(v));
+        }
+        builder
+            .body(Full::new(Bytes::from(app.apply(&mock.body))))
+            .unwrap_or_else(|e| text(500, format!("invalid mock: {}", e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches() {
+        let mock = Mock {
+            method: Some("GET".into()),
+            path: "/users/{id}/posts".into(),
+            status: 200,
+            headers: HashMap::new(),
+            body: String::new(),
+        };
+
+        let captures = mock.matches("get", "/users/1/posts").unwrap();
+        assert_eq!(captures.get("path.id"), Some(&"1".to_string()));
+        assert!(mock.matches("POST", "/users/1/posts").is_none());
+        assert!(mock.matches("GET", "/users/1").is_none());
+        assert!(mock.matches("GET", "/people/1/posts").is_none());
+        assert_eq!(mock.specificity(), 2);
+    }
+}
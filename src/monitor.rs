@@ -0,0 +1,200 @@
+/// Monitor is used to track the status of tests and requests that are
+/// run over and over, like an uptime checker.
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// MonitorError is the error type for monitors.
+#[derive(Error, Debug)]
+pub enum MonitorError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("webhook error: {0}")]
+    Webhook(#[from] reqwest::Error),
+}
+
+/// Result is the result type for monitors.
+pub type Result<T> = std::result::Result<T, MonitorError>;
+
+/// Check is the outcome of running a monitored test or request once.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Check {
+    pub name: String,
+    #[serde(with = "humantime_serde")]
+    pub time: SystemTime,
+    #[serde(with = "humantime_serde")]
+    pub duration: Duration,
+    pub passed: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failures: Vec<String>,
+}
+
+/// Status is the rolling status of a monitored test or request.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Status {
+    pub name: String,
+    /// Whether the last check passed, or None if it hasn't run yet.
+    pub passed: Option<bool>,
+    /// When the status last changed.
+    pub since: SystemTime,
+    pub runs: usize,
+    pub passes: usize,
+    pub last: Option<Check>,
+}
+
+/// Monitor keeps the status of each monitored test or request. Checks
+/// can be appended to a JSONL file and a webhook is called with the
+/// check whenever a status changes.
+#[derive(Debug, Default)]
+pub struct Monitor {
+    pub statuses: Vec<Status>,
+    log: Option<PathBuf>,
+    webhook: Option<String>,
+}
+
+impl Monitor {
+    pub fn new(names: &[String]) -> Self {
+        Self {
+            statuses: names
+                .iter()
+                .map(|name| Status {
+                    name: name.clone(),
+                    passed: None,
+                    since: SystemTime::now(),
+                    runs: 0,
+                    passes: 0,
+                    last: None,
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Append every check to the file as a JSON line.
+    pub fn with_log(mut self, log: Option<PathBuf>) -> Self {
+        self.log = log;
+        self
+    }
+
+    /// POST the check as JSON to the URL when a status changes.
+    pub fn with_webhook(mut self, webhook: Option<String>) -> Self {
+        self.webhook = webhook;
+        self
+    }
+
+    /// Record the check, returning whether the status changed. The first
+    /// check of each test or request isn't a change.
+    pub async fn record(&mut self, check: Check) -> Result<bool> {
+        if let Some(log) = &self.log {
+            let mut file = OpenOptions::new().create(true).append(true).open(log)?;
+            writeln!(file, "{}", serde_json::to_string(&check)?)?;
+        }
+
+        let status = match self.statuses.iter_mut().find(|s| s.name == check.name) {
+            Some(s) => s,
+            None => return Ok(false),
+        };
+        let changed = status.passed.is_some_and(|p| p != check.passed);
+        if status.passed != Some(check.passed) {
+            status.since = check.time;
+        }
+        status.passed = Some(check.passed);
+        status.runs += 1;
+        status.passes += check.passed as usize;
+        status.last = Some(check.clone());
+
+        if changed {
+            tracing::warn!(name = %check.name, passed = check.passed, "status changed");
+            if let Some(webhook) = &self.webhook {
+                reqwest::Client::new()
+                    .post(webhook)
+                    .json(&check)
+                    .send()
+                    .await?
+                    .error_for_status()?;
+            }
+        }
+        Ok(changed)
+    }
+
+    /// A line for each test or request with its status, how long it has
+    /// had it, the last duration and the share of checks that passed.
+    pub fn summary(&self) -> Vec<String> {
+        let width = self
+            .statuses
+            .iter()
+            .map(|s| s.name.len())
+            .max()
+            .unwrap_or(0);
+        self.statuses
+            .iter()
+            .map(|s| {
+                let state = match s.passed {
+                    None => "⏸",
+                    Some(true) => "✅",
+                    Some(false) => "❌",
+                };
+                let since = SystemTime::now()
+                    .duration_since(s.since)
+                    .map(|d| Duration::from_secs(d.as_secs()))
+                    .unwrap_or_default();
+                let duration = s.last.as_ref().map(|c| c.duration).unwrap_or_default();
+                let uptime = match s.runs {
+                    0 => 0.0,
+                    runs => s.passes as f64 * 100.0 / runs as f64,
+                };
+                let mut line = format!(
+                    "{} {:width$}  for {}  last {:?}  up {:.1}% of {}",
+                    state,
+                    s.name,
+                    humantime::format_duration(since),
+                    duration,
+                    uptime,
+                    s.runs,
+                    width = width
+                );
+                if let Some(failure) = s.last.as_ref().and_then(|c| c.failures.first()) {
+                    line.push_str(&format!("  {}", failure));
+                }
+                line
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn record() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("monitor.jsonl");
+        let mut monitor = Monitor::new(&["smoke".to_string()]).with_log(Some(log.clone()));
+        let check = |passed| Check {
+            name: "smoke".into(),
+            time: SystemTime::now(),
+            duration: Duration::from_millis(5),
+            passed,
+            failures: vec![],
+        };
+
+        assert!(!monitor.record(check(true)).await.unwrap());
+        assert!(!monitor.record(check(true)).await.unwrap());
+        assert!(monitor.record(check(false)).await.unwrap());
+        assert_eq!(monitor.statuses[0].runs, 3);
+        assert_eq!(monitor.statuses[0].passes, 2);
+        assert!(monitor.summary()[0].contains("up 66.7% of 3"));
+
+        let lines = std::fs::read_to_string(&log).unwrap();
+        assert_eq!(lines.lines().count(), 3);
+    }
+}
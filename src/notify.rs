@@ -0,0 +1,133 @@
+/// Notify is used to send alerts to webhooks, like Slack, when tests or
+/// monitored checks fail.
+use std::collections::HashMap;
+
+use crate::Applicator;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// NotifyError is the error type for notifications.
+#[derive(Error, Debug)]
+pub enum NotifyError {
+    #[error("webhook error: {0}")]
+    Webhook(#[from] reqwest::Error),
+}
+
+/// Result is the result type for notifications.
+pub type Result<T> = std::result::Result<T, NotifyError>;
+
+/// Notification is a webhook called when there are failures. The URL,
+/// headers and message are templates that can use the context and the
+/// summary variables `${source}`, `${total}`, `${passed}`, `${failed}`,
+/// `${skipped}`, `${timed_out}` and `${failures}`. The message is sent
+/// as `{"text": message}`, which Slack and most chat webhooks accept,
+/// along with the summary.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
+pub struct Notification {
+    pub url: String,
+    #[serde(default = "default_message")]
+    pub message: String,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub headers: HashMap<String, String>,
+}
+
+fn default_message() -> String {
+    "apictl ${source}: ${failed} of ${total} failed\n${failures}".into()
+}
+
+/// Summary describes the run a notification is for.
+#[derive(Clone, Debug, Default, Serialize, PartialEq)]
+pub struct Summary {
+    /// What found the failures, like `tests run` or `monitor`.
+    pub source: String,
+    pub total: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    pub timed_out: usize,
+    pub failures: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct Payload<'a> {
+    text: String,
+    #[serde(flatten)]
+    summary: &'a Summary,
+}
+
+impl Notification {
+    /// Send the notification for the summary.
+    pub async fn send(&self, app: &Applicator, summary: &Summary) -> Result<()> {
+        let app = summary.applicator(app);
+        let mut request = reqwest::Client::new().post(app.apply(&self.url));
+        for (key, value) in &self.headers {
+            request = request.header(key, app.apply(value));
+        }
+        request
+            .json(&Payload {
+                text: app.apply(&self.message),
+                summary,
+            })
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+impl Summary {
+    /// An applicator with the summary variables set.
+    fn applicator(&self, app: &Applicator) -> Applicator {
+        let mut app = app.with_defaults(&HashMap::new());
+        app.set("source", self.source.clone());
+        app.set("total", self.total.to_string());
+        let passed = self
+            .total
+            .saturating_sub(self.failed + self.skipped + self.timed_out);
+        app.set("passed", passed.to_string());
+        app.set("failed", self.failed.to_string());
+        app.set("skipped", self.skipped.to_string());
+        app.set("timed_out", self.timed_out.to_string());
+        app.set("failures", self.failures.join("\n"));
+        app
+    }
+}
+
+/// Send every notification for the summary. Failing to send one is
+/// logged rather than stopping the others.
+pub async fn send_all(notifications: &[Notification], app: &Applicator, summary: &Summary) {
+    for notification in notifications {
+        if let Err(e) = notification.send(app, summary).await {
+            tracing::error!(url = %notification.url, error = %e, "sending notification failed");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn message() {
+        let notification: Notification = serde_yaml::from_str("url: http://hooks/${team}").unwrap();
+        let summary = Summary {
+            source: "tests run".into(),
+            total: 3,
+            failed: 1,
+            skipped: 1,
+            failures: vec!["smoke/get/status_code == 200: got 500".into()],
+            ..Default::default()
+        };
+        let context = vec![("team".to_string(), "api".to_string())]
+            .into_iter()
+            .collect();
+        let app = summary.applicator(&Applicator::new(context, HashMap::new()));
+        assert_eq!(app.apply(&notification.url), "http://hooks/api");
+        assert_eq!(
+            app.apply(&notification.message),
+            "apictl tests run: 1 of 3 failed\nsmoke/get/status_code == 200: got 500"
+        );
+        assert_eq!(app.apply("${passed}"), "1");
+    }
+}
@@ -0,0 +1,255 @@
+/// Ntlm builds the messages of the NTLM handshake that some legacy
+/// gateways require. Only NTLMv2 responses are sent.
+use hmac::{Hmac, KeyInit, Mac};
+use md4::{Digest, Md4};
+use md5::Md5;
+use thiserror::Error;
+
+/// NtlmError is the error type for NTLM.
+#[derive(Error, Debug)]
+pub enum NtlmError {
+    #[error("invalid challenge: {0}")]
+    Challenge(String),
+}
+
+/// Result is the result type for NTLM.
+pub type Result<T> = std::result::Result<T, NtlmError>;
+
+const SIGNATURE: &[u8; 8] = b"NTLMSSP\0";
+
+const NEGOTIATE_UNICODE: u32 = 0x0000_0001;
+const REQUEST_TARGET: u32 = 0x0000_0004;
+const NEGOTIATE_NTLM: u32 = 0x0000_0200;
+const NEGOTIATE_ALWAYS_SIGN: u32 = 0x0000_8000;
+const NEGOTIATE_EXTENDED_SESSION_SECURITY: u32 = 0x0008_0000;
+const NEGOTIATE_128: u32 = 0x2000_0000;
+const NEGOTIATE_56: u32 = 0x8000_0000;
+
+/// The flags the client asks for.
+const FLAGS: u32 = NEGOTIATE_UNICODE
+    | REQUEST_TARGET
+    | NEGOTIATE_NTLM
+    | NEGOTIATE_ALWAYS_SIGN
+    | NEGOTIATE_EXTENDED_SESSION_SECURITY
+    | NEGOTIATE_128
+    | NEGOTIATE_56;
+
+/// The ids of the attribute-value pairs in the challenge's target info
+/// that are read.
+const AV_EOL: u16 = 0;
+const AV_TIMESTAMP: u16 = 7;
+
+/// The seconds between 1601, when Windows time starts, and 1970.
+const WINDOWS_EPOCH_OFFSET: u64 = 11_644_473_600;
+
+/// Challenge is what the server sends in answer to the negotiate
+/// message.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Challenge {
+    pub flags: u32,
+    pub server_challenge: [u8; 8],
+    pub target_info: Vec<u8>,
+}
+
+/// The NEGOTIATE message that starts the handshake.
+pub fn negotiate() -> Vec<u8> {
+    let mut message = SIGNATURE.to_vec();
+    message.extend(1u32.to_le_bytes());
+    message.extend(FLAGS.to_le_bytes());
+    // The domain and workstation aren't sent.
+    message.extend([0; 16]);
+    message
+}
+
+fn u16_at(message: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        message.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn u32_at(message: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        message.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+/// Parse the server's CHALLENGE message.
+pub fn parse_challenge(message: &[u8]) -> Result<Challenge> {
+    if message.get(..8) != Some(SIGNATURE) || u32_at(message, 8) != Some(2) {
+        return Err(NtlmError::Challenge("not a challenge message".into()));
+    }
+    let truncated = || NtlmError::Challenge("truncated".into());
+    let flags = u32_at(message, 20).ok_or_else(truncated)?;
+    let server_challenge = message
+        .get(24..32)
+        .and_then(|c| c.try_into().ok())
+        .ok_or_else(truncated)?;
+    let target_info = match (u16_at(message, 40), u32_at(message, 44)) {
+        (Some(len), Some(offset)) => {
+            let start = offset as usize;
+            message
+                .get(start..start + len as usize)
+                .ok_or_else(truncated)?
+                .to_vec()
+        }
+        _ => vec![],
+    };
+    Ok(Challenge {
+        flags,
+        server_challenge,
+        target_info,
+    })
+}
+
+/// The value of the attribute-value pair in the target info.
+fn av_pair(target_info: &[u8], id: u16) -> Option<&[u8]> {
+    let mut offset = 0;
+    loop {
+        let av_id = u16_at(target_info, offset)?;
+        let len = u16_at(target_info, offset + 2)? as usize;
+        if av_id == AV_EOL {
+            return None;
+        }
+        let value = target_info.get(offset + 4..offset + 4 + len)?;
+        if av_id == id {
+            return Some(value);
+        }
+        offset += 4 + len;
+    }
+}
+
+fn utf16(s: &str) -> Vec<u8> {
+    s.encode_utf16().flat_map(u16::to_le_bytes).collect()
+}
+
+fn hmac_md5(key: &[u8], data: &[u8]) -> [u8; 16] {
+    let mut mac = Hmac::<Md5>::new_from_slice(key).expect("hmac takes any key");
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+/// The key NTLMv2 responses are made with.
+fn ntowfv2(domain: &str, username: &str, password: &str) -> [u8; 16] {
+    let hash = Md4::digest(utf16(password));
+    hmac_md5(
+        &hash,
+        &utf16(&format!("{}{}", username.to_uppercase(), domain)),
+    )
+}
+
+/// The time now in Windows' FILETIME: 100ns intervals since 1601.
+pub fn filetime(time: std::time::SystemTime) -> u64 {
+    let since = time
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    (since.as_secs() + WINDOWS_EPOCH_OFFSET) * 10_000_000 + u64::from(since.subsec_nanos() / 100)
+}
+
+/// The AUTHENTICATE message answering the challenge with NTLMv2
+/// responses. The server's time in the challenge is used when it sends
+/// one.
+pub fn authenticate(
+    challenge: &Challenge,
+    domain: &str,
+    username: &str,
+    password: &str,
+    client_challenge: [u8; 8],
+    time: u64,
+) -> Vec<u8> {
+    let key = ntowfv2(domain, username, password);
+    let timestamp = av_pair(&challenge.target_info, AV_TIMESTAMP)
+        .and_then(|t| t.try_into().ok())
+        .map(u64::from_le_bytes);
+
+    let mut blob = vec![1, 1, 0, 0, 0, 0, 0, 0];
+    blob.extend(timestamp.unwrap_or(time).to_le_bytes());
+    blob.extend(client_challenge);
+    blob.extend([0; 4]);
+    blob.extend(&challenge.target_info);
+    blob.extend([0; 4]);
+    let proof = hmac_md5(&key, &[&challenge.server_challenge[..], &blob].concat());
+    let nt = [&proof[..], &blob].concat();
+    // The LMv2 response is left empty when the server sends its time.
+    let lm = match timestamp {
+        Some(_) => vec![0; 24],
+        None => {
+            let challenges = [challenge.server_challenge, client_challenge].concat();
+            [&hmac_md5(&key, &challenges)[..], &client_challenge].concat()
+        }
+    };
+
+    // The fields are described in the header and follow it: the
+    // responses, domain, user, workstation and session key.
+    let fields = [lm, nt, utf16(domain), utf16(username), vec![], vec![]];
+    let mut header = SIGNATURE.to_vec();
+    header.extend(3u32.to_le_bytes());
+    let mut payload: Vec<u8> = vec![];
+    let mut offset = 8 + 4 + fields.len() * 8 + 4;
+    for field in &fields {
+        header.extend((field.len() as u16).to_le_bytes());
+        header.extend((field.len() as u16).to_le_bytes());
+        header.extend((offset as u32).to_le_bytes());
+        offset += field.len();
+        payload.extend(field);
+    }
+    header.extend((FLAGS & challenge.flags | NEGOTIATE_UNICODE).to_le_bytes());
+    header.extend(payload);
+    header
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn authenticate() {
+        // From the NTLMv2 example in MS-NLMP.
+        let mut target_info = vec![2, 0, 12, 0];
+        target_info.extend(utf16("Domain"));
+        target_info.extend([1, 0, 12, 0]);
+        target_info.extend(utf16("Server"));
+        target_info.extend([0, 0, 0, 0]);
+        let challenge = Challenge {
+            flags: 0xe28a_8233,
+            server_challenge: hex("0123456789abcdef").try_into().unwrap(),
+            target_info,
+        };
+        assert_eq!(
+            ntowfv2("Domain", "User", "Password").to_vec(),
+            hex("0c868a403bfd7a93a3001ef22ef02e3f")
+        );
+
+        let message = super::authenticate(&challenge, "Domain", "User", "Password", [0xaa; 8], 0);
+        let field = |at: usize| {
+            let len = u16_at(&message, at).unwrap() as usize;
+            let offset = u32_at(&message, at + 4).unwrap() as usize;
+            message[offset..offset + len].to_vec()
+        };
+        assert_eq!(
+            field(12),
+            hex("86c35097ac9cec102554764a57cccc19aaaaaaaaaaaaaaaa")
+        );
+        assert_eq!(field(20)[..16], hex("68cd0ab851e51c96aabc927bebef6a1c"));
+        assert_eq!(field(36), utf16("User"));
+
+        let mut server = SIGNATURE.to_vec();
+        server.extend(2u32.to_le_bytes());
+        server.extend([0; 8]);
+        server.extend(challenge.flags.to_le_bytes());
+        server.extend(challenge.server_challenge);
+        server.extend([0; 8]);
+        server.extend((challenge.target_info.len() as u16).to_le_bytes());
+        server.extend((challenge.target_info.len() as u16).to_le_bytes());
+        server.extend(48u32.to_le_bytes());
+        server.extend(&challenge.target_info);
+        assert_eq!(parse_challenge(&server).unwrap(), challenge);
+        assert!(parse_challenge(&negotiate()).is_err());
+    }
+}
@@ -0,0 +1,384 @@
+/// OpenApi is used to export a best-effort OpenAPI 3 document from the
+/// requests and the responses cached for them.
+use std::collections::{BTreeMap, HashMap};
+
+use crate::request::{Body, MultiPartField, RawBody};
+use crate::template::Segment;
+use crate::{Context, Request, Response, Template};
+
+use serde::Serialize;
+use serde_json::{json, Value};
+
+/// OpenApi is the root of an OpenAPI 3 document.
+#[derive(Debug, Serialize, PartialEq)]
+pub struct OpenApi {
+    pub openapi: String,
+    pub info: Info,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub servers: Vec<Server>,
+    /// The operations for each path by method.
+    pub paths: BTreeMap<String, BTreeMap<String, Operation>>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Info {
+    pub title: String,
+    pub version: String,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Server {
+    pub url: String,
+    pub description: String,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Operation {
+    pub operation_id: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub summary: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub parameters: Vec<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_body: Option<Value>,
+    pub responses: BTreeMap<String, Value>,
+}
+
+/// Headers that describe the body or are set by the client, which
+/// aren't parameters of the operation.
+const SKIPPED_HEADERS: [&str; 4] = ["accept", "authorization", "content-type", "user-agent"];
+
+impl OpenApi {
+    /// Build the document from the requests. The base URLs of the
+    /// contexts are used as the servers and the responses cached for
+    /// each request become its example responses.
+    pub fn from_requests(
+        title: &str,
+        requests: &HashMap<String, Request>,
+        responses: &HashMap<String, Response>,
+        contexts: &HashMap<String, Context>,
+    ) -> Self {
+        let mut servers = contexts
+            .iter()
+            .filter_map(|(name, c)| {
+                Some(Server {
+                    url: c.variables.get("base_url")?.clone(),
+                    description: name.clone(),
+                })
+            })
+            .collect::<Vec<_>>();
+        servers.sort_by(|a, b| a.description.cmp(&b.description));
+
+        let mut paths: BTreeMap<String, BTreeMap<String, Operation>> = BTreeMap::new();
+        let mut names = requests.keys().collect::<Vec<_>>();
+        names.sort();
+        for name in names {
+            let request = &requests[name];
+            if request.websocket.is_some() || request.grpc.is_some() {
+                continue;
+            }
+            let (path, path_parameters) = path(&request.url);
+            let operation = operation(name, request, path_parameters, responses.get(name));
+            let methods = paths.entry(path).or_default();
+            match methods.get_mut(&request.method.to_lowercase()) {
+                // Requests to the same operation add their responses.
+                Some(existing) => {
+                    for (status, response) in operation.responses {
+                        existing.responses.entry(status).or_insert(response);
+                    }
+                }
+                None => {
+                    methods.insert(request.method.to_lowercase(), operation);
+                }
+            }
+        }
+
+        Self {
+            openapi: "3.0.3".into(),
+            info: Info {
+                title: title.into(),
+                version: "1.0.0".into(),
+            },
+            servers,
+            paths,
+        }
+    }
+}
+
+/// The path of the URL without the base URL or query, with variables
+/// written as `{name}`, and the names of those variables.
+fn path(url: &str) -> (String, Vec<String>) {
+    let mut url = url.split('?').next().unwrap_or_default();
+    if let Some(rest) = url.strip_prefix("${") {
+        // A leading variable is the base URL.
+        url = rest.split_once('}').map(|(_, r)| r).unwrap_or_default();
+    } else if let Some((_, rest)) = url.split_once("://") {
+        url = rest.find('/').map(|i| &rest[i..]).unwrap_or_default();
+    }
+
+    let mut parameters = vec![];
+    let mut path = String::new();
+    for segment in Template::parse(url).segments() {
+        match segment {
+            Segment::Literal(s) => path.push_str(s),
+            Segment::Call(..) | Segment::Expression(_) => path.push_str(&segment.to_string()),
+            Segment::Variable(name) => {
+                // Use the last part of response paths like response.user.id.
+                let name = name.rsplit('.').next().unwrap_or(name).to_string();
+                path.push_str(&format!("{{{}}}", name));
+                if !parameters.contains(&name) {
+                    parameters.push(name);
+                }
+            }
+        }
+    }
+    let path = match path.starts_with('/') {
+        true => path,
+        false => format!("/{}", path),
+    };
+    (path, parameters)
+}
+
+fn operation(
+    name: &str,
+    request: &Request,
+    path_parameters: Vec<String>,
+    response: Option<&Response>,
+) -> Operation {
+    let mut parameters = path_parameters
+        .into_iter()
+        .map(|p| json!({"name": p, "in": "path", "required": true, "schema": {"type": "string"}}))
+        .collect::<Vec<_>>();
+    for (location, values) in [
+        ("query", &request.query_parameters),
+        ("header", &request.headers),
+    ] {
+        let mut names = values
+            .keys()
+            .filter(|k| {
+                location != "header" || !SKIPPED_HEADERS.contains(&k.to_lowercase().as_str())
+            })
+            .collect::<Vec<_>>();
+        names.sort();
+        for name in names {
+            let mut parameter = json!({"name": name, "in": location, "schema": {"type": "string"}});
+            if !values[name].contains("${") {
+                parameter["example"] = Value::String(values[name].clone());
+            }
+            parameters.push(parameter);
+        }
+    }
+
+    let mut responses = BTreeMap::new();
+    match response {
+        Some(response) => {
+            let mut description = json!({"description": format!("example from {}", name)});
+            let content_type = response
+                .headers
+                .get("content-type")
+                .map(|c| c.split(';').next().unwrap_or_default().trim().to_string())
+                .unwrap_or_else(|| "text/plain".into());
+            if !response.body.is_empty() {
+                let example = response
+                    .json()
+                    .cloned()
+                    .unwrap_or_else(|| Value::String(response.body.clone()));
+                description["content"] = json!({
+                    content_type: {"schema": schema(&example), "example": example}
+                });
+            }
+            responses.insert(response.status_code.to_string(), description);
+        }
+        None => {
+            responses.insert("default".into(), json!({"description": "response"}));
+        }
+    }
+
+    Operation {
+        operation_id: name.into(),
+        summary: request.description.clone(),
+        tags: request.tags.clone(),
+        parameters,
+        request_body: request_body(&request.body),
+        responses,
+    }
+}
+
+/// The request body of the operation, with the body as the example.
+fn request_body(body: &Body) -> Option<Value> {
+    let (content_type, example) = match body {
+        Body::None => return None,
+        Body::Form { data } => ("application/x-www-form-urlencoded", json!(data)),
+        Body::MultiPart { data } => (
+            "multipart/form-data",
+            Value::Object(
+                data.iter()
+                    .map(|(k, v)| {
+                        let mut parts = v
+                            .parts()
+                            .iter()
+                            .map(|p| match p {
+                                MultiPartField::Text { data, .. } => Value::String(data.clone()),
+                                MultiPartField::File { path, .. } => {
+                                    Value::String(format!("@{}", path))
+                                }
+                            })
+                            .collect::<Vec<_>>();
+                        let v = match parts.len() {
+                            1 => parts.remove(0),
+                            _ => Value::Array(parts),
+                        };
+                        (k.clone(), v)
+                    })
+                    .collect(),
+            ),
+        ),
+        Body::Raw {
+            from: RawBody::Text { data },
+        } => match serde_json::from_str::<Value>(data) {
+            Ok(v) => ("application/json", v),
+            Err(_) => ("text/plain", Value::String(data.clone())),
+        },
+        Body::Raw {
+            from: RawBody::Template { path },
+        } => match std::fs::read_to_string(path) {
+            Ok(data) => match serde_json::from_str::<Value>(&data) {
+                Ok(v) => ("application/json", v),
+                Err(_) => ("text/plain", Value::String(data)),
+            },
+            Err(_) => ("application/octet-stream", Value::Null),
+        },
+        Body::Raw {
+            from: RawBody::File { .. },
+        } => ("application/octet-stream", Value::Null),
+        Body::GraphQL {
+            query, variables, ..
+        } => (
+            "application/json",
+            json!({"query": query, "variables": variables}),
+        ),
+        Body::Soap {
+            envelope, header, ..
+        } => (
+            "text/xml",
+            Value::String(Body::soap_envelope(envelope, header.as_deref())),
+        ),
+    };
+    let mut media = json!({"schema": schema(&example)});
+    if !example.is_null() {
+        media["example"] = example;
+    }
+    Some(json!({"content": {content_type: media}}))
+}
+
+/// Infer the schema of the value. Arrays use the schema of their first
+/// element.
+pub fn schema(value: &Value) -> Value {
+    match value {
+        Value::Null => json!({}),
+        Value::Bool(_) => json!({"type": "boolean"}),
+        Value::Number(n) if n.is_i64() || n.is_u64() => json!({"type": "integer"}),
+        Value::Number(_) => json!({"type": "number"}),
+        Value::String(_) => json!({"type": "string"}),
+        Value::Array(a) => json!({
+            "type": "array",
+            "items": a.first().map(schema).unwrap_or_else(|| json!({})),
+        }),
+        Value::Object(o) => json!({
+            "type": "object",
+            "properties": o
+                .iter()
+                .map(|(k, v)| (k.clone(), schema(v)))
+                .collect::<serde_json::Map<_, _>>(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths() {
+        assert_eq!(
+            path("${base_url}/users/${id}/posts?limit=1"),
+            ("/users/{id}/posts".into(), vec!["id".into()])
+        );
+        assert_eq!(
+            path("https://api.example.com/v1/items/${response.list.id}"),
+            ("/v1/items/{id}".into(), vec!["id".into()])
+        );
+        assert_eq!(path("health"), ("/health".into(), vec![]));
+    }
+
+    #[test]
+    fn from_requests() {
+        let requests: HashMap<String, Request> = serde_yaml::from_str(
+            r#"
+get-user:
+  description: get a user
+  tags: [users]
+  url: ${base_url}/users/${id}
+  query_parameters: {fields: name}
+create-user:
+  description: ""
+  tags: [users]
+  url: ${base_url}/users
+  method: POST
+  headers: {content-type: application/json}
+  body: {type: raw, from: {type: text, data: '{"name": "bob", "age": 3}'}}
+"#,
+        )
+        .unwrap();
+        let mut response = Response {
+            status_code: 200,
+            body: r#"{"id": "7", "tags": ["a"]}"#.into(),
+            ..Default::default()
+        };
+        response.headers.insert(
+            "content-type".into(),
+            "application/json; charset=utf-8".into(),
+        );
+        let responses = vec![("get-user".to_string(), response)]
+            .into_iter()
+            .collect();
+        let contexts = vec![(
+            "local".to_string(),
+            Context {
+                variables: vec![("base_url".to_string(), "http://localhost:8080".to_string())]
+                    .into_iter()
+                    .collect(),
+                ..Default::default()
+            },
+        )]
+        .into_iter()
+        .collect();
+
+        let spec = OpenApi::from_requests("api", &requests, &responses, &contexts);
+        assert_eq!(spec.servers[0].url, "http://localhost:8080");
+
+        let get = &spec.paths["/users/{id}"]["get"];
+        assert_eq!(get.operation_id, "get-user");
+        assert_eq!(get.parameters[0]["in"], "path");
+        assert_eq!(get.parameters[1]["example"], "name");
+        assert_eq!(
+            get.responses["200"]["content"]["application/json"]["schema"],
+            json!({"type": "object", "properties": {
+                "id": {"type": "string"},
+                "tags": {"type": "array", "items": {"type": "string"}},
+            }})
+        );
+
+        let post = &spec.paths["/users"]["post"];
+        assert!(post.parameters.is_empty());
+        assert_eq!(
+            post.request_body.as_ref().unwrap()["content"]["application/json"]["schema"]
+                ["properties"]["age"],
+            json!({"type": "integer"})
+        );
+        assert!(post.responses.contains_key("default"));
+    }
+}
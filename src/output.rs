@@ -1,8 +1,12 @@
 /// Output is used to help output commands in a variety of formats.
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::Context;
 
 use prettytable::{Cell, Row, Table};
 use serde::Serialize;
+use serde_yaml::{Mapping, Value};
 use thiserror::Error;
 
 /// OutputFormat is the format to output the data in.
@@ -14,6 +18,8 @@ pub enum OutputFormat {
     TSV,
     /// yaml
     Yaml,
+    /// json
+    Json,
 }
 
 /// Errors that can occur when outputting data.
@@ -22,8 +28,17 @@ pub enum OutputError {
     #[error("yaml parse error: {0}")]
     Yaml(#[from] serde_yaml::Error),
 
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+
     #[error("format error: {0}")]
     Format(String),
+
+    #[error("sort error: {0}")]
+    Sort(String),
+
+    #[error("group error: {0}")]
+    Group(String),
 }
 
 /// Result is a convenience type for output results.
@@ -37,59 +52,355 @@ impl std::str::FromStr for OutputFormat {
             "table" => Ok(OutputFormat::Table),
             "tsv" => Ok(OutputFormat::TSV),
             "yaml" => Ok(OutputFormat::Yaml),
+            "json" => Ok(OutputFormat::Json),
             _ => Err(OutputError::Format(format!("unknown format: {}", s))),
         }
     }
 }
 
+/// Summary counts the entries of a list and how many are in each group,
+/// like the requests with each tag.
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct Summary {
+    pub total: usize,
+    #[serde(flatten)]
+    pub groups: BTreeMap<String, BTreeMap<String, usize>>,
+}
+
+/// The group entries without a value for a field are counted in.
+pub const NO_GROUP: &str = "(none)";
+
 /// List is a trait for types that can be output.
 pub trait List: Serialize {
     /// Returns the headers (fields) for the output.
     fn headers(&self) -> Vec<String>;
 
-    /// Returns the values for the output.
+    /// Returns the values for the output. The first value of each row
+    /// is its name.
     fn values(&self) -> Vec<Vec<String>>;
 
-    /// Outputs the data in the given format.
-    fn output(&self, format: OutputFormat) -> Result<()> {
-        match format {
-            OutputFormat::Yaml => {
-                println!("{}", serde_yaml::to_string(&self)?);
+    /// Returns the fields the entries can be grouped by, like tag.
+    fn group_fields(&self) -> Vec<String> {
+        vec![]
+    }
+
+    /// Returns the groups the named entry is in for the field. An entry
+    /// can be in many groups, like with tags, or none.
+    fn groups(&self, _name: &str, _field: &str) -> Vec<String> {
+        vec![]
+    }
+
+    /// Returns the number of entries and the number in each group.
+    fn summary(&self) -> Summary {
+        let values = self.values();
+        let mut summary = Summary {
+            total: values.len(),
+            ..Default::default()
+        };
+        for field in self.group_fields() {
+            let counts = summary.groups.entry(field.clone()).or_default();
+            for row in &values {
+                let groups = self.groups(&row[0], &field);
+                if groups.is_empty() {
+                    *counts.entry(NO_GROUP.into()).or_default() += 1;
+                }
+                for group in groups {
+                    *counts.entry(group).or_default() += 1;
+                }
             }
+        }
+        summary
+    }
+
+    /// Outputs the summary in the given format.
+    fn output_summary(&self, format: OutputFormat) -> Result<()> {
+        let summary = self.summary();
+        let mut rows = vec![vec![
+            "total".to_string(),
+            String::new(),
+            summary.total.to_string(),
+        ]];
+        for (field, counts) in &summary.groups {
+            for (group, count) in counts {
+                rows.push(vec![field.clone(), group.clone(), count.to_string()]);
+            }
+        }
+        match format {
+            OutputFormat::Yaml => println!("{}", serde_yaml::to_string(&summary)?),
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&summary)?),
             OutputFormat::TSV => {
-                for l in self.values() {
-                    println!("{}", l.join("\t"));
+                for row in rows {
+                    println!("{}", row.join("\t"));
                 }
             }
             OutputFormat::Table => {
-                let mut table = Table::new();
-                let mut header = Row::empty();
-                for h in self.headers() {
-                    header.add_cell(Cell::new(&h).style_spec("b"));
+                print_table(&["Field".into(), "Group".into(), "Count".into()], rows)
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the sorted rows in each group of the field. An entry is
+    /// in every group it has and ones without any are in NO_GROUP.
+    fn grouped_values(
+        &self,
+        sort: &str,
+        field: &str,
+    ) -> Result<BTreeMap<String, Vec<Vec<String>>>> {
+        let fields = self.group_fields();
+        if !fields.iter().any(|f| f == field) {
+            return Err(OutputError::Group(format!(
+                "unknown field '{}', expected one of: {}",
+                field,
+                fields.join(", ")
+            )));
+        }
+        let mut grouped: BTreeMap<String, Vec<Vec<String>>> = BTreeMap::new();
+        for row in self.sorted_values(sort)? {
+            let mut groups = self.groups(&row[0], field);
+            if groups.is_empty() {
+                groups.push(NO_GROUP.into());
+            }
+            for group in groups {
+                grouped.entry(group).or_default().push(row.clone());
+            }
+        }
+        Ok(grouped)
+    }
+
+    /// Outputs the data grouped by the field, like tag. Tables are
+    /// printed for each group and YAML and JSON nest the entries under
+    /// their groups.
+    fn output_grouped(&self, format: OutputFormat, sort: &str, field: &str) -> Result<()> {
+        let grouped = self.grouped_values(sort, field)?;
+        match format {
+            OutputFormat::Yaml | OutputFormat::Json => {
+                let mapping = mapping(self)?;
+                let nested = grouped
+                    .iter()
+                    .map(|(group, values)| {
+                        (
+                            Value::String(group.clone()),
+                            Value::Mapping(entries(&mapping, values)),
+                        )
+                    })
+                    .collect::<Mapping>();
+                match format {
+                    OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&nested)?),
+                    _ => println!("{}", serde_yaml::to_string(&nested)?),
                 }
-                table.add_row(header);
-                for l in self.values() {
-                    let mut row = Row::empty();
-                    for c in l {
-                        row.add_cell(Cell::new(&c));
+            }
+            OutputFormat::TSV => {
+                for (group, values) in grouped {
+                    for l in values {
+                        println!("{}\t{}", group, l.join("\t"));
                     }
-                    table.add_row(row);
                 }
-                table.printstd();
             }
+            OutputFormat::Table => {
+                for (group, values) in grouped {
+                    println!("{}: {}", field, group);
+                    print_table(&self.headers(), values);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the values sorted by the column with the given header,
+    /// ignoring case, or in descending order when the header starts
+    /// with `-`. Numbers are sorted by value and rows that tie are
+    /// sorted by name, so the order is the same on every run.
+    fn sorted_values(&self, sort: &str) -> Result<Vec<Vec<String>>> {
+        let (sort, descending) = match sort.strip_prefix('-') {
+            Some(sort) => (sort, true),
+            None => (sort, false),
+        };
+        let column = self
+            .headers()
+            .iter()
+            .position(|h| h.eq_ignore_ascii_case(sort))
+            .ok_or_else(|| {
+                OutputError::Sort(format!(
+                    "unknown column '{}', expected one of: {}",
+                    sort,
+                    self.headers().join(", ")
+                ))
+            })?;
+        let mut values = self.values();
+        values.sort_by(|a, b| {
+            let order = compare(&a[column], &b[column]);
+            match descending {
+                true => order.reverse(),
+                false => order,
+            }
+            .then_with(|| a[0].cmp(&b[0]))
+        });
+        Ok(values)
+    }
+
+    /// Outputs the data in the given format, sorted by the column with
+    /// the given header. YAML and JSON keep the sorted order for the
+    /// entries and sort the keys within them.
+    fn output(&self, format: OutputFormat, sort: &str) -> Result<()> {
+        let values = self.sorted_values(sort)?;
+        match format {
+            OutputFormat::Yaml => {
+                let entries = entries(&mapping(self)?, &values);
+                println!("{}", serde_yaml::to_string(&entries)?);
+            }
+            OutputFormat::Json => {
+                let entries = entries(&mapping(self)?, &values);
+                println!("{}", serde_json::to_string_pretty(&entries)?);
+            }
+            OutputFormat::TSV => {
+                for l in values {
+                    println!("{}", l.join("\t"));
+                }
+            }
+            OutputFormat::Table => print_table(&self.headers(), values),
         };
 
         Ok(())
     }
 }
 
+fn print_table(headers: &[String], values: Vec<Vec<String>>) {
+    let mut table = Table::new();
+    let mut header = Row::empty();
+    for h in headers {
+        header.add_cell(Cell::new(h).style_spec("b"));
+    }
+    table.add_row(header);
+    for l in values {
+        let mut row = Row::empty();
+        for c in l {
+            row.add_cell(Cell::new(&c));
+        }
+        table.add_row(row);
+    }
+    table.printstd();
+}
+
+/// The entries of the list as a YAML mapping.
+fn mapping<L: List + ?Sized>(list: &L) -> Result<Mapping> {
+    match serde_yaml::to_value(list)? {
+        Value::Mapping(m) => Ok(m),
+        _ => Ok(Mapping::new()),
+    }
+}
+
+/// The entries for the rows, in the order of the rows and with the keys
+/// within them sorted.
+fn entries(mapping: &Mapping, values: &[Vec<String>]) -> Mapping {
+    values
+        .iter()
+        .filter_map(|row| {
+            let v = mapping.get(row[0].as_str())?;
+            Some((Value::String(row[0].clone()), sort_keys(v.clone())))
+        })
+        .collect()
+}
+
+/// Compare the values as numbers if they both are and as text if not.
+fn compare(a: &str, b: &str) -> Ordering {
+    match (a.parse::<f64>(), b.parse::<f64>()) {
+        (Ok(a), Ok(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+        _ => a.cmp(b),
+    }
+}
+
+/// Sort the keys of every mapping in the value.
+fn sort_keys(value: Value) -> Value {
+    match value {
+        Value::Mapping(m) => {
+            let mut entries = m
+                .into_iter()
+                .map(|(k, v)| (k, sort_keys(v)))
+                .collect::<Vec<_>>();
+            entries.sort_by(|(a, _), (b, _)| {
+                serde_yaml::to_string(a)
+                    .unwrap_or_default()
+                    .cmp(&serde_yaml::to_string(b).unwrap_or_default())
+            });
+            Value::Mapping(entries.into_iter().collect())
+        }
+        Value::Sequence(s) => Value::Sequence(s.into_iter().map(sort_keys).collect()),
+        v => v,
+    }
+}
+
 /// This will implement List for Contexts.
-impl List for HashMap<String, HashMap<String, String>> {
+impl List for HashMap<String, Context> {
     fn headers(&self) -> Vec<String> {
         vec!["Name".into()]
     }
 
     fn values(&self) -> Vec<Vec<String>> {
-        self.iter().map(|(n, _)| vec![n.clone()]).collect()
+        self.keys().map(|n| vec![n.clone()]).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sorted_values() {
+        let contexts: HashMap<String, Context> = ["b", "c", "a"]
+            .iter()
+            .map(|n| (n.to_string(), Context::default()))
+            .collect();
+        let names = |sort| {
+            contexts
+                .sorted_values(sort)
+                .unwrap()
+                .into_iter()
+                .map(|r| r[0].clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names("name"), vec!["a", "b", "c"]);
+        assert_eq!(names("NAME"), vec!["a", "b", "c"]);
+        assert_eq!(names("-name"), vec!["c", "b", "a"]);
+        assert!(contexts.sorted_values("url").is_err());
+        assert_eq!(compare("9", "10"), Ordering::Less);
+        assert_eq!(compare("b", "a"), Ordering::Greater);
+    }
+
+    #[test]
+    fn summary() {
+        let requests: HashMap<String, crate::Request> = serde_yaml::from_str(
+            r#"
+users:
+  description: ""
+  tags: [users, admin]
+  method: get
+  url: /users
+create:
+  description: ""
+  tags: []
+  method: post
+  url: /users
+"#,
+        )
+        .unwrap();
+        let counts = |pairs: &[(&str, usize)]| {
+            pairs
+                .iter()
+                .map(|(g, n)| (g.to_string(), *n))
+                .collect::<BTreeMap<_, _>>()
+        };
+        let summary = requests.summary();
+        assert_eq!(summary.total, 2);
+        assert_eq!(
+            summary.groups["tag"],
+            counts(&[("admin", 1), ("users", 1), (NO_GROUP, 1)])
+        );
+        assert_eq!(summary.groups["method"], counts(&[("GET", 1), ("POST", 1)]));
+        assert_eq!(summary.groups["file"], counts(&[(NO_GROUP, 2)]));
+        assert_eq!(
+            HashMap::<String, Context>::new().summary(),
+            Summary::default()
+        );
     }
 }
@@ -0,0 +1,156 @@
+/// Paginate is used to describe how to follow the pages of a paginated
+/// endpoint.
+use crate::Response;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Paginate describes how to find the items in a page and the next
+/// page. The next page is found with the first of these that is set:
+/// next_path (a URL, or a cursor when cursor_param is set), next_link
+/// (the `Link` header with `rel="next"`), or page_param (a page number
+/// incremented until a page has no items).
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, JsonSchema)]
+pub struct Paginate {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub items: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor_param: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub next_link: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_param: Option<String>,
+    #[serde(default = "default_max_pages")]
+    pub max_pages: usize,
+}
+
+fn default_max_pages() -> usize {
+    10
+}
+
+/// Next is how to request the next page.
+#[derive(Debug, PartialEq)]
+pub enum Next {
+    /// Request the URL.
+    Url(String),
+    /// Set the query parameter to the value.
+    Param(String, String),
+}
+
+impl Paginate {
+    /// The items in the page. If items isn't set, the body should be an
+    /// array.
+    pub fn items(&self, response: &Response) -> Vec<Value> {
+        let body = match response.json() {
+            Some(b) => b,
+            None => return vec![],
+        };
+        let items = match &self.items {
+            Some(path) => path
+                .split('.')
+                .try_fold(body, |v, t| match t.parse::<usize>() {
+                    Ok(i) => v.get(i),
+                    Err(_) => v.get(t),
+                }),
+            None => Some(body),
+        };
+        match items {
+            Some(Value::Array(a)) => a.clone(),
+            _ => vec![],
+        }
+    }
+
+    /// Determine how to get the page after the response. The current
+    /// page number is used with page_param.
+    pub fn next(&self, response: &Response, page: usize, items: usize) -> Option<Next> {
+        if let Some(path) = &self.next_path {
+            let next = response.find_path_in_body(path)?;
+            if next.is_empty() || next == "null" {
+                return None;
+            }
+            return Some(match &self.cursor_param {
+                Some(param) => Next::Param(param.clone(), next),
+                None => Next::Url(next),
+            });
+        }
+        if self.next_link {
+            return response
+                .headers
+                .get("link")
+                .and_then(|l| next_link(l))
+                .map(Next::Url);
+        }
+        if let Some(param) = &self.page_param {
+            if items > 0 {
+                return Some(Next::Param(param.clone(), (page + 1).to_string()));
+            }
+        }
+        None
+    }
+}
+
+/// Find the URL with `rel="next"` in a Link header.
+fn next_link(header: &str) -> Option<String> {
+    header.split(',').find_map(|link| {
+        let (url, params) = link.split_once(';')?;
+        params
+            .split(';')
+            .any(|p| p.trim().trim_start_matches("rel=").trim_matches('"') == "next")
+            .then(|| {
+                url.trim()
+                    .trim_start_matches('<')
+                    .trim_end_matches('>')
+                    .to_string()
+            })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn next() {
+        let response = Response {
+            headers: vec![(
+                "link".to_string(),
+                r#"<http://a/?page=1>; rel="prev", <http://a/?page=3>; rel="next""#.to_string(),
+            )]
+            .into_iter()
+            .collect(),
+            body: r#"{"data": [1, 2], "meta": {"next": "abc"}}"#.into(),
+            ..Default::default()
+        };
+
+        let mut paginate = Paginate {
+            items: Some("data".into()),
+            next_link: true,
+            ..Default::default()
+        };
+        assert_eq!(paginate.items(&response).len(), 2);
+        assert_eq!(
+            paginate.next(&response, 1, 2),
+            Some(Next::Url("http://a/?page=3".into()))
+        );
+
+        paginate.next_path = Some("meta.next".into());
+        paginate.cursor_param = Some("cursor".into());
+        assert_eq!(
+            paginate.next(&response, 1, 2),
+            Some(Next::Param("cursor".into(), "abc".into()))
+        );
+
+        let paginate = Paginate {
+            page_param: Some("page".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            paginate.next(&response, 1, 2),
+            Some(Next::Param("page".into(), "2".into()))
+        );
+        assert_eq!(paginate.next(&response, 1, 0), None);
+    }
+}
@@ -0,0 +1,142 @@
+/// Profiles are named sets of defaults in the user's own configuration,
+/// like the contexts and proxy for one client's projects, so switching
+/// between them doesn't mean remembering different flags.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::Tls;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// ProfileError is the error type for profiles.
+#[derive(Error, Debug)]
+pub enum ProfileError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("yaml error in {0}: {1}")]
+    Yaml(String, serde_yaml::Error),
+
+    #[error("profile not found: {0}")]
+    NotFound(String),
+}
+
+/// Result is the result type for profiles.
+pub type Result<T> = std::result::Result<T, ProfileError>;
+
+/// Profile is what a project's configuration is merged onto. The
+/// project's own settings win.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    /// The contexts to use when a command isn't given any.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub contexts: Vec<String>,
+    /// The folder used to store responses when --cache isn't given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache: Option<PathBuf>,
+    /// How servers' certificates are verified.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<Tls>,
+    /// The proxy requests are sent through, like http://proxy:3128.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+}
+
+/// UserConfig is the user's own configuration, shared by every project.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct UserConfig {
+    /// The profile used when --profile isn't given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_profile: Option<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub profiles: HashMap<String, Profile>,
+}
+
+impl UserConfig {
+    /// Where the user's configuration is kept:
+    /// `$XDG_CONFIG_HOME/apictl/config.yaml`, which defaults to
+    /// `~/.config/apictl/config.yaml`.
+    pub fn default_path() -> Option<PathBuf> {
+        Some(config_dir()?.join("apictl").join("config.yaml"))
+    }
+
+    /// Load the user's configuration. It's empty when the file doesn't
+    /// exist.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e.into()),
+        };
+        serde_yaml::from_str::<Option<Self>>(&contents)
+            .map(Option::unwrap_or_default)
+            .map_err(|e| ProfileError::Yaml(path.display().to_string(), e))
+    }
+
+    /// The profile with the name or, without one, the default profile.
+    /// It's an error when a profile is asked for but doesn't exist.
+    pub fn profile(&self, name: Option<&str>) -> Result<Option<&Profile>> {
+        match name.or(self.default_profile.as_deref()) {
+            Some(name) => self
+                .profiles
+                .get(name)
+                .map(Some)
+                .ok_or_else(|| ProfileError::NotFound(name.to_string())),
+            None => Ok(None),
+        }
+    }
+}
+
+/// The folder users keep tools' configuration in: `$XDG_CONFIG_HOME` or
+/// `~/.config`.
+pub(crate) fn config_dir() -> Option<PathBuf> {
+    match std::env::var_os("XDG_CONFIG_HOME").filter(|d| !d.is_empty()) {
+        Some(dir) => Some(PathBuf::from(dir)),
+        None => Some(PathBuf::from(std::env::var_os("HOME")?).join(".config")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profile() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("user.yaml");
+        assert_eq!(UserConfig::load(&path).unwrap(), UserConfig::default());
+
+        std::fs::write(
+            &path,
+            r#"default_profile: acme
+profiles:
+  acme:
+    contexts: [acme-dev]
+    cache: /tmp/acme
+    proxy: http://proxy.acme.corp:3128
+    tls:
+      ca_cert: /etc/acme/ca.pem
+  personal:
+    contexts: [local]
+"#,
+        )
+        .unwrap();
+        let user = UserConfig::load(&path).unwrap();
+
+        let acme = user.profile(None).unwrap().unwrap();
+        assert_eq!(acme.contexts, vec!["acme-dev"]);
+        assert_eq!(acme.proxy.as_deref(), Some("http://proxy.acme.corp:3128"));
+        assert_eq!(
+            acme.tls.as_ref().and_then(|t| t.ca_cert.as_deref()),
+            Some(Path::new("/etc/acme/ca.pem"))
+        );
+        let personal = user.profile(Some("personal")).unwrap().unwrap();
+        assert_eq!(personal.cache, None);
+        assert!(matches!(
+            user.profile(Some("missing")),
+            Err(ProfileError::NotFound(_))
+        ));
+        assert_eq!(UserConfig::default().profile(None).unwrap(), None);
+    }
+}
@@ -0,0 +1,227 @@
+/// Record is used to capture traffic passing through a local proxy and
+/// turn it into requests and cached responses.
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crate::request::{Body, RawBody};
+use crate::server::{text, Received};
+use crate::{Config, Request, Response, ResponseError};
+
+use http_body_util::Full;
+use hyper::body::Bytes;
+use thiserror::Error;
+
+/// Headers that only apply to a single connection and shouldn't be
+/// forwarded or recorded.
+const HOP_BY_HOP: &[&str] = &[
+    "connection",
+    "content-length",
+    "host",
+    "keep-alive",
+    "proxy-authorization",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// RecordError is the error type for recording.
+#[derive(Error, Debug)]
+pub enum RecordError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("yaml error: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+
+    #[error("http error: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("response error: {0}")]
+    Response(#[from] ResponseError),
+
+    #[error("invalid url: {0}")]
+    Url(String),
+}
+
+/// Result is the result type for recording.
+pub type Result<T> = std::result::Result<T, RecordError>;
+
+/// Recorder forwards the requests it receives and records them. When a
+/// target is given, it acts as a reverse proxy for that base URL.
+/// Otherwise clients should use it as their HTTP proxy.
+pub struct Recorder {
+    output: PathBuf,
+    response_dir: PathBuf,
+    target: Option<reqwest::Url>,
+    client: reqwest::Client,
+    requests: Mutex<Vec<(String, Request)>>,
+}
+
+impl Recorder {
+    pub fn new(output: PathBuf, response_dir: PathBuf, target: Option<reqwest::Url>) -> Self {
+        Self {
+            output,
+            response_dir,
+            target,
+            client: reqwest::Client::new(),
+            requests: Mutex::new(vec![]),
+        }
+    }
+
+    /// Record requests on the given address until the process exits.
+    pub async fn serve(self, addr: SocketAddr) -> Result<()> {
+        let recorder = Arc::new(self);
+        crate::server::serve(addr, move |req| {
+            let recorder = recorder.clone();
+            async move {
+                match recorder.handle(req).await {
+                    Ok(r) => r,
+                    Err(e) => {
+                        tracing::error!(error = %e, "recording failed");
+                        text(502, format!("apictl record: {}", e))
+                    }
+                }
+            }
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn handle(&self, req: Received) -> Result<hyper::Response<Full<Bytes>>> {
+        if req.method == "CONNECT" {
+            return Ok(text(
+                501,
+                "https can't be recorded through the proxy, use --target instead".into(),
+            ));
+        }
+
+        let mut url = match &self.target {
+            Some(target) => {
+                let path = req.uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+                target
+                    .join(path.trim_start_matches('/'))
+                    .map_err(|e| RecordError::Url(e.to_string()))?
+            }
+            None => reqwest::Url::parse(&req.uri.to_string())
+                .map_err(|_| RecordError::Url(format!("not a proxy request: {}", req.uri)))?,
+        };
+
+        // Compressed bodies wouldn't be readable once recorded.
+        let headers = req
+            .headers
+            .iter()
+            .filter(|(k, _)| !HOP_BY_HOP.contains(&k.as_str()) && *k != "accept-encoding")
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect::<HashMap<_, _>>();
+
+        let method = reqwest::Method::from_bytes(req.method.as_bytes())
+            .map_err(|e| RecordError::Url(e.to_string()))?;
+        let mut builder = self.client.request(method, url.clone());
+        for (k, v) in &headers {
+            builder = builder.header(k, v);
+        }
+        let resp = builder.body(req.body.clone()).send().await?;
+
+        let mut forwarded = hyper::Response::builder().status(resp.status().as_u16());
+        for (k, v) in resp.headers() {
+            if !HOP_BY_HOP.contains(&k.as_str()) {
+                forwarded = forwarded.header(k, v);
+            }
+        }
+        let response = Response::from(resp).await?;
+
+        let query_parameters = url
+            .query_pairs()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        url.set_query(None);
+        let body = match req.body.is_empty() {
+            true => Body::None,
+            false => Body::Raw {
+                from: RawBody::Text {
+                    data: String::from_utf8_lossy(&req.body).to_string(),
+                },
+            },
+        };
+        let request = Request {
+            description: format!("recorded {} {}", req.method, url.path()),
+            tags: vec!["recorded".into()],
+            url: url.to_string(),
+            method: req.method.clone(),
+            headers,
+            query_parameters,
+            body,
+            ..Default::default()
+        };
+        let name = self.record(request)?;
+        response.save(&self.response_dir, &name)?;
+
+        Ok(forwarded
+            .body(Full::new(Bytes::from(response.body)))
+            .unwrap_or_else(|e| text(502, e.to_string())))
+    }
+
+    /// Add the request to the recording and write the recording out.
+    fn record(&self, request: Request) -> Result<String> {
+        let mut requests = self.requests.lock().unwrap();
+        let base = request_name(&request.method, &request.url);
+        let mut name = base.clone();
+        let mut i = 2;
+        while requests.iter().any(|(n, _)| *n == name) {
+            name = format!("{}-{}", base, i);
+            i += 1;
+        }
+        requests.push((name.clone(), request));
+
+        let cfg = Config {
+            requests: requests.iter().cloned().collect(),
+            ..Default::default()
+        };
+        std::fs::write(&self.output, serde_yaml::to_string(&cfg)?)?;
+        Ok(name)
+    }
+}
+
+/// Create a request name from the method and the URL's path.
+fn request_name(method: &str, url: &str) -> String {
+    let path = reqwest::Url::parse(url)
+        .map(|u| u.path().to_string())
+        .unwrap_or_default();
+    let path = path
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() {
+            true => c.to_ascii_lowercase(),
+            false => '-',
+        })
+        .collect::<String>()
+        .split('-')
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+    match path.is_empty() {
+        true => format!("{}-root", method.to_lowercase()),
+        false => format!("{}-{}", method.to_lowercase(), path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        assert_eq!(
+            request_name("GET", "http://localhost/users/1/posts?x=1"),
+            "get-users-1-posts"
+        );
+        assert_eq!(request_name("POST", "http://localhost/"), "post-root");
+        assert_eq!(
+            request_name("DELETE", "http://localhost/a_b//c.json"),
+            "delete-a-b-c-json"
+        );
+    }
+}
@@ -0,0 +1,297 @@
+/// Redact is used to mask secrets before they are printed or saved.
+use std::collections::HashMap;
+
+use crate::{Config, Request, Response, Scope, Template};
+
+use regex::{Regex, RegexBuilder};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// The replacement for redacted values.
+pub const MASK: &str = "********";
+
+/// Context values shorter than this aren't masked, since they would
+/// match too much of the output.
+const MIN_SECRET_LEN: usize = 4;
+
+/// Redaction configures what is masked. Keys are case-insensitive
+/// regexes matched against context variable names, header names and,
+/// when bodies is set, JSON body keys.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
+pub struct Redaction {
+    #[serde(default = "default_keys")]
+    pub keys: Vec<String>,
+    #[serde(default)]
+    pub bodies: bool,
+}
+
+impl Default for Redaction {
+    fn default() -> Self {
+        Self {
+            keys: default_keys(),
+            bodies: false,
+        }
+    }
+}
+
+fn default_keys() -> Vec<String> {
+    vec![
+        "token".into(),
+        "password".into(),
+        "secret".into(),
+        "authorization".into(),
+        "api[-_]?key".into(),
+        "cookie".into(),
+    ]
+}
+
+/// Redactor masks the values of secret keys and any place the values
+/// of secret context variables appear.
+#[derive(Clone, Debug, Default)]
+pub struct Redactor {
+    keys: Vec<Regex>,
+    secrets: Vec<String>,
+    bodies: bool,
+}
+
+impl Redactor {
+    /// Create a redactor for the context. Invalid key patterns are an
+    /// error.
+    pub fn new(
+        redaction: &Redaction,
+        context: &HashMap<String, String>,
+    ) -> Result<Self, regex::Error> {
+        let keys = redaction
+            .keys
+            .iter()
+            .map(|k| RegexBuilder::new(k).case_insensitive(true).build())
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut redactor = Self {
+            keys,
+            secrets: vec![],
+            bodies: redaction.bodies,
+        };
+        redactor.add_secrets(context);
+        Ok(redactor)
+    }
+
+    /// Also mask the secret variables of the scopes.
+    pub fn with_scopes(mut self, scopes: &[Scope]) -> Self {
+        for scope in scopes {
+            self.add_secrets(&scope.variables);
+        }
+        self
+    }
+
+    /// Also mask the secrets the requests authenticate with, like API
+    /// keys, and the context variables they're rendered from whatever
+    /// they're named.
+    pub fn with_requests(
+        mut self,
+        requests: &HashMap<String, Request>,
+        context: &HashMap<String, String>,
+    ) -> Self {
+        let secrets = requests
+            .values()
+            .filter_map(|r| r.auth.as_ref())
+            .flat_map(|auth| auth.secrets())
+            .flat_map(|secret| {
+                let template = Template::parse(secret);
+                match template.variables().as_slice() {
+                    [] => vec![secret.clone()],
+                    variables => variables
+                        .iter()
+                        .filter_map(|v| context.get(*v).cloned())
+                        .collect(),
+                }
+            })
+            .collect();
+        self.extend_secrets(secrets);
+        self
+    }
+
+    fn add_secrets(&mut self, variables: &HashMap<String, String>) {
+        let secrets = variables
+            .iter()
+            .filter(|(k, _)| self.is_secret(k))
+            .map(|(_, v)| v.clone())
+            .collect();
+        self.extend_secrets(secrets);
+    }
+
+    fn extend_secrets(&mut self, secrets: Vec<String>) {
+        self.secrets
+            .extend(secrets.into_iter().filter(|s| s.len() >= MIN_SECRET_LEN));
+        // Longer secrets first so a secret containing another is masked
+        // entirely.
+        self.secrets.sort_by_key(|s| std::cmp::Reverse(s.len()));
+    }
+
+    /// Whether the key names a secret.
+    pub fn is_secret(&self, key: &str) -> bool {
+        self.keys.iter().any(|k| k.is_match(key))
+    }
+
+    /// Mask the secret context values in the text.
+    pub fn text(&self, s: &str) -> String {
+        self.secrets
+            .iter()
+            .fold(s.to_string(), |s, secret| s.replace(secret, MASK))
+    }
+
+    /// A copy of the response with secret headers masked. Bodies are
+    /// only masked when enabled because later requests may need the
+    /// values in them.
+    pub fn response(&self, response: &Response) -> Response {
+        let mut response = response.clone();
+        for (key, value) in response.headers.iter_mut() {
+            *value = match self.is_secret(key) {
+                true => MASK.into(),
+                false => self.text(value),
+            };
+        }
+        if self.bodies {
+            let body = match response.json() {
+                Some(body) => {
+                    let mut body = body.clone();
+                    self.json(&mut body);
+                    self.text(&body.to_string())
+                }
+                None => self.text(&response.body),
+            };
+            response.set_body(body);
+            response.messages = response.messages.iter().map(|m| self.text(m)).collect();
+        }
+        response
+    }
+
+    /// Mask the secrets in the config's contexts, headers and auth so it
+    /// can be shared. Secrets that are templates are kept since they only
+    /// name the variables.
+    pub fn config(&self, cfg: &mut Config) {
+        for context in cfg.contexts.values_mut() {
+            self.map(&mut context.variables);
+            self.map(&mut context.default_headers);
+            for scope in context.scopes.iter_mut() {
+                self.map(&mut scope.variables);
+            }
+        }
+        self.map(&mut cfg.default_headers);
+        for request in cfg.requests.values_mut() {
+            self.map(&mut request.headers);
+            let secrets = request.auth.iter_mut().flat_map(|a| a.secrets_mut());
+            for secret in secrets.filter(|s| !s.contains("${")) {
+                *secret = MASK.into();
+            }
+        }
+    }
+
+    /// Mask the values of secret keys and the secrets in other values.
+    pub fn map(&self, map: &mut HashMap<String, String>) {
+        for (key, value) in map.iter_mut() {
+            *value = match self.is_secret(key) && !value.contains("${") {
+                true => MASK.into(),
+                false => self.text(value),
+            };
+        }
+    }
+
+    fn json(&self, value: &mut Value) {
+        match value {
+            Value::Object(object) => {
+                for (key, value) in object.iter_mut() {
+                    match self.is_secret(key) && !value.is_object() && !value.is_array() {
+                        true => *value = Value::String(MASK.into()),
+                        false => self.json(value),
+                    }
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(|v| self.json(v)),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redact() {
+        let context = vec![
+            ("api_token".to_string(), "abc123xyz".to_string()),
+            ("password".to_string(), "pw".to_string()),
+            ("user".to_string(), "bob-the-user".to_string()),
+        ]
+        .into_iter()
+        .collect();
+        let mut redaction = Redaction::default();
+        let redactor = Redactor::new(&redaction, &context).unwrap();
+        assert_eq!(
+            redactor.text("Bearer abc123xyz for bob-the-user pw"),
+            format!("Bearer {} for bob-the-user pw", MASK)
+        );
+
+        let response = Response {
+            headers: vec![
+                ("set-cookie".to_string(), "session=1".to_string()),
+                ("x-echo".to_string(), "abc123xyz".to_string()),
+            ]
+            .into_iter()
+            .collect(),
+            body: r#"{"user":{"access_token":"t0k3n","name":"bob"}}"#.into(),
+            ..Default::default()
+        };
+        let redacted = redactor.response(&response);
+        assert_eq!(redacted.headers["set-cookie"], MASK);
+        assert_eq!(redacted.headers["x-echo"], MASK);
+        assert_eq!(redacted.body, response.body);
+
+        redaction.bodies = true;
+        let redactor = Redactor::new(&redaction, &context).unwrap();
+        assert_eq!(
+            redactor.response(&response).body,
+            format!(r#"{{"user":{{"access_token":"{}","name":"bob"}}}}"#, MASK)
+        );
+
+        // Auth secrets are masked whatever the variables are named.
+        let mut cfg: Config = serde_yaml::from_str(
+            r#"requests:
+  items:
+    description: items
+    tags: []
+    url: http://localhost/items
+    auth:
+      type: api_key
+      name: key
+      value: ${user}
+  login:
+    description: login
+    tags: []
+    url: http://localhost/login
+    auth:
+      type: digest
+      username: bob
+      password: hunter22
+"#,
+        )
+        .unwrap();
+        let redactor = Redactor::new(&redaction, &context)
+            .unwrap()
+            .with_requests(&cfg.requests, &context);
+        assert_eq!(
+            redactor.text("?key=bob-the-user&pw=hunter22"),
+            format!("?key={}&pw={}", MASK, MASK)
+        );
+        redactor.config(&mut cfg);
+        assert_eq!(
+            cfg.requests["items"].auth.as_ref().unwrap().secrets(),
+            ["${user}"]
+        );
+        assert_eq!(
+            cfg.requests["login"].auth.as_ref().unwrap().secrets(),
+            [MASK]
+        );
+    }
+}
@@ -1,7 +1,18 @@
 use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 
-use crate::{Applicator, List, Response, ResponseError};
+use crate::paginate::Next;
+use crate::response::DEFAULT_MAX_BODY_SIZE;
+use crate::{
+    Applicator, Auth, AuthError, Expect, Grpc, GrpcError, Limiter, List, Paginate, Response,
+    ResponseError, Tls, WebSocket, WebSocketError,
+};
 
+use regex::Regex;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
@@ -28,6 +39,19 @@ impl List for HashMap<String, Request> {
             })
             .collect()
     }
+
+    fn group_fields(&self) -> Vec<String> {
+        vec!["tag".into(), "method".into(), "file".into()]
+    }
+
+    fn groups(&self, name: &str, field: &str) -> Vec<String> {
+        match (self.get(name), field) {
+            (Some(r), "tag") => r.tags.clone(),
+            (Some(r), "method") => vec![r.method.to_uppercase()],
+            (Some(r), "file") => r.source.iter().map(|s| s.display().to_string()).collect(),
+            _ => vec![],
+        }
+    }
 }
 
 /// RequestError is the error type for requests.
@@ -44,13 +68,46 @@ pub enum RequestError {
 
     #[error("unsupported method: {0}")]
     UnsupportedMethod(String),
+
+    #[error("relative url without a base_url in the context: {0}")]
+    RelativeUrl(String),
+
+    #[error("invalid resolve entry, want host:port:addr: {0}")]
+    Resolve(String),
+
+    #[error("invalid url: {0}")]
+    Url(String),
+
+    #[error("unix sockets aren't supported on this platform: {0}")]
+    UnixSocket(String),
+
+    #[error("websocket error: {0}")]
+    WebSocket(#[from] WebSocketError),
+
+    #[error("invalid header: {0}")]
+    Header(String),
+
+    #[error("grpc error: {0}")]
+    Grpc(#[from] GrpcError),
+
+    #[error("unresolved variables: {}", .0.join(", "))]
+    Unresolved(Vec<String>),
+
+    #[error("invalid proxy: {0}")]
+    Proxy(String),
+
+    #[error("tls error: {0}")]
+    Tls(String),
+
+    #[error("auth error: {0}")]
+    Auth(#[from] AuthError),
 }
 
 /// Result is the result type for requests.
 type Result<T> = std::result::Result<T, RequestError>;
 
 /// Requests from the configuration.
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 pub struct Request {
     pub description: String,
     pub tags: Vec<String>,
@@ -61,27 +118,349 @@ pub struct Request {
     pub headers: HashMap<String, String>,
     #[serde(default)]
     pub query_parameters: HashMap<String, String>,
+    /// A query string sent exactly as written, like `a=b&c=d%20e`. It
+    /// comes before any query_parameters.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_query: Option<String>,
+    /// How query_parameters and form bodies are encoded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url_encoding: Option<UrlEncoding>,
     #[serde(default)]
     pub body: Body,
+    /// When set, the request is made as a websocket session instead of
+    /// an HTTP request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub websocket: Option<WebSocket>,
+    /// When set, the request is a gRPC call using the Connect or
+    /// gRPC-Web protocol. The URL is the server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grpc: Option<Grpc>,
+    /// The response the request should get, checked whenever it's run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expect: Option<Expect>,
+    /// How to retry when the request is rate limited.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<RateLimit>,
+    /// How to follow the pages of a paginated endpoint.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub paginate: Option<Paginate>,
+    /// Addresses to use for hosts instead of looking them up, as
+    /// `host:port:addr` like curl's `--resolve`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub resolve: Vec<String>,
+    /// Connect to this unix socket instead of the host in the URL.
+    /// Relative URLs are requested from `http://localhost`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unix_socket: Option<String>,
+    /// How compressed responses are requested and decoded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<Compression>,
+    /// The largest body kept in memory in bytes. Larger bodies are
+    /// written to a file and only the start is kept.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_body_size: Option<u64>,
+    /// How the request is authenticated when it's sent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<Auth>,
+    /// Values in the JSON body to replace before the response is saved,
+    /// as a path like `$.items[*].updated_at` and its replacement. This
+    /// keeps ids, timestamps and tokens out of the cached responses.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub sanitize: HashMap<String, String>,
+    /// Default values for the variables used in the request. Contexts
+    /// override them.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub variables: HashMap<String, String>,
+    /// The file the request was defined in, if it was loaded from one.
+    #[serde(skip)]
+    pub source: Option<PathBuf>,
+    /// The request this one extends, if it was loaded with `extends`.
+    #[serde(skip)]
+    pub extends: Option<String>,
+    /// The client to send the request with instead of building one for
+    /// it, so requests can share connections.
+    #[serde(skip)]
+    pub client: Option<reqwest::Client>,
+    /// The rate limits shared by every request.
+    #[serde(skip)]
+    pub limiter: Option<Arc<Limiter>>,
+    /// The proxy to send the request through, from the configuration.
+    #[serde(skip)]
+    pub proxy: Option<String>,
+    /// How the server's certificate is verified, from the configuration.
+    #[serde(skip)]
+    pub tls: Option<Tls>,
 }
 
 fn default_method() -> String {
     "GET".to_string()
 }
 
+/// Relative URLs are resolved against the context's base_url. URLs
+/// that start with a variable are assumed to include their own base.
+pub fn is_relative_url(url: &str) -> bool {
+    !url.contains("://") && !url.trim_start().starts_with("${")
+}
+
+impl Default for Request {
+    fn default() -> Self {
+        Self {
+            description: String::new(),
+            tags: Vec::new(),
+            url: String::new(),
+            method: default_method(),
+            headers: HashMap::new(),
+            query_parameters: HashMap::new(),
+            raw_query: None,
+            url_encoding: None,
+            body: Body::None,
+            websocket: None,
+            grpc: None,
+            expect: None,
+            rate_limit: None,
+            paginate: None,
+            resolve: Vec::new(),
+            unix_socket: None,
+            compression: None,
+            max_body_size: None,
+            auth: None,
+            sanitize: HashMap::new(),
+            variables: HashMap::new(),
+            source: None,
+            extends: None,
+            client: None,
+            limiter: None,
+            proxy: None,
+            tls: None,
+        }
+    }
+}
+
 impl Request {
+    /// Find the parts of the request definition that match the regex.
+    /// Each match is described as `field: value`. Templates are matched
+    /// as written, before any context is applied.
+    pub fn search(&self, re: &Regex) -> Vec<String> {
+        let mut fields = vec![
+            ("url".to_string(), self.url.clone()),
+            ("method".to_string(), self.method.clone()),
+            ("description".to_string(), self.description.clone()),
+        ];
+        fields.extend(self.tags.iter().map(|t| ("tag".to_string(), t.clone())));
+        let mut headers = self.headers.iter().collect::<Vec<_>>();
+        headers.sort();
+        fields.extend(
+            headers
+                .into_iter()
+                .map(|(k, v)| ("header".to_string(), format!("{}: {}", k, v))),
+        );
+        let mut query = self.query_parameters.iter().collect::<Vec<_>>();
+        query.sort();
+        fields.extend(
+            query
+                .into_iter()
+                .map(|(k, v)| ("query".to_string(), format!("{}={}", k, v))),
+        );
+        if let Ok(body) = serde_yaml::to_string(&self.body) {
+            fields.extend(
+                body.lines()
+                    .map(|l| ("body".to_string(), l.trim().to_string())),
+            );
+        }
+
+        fields
+            .into_iter()
+            .filter(|(_, v)| re.is_match(v))
+            .map(|(f, v)| format!("{}: {}", f, v))
+            .collect()
+    }
+
+    /// The strings in the request that are templates and where they
+    /// are, like `url` or `header Authorization`. Template bodies are
+    /// read from their file.
+    pub fn templates(&self) -> Vec<(String, String)> {
+        let mut templates = vec![
+            ("url".to_string(), self.url.clone()),
+            ("method".to_string(), self.method.clone()),
+        ];
+        let mut add = |location: String, value: &str| {
+            if value.contains("${") {
+                templates.push((location, value.to_string()));
+            }
+        };
+        if let Some(unix_socket) = &self.unix_socket {
+            add("unix_socket".into(), unix_socket);
+        }
+        for entry in &self.resolve {
+            add("resolve".into(), entry);
+        }
+        for (key, value) in &self.headers {
+            add(format!("header {}", key), value);
+        }
+        for (key, value) in &self.query_parameters {
+            add(format!("query {}", key), value);
+        }
+        if let Some(raw_query) = &self.raw_query {
+            add("raw_query".into(), raw_query);
+        }
+        match &self.body {
+            Body::None => {}
+            Body::Form { data } => {
+                for (key, value) in data {
+                    add(format!("body {}", key), value);
+                }
+            }
+            Body::Raw { from } => match from {
+                RawBody::File { path } => add("body path".into(), path),
+                RawBody::Text { data } => add("body".into(), data),
+                RawBody::Template { path } => {
+                    add("body path".into(), path);
+                    if let Ok(contents) = std::fs::read_to_string(path) {
+                        add(format!("body {}", path), &contents);
+                    }
+                }
+            },
+            Body::MultiPart { data } => {
+                for (key, value) in data {
+                    for part in value.parts() {
+                        let (value, headers) = match part {
+                            MultiPartField::Text { data, headers, .. } => (data, headers),
+                            MultiPartField::File { path, headers, .. } => (path, headers),
+                        };
+                        add(format!("body {}", key), value);
+                        if let MultiPartField::File {
+                            filename: Some(filename),
+                            ..
+                        } = part
+                        {
+                            add(format!("body {} filename", key), filename);
+                        }
+                        for (header, value) in headers {
+                            add(format!("body {} header {}", key, header), value);
+                        }
+                    }
+                }
+            }
+            Body::GraphQL {
+                query, variables, ..
+            } => {
+                add("body query".into(), query);
+                if let Some(variables) = variables {
+                    add("body variables".into(), &variables.to_string());
+                }
+            }
+            Body::Soap {
+                action,
+                envelope,
+                header,
+            } => {
+                add("body action".into(), action);
+                add("body envelope".into(), envelope);
+                if let Some(header) = header {
+                    add("body header".into(), header);
+                }
+            }
+        }
+        if let Some(websocket) = &self.websocket {
+            for message in &websocket.messages {
+                add("websocket message".into(), message);
+            }
+            if let Some(until) = &websocket.until {
+                add("websocket until".into(), until);
+            }
+        }
+        if let Some(grpc) = &self.grpc {
+            add("grpc service".into(), &grpc.service);
+            add("grpc method".into(), &grpc.method);
+            add("grpc message".into(), &grpc.message);
+        }
+        if let Some(auth) = &self.auth {
+            for (location, value) in auth.templates() {
+                add(location, &value);
+            }
+        }
+        templates
+    }
+
+    /// The variables that can't be resolved with the applicator and the
+    /// request's defaults, described as `NAME in LOCATION`.
+    pub fn unresolved(&self, app: &Applicator) -> Vec<String> {
+        let own = self.applicator(app);
+        let app = own.as_ref().unwrap_or(app);
+        let mut unresolved = self
+            .templates()
+            .into_iter()
+            .flat_map(|(location, value)| {
+                app.unresolved(&value)
+                    .into_iter()
+                    .map(move |name| format!("{} in {}", name, location))
+            })
+            .collect::<Vec<_>>();
+        unresolved.sort();
+        unresolved
+    }
+
+    /// Apply the request like apply, but when the applicator is strict
+    /// it's an error if any variables can't be resolved.
+    pub fn try_apply(&mut self, app: &Applicator) -> Result<()> {
+        if app.is_strict() {
+            let unresolved = self.unresolved(app);
+            if !unresolved.is_empty() {
+                return Err(RequestError::Unresolved(unresolved));
+            }
+        }
+        self.apply(app);
+        Ok(())
+    }
+
+    /// The applicator for the request, if it needs its own: the scopes
+    /// that match the request override the context and the request's
+    /// defaults fill in the variables the context doesn't have.
+    pub fn applicator(&self, app: &Applicator) -> Option<Applicator> {
+        let scoped = app.scoped(&self.tags, &self.url);
+        let current = scoped.as_ref().unwrap_or(app);
+        match self.variables.keys().any(|k| current.get(k).is_none()) {
+            true => Some(current.with_defaults(&self.variables)),
+            false => scoped,
+        }
+    }
+
     /// Apply the configuration and context to the request. All parts
     /// of the request are replaced with the response values and
     /// contexts.
     pub fn apply(&mut self, app: &Applicator) {
+        let own = self.applicator(app);
+        let app = own.as_ref().unwrap_or(app);
         self.url = app.apply(&self.url);
+        if let Some(unix_socket) = &mut self.unix_socket {
+            *unix_socket = app.apply(unix_socket);
+        }
+        if is_relative_url(&self.url) {
+            let base_url = match &self.unix_socket {
+                Some(_) => Some("http://localhost".to_string()),
+                None => app.get("base_url").cloned(),
+            };
+            if let Some(base_url) = base_url {
+                self.url = format!(
+                    "{}/{}",
+                    base_url.trim_end_matches('/'),
+                    self.url.trim_start_matches('/')
+                );
+            }
+        }
         self.method = app.apply(&self.method);
+        for entry in self.resolve.iter_mut() {
+            *entry = app.apply(entry);
+        }
         for value in self.headers.values_mut() {
             *value = app.apply(value);
         }
         for value in self.query_parameters.values_mut() {
             *value = app.apply(value);
         }
+        if let Some(raw_query) = &mut self.raw_query {
+            *raw_query = app.apply(raw_query);
+        }
         match &mut self.body {
             Body::None => {}
             Body::Form { data } => {
@@ -96,47 +475,333 @@ impl Request {
                 RawBody::Text { data } => {
                     *data = app.apply(data);
                 }
+                RawBody::Template { path } => {
+                    *path = app.apply(path);
+                    // If the file can't be read, sending the request
+                    // reports the error.
+                    if let Ok(contents) = std::fs::read_to_string(&*path) {
+                        *from = RawBody::Text {
+                            data: app.apply(&contents),
+                        };
+                    }
+                }
             },
             Body::MultiPart { data } => {
-                for value in data.values_mut() {
-                    match value {
-                        MultiPartField::Text { data } => {
+                for part in data.values_mut().flat_map(|v| v.parts_mut()) {
+                    let headers = match part {
+                        MultiPartField::Text { data, headers, .. } => {
                             *data = app.apply(data);
+                            headers
                         }
-                        MultiPartField::File { path } => {
+                        MultiPartField::File {
+                            path,
+                            filename,
+                            headers,
+                            ..
+                        } => {
                             *path = app.apply(path);
+                            if let Some(filename) = filename {
+                                *filename = app.apply(filename);
+                            }
+                            headers
                         }
+                    };
+                    for value in headers.values_mut() {
+                        *value = app.apply(value);
                     }
                 }
             }
+            Body::GraphQL {
+                query, variables, ..
+            } => {
+                *query = app.apply(query);
+                if let Some(variables) = variables {
+                    app.apply_value(variables);
+                }
+            }
+            Body::Soap {
+                action,
+                envelope,
+                header,
+            } => {
+                *action = app.apply(action);
+                *envelope = app.apply(envelope);
+                if let Some(header) = header {
+                    *header = app.apply(header);
+                }
+            }
+        }
+        if let Some(websocket) = &mut self.websocket {
+            websocket.apply(app);
+        }
+        if let Some(grpc) = &mut self.grpc {
+            grpc.apply(app);
+        }
+        if let Some(auth) = &mut self.auth {
+            auth.apply(app);
         }
     }
 
-    /// Perform the request and return it's response.
+    /// Perform the request and return it's response. If the request
+    /// paginates, all of the pages are requested and the response body
+    /// is an array of the items from every page.
     pub async fn request(&self) -> Result<Response> {
-        use reqwest::Client;
+        let paginate = match &self.paginate {
+            Some(p) => p,
+            None => return self.request_once().await,
+        };
+
+        let mut page = self.clone();
+        let mut number = page
+            .page_param()
+            .and_then(|p| p.parse::<usize>().ok())
+            .unwrap_or(1);
+        if let (Some(param), None) = (&paginate.page_param, page.page_param()) {
+            page.query_parameters
+                .insert(param.clone(), number.to_string());
+        }
+
+        let mut items = vec![];
+        let mut last;
+        let mut pages = 0;
+        loop {
+            last = page.request_once().await?;
+            pages += 1;
+            let found = paginate.items(&last);
+            let count = found.len();
+            tracing::debug!(page = pages, items = count, "fetched page");
+            items.extend(found);
+            if pages >= paginate.max_pages {
+                break;
+            }
+            match paginate.next(&last, number, count) {
+                Some(Next::Url(url)) => {
+                    page.url = reqwest::Url::parse(&page.url)
+                        .and_then(|u| u.join(&url))
+                        .map(|u| u.to_string())
+                        .unwrap_or(url);
+                    page.query_parameters.clear();
+                    page.raw_query = None;
+                }
+                Some(Next::Param(param, value)) => {
+                    page.query_parameters.insert(param, value);
+                }
+                None => break,
+            }
+            number += 1;
+        }
+        last.body = serde_json::Value::Array(items).to_string();
+        Ok(last)
+    }
+
+    /// The current value of the page parameter, if paginating by page.
+    fn page_param(&self) -> Option<&String> {
+        let param = self.paginate.as_ref()?.page_param.as_ref()?;
+        self.query_parameters.get(param)
+    }
+
+    /// Perform a single request. Requests that are rate limited (429)
+    /// are retried after the time the server asks for in Retry-After or
+    /// with an exponential backoff.
+    async fn request_once(&self) -> Result<Response> {
+        if is_relative_url(&self.url) {
+            return Err(RequestError::RelativeUrl(self.url.clone()));
+        }
+        self.wait_for_limit().await;
+        if let Some(websocket) = &self.websocket {
+            return Ok(websocket
+                .request(&self.url, &self.headers, &self.query_parameters)
+                .await?);
+        }
+        if let Some(grpc) = &self.grpc {
+            return Ok(grpc
+                .request(&self.client()?, &self.url, &self.headers)
+                .await?);
+        }
+
+        let rate_limit = self.rate_limit.clone().unwrap_or_default();
+        let mut attempt = 0;
+        loop {
+            let resp = self.send().await?;
+            tracing::debug!(method = %self.method, url = %self.url, status = resp.status().as_u16(), "received response");
+            if resp.status() != reqwest::StatusCode::TOO_MANY_REQUESTS
+                || attempt >= rate_limit.retries
+            {
+                let max_body_size = self.max_body_size.unwrap_or(DEFAULT_MAX_BODY_SIZE);
+                let decompress = self.compression.as_ref().map_or(true, |c| c.decompress);
+                return Response::from_limited(resp, max_body_size, decompress)
+                    .await
+                    .map_err(RequestError::Parse);
+            }
+            let retry_after = resp.headers().get(reqwest::header::RETRY_AFTER);
+            let wait = rate_limit.retry_after(retry_after.and_then(|v| v.to_str().ok()), attempt);
+            tracing::warn!(url = %self.url, attempt, ?wait, "rate limited, retrying");
+            tokio::time::sleep(wait).await;
+            attempt += 1;
+            self.wait_for_limit().await;
+        }
+    }
+
+    /// Wait until the rate limit of the host, if it has one, allows
+    /// another request.
+    async fn wait_for_limit(&self) {
+        if let Some(limiter) = &self.limiter {
+            limiter.acquire(&self.url).await;
+        }
+    }
+
+    /// Add the resolve entries for hosts the request doesn't already
+    /// resolve itself.
+    pub fn add_resolve(&mut self, entries: &[String]) {
+        let target = |e: &String| parse_resolve(e).map(|(host, port, _)| (host.to_string(), port));
+        for entry in entries {
+            let exists = self.resolve.iter().any(|e| target(e) == target(entry));
+            if !exists {
+                self.resolve.push(entry.clone());
+            }
+        }
+    }
+
+    /// The client to send the request with: the one it was given or,
+    /// without one, a new client just for it.
+    fn client(&self) -> Result<reqwest::Client> {
+        match &self.client {
+            Some(client) => Ok(client.clone()),
+            None => self.client_builder()?.build().map_err(RequestError::Http),
+        }
+    }
+
+    /// Configure a client for the request. It connects to the unix
+    /// socket if there is one and otherwise uses the resolve entries that
+    /// match the host and port of the URL.
+    pub fn client_builder(&self) -> Result<reqwest::ClientBuilder> {
+        let mut builder = reqwest::Client::builder().tls_info(true);
+        if let Some(tls) = &self.tls {
+            if let Some(path) = &tls.ca_cert {
+                let pem = std::fs::read(path).map_err(|e| {
+                    RequestError::Tls(format!("can't read {}: {}", path.display(), e))
+                })?;
+                let certificates = reqwest::Certificate::from_pem_bundle(&pem)
+                    .map_err(|e| RequestError::Tls(format!("{}: {}", path.display(), e)))?;
+                for certificate in certificates {
+                    builder = builder.add_root_certificate(certificate);
+                }
+            }
+            builder = builder.danger_accept_invalid_certs(tls.insecure);
+        }
+        if let Some(proxy) = &self.proxy {
+            let proxy =
+                reqwest::Proxy::all(proxy).map_err(|e| RequestError::Proxy(e.to_string()))?;
+            builder = builder.proxy(proxy);
+        }
+        if let Some(unix_socket) = &self.unix_socket {
+            #[cfg(unix)]
+            return Ok(builder.unix_socket(std::path::Path::new(unix_socket)));
+            #[cfg(not(unix))]
+            return Err(RequestError::UnixSocket(unix_socket.clone()));
+        }
+        if !self.resolve.is_empty() {
+            let url =
+                reqwest::Url::parse(&self.url).map_err(|e| RequestError::Url(e.to_string()))?;
+            let host = url.host_str().unwrap_or_default();
+            let port = url.port_or_known_default().unwrap_or_default();
+            for entry in &self.resolve {
+                let (h, p, addr) =
+                    parse_resolve(entry).ok_or_else(|| RequestError::Resolve(entry.clone()))?;
+                if h == host && p == port {
+                    builder = builder.resolve(h, SocketAddr::new(addr, p));
+                }
+            }
+        }
+        Ok(builder)
+    }
 
+    /// Build the HTTP request and send it once. Challenge-response auth
+    /// sends it again with the answer to the server's challenge, on the
+    /// same client so the connection can be reused.
+    async fn send(&self) -> Result<reqwest::Response> {
+        tracing::debug!(method = %self.method, url = %self.url, "sending request");
+        let client = self.client()?;
+        let request = self.build(&client).await?;
+        let (method, url) = (request.method().clone(), request.url().clone());
+        let resp = client.execute(request).await.map_err(|e| self.http(e))?;
+        let answer = match &self.auth {
+            Some(auth) => auth.respond(&method, &url, &resp)?,
+            None => None,
+        };
+        let Some(answer) = answer else {
+            return Ok(resp);
+        };
+        tracing::debug!(url = %self.url, "answering auth challenge");
+        // The body is read so the connection is kept open.
+        let _ = resp.bytes().await;
+        let mut request = self.build(&client).await?;
+        request
+            .headers_mut()
+            .insert(reqwest::header::AUTHORIZATION, answer);
+        client.execute(request).await.map_err(|e| self.http(e))
+    }
+
+    /// The error sending the request, without the auth's secrets in its
+    /// URL.
+    fn http(&self, mut e: reqwest::Error) -> RequestError {
+        if let (Some(auth), Some(url)) = (&self.auth, e.url_mut()) {
+            auth.mask(url);
+        }
+        RequestError::Http(e)
+    }
+
+    /// Build the HTTP request with the client, with the auth's
+    /// credentials added.
+    async fn build(&self, client: &reqwest::Client) -> Result<reqwest::Request> {
+        let encoding = self.url_encoding.unwrap_or_default();
+        let mut url = self.url.clone();
+        let mut query = self.raw_query.clone().into_iter().collect::<Vec<_>>();
+        if encoding == UrlEncoding::Percent && !self.query_parameters.is_empty() {
+            query.push(percent_encode_pairs(&self.query_parameters));
+        }
+        if !query.is_empty() {
+            url.push(if url.contains('?') { '&' } else { '?' });
+            url.push_str(&query.join("&"));
+        }
         let mut builder = match self.method.as_str() {
-            "GET" => Client::new().get(&self.url),
-            "POST" => Client::new().post(&self.url),
-            "PUT" => Client::new().put(&self.url),
-            "DELETE" => Client::new().delete(&self.url),
+            "GET" => client.get(&url),
+            "POST" => client.post(&url),
+            "PUT" => client.put(&url),
+            "DELETE" => client.delete(&url),
             _ => return Err(RequestError::UnsupportedMethod(self.method.clone())),
         };
 
+        let accept_encoding = self
+            .compression
+            .as_ref()
+            .and_then(|c| c.accept_encoding.as_ref());
+        if let Some(accept_encoding) = accept_encoding {
+            builder = builder.header(reqwest::header::ACCEPT_ENCODING, accept_encoding);
+        }
         for (key, value) in self.headers.iter() {
             builder = builder.header(key, value);
         }
 
-        builder = builder.query(&self.query_parameters);
+        if encoding == UrlEncoding::Form {
+            builder = builder.query(&self.query_parameters);
+        }
 
         match &self.body {
             Body::None => {}
-            Body::Form { data } => {
-                builder = builder.form(data);
-            }
+            Body::Form { data } => match encoding {
+                UrlEncoding::Form => builder = builder.form(data),
+                UrlEncoding::Percent => {
+                    builder = builder
+                        .header(
+                            reqwest::header::CONTENT_TYPE,
+                            "application/x-www-form-urlencoded",
+                        )
+                        .body(percent_encode_pairs(data));
+                }
+            },
             Body::Raw { from } => match from {
-                RawBody::File { path } => {
+                RawBody::File { path } | RawBody::Template { path } => {
                     builder =
                         builder.body(std::fs::read_to_string(path).map_err(RequestError::Io)?);
                 }
@@ -147,32 +812,169 @@ impl Request {
             Body::MultiPart { data } => {
                 let mut form = reqwest::multipart::Form::new();
                 for (key, value) in data.iter() {
-                    match value {
-                        MultiPartField::Text { data } => {
-                            form = form.text(key.clone(), data.clone());
-                        }
-                        MultiPartField::File { path } => {
-                            let mut part = reqwest::multipart::Part::stream(
-                                tokio::fs::File::open(path)
-                                    .await
-                                    .map_err(RequestError::Io)?,
-                            );
-                            part = part.file_name(path.clone());
-                            form = form.part(key.clone(), part);
-                        }
+                    for field in value.parts() {
+                        form = form.part(key.clone(), field.part().await?);
                     }
                 }
                 builder = builder.multipart(form);
             }
+            Body::GraphQL {
+                query, variables, ..
+            } => {
+                builder = builder.json(&serde_json::json!({
+                    "query": query,
+                    "variables": variables,
+                }));
+            }
+            Body::Soap {
+                action,
+                envelope,
+                header,
+            } => {
+                // Headers on the request take precedence.
+                let has = |name: &str| self.headers.keys().any(|k| k.eq_ignore_ascii_case(name));
+                if !has("content-type") {
+                    builder = builder.header("content-type", "text/xml; charset=utf-8");
+                }
+                if !has("soapaction") {
+                    builder = builder.header("SOAPAction", format!("\"{}\"", action));
+                }
+                builder = builder.body(Body::soap_envelope(envelope, header.as_deref()));
+            }
+        }
+
+        let mut request = builder.build().map_err(RequestError::Http)?;
+        if let Some(auth) = &self.auth {
+            auth.authorize(&mut request)?;
+        }
+        Ok(request)
+    }
+}
+
+/// Parse a `host:port:addr` resolve entry. IPv6 addresses can be in
+/// brackets.
+pub fn parse_resolve(entry: &str) -> Option<(&str, u16, IpAddr)> {
+    let (host, rest) = entry.split_once(':')?;
+    let (port, addr) = rest.split_once(':')?;
+    let addr = addr.trim_start_matches('[').trim_end_matches(']');
+    Some((host, port.parse().ok()?, addr.parse().ok()?))
+}
+
+/// UrlEncoding is how query parameters and form bodies are encoded.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum UrlEncoding {
+    /// HTML form encoding, where spaces are `+`.
+    #[default]
+    Form,
+    /// Everything but unreserved characters is percent-encoded, so
+    /// spaces are `%20`.
+    Percent,
+}
+
+/// Percent-encode everything but the unreserved characters of RFC 3986.
+pub(crate) fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Percent-encode the pairs as `key=value&...`, sorted by key.
+fn percent_encode_pairs(pairs: &HashMap<String, String>) -> String {
+    let mut pairs = pairs
+        .iter()
+        .map(|(k, v)| format!("{}={}", percent_encode(k), percent_encode(v)))
+        .collect::<Vec<_>>();
+    pairs.sort();
+    pairs.join("&")
+}
+
+/// Compression controls the encodings a request accepts and whether the
+/// response is decoded. Responses are decoded by default.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
+pub struct Compression {
+    /// The `Accept-Encoding` header to send, like `gzip, br`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accept_encoding: Option<String>,
+    #[serde(default = "default_decompress")]
+    pub decompress: bool,
+}
+
+fn default_decompress() -> bool {
+    true
+}
+
+/// RateLimit controls how requests that get a 429 are retried.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
+pub struct RateLimit {
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+    #[serde(default = "default_backoff", with = "humantime_serde")]
+    #[schemars(with = "String")]
+    pub backoff: Duration,
+    /// The longest the backoff grows to.
+    #[serde(default = "default_max_backoff", with = "humantime_serde")]
+    #[schemars(with = "String")]
+    pub max_backoff: Duration,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self {
+            retries: default_retries(),
+            backoff: default_backoff(),
+            max_backoff: default_max_backoff(),
         }
+    }
+}
+
+fn default_retries() -> u32 {
+    3
+}
+
+fn default_backoff() -> Duration {
+    Duration::from_secs(1)
+}
+
+fn default_max_backoff() -> Duration {
+    Duration::from_secs(60)
+}
 
-        Response::from(builder.send().await.map_err(RequestError::Http)?)
-            .await
-            .map_err(RequestError::Parse)
+impl RateLimit {
+    /// How long to wait before the retry after the attempt: the backoff
+    /// doubled each attempt, up to the max backoff.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        2u32.checked_pow(attempt)
+            .and_then(|factor| self.backoff.checked_mul(factor))
+            .map_or(self.max_backoff, |delay| delay.min(self.max_backoff))
+    }
+
+    /// How long to wait before the retry after the attempt when the
+    /// server sent the Retry-After value, in seconds or as an HTTP date.
+    /// It's capped at the max backoff, and the backoff is used when the
+    /// value can't be read.
+    pub fn retry_after(&self, value: Option<&str>, attempt: u32) -> Duration {
+        let value = value.map(str::trim);
+        let seconds = value.and_then(|v| v.parse::<u64>().ok());
+        let wait = match seconds {
+            Some(seconds) => Some(Duration::from_secs(seconds)),
+            None => value
+                .and_then(|v| httpdate::parse_http_date(v).ok())
+                .map(|at| {
+                    at.duration_since(std::time::SystemTime::now())
+                        .unwrap_or_default()
+                }),
+        };
+        wait.map_or_else(|| self.delay(attempt), |w| w.min(self.max_backoff))
     }
 }
 
-#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, JsonSchema)]
 #[serde(tag = "type", rename_all = "lowercase")]
 pub enum Body {
     #[default]
@@ -184,28 +986,221 @@ pub enum Body {
         from: RawBody,
     },
     MultiPart {
-        data: HashMap<String, MultiPartField>,
+        data: HashMap<String, MultiPartValue>,
+    },
+    /// A GraphQL query sent as JSON. The schema names the cached
+    /// introspection to validate against and defaults to the request
+    /// name.
+    GraphQL {
+        query: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        variables: Option<serde_json::Value>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        schema: Option<String>,
+    },
+    /// A SOAP 1.1 call. The envelope is the XML of the SOAP body, which
+    /// is wrapped in an envelope and sent with the SOAPAction and
+    /// content type headers.
+    Soap {
+        action: String,
+        envelope: String,
+        /// The XML of the SOAP header, if any.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        header: Option<String>,
     },
 }
 
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+impl Body {
+    /// The full SOAP envelope for a soap body's payload and header.
+    pub fn soap_envelope(envelope: &str, header: Option<&str>) -> String {
+        let header = header
+            .map(|h| format!("\n  <soap:Header>\n    {}\n  </soap:Header>", h.trim()))
+            .unwrap_or_default();
+        format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+             <soap:Envelope xmlns:soap=\"http://schemas.xmlsoap.org/soap/envelope/\">{}\n  \
+             <soap:Body>\n    {}\n  </soap:Body>\n</soap:Envelope>\n",
+            header,
+            envelope.trim()
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[serde(tag = "type", rename_all = "lowercase")]
 pub enum RawBody {
-    File { path: String },
-    Text { data: String },
+    File {
+        path: String,
+    },
+    Text {
+        data: String,
+    },
+    /// A file whose contents are a template, rendered with the context
+    /// like a text body.
+    Template {
+        path: String,
+    },
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+/// The value of a multi-part field, which is one part or a list of
+/// parts sent under the same name.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(untagged)]
+pub enum MultiPartValue {
+    One(MultiPartField),
+    Many(Vec<MultiPartField>),
+}
+
+impl MultiPartValue {
+    /// The parts sent for the field.
+    pub fn parts(&self) -> &[MultiPartField] {
+        match self {
+            MultiPartValue::One(part) => std::slice::from_ref(part),
+            MultiPartValue::Many(parts) => parts,
+        }
+    }
+
+    pub fn parts_mut(&mut self) -> &mut [MultiPartField] {
+        match self {
+            MultiPartValue::One(part) => std::slice::from_mut(part),
+            MultiPartValue::Many(parts) => parts,
+        }
+    }
+}
+
+impl From<MultiPartField> for MultiPartValue {
+    fn from(part: MultiPartField) -> Self {
+        MultiPartValue::One(part)
+    }
+}
+
+/// A part of a multi-part body. Files are sent with their path as the
+/// file name unless `filename` is set.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
 #[serde(tag = "type", rename_all = "lowercase")]
 pub enum MultiPartField {
-    File { path: String },
-    Text { data: String },
+    File {
+        path: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        filename: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        content_type: Option<String>,
+        #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+        headers: HashMap<String, String>,
+    },
+    Text {
+        data: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        content_type: Option<String>,
+        #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+        headers: HashMap<String, String>,
+    },
+}
+
+impl MultiPartField {
+    /// A file part for the path.
+    pub fn file(path: &str) -> Self {
+        MultiPartField::File {
+            path: path.to_string(),
+            filename: None,
+            content_type: None,
+            headers: HashMap::new(),
+        }
+    }
+
+    /// A text part with the data.
+    pub fn text(data: &str) -> Self {
+        MultiPartField::Text {
+            data: data.to_string(),
+            content_type: None,
+            headers: HashMap::new(),
+        }
+    }
+
+    /// Build the part to send, opening the file for file parts.
+    async fn part(&self) -> Result<reqwest::multipart::Part> {
+        let (part, content_type, headers) = match self {
+            MultiPartField::Text {
+                data,
+                content_type,
+                headers,
+            } => (
+                reqwest::multipart::Part::text(data.clone()),
+                content_type,
+                headers,
+            ),
+            MultiPartField::File {
+                path,
+                filename,
+                content_type,
+                headers,
+            } => {
+                let file = tokio::fs::File::open(path)
+                    .await
+                    .map_err(RequestError::Io)?;
+                let part = reqwest::multipart::Part::stream(file)
+                    .file_name(filename.clone().unwrap_or_else(|| path.clone()));
+                (part, content_type, headers)
+            }
+        };
+        let mut part = match content_type {
+            Some(content_type) => part.mime_str(content_type).map_err(RequestError::Http)?,
+            None => part,
+        };
+        if !headers.is_empty() {
+            let mut map = reqwest::header::HeaderMap::new();
+            for (key, value) in headers {
+                map.insert(
+                    reqwest::header::HeaderName::try_from(key.as_str())
+                        .map_err(|e| RequestError::Header(e.to_string()))?,
+                    reqwest::header::HeaderValue::try_from(value.as_str())
+                        .map_err(|e| RequestError::Header(e.to_string()))?,
+                );
+            }
+            part = part.headers(map);
+        }
+        Ok(part)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn group() {
+        let requests: HashMap<String, Request> = serde_yaml::from_str(
+            r#"
+a: {description: "", tags: [users, admin], url: /a}
+b: {description: "", tags: [users], url: /b, method: post}
+c: {description: "", tags: [], url: /c}
+"#,
+        )
+        .unwrap();
+        let names = |field| {
+            requests
+                .grouped_values("name", field)
+                .unwrap()
+                .into_iter()
+                .map(|(g, rows)| (g, rows.into_iter().map(|r| r[0].clone()).collect()))
+                .collect::<Vec<(String, Vec<String>)>>()
+        };
+        assert_eq!(
+            names("tag"),
+            vec![
+                ("(none)".into(), vec!["c".into()]),
+                ("admin".into(), vec!["a".into()]),
+                ("users".into(), vec!["a".into(), "b".into()]),
+            ]
+        );
+        assert_eq!(names("method")[1], ("POST".into(), vec!["b".into()]));
+        assert!(requests.grouped_values("name", "size").is_err());
+
+        let summary = requests.summary();
+        assert_eq!(summary.total, 3);
+        assert_eq!(summary.groups["tag"]["users"], 2);
+    }
+
     #[test]
     fn deserialize() {
         let request = r#"
@@ -242,6 +1237,68 @@ body:
         );
     }
 
+    #[test]
+    fn deserialize_multipart() {
+        let body = r#"
+type: multipart
+data:
+  title:
+    type: text
+    data: hello
+  photos:
+    - type: file
+      path: a.png
+      content_type: image/png
+    - type: file
+      path: b.jpg
+      filename: cover.jpg
+      headers:
+        X-Part: "2"
+"#;
+        let body: Body = serde_yaml::from_str(body).unwrap();
+        let Body::MultiPart { data } = body else {
+            panic!("want a multipart body");
+        };
+        assert_eq!(data["title"], MultiPartField::text("hello").into());
+        let photos = data["photos"].parts();
+        assert_eq!(photos.len(), 2);
+        assert!(matches!(
+            &photos[0],
+            MultiPartField::File { content_type: Some(c), .. } if c == "image/png"
+        ));
+        assert!(matches!(
+            &photos[1],
+            MultiPartField::File { filename: Some(f), headers, .. }
+                if f == "cover.jpg" && headers["X-Part"] == "2"
+        ));
+    }
+
+    #[test]
+    fn try_apply() {
+        let mut request: Request = serde_yaml::from_str(
+            r#"
+tags: []
+description: ""
+url: "${base_url}/users/${id}"
+headers:
+  Authorization: "Bearer ${token}"
+variables:
+  id: "7"
+"#,
+        )
+        .unwrap();
+        let context = HashMap::from([("base_url".to_string(), "http://localhost".to_string())]);
+        let app = Applicator::new(context, HashMap::new()).with_strict(true);
+        assert_eq!(
+            request.clone().try_apply(&app).unwrap_err().to_string(),
+            "unresolved variables: token in header Authorization"
+        );
+
+        request.headers.clear();
+        request.try_apply(&app).unwrap();
+        assert_eq!(request.url, "http://localhost/users/7");
+    }
+
     #[test]
     fn apply() {
         let request = r#"
@@ -289,4 +1346,261 @@ body:
             }
         );
     }
+
+    #[tokio::test]
+    async fn retry_rate_limited() {
+        use crate::{Mock, MockServer};
+
+        let dir = tempfile::tempdir().unwrap();
+        let record = dir.path().join("record.jsonl");
+        let mocks = vec![(
+            "limited".to_string(),
+            Mock {
+                method: None,
+                path: "/limited".into(),
+                status: 429,
+                headers: vec![(
+                    "retry-after".to_string(),
+                    "Wed, 21 Oct 2015 07:28:00 GMT".to_string(),
+                )]
+                .into_iter()
+                .collect(),
+                body: String::new(),
+            },
+        )]
+        .into_iter()
+        .collect();
+        let server = MockServer::new(&mocks, HashMap::new(), Some(record.clone())).unwrap();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(server.serve_listener(listener));
+
+        let request = Request {
+            url: format!("http://{}/limited", addr),
+            rate_limit: Some(RateLimit {
+                retries: 2,
+                backoff: Duration::from_millis(1),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let response = request.request().await.unwrap();
+        assert_eq!(response.status_code, 429);
+        let attempts = std::fs::read_to_string(&record).unwrap().lines().count();
+        assert_eq!(attempts, 3);
+
+        // The backoff doubles up to the max without overflowing.
+        let rate_limit = RateLimit::default();
+        assert_eq!(rate_limit.delay(2), Duration::from_secs(4));
+        assert_eq!(rate_limit.delay(6), Duration::from_secs(60));
+        assert_eq!(rate_limit.delay(u32::MAX), Duration::from_secs(60));
+
+        // Retry-After is read as seconds or a date, and capped.
+        let wait = |v: &str| rate_limit.retry_after(Some(v), 2);
+        assert_eq!(wait("5"), Duration::from_secs(5));
+        assert_eq!(wait("86400"), Duration::from_secs(60));
+        assert_eq!(wait("Wed, 21 Oct 2015 07:28:00 GMT"), Duration::ZERO);
+        let soon = std::time::SystemTime::now() + Duration::from_secs(30);
+        let soon = wait(&httpdate::fmt_http_date(soon));
+        assert!(soon > Duration::from_secs(28) && soon <= Duration::from_secs(30));
+        let later = std::time::SystemTime::now() + Duration::from_secs(3600);
+        assert_eq!(
+            wait(&httpdate::fmt_http_date(later)),
+            Duration::from_secs(60)
+        );
+        assert_eq!(wait("soon"), Duration::from_secs(4));
+        assert_eq!(rate_limit.retry_after(None, 2), Duration::from_secs(4));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn unix_socket() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let dir = tempfile::tempdir().unwrap();
+        let listener = tokio::net::UnixListener::bind(dir.path().join("app.sock")).unwrap();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut received = vec![];
+            let mut buf = [0; 1024];
+            while !received.ends_with(b"\r\n\r\n") {
+                let n = stream.read(&mut buf).await.unwrap();
+                if n == 0 {
+                    break;
+                }
+                received.extend_from_slice(&buf[..n]);
+            }
+            let response = "HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok";
+            stream.write_all(response.as_bytes()).await.unwrap();
+            String::from_utf8(received).unwrap()
+        });
+
+        // The socket and the path are both templated.
+        let mut request = Request {
+            url: "/containers/${id}/json".into(),
+            unix_socket: Some("${dir}/app.sock".into()),
+            ..Default::default()
+        };
+        let context = HashMap::from([
+            ("id".to_string(), "web".to_string()),
+            ("dir".to_string(), dir.path().display().to_string()),
+        ]);
+        request.apply(&Applicator::new(context, HashMap::new()));
+        let response = request.request().await.unwrap();
+        assert_eq!(response.status_code, 200);
+        assert_eq!(response.body, "ok");
+        let received = server.await.unwrap();
+        assert!(
+            received.starts_with("GET /containers/web/json HTTP/1.1\r\n"),
+            "{}",
+            received
+        );
+    }
+
+    #[test]
+    fn search() {
+        let request: Request = serde_yaml::from_str(
+            r#"
+description: get a user
+tags: [users]
+url: ${base_url}/v2/users/${id}
+headers:
+  X-Api-Version: v2
+body:
+  type: raw
+  from:
+    type: text
+    data: '{"path": "/v2/users"}'
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            request.search(&Regex::new("/v2/users").unwrap()),
+            vec![
+                "url: ${base_url}/v2/users/${id}",
+                r#"body: data: '{"path": "/v2/users"}'"#,
+            ]
+        );
+        assert!(request.search(&Regex::new("posts").unwrap()).is_empty());
+    }
+
+    #[test]
+    fn resolve() {
+        assert_eq!(
+            parse_resolve("api.test:443:10.0.0.1"),
+            Some(("api.test", 443, "10.0.0.1".parse().unwrap()))
+        );
+        assert_eq!(
+            parse_resolve("api.test:80:[::1]"),
+            Some(("api.test", 80, "::1".parse().unwrap()))
+        );
+        assert_eq!(parse_resolve("api.test:10.0.0.1"), None);
+
+        let mut request = Request {
+            resolve: vec!["api.test:443:10.0.0.1".into()],
+            ..Default::default()
+        };
+        request.add_resolve(&["api.test:443:10.0.0.2".into(), "api.test:80:[::1]".into()]);
+        assert_eq!(
+            request.resolve,
+            vec!["api.test:443:10.0.0.1", "api.test:80:[::1]"]
+        );
+    }
+
+    #[test]
+    fn template_body() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("body.json");
+        std::fs::write(&path, r#"{"id": "${id}", "name": "${name}"}"#).unwrap();
+        let mut request = Request {
+            url: "http://localhost/users".into(),
+            body: Body::Raw {
+                from: RawBody::Template {
+                    path: path.to_string_lossy().to_string(),
+                },
+            },
+            ..Default::default()
+        };
+        let context = vec![
+            ("id".to_string(), "7".to_string()),
+            ("name".to_string(), "bob".to_string()),
+        ]
+        .into_iter()
+        .collect();
+        request.apply(&Applicator::new(context, HashMap::new()));
+        assert_eq!(
+            request.body,
+            Body::Raw {
+                from: RawBody::Text {
+                    data: r#"{"id": "7", "name": "bob"}"#.into()
+                }
+            }
+        );
+    }
+
+    #[test]
+    fn percent_encoding() {
+        let pairs = vec![
+            ("q".to_string(), "a b+c/d".to_string()),
+            ("name".to_string(), "bob~1".to_string()),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(percent_encode_pairs(&pairs), "name=bob~1&q=a%20b%2Bc%2Fd");
+    }
+
+    #[test]
+    fn soap_envelope() {
+        assert_eq!(
+            Body::soap_envelope("<GetUser><Id>7</Id></GetUser>\n", Some("<Token>t</Token>")),
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<soap:Envelope xmlns:soap="http://schemas.xmlsoap.org/soap/envelope/">
+  <soap:Header>
+    <Token>t</Token>
+  </soap:Header>
+  <soap:Body>
+    <GetUser><Id>7</Id></GetUser>
+  </soap:Body>
+</soap:Envelope>
+"#
+        );
+    }
+
+    #[test]
+    fn relative_url() {
+        let mut request = Request {
+            url: "/posts/${id}".into(),
+            ..Default::default()
+        };
+        let context = vec![
+            ("base_url".to_string(), "http://localhost:3000/".to_string()),
+            ("id".to_string(), "1".to_string()),
+        ]
+        .into_iter()
+        .collect();
+        request.apply(&Applicator::new(context, HashMap::new()));
+        assert_eq!(request.url, "http://localhost:3000/posts/1");
+
+        let mut request = Request {
+            url: "${base_url}/posts?limit=${limit}".into(),
+            variables: vec![
+                ("base_url".to_string(), "http://example.com".to_string()),
+                ("limit".to_string(), "10".to_string()),
+            ]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        };
+        request.apply(&Applicator::new(
+            vec![("limit".to_string(), "5".to_string())]
+                .into_iter()
+                .collect(),
+            HashMap::new(),
+        ));
+        assert_eq!(request.url, "http://example.com/posts?limit=5");
+
+        assert!(is_relative_url("posts"));
+        assert!(!is_relative_url("${base_url}/posts"));
+        assert!(!is_relative_url("https://example.com/posts"));
+    }
 }
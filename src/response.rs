@@ -1,7 +1,14 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
 use std::{collections::HashMap, path::Path};
 
-use crate::List;
+use crate::{transform, Certificate, List};
 
+use fs4::FileExt;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
@@ -24,6 +31,17 @@ impl List for HashMap<String, Response> {
             })
             .collect()
     }
+
+    fn group_fields(&self) -> Vec<String> {
+        vec!["status".into()]
+    }
+
+    fn groups(&self, name: &str, field: &str) -> Vec<String> {
+        match (self.get(name), field) {
+            (Some(r), "status") => vec![r.status_code.to_string()],
+            _ => vec![],
+        }
+    }
 }
 
 #[derive(Error, Debug)]
@@ -43,12 +61,60 @@ pub enum ResponseError {
 
 pub type Result<T> = std::result::Result<T, ResponseError>;
 
+/// The default largest body kept in memory, 64 MiB.
+pub const DEFAULT_MAX_BODY_SIZE: u64 = 64 * 1024 * 1024;
+
+/// BodyFile is where a body too large to keep in memory was written.
+/// A file written for a response is deleted once the response and its
+/// copies are dropped.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct BodyFile {
+    pub path: PathBuf,
+    /// The size of the whole body in bytes.
+    pub size: u64,
+    /// Deletes the file when the last copy is dropped.
+    #[serde(skip)]
+    _temp: Option<Arc<tempfile::TempPath>>,
+}
+
+impl PartialEq for BodyFile {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path && self.size == other.size
+    }
+}
+
+/// Response is what a request got back. It's non-exhaustive so fields
+/// can be added; build one with new or Default.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct Response {
     pub status_code: u16,
     pub version: String,
     pub headers: HashMap<String, String>,
     pub body: String,
+    /// The messages received during a websocket request.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub messages: Vec<String>,
+    /// When the body was larger than the maximum, the whole body is
+    /// written to this file and body only holds the start of it, so
+    /// asserts and templates only see that part.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body_file: Option<BodyFile>,
+    /// The size of the body in bytes as it was received, which may be
+    /// compressed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wire_size: Option<u64>,
+    /// The size of the body in bytes once it was decompressed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body_size: Option<u64>,
+    /// The certificate the server presented for HTTPS requests.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub certificate: Option<Certificate>,
+    /// The body parsed as JSON the first time it's needed. Use set_body
+    /// to change the body afterward so it's parsed again.
+    #[serde(skip)]
+    pub(crate) json: OnceLock<Option<serde_json::Value>>,
 }
 
 impl std::fmt::Display for Response {
@@ -66,14 +132,54 @@ impl std::fmt::Display for Response {
             self.status_code,
             headers.join("\r\n"),
             self.body
-        )
+        )?;
+        if let Some(file) = &self.body_file {
+            write!(
+                f,
+                "\r\n\r\n... truncated, the whole {} byte body is in {}",
+                file.size,
+                file.path.display()
+            )?;
+        }
+        Ok(())
     }
 }
 
 impl Response {
+    /// A response with the status, headers and body, like one from a
+    /// mock or another tool.
+    pub fn new(status_code: u16, headers: HashMap<String, String>, body: String) -> Self {
+        Self {
+            status_code,
+            headers,
+            body,
+            ..Default::default()
+        }
+    }
+
+    /// Read the whole response into memory.
     pub async fn from(response: reqwest::Response) -> Result<Self> {
+        Self::from_limited(response, u64::MAX, false).await
+    }
+
+    /// Read the response, keeping at most max_body_size bytes of the
+    /// body in memory. Larger bodies are streamed to a temporary file
+    /// that is kept in body_file. When decompress is set, gzip,
+    /// deflate, brotli and zstd bodies are decoded. Both the size on the
+    /// wire and the decoded size are recorded.
+    pub async fn from_limited(
+        response: reqwest::Response,
+        max_body_size: u64,
+        decompress: bool,
+    ) -> Result<Self> {
+        use async_compression::tokio::bufread::{
+            BrotliDecoder, GzipDecoder, ZlibDecoder, ZstdDecoder,
+        };
+        use futures_util::TryStreamExt;
+        use tokio::io::{AsyncRead, AsyncReadExt};
+
         let status_code = response.status().as_u16();
-        let headers = response
+        let headers: HashMap<String, String> = response
             .headers()
             .iter()
             .map(|(k, v)| {
@@ -86,35 +192,206 @@ impl Response {
             })
             .collect::<Result<_>>()?;
         let version = format!("{:?}", &response.version());
-        let body = response.text().await.map_err(ResponseError::Http)?;
+        let certificate = response
+            .extensions()
+            .get::<reqwest::tls::TlsInfo>()
+            .and_then(|t| t.peer_certificate())
+            .and_then(Certificate::from_der);
+
+        let wire_size = Arc::new(AtomicU64::new(0));
+        let counter = wire_size.clone();
+        let stream = response
+            .bytes_stream()
+            .map_ok(move |chunk| {
+                counter.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+                chunk
+            })
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e));
+        let raw = tokio_util::io::StreamReader::new(stream);
+        let encoding = headers
+            .get("content-encoding")
+            .map(|e| e.trim().to_lowercase());
+        let mut reader: Pin<Box<dyn AsyncRead + Send>> = match encoding.as_deref() {
+            Some("gzip" | "x-gzip") if decompress => Box::pin(GzipDecoder::new(raw)),
+            Some("deflate") if decompress => Box::pin(ZlibDecoder::new(raw)),
+            Some("br") if decompress => Box::pin(BrotliDecoder::new(raw)),
+            Some("zstd") if decompress => Box::pin(ZstdDecoder::new(raw)),
+            _ => Box::pin(raw),
+        };
+
+        let mut body = vec![];
+        let mut body_size = 0;
+        let mut spill: Option<(File, BodyFile)> = None;
+        let mut chunk = vec![0; 64 * 1024];
+        loop {
+            let n = reader.read(&mut chunk).await?;
+            if n == 0 {
+                break;
+            }
+            let chunk = &chunk[..n];
+            body_size += n as u64;
+            if let Some((file, body_file)) = &mut spill {
+                file.write_all(chunk)?;
+                body_file.size += n as u64;
+                continue;
+            }
+            body.extend_from_slice(chunk);
+            if body.len() as u64 > max_body_size {
+                let (mut file, temp) = tempfile::Builder::new()
+                    .prefix("apictl-body-")
+                    .tempfile()?
+                    .into_parts();
+                file.write_all(&body)?;
+                tracing::warn!(path = %temp.display(), max_body_size, "body too large, writing it to a file");
+                spill = Some((
+                    file,
+                    BodyFile {
+                        path: temp.to_path_buf(),
+                        size: body.len() as u64,
+                        _temp: Some(Arc::new(temp)),
+                    },
+                ));
+                body.truncate(max_body_size as usize);
+            }
+        }
+
         Ok(Self {
             version,
             status_code,
             headers,
-            body,
+            body: String::from_utf8_lossy(&body).to_string(),
+            body_file: spill.map(|(_, body_file)| body_file),
+            wire_size: Some(wire_size.load(Ordering::Relaxed)),
+            body_size: Some(body_size),
+            certificate,
+            ..Default::default()
         })
     }
 
+    /// Save the response to `<name>.yaml` in the cache dir. Writers of
+    /// the same name hold an advisory lock on `<name>.yaml.lock`, which
+    /// is removed once written, and the response is written to a
+    /// temporary file that is renamed into place, so readers never see a
+    /// partial file. The cache dir is created if needed.
     pub fn save(&self, cache_dir: &Path, name: &str) -> Result<()> {
+        static TEMP: AtomicUsize = AtomicUsize::new(0);
+
         let path = cache_dir.join(format!("{}.yaml", name));
-        std::fs::write(path, serde_yaml::to_string(&self)?).map_err(ResponseError::Io)
+        let contents = serde_yaml::to_string(&self)?;
+        std::fs::create_dir_all(cache_dir)?;
+        let lock_path = cache_dir.join(format!("{}.yaml.lock", name));
+        let lock = File::create(&lock_path)?;
+        lock.lock_exclusive()?;
+        let temp = cache_dir.join(format!(
+            ".{}.yaml.{}.{}",
+            name,
+            std::process::id(),
+            TEMP.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::write(&temp, contents)?;
+        let renamed = std::fs::rename(&temp, &path);
+        if renamed.is_err() {
+            let _ = std::fs::remove_file(&temp);
+        }
+        // It's removed while still held; a writer that was waiting on it
+        // still writes whole files.
+        let _ = std::fs::remove_file(&lock_path);
+        renamed.map_err(ResponseError::Io)
     }
 
-    pub fn find_path_in_body(&self, key: &str) -> Option<String> {
-        use serde_json::value::Index;
-        let tokens = key.split('.').collect::<Vec<_>>();
-
-        let mut cur: serde_json::Value = serde_json::from_str(&self.body).ok()?;
-        for token in tokens {
-            let t: Box<dyn Index> = match token.parse::<usize>() {
-                Ok(v) => Box::new(v),
-                Err(_) => Box::new(token),
-            };
-            cur = match cur.get(t.as_ref()) {
-                Some(v) => v.clone(),
-                None => return None,
-            }
+    /// The body parsed as JSON or None if it isn't JSON. The body is
+    /// only parsed once, so many asserts and templates on the same
+    /// response stay cheap.
+    pub fn json(&self) -> Option<&serde_json::Value> {
+        self.json
+            .get_or_init(|| serde_json::from_str(&self.body).ok())
+            .as_ref()
+    }
+
+    /// Replace the body, clearing the parsed JSON.
+    pub fn set_body(&mut self, body: String) {
+        self.body = body;
+        self.json = OnceLock::new();
+    }
+
+    /// A copy of the response with the values at the paths in a JSON
+    /// body replaced, as given by a request's `sanitize`. Other bodies
+    /// are kept as is.
+    pub fn sanitize(&self, rules: &HashMap<String, String>) -> Response {
+        let mut response = self.clone();
+        let original = match rules.is_empty() {
+            true => return response,
+            false => match self.json() {
+                Some(v) => v,
+                None => return response,
+            },
+        };
+        let mut value = original.clone();
+        for (path, replacement) in rules {
+            transform::replace(
+                &mut value,
+                path,
+                &serde_json::Value::String(replacement.clone()),
+            );
+        }
+        if value != *original {
+            response.set_body(value.to_string());
+        }
+        response
+    }
+
+    /// Whether the body is newline delimited JSON, going by the content
+    /// type.
+    pub fn is_ndjson(&self) -> bool {
+        self.headers
+            .get("content-type")
+            .is_some_and(|c| c.contains("ndjson") || c.contains("jsonl"))
+    }
+
+    /// The non-empty lines of the body, each parsed as JSON. Lines that
+    /// aren't JSON are kept as strings.
+    pub fn lines(&self) -> Vec<serde_json::Value> {
+        self.body
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(|l| {
+                serde_json::from_str(l).unwrap_or_else(|_| serde_json::Value::String(l.into()))
+            })
+            .collect()
+    }
+
+    /// Find the JSON value at the path in a JSON body, as with
+    /// find_path_in_body. An empty path is the whole body. Paths in
+    /// newline delimited JSON bodies start with the line, like
+    /// `lines[3].status`.
+    pub fn find_value_in_body(&self, key: &str) -> Option<serde_json::Value> {
+        let key = key.replace('[', ".").replace(']', "");
+        let tokens = key.split('.').filter(|t| !t.is_empty()).collect::<Vec<_>>();
+
+        if self.is_ndjson() && tokens.first() == Some(&"lines") {
+            return select(&serde_json::Value::Array(self.lines()), &tokens[1..]);
+        }
+        select(self.json()?, &tokens)
+    }
+
+    /// Find the status code (`status_code`), a header (`headers.NAME`),
+    /// the body (`body`), or a path in the body (`body.PATH`) of the
+    /// response.
+    pub fn find_field(&self, key: &str) -> Option<String> {
+        match key.split_once('.') {
+            None if key == "status_code" => Some(self.status_code.to_string()),
+            None if key == "body" => Some(self.body.clone()),
+            Some(("headers", name)) => self.headers.get(&name.to_lowercase()).cloned(),
+            Some(("body", path)) => self.find_path_in_body(path),
+            _ => None,
         }
+    }
+
+    /// Find the value at the dot separated path in a JSON body. Array
+    /// indexes can also be given in brackets (`items[0]`) and `*`
+    /// selects every element, returning an array of the matches.
+    pub fn find_path_in_body(&self, key: &str) -> Option<String> {
+        let cur = self.find_value_in_body(key)?;
         Some(
             cur.to_string()
                 .trim_start_matches('"')
@@ -123,3 +400,251 @@ impl Response {
         )
     }
 }
+
+/// Select the value at the path made up of tokens.
+fn select(value: &serde_json::Value, tokens: &[&str]) -> Option<serde_json::Value> {
+    use serde_json::value::Index;
+
+    let (token, rest) = match tokens.split_first() {
+        Some(t) => t,
+        None => return Some(value.clone()),
+    };
+    if *token == "*" {
+        let values: Box<dyn Iterator<Item = &serde_json::Value>> = match value {
+            serde_json::Value::Array(a) => Box::new(a.iter()),
+            serde_json::Value::Object(o) => Box::new(o.values()),
+            _ => return None,
+        };
+        return Some(serde_json::Value::Array(
+            values.filter_map(|v| select(v, rest)).collect(),
+        ));
+    }
+    let t: Box<dyn Index> = match token.parse::<usize>() {
+        Ok(v) => Box::new(v),
+        Err(_) => Box::new(*token),
+    };
+    select(value.get(t.as_ref())?, rest)
+}
+
+/// WriteBehind keeps the latest response for each name in memory and
+/// saves them all at once, so many concurrent requests don't write the
+/// same files over and over.
+#[derive(Debug, Default)]
+pub struct WriteBehind {
+    responses: Mutex<HashMap<String, Response>>,
+}
+
+impl WriteBehind {
+    /// Keep the response, replacing any earlier one with the name.
+    pub fn insert(&self, name: &str, response: Response) {
+        self.responses
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), response);
+    }
+
+    /// Remove and return the kept responses.
+    pub fn take(&self) -> HashMap<String, Response> {
+        std::mem::take(&mut *self.responses.lock().unwrap())
+    }
+
+    /// Save the kept responses to the cache dir.
+    pub fn flush(&self, cache_dir: &Path) -> Result<()> {
+        for (name, response) in self.take() {
+            response.save(cache_dir, &name)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn from_limited() {
+        use crate::{Mock, MockServer};
+
+        let mocks = vec![(
+            "big".to_string(),
+            Mock {
+                method: None,
+                path: "/big".into(),
+                status: 200,
+                headers: HashMap::new(),
+                body: "x".repeat(100_000),
+            },
+        )]
+        .into_iter()
+        .collect();
+        let server = MockServer::new(&mocks, HashMap::new(), None).unwrap();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(server.serve_listener(listener));
+
+        let resp = reqwest::get(format!("http://{}/big", addr)).await.unwrap();
+        let response = Response::from_limited(resp, 1000, true).await.unwrap();
+        assert_eq!(response.body.len(), 1000);
+        assert_eq!(response.body_size, Some(100_000));
+        let body_file = response.body_file.clone().unwrap();
+        assert_eq!(body_file.size, 100_000);
+        assert_eq!(std::fs::read(&body_file.path).unwrap().len(), 100_000);
+
+        // The file is deleted once the last copy of the response is.
+        let path = body_file.path.clone();
+        drop(response);
+        assert!(path.exists());
+        drop(body_file);
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn decompress() {
+        use async_compression::tokio::bufread::{BrotliEncoder, GzipEncoder, ZlibEncoder};
+        use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+
+        let body = r#"{"items": ["apictl"]}"#.repeat(1000);
+        let encoders: Vec<(&str, Box<dyn AsyncRead + Unpin>)> = vec![
+            ("gzip", Box::new(GzipEncoder::new(body.as_bytes()))),
+            ("deflate", Box::new(ZlibEncoder::new(body.as_bytes()))),
+            ("br", Box::new(BrotliEncoder::new(body.as_bytes()))),
+        ];
+        for (encoding, mut encoder) in encoders {
+            let mut compressed = vec![];
+            encoder.read_to_end(&mut compressed).await.unwrap();
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let wire = compressed.clone();
+            tokio::spawn(async move {
+                for _ in 0..2 {
+                    let (mut stream, _) = listener.accept().await.unwrap();
+                    let mut request = [0; 1024];
+                    let _ = stream.read(&mut request).await.unwrap();
+                    let head = format!(
+                        "HTTP/1.1 200 OK\r\ncontent-encoding: {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+                        encoding,
+                        wire.len()
+                    );
+                    stream.write_all(head.as_bytes()).await.unwrap();
+                    stream.write_all(&wire).await.unwrap();
+                }
+            });
+
+            let url = format!("http://{}/", addr);
+            let resp = reqwest::get(&url).await.unwrap();
+            let response = Response::from_limited(resp, u64::MAX, true).await.unwrap();
+            assert_eq!(response.body, body, "{}", encoding);
+            assert_eq!(response.wire_size, Some(compressed.len() as u64));
+            assert_eq!(response.body_size, Some(body.len() as u64));
+            assert!(response.wire_size < response.body_size);
+
+            // Without decompressing, the body is what was sent.
+            let resp = reqwest::get(&url).await.unwrap();
+            let response = Response::from_limited(resp, u64::MAX, false).await.unwrap();
+            assert_eq!(response.body_size, Some(compressed.len() as u64));
+        }
+    }
+
+    #[test]
+    fn sanitize() {
+        let response = Response {
+            body: r#"{"id": "abc", "items": [{"at": 1}, {"at": 2}], "n": 1}"#.into(),
+            ..Default::default()
+        };
+        let rules = [
+            ("$.id".to_string(), "<id>".to_string()),
+            ("items[*].at".to_string(), "<time>".to_string()),
+            ("missing".to_string(), "x".to_string()),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(
+            response.sanitize(&rules).json(),
+            Some(
+                &serde_json::json!({"id": "<id>", "items": [{"at": "<time>"}, {"at": "<time>"}], "n": 1})
+            )
+        );
+        assert_eq!(response.sanitize(&HashMap::new()).body, response.body);
+    }
+
+    #[test]
+    fn find_path() {
+        let response = Response {
+            body: r#"{"items": [{"id": 1, "name": "a"}, {"id": 2}], "count": 2}"#.into(),
+            ..Default::default()
+        };
+        assert_eq!(response.find_path_in_body("count"), Some("2".into()));
+        assert_eq!(response.find_path_in_body("items.0.name"), Some("a".into()));
+        assert_eq!(response.find_path_in_body("items[1].id"), Some("2".into()));
+        assert_eq!(
+            response.find_path_in_body("items[*].id"),
+            Some("[1,2]".into())
+        );
+        assert_eq!(
+            response.find_path_in_body("items.*.name"),
+            Some(r#"["a"]"#.into())
+        );
+        assert_eq!(response.find_path_in_body("items.2"), None);
+
+        let mut response = response.clone();
+        response.set_body(r#"{"count": 3}"#.into());
+        assert_eq!(response.find_path_in_body("count"), Some("3".into()));
+    }
+
+    #[test]
+    fn ndjson() {
+        let mut response = Response {
+            body: "{\"status\": \"ok\"}\n\n{\"status\": \"failed\", \"id\": 2}\n".into(),
+            ..Default::default()
+        };
+        assert_eq!(response.find_path_in_body("lines[1].status"), None);
+        response
+            .headers
+            .insert("content-type".into(), "application/x-ndjson".into());
+        assert_eq!(response.lines().len(), 2);
+        assert_eq!(
+            response.find_path_in_body("lines[1].status"),
+            Some("failed".into())
+        );
+        assert_eq!(
+            response.find_path_in_body("lines[*].status"),
+            Some(r#"["ok","failed"]"#.into())
+        );
+    }
+
+    #[test]
+    fn concurrent_save() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().to_path_buf();
+
+        let handles = (0..8)
+            .map(|i| {
+                let dir = dir.clone();
+                std::thread::spawn(move || {
+                    let response = Response {
+                        status_code: 200,
+                        body: i.to_string().repeat(10_000),
+                        ..Default::default()
+                    };
+                    for _ in 0..10 {
+                        response.save(&dir, "same").unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        handles.into_iter().for_each(|h| h.join().unwrap());
+
+        let contents = std::fs::read_to_string(dir.join("same.yaml")).unwrap();
+        let response: Response = serde_yaml::from_str(&contents).unwrap();
+        assert_eq!(response.body.len(), 10_000);
+        let first = response.body.chars().next().unwrap();
+        assert!(response.body.chars().all(|c| c == first));
+        assert!(!dir.join("same.yaml.lock").exists());
+
+        let write_behind = WriteBehind::default();
+        write_behind.insert("later", response.clone());
+        write_behind.insert("later", Response::default());
+        write_behind.flush(&dir).unwrap();
+        assert!(dir.join("later.yaml").exists());
+    }
+}
@@ -1,4 +1,5 @@
-use std::io::{Stdout, Write};
+use std::cell::Cell;
+use std::io::Write;
 use std::time::{Duration, Instant};
 
 use crossterm::{cursor, terminal, ExecutableCommand};
@@ -10,19 +11,53 @@ use thiserror::Error;
 pub enum ResultsError {
     #[error("terminal error: {0}")]
     TerminalError(std::io::Error),
+
+    #[error("result not found: {0}")]
+    NodeNotFound(String),
+
+    #[error("unknown verbosity: {0}")]
+    Verbosity(String),
 }
 
 /// Result is the result type for tests.
 pub type Result<T> = std::result::Result<T, ResultsError>;
 
+/// Verbosity is how much of the results are shown. Failures are always
+/// shown down to the asserts that failed.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Verbosity {
+    /// Only the tests.
+    Tests,
+    /// Everything until it passes, then only the result that passed.
+    #[default]
+    Auto,
+    /// Everything.
+    All,
+}
+
+impl std::str::FromStr for Verbosity {
+    type Err = ResultsError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "tests" => Ok(Verbosity::Tests),
+            "auto" => Ok(Verbosity::Auto),
+            "all" => Ok(Verbosity::All),
+            _ => Err(ResultsError::Verbosity(s.into())),
+        }
+    }
+}
+
+/// The frames of the spinner shown for running results.
+const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
 /// State is the current state of a result.
-#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+#[derive(Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
 pub enum State {
     /// NotRun indicates that the result has not been run.
     #[default]
     NotRun,
 
-    // TODO: (?) we could potentially do running later.
     /// Running indicates that the result is currently running.
     Running,
 
@@ -31,6 +66,30 @@ pub enum State {
 
     /// Failed indicates that the result has failed.
     Failed(String),
+
+    /// Skipped indicates that the result was not run for the given
+    /// reason.
+    Skipped(String),
+
+    /// FlakyPass indicates that a flaky test passed on the given
+    /// attempt after failing.
+    FlakyPass(usize),
+
+    /// TimedOut indicates that the result didn't finish before the
+    /// run's deadline.
+    TimedOut,
+}
+
+impl State {
+    /// How bad the state is. Parents take on the worst state of their
+    /// children.
+    fn severity(&self) -> u8 {
+        match self {
+            State::Failed(_) => 2,
+            State::TimedOut => 1,
+            _ => 0,
+        }
+    }
 }
 
 impl std::fmt::Display for State {
@@ -40,16 +99,69 @@ impl std::fmt::Display for State {
             State::Running => write!(f, "🏃"),
             State::Passed => write!(f, "✅"),
             State::Failed(_) => write!(f, "❌"),
+            State::Skipped(_) => write!(f, "⏭"),
+            State::FlakyPass(_) => write!(f, "🔁"),
+            State::TimedOut => write!(f, "⌛"),
         }
     }
 }
 
-#[derive(Debug)]
+/// Counts are how many tests ended in each state.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Counts {
+    pub passed: usize,
+    pub failed: usize,
+    pub flaky: usize,
+    pub skipped: usize,
+    pub timed_out: usize,
+    pub not_run: usize,
+}
+
+impl std::fmt::Display for Counts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let counts = [
+            (self.passed, "passed"),
+            (self.failed, "failed"),
+            (self.flaky, "flaky"),
+            (self.skipped, "skipped"),
+            (self.timed_out, "timed out"),
+            (self.not_run, "not run"),
+        ];
+        let counts = counts
+            .iter()
+            .filter(|(n, _)| *n > 0)
+            .map(|(n, s)| format!("{} {}", n, s))
+            .collect::<Vec<_>>();
+        match counts.is_empty() {
+            true => write!(f, "no tests"),
+            false => write!(f, "{}", counts.join(", ")),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Results {
     pub name: String,
     pub state: State,
+    #[serde(with = "humantime_serde")]
     pub duration: Duration,
+    /// The seed the tests were shuffled with, if they were.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
     pub children: Vec<Results>,
+    /// Whether the state has been set to a finished one, to tell
+    /// siblings with the same name apart.
+    #[serde(skip)]
+    updated: bool,
+    /// When the result started running, to show how long it's been.
+    #[serde(skip)]
+    started: Option<Instant>,
+    /// How much of the results below these are printed.
+    #[serde(skip)]
+    pub verbosity: Verbosity,
+    /// The lines last printed, to clear them.
+    #[serde(skip)]
+    printed: Cell<usize>,
 }
 
 impl Results {
@@ -58,7 +170,12 @@ impl Results {
             name: name.to_string(),
             state: State::NotRun,
             duration: Duration::default(),
+            seed: None,
             children: Vec::new(),
+            updated: false,
+            started: None,
+            verbosity: Verbosity::default(),
+            printed: Cell::default(),
         }
     }
 
@@ -71,30 +188,18 @@ impl Results {
     }
 
     pub fn from_test(name: &str, test: &crate::Test) -> Self {
-        Self {
-            name: name.to_string(),
-            state: State::NotRun,
-            duration: Duration::default(),
-            children: test
-                .steps
-                .iter()
-                .map(|s| Self {
-                    name: s.name.clone(),
-                    state: State::NotRun,
-                    duration: Duration::default(),
-                    children: s
-                        .asserts
-                        .iter()
-                        .map(|a| Self {
-                            name: format!("{}", a),
-                            state: State::NotRun,
-                            duration: Duration::default(),
-                            children: Vec::new(),
-                        })
-                        .collect(),
-                })
-                .collect(),
+        let mut results = Self::new(name);
+        for s in &test.steps {
+            let mut step = Self::new(&s.name);
+            // The iterations of a foreach are added once they are known.
+            if s.foreach.is_none() {
+                for a in &s.asserts {
+                    step.add(&format!("{}", a));
+                }
+            }
+            results.add_results(step);
         }
+        results
     }
 
     pub fn is_empty(&self) -> bool {
@@ -109,52 +214,356 @@ impl Results {
         len
     }
 
-    pub fn update(&mut self, names: &[String], state: State, start: Instant) {
-        if names.len() == 1 && self.name == names[0] {
-            self.duration = start.elapsed();
-            self.state = state;
-        } else if !names.is_empty() && self.name == names[0] {
-            let child = self
-                .children
-                .iter_mut()
-                .find(|c| c.name == names[1])
-                .unwrap();
-            child.update(&names[1..], state, start);
+    /// The failures under the results as `path: message`, where the
+    /// path is the names below this one joined by `/`. Results that
+    /// failed because of the results below them aren't included.
+    pub fn failures(&self) -> Vec<String> {
+        let mut failures = vec![];
+        for child in &self.children {
+            child.collect_failures(&child.name, &mut failures);
+        }
+        failures
+    }
+
+    /// How many of the tests, the results directly below these, ended in
+    /// each state. A test with a failure anywhere below it failed.
+    pub fn counts(&self) -> Counts {
+        let mut counts = Counts::default();
+        for test in &self.children {
+            if matches!(test.state, State::Failed(_)) || !test.failures().is_empty() {
+                counts.failed += 1;
+                continue;
+            }
+            match test.state {
+                State::Passed => counts.passed += 1,
+                State::FlakyPass(_) => counts.flaky += 1,
+                State::Skipped(_) => counts.skipped += 1,
+                State::TimedOut => counts.timed_out += 1,
+                State::NotRun | State::Running | State::Failed(_) => counts.not_run += 1,
+            }
         }
+        counts
+    }
+
+    /// The slowest steps of the tests, as `test/step` and how long they
+    /// took, the slowest first. Steps that didn't run aren't included.
+    pub fn slowest_steps(&self, n: usize) -> Vec<(String, Duration)> {
+        let mut steps = self
+            .children
+            .iter()
+            .flat_map(|t| t.children.iter().map(move |s| (t, s)))
+            .filter(|(_, s)| !matches!(s.state, State::NotRun | State::Skipped(_)))
+            .map(|(t, s)| (format!("{}/{}", t.name, s.name), s.duration))
+            .collect::<Vec<_>>();
+        steps.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        steps.truncate(n);
+        steps
     }
 
-    pub fn print(&self, s: &mut Stdout, prefix: &str) -> Result<()> {
-        writeln!(
-            s,
-            "{}{} ({:?}) {}",
-            prefix, self.state, self.duration, self.name
-        )
-        .map_err(ResultsError::TerminalError)?;
+    fn collect_failures(&self, path: &str, failures: &mut Vec<String>) {
+        if let State::Failed(message) = &self.state {
+            if !self.children.iter().any(|c| c.state.severity() == 2) {
+                failures.push(format!("{}: {}", path, message));
+            }
+        }
         for child in &self.children {
-            child.print(s, &format!("{}  ", prefix))?;
+            child.collect_failures(&format!("{}/{}", path, child.name), failures);
+        }
+    }
+
+    /// Set the state of the node at the path of names. What's below a
+    /// skipped or timed out node didn't run either, so it gets the same
+    /// state.
+    pub fn update(&mut self, names: &[String], state: State, start: Instant) -> Result<()> {
+        let not_found = || ResultsError::NodeNotFound(names.join("/"));
+        let (first, rest) = names.split_first().ok_or_else(not_found)?;
+        if self.name != *first {
+            return Err(not_found());
+        }
+        match rest.first() {
+            None => {
+                self.duration = start.elapsed();
+                if matches!(state, State::Skipped(_) | State::TimedOut) {
+                    for child in &mut self.children {
+                        child.stop(&state);
+                    }
+                }
+                self.started = (state == State::Running).then_some(start);
+                self.updated = state != State::Running;
+                self.state = state;
+            }
+            Some(name) => {
+                let child = self.child_mut(name).ok_or_else(not_found)?;
+                child.update(rest, state, start)?;
+            }
         }
+        self.aggregate();
         Ok(())
     }
 
-    pub fn output(&self, s: &mut Stdout, prefix: &str) -> Result<()> {
-        self.clear(s)?;
-        writeln!(
-            s,
-            "{}{} ({:?}) {}",
-            prefix, self.state, self.duration, self.name
-        )
-        .map_err(ResultsError::TerminalError)?;
-        for child in &self.children {
-            child.print(s, &format!("{}  ", prefix))?;
+    /// The child with the name. When siblings share a name, like two
+    /// steps that make the same request, it's the first that hasn't been
+    /// updated, or the last if they all have.
+    fn child_mut(&mut self, name: &str) -> Option<&mut Results> {
+        let mut named = self.children.iter_mut().filter(|c| c.name == name);
+        let first = named.next()?;
+        if !first.updated {
+            return Some(first);
+        }
+        let mut last = first;
+        for child in named {
+            if !child.updated {
+                return Some(child);
+            }
+            last = child;
+        }
+        Some(last)
+    }
+
+    /// Set the state once the children are done, like for the root of a
+    /// run, taking the worst of the children's states.
+    pub fn finish(&mut self, state: State) {
+        self.state = state;
+        self.aggregate();
+    }
+
+    /// Take on the worst state of the children if it's worse than this
+    /// one's.
+    fn aggregate(&mut self) {
+        let worst = self.children.iter().max_by_key(|c| c.state.severity());
+        let worst = match worst {
+            Some(c) if c.state.severity() > self.state.severity() => &c.state,
+            _ => return,
+        };
+        self.state = match worst {
+            State::Failed(_) => {
+                let failed = self
+                    .children
+                    .iter()
+                    .filter(|c| c.state.severity() == 2)
+                    .count();
+                State::Failed(format!("{} of {} failed", failed, self.children.len()))
+            }
+            state => state.clone(),
+        };
+    }
+
+    /// Mark the results that haven't finished, and any below them, with
+    /// the state, like when the run is stopped.
+    pub fn stop(&mut self, state: &State) {
+        if matches!(self.state, State::NotRun | State::Running) {
+            self.state = state.clone();
         }
+        for child in &mut self.children {
+            child.stop(state);
+        }
+    }
+
+    /// Find the node at the given path of names.
+    pub fn find_mut(&mut self, names: &[String]) -> Option<&mut Results> {
+        let (first, rest) = names.split_first()?;
+        if self.name != *first {
+            return None;
+        }
+        match rest.first() {
+            None => Some(self),
+            Some(name) => self.child_mut(name)?.find_mut(rest),
+        }
+    }
+
+    pub fn print(&self, s: &mut impl Write, prefix: &str) -> Result<()> {
+        let printed = self.print_tree(s, prefix, self.verbosity, true)?;
+        self.printed.set(printed);
         Ok(())
     }
 
-    pub fn clear(&self, s: &mut Stdout) -> Result<()> {
-        s.execute(cursor::MoveUp(self.len() as u16))
+    pub fn output(&self, s: &mut impl Write, prefix: &str) -> Result<()> {
+        self.clear(s)?;
+        self.print(s, prefix)
+    }
+
+    /// Print these results and the children that are shown at the
+    /// verbosity, returning the number of lines printed.
+    fn print_tree(
+        &self,
+        s: &mut impl Write,
+        prefix: &str,
+        verbosity: Verbosity,
+        top: bool,
+    ) -> Result<usize> {
+        self.print_line(s, prefix)?;
+        let mut printed = 1;
+        let expanded = top
+            || self.state.severity() > 0
+            || match verbosity {
+                Verbosity::Tests => false,
+                Verbosity::Auto => !matches!(
+                    self.state,
+                    State::Passed | State::FlakyPass(_) | State::Skipped(_)
+                ),
+                Verbosity::All => true,
+            };
+        if expanded {
+            for child in &self.children {
+                printed += child.print_tree(s, &format!("{}  ", prefix), verbosity, false)?;
+            }
+        }
+        Ok(printed)
+    }
+
+    /// Print the state, duration and name. A running result shows a
+    /// spinner and how long it has been running instead.
+    fn print_line(&self, s: &mut impl Write, prefix: &str) -> Result<()> {
+        let line = match (&self.state, self.started) {
+            (State::Running, Some(started)) => {
+                let elapsed = started.elapsed();
+                let frame = SPINNER[(elapsed.as_millis() / 100) as usize % SPINNER.len()];
+                let elapsed = Duration::from_millis(elapsed.as_millis() as u64);
+                format!("{}{} ({:?}) {}", prefix, frame, elapsed, self.name)
+            }
+            _ => format!(
+                "{}{} ({:?}) {}",
+                prefix, self.state, self.duration, self.name
+            ),
+        };
+        writeln!(s, "{}", line).map_err(ResultsError::TerminalError)
+    }
+
+    /// Clear the lines last printed.
+    pub fn clear(&self, s: &mut impl Write) -> Result<()> {
+        let printed = self.printed.take();
+        if printed == 0 {
+            return Ok(());
+        }
+        s.execute(cursor::MoveUp(printed as u16))
             .map_err(ResultsError::TerminalError)?;
         s.execute(terminal::Clear(terminal::ClearType::FromCursorDown))
             .map_err(ResultsError::TerminalError)?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stop() {
+        let mut results = Results::new("test results");
+        results.add("finished");
+        results.add("running");
+        results.children[0].state = State::Passed;
+        results.children[1].state = State::Running;
+        results.children[1].add("step");
+        results.stop(&State::TimedOut);
+        assert_eq!(results.state, State::TimedOut);
+        assert_eq!(results.children[0].state, State::Passed);
+        assert_eq!(results.children[1].state, State::TimedOut);
+        assert_eq!(results.children[1].children[0].state, State::TimedOut);
+        assert_eq!(results.counts().to_string(), "1 passed, 1 timed out");
+
+        let names = ["test results".to_string(), "finished".to_string()];
+        results.children[0].add("step");
+        let skipped = State::Skipped("condition".into());
+        results
+            .update(&names, skipped.clone(), Instant::now())
+            .unwrap();
+        assert_eq!(results.children[0].children[0].state, skipped);
+        assert_eq!(Results::new("none").counts().to_string(), "no tests");
+    }
+
+    #[test]
+    fn aggregate() {
+        let mut results = Results::from_test("get", &serde_yaml::from_str(
+            "{description: '', steps: [{name: a, request: a, asserts: [{type: status_code, value: 200}]}, {name: a, request: b, asserts: []}]}",
+        ).unwrap());
+        let path = |p: &str| p.split('/').map(String::from).collect::<Vec<_>>();
+        let now = Instant::now();
+        results
+            .update(
+                &path("get/a/status_code == 200"),
+                State::Failed("got 500".into()),
+                now,
+            )
+            .unwrap();
+        // The steps share a name, so they're updated in order.
+        results.update(&path("get/a"), State::Passed, now).unwrap();
+        results.update(&path("get/a"), State::Passed, now).unwrap();
+        results.update(&path("get"), State::Passed, now).unwrap();
+        assert!(matches!(
+            results.update(&path("get/c"), State::Passed, now),
+            Err(ResultsError::NodeNotFound(p)) if p == "get/c"
+        ));
+        assert_eq!(results.state, State::Failed("1 of 2 failed".into()));
+        assert_eq!(
+            results.children[0].state,
+            State::Failed("1 of 1 failed".into())
+        );
+        assert_eq!(results.children[1].state, State::Passed);
+        assert_eq!(results.failures(), vec!["a/status_code == 200: got 500"]);
+
+        let mut root = Results::new("test results");
+        root.add_results(results);
+        root.finish(State::Passed);
+        assert_eq!(root.state, State::Failed("1 of 1 failed".into()));
+
+        // The step that passed is collapsed unless everything is shown.
+        let lines = |root: &mut Results, verbosity| {
+            root.verbosity = verbosity;
+            let mut out = vec![];
+            root.print(&mut out, "").unwrap();
+            assert_eq!(
+                root.printed.get(),
+                out.iter().filter(|b| **b == b'\n').count()
+            );
+            root.printed.get()
+        };
+        assert_eq!(lines(&mut root, Verbosity::Auto), 5);
+        assert_eq!(lines(&mut root, Verbosity::Tests), 5);
+        assert_eq!(lines(&mut root, Verbosity::All), 5);
+        root.children[0].children[0].children[0].state = State::Passed;
+        root.children[0].children[0].state = State::Passed;
+        root.children[0].state = State::Passed;
+        assert_eq!(lines(&mut root, Verbosity::Auto), 2);
+        assert_eq!(lines(&mut root, Verbosity::All), 5);
+
+        root.children[0].children[1].duration = Duration::from_secs(2);
+        root.children[0].children[0].duration = Duration::from_secs(1);
+        assert_eq!(
+            root.slowest_steps(1),
+            vec![("get/a".to_string(), Duration::from_secs(2))]
+        );
+    }
+
+    #[test]
+    fn running() {
+        let mut results = Results::new("test results");
+        results.add("slow");
+        let started = Instant::now() - Duration::from_millis(200);
+        let names = ["test results".to_string(), "slow".to_string()];
+        results.update(&names, State::Running, started).unwrap();
+
+        // The spinner turns every 100ms and the elapsed time is shown
+        // in whole milliseconds.
+        let mut out = vec![];
+        results.children[0].print_line(&mut out, "  ").unwrap();
+        let line = String::from_utf8(out).unwrap();
+        let (head, elapsed) = line.split_once(" (").unwrap();
+        assert_eq!(head, "  ⠹");
+        let (elapsed, name) = elapsed.split_once(") ").unwrap();
+        assert!(
+            elapsed.ends_with("ms") && !elapsed.contains('.'),
+            "{}",
+            elapsed
+        );
+        assert!(elapsed.trim_end_matches("ms").parse::<u64>().unwrap() >= 200);
+        assert_eq!(name, "slow\n");
+
+        results.update(&names, State::Passed, started).unwrap();
+        let mut out = vec![];
+        results.children[0].print_line(&mut out, "").unwrap();
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .starts_with(&State::Passed.to_string()));
+    }
+}
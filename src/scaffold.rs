@@ -0,0 +1,281 @@
+/// Scaffold is used to create new configurations and add entries to
+/// existing ones.
+use std::path::Path;
+
+use crate::{Request, Test};
+
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+use thiserror::Error;
+
+/// The starter configuration written by `apictl new`.
+pub const STARTER: &str = r#"# This is a starter configuration for apictl. See
+# https://github.com/icub3d/apictl for details on each section.
+contexts:
+  local:
+    base_url: http://localhost:3000
+
+requests:
+  get-posts:
+    tags: [posts]
+    description: get all of the posts
+    url: "${base_url}/posts"
+    query_parameters:
+      _limit: "10"
+
+  new-post:
+    tags: [posts]
+    description: make a new post
+    url: "${base_url}/posts"
+    method: POST
+    headers:
+      content-type: application/json
+    body:
+      type: raw
+      from:
+        type: text
+        data: |
+          {
+            "userId": 1,
+            "title": "my first post"
+          }
+
+  get-new-post:
+    tags: [posts]
+    description: get the post created by new-post
+    url: "${base_url}/posts/${response.new-post.id}"
+
+tests:
+  create-post:
+    description: create a new post and make sure it exists.
+    steps:
+      - name: create
+        request: new-post
+        asserts:
+          - type: status_code
+            value: 201
+      - name: check
+        request: get-new-post
+        asserts:
+          - type: status_code
+            value: 200
+          - type: equals
+            key: title
+            value: "my first post"
+"#;
+
+/// Errors that can occur while scaffolding configurations.
+#[derive(Debug, Error)]
+pub enum ScaffoldError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("yaml parse error: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+
+    #[error("file already exists: {0}")]
+    FileExists(String),
+
+    #[error("{0} already exists: {1}")]
+    EntryExists(String, String),
+
+    #[error("not a yaml mapping: {0}")]
+    NotMapping(String),
+}
+
+/// Result is a convenience type for scaffold errors.
+pub type Result<T> = std::result::Result<T, ScaffoldError>;
+
+/// Write the starter configuration to the given path. Existing files
+/// are only replaced when force is set.
+pub fn new(path: &Path, force: bool) -> Result<()> {
+    if path.exists() && !force {
+        return Err(ScaffoldError::FileExists(path.display().to_string()));
+    }
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() {
+            std::fs::create_dir_all(parent)?;
+        }
+    }
+    std::fs::write(path, STARTER)?;
+    Ok(())
+}
+
+/// Add the request to the configuration file at the given path. The
+/// file is created if it doesn't exist.
+pub fn add_request(path: &Path, name: &str, request: &Request) -> Result<()> {
+    add_entry(path, "requests", name, request)
+}
+
+/// Add the test to the configuration file at the given path. The file
+/// is created if it doesn't exist.
+pub fn add_test(path: &Path, name: &str, test: &Test) -> Result<()> {
+    add_entry(path, "tests", name, test)
+}
+
+fn add_entry<T: Serialize>(path: &Path, section: &str, name: &str, entry: &T) -> Result<()> {
+    let contents = match path.exists() {
+        true => std::fs::read_to_string(path)?,
+        false => String::new(),
+    };
+
+    // Every document is checked since they're merged when loaded.
+    for document in serde_yaml::Deserializer::from_str(&contents) {
+        let root = Value::deserialize(document)?;
+        if root.is_null() {
+            continue;
+        }
+        let root = root
+            .as_mapping()
+            .ok_or_else(|| ScaffoldError::NotMapping(path.display().to_string()))?;
+        let entries = match root.get(section) {
+            None | Some(Value::Null) => continue,
+            Some(entries) => entries
+                .as_mapping()
+                .ok_or_else(|| ScaffoldError::NotMapping(section.into()))?,
+        };
+        if entries.contains_key(name) {
+            return Err(ScaffoldError::EntryExists(
+                section.trim_end_matches('s').into(),
+                name.into(),
+            ));
+        }
+    }
+
+    let mut mapping = Mapping::new();
+    mapping.insert(Value::String(name.into()), serde_yaml::to_value(entry)?);
+    let entry = serde_yaml::to_string(&mapping)?;
+    let contents = insert_entry(&contents, section, &entry)
+        .ok_or_else(|| ScaffoldError::NotMapping(section.into()))?;
+    std::fs::write(path, contents)?;
+    Ok(())
+}
+
+/// Add the entry to the end of the section as text, so the comments and
+/// formatting of the rest of the file are kept. The section is added to
+/// the end of the file when it doesn't have one. Sections written as
+/// flow mappings, like `requests: {a: ...}`, can't be added to.
+fn insert_entry(contents: &str, section: &str, entry: &str) -> Option<String> {
+    let mut lines = contents.lines().map(String::from).collect::<Vec<_>>();
+    let Some(header) = lines
+        .iter()
+        .position(|l| section_value(l, section).is_some())
+    else {
+        let mut contents = contents.to_string();
+        if !contents.is_empty() && !contents.ends_with('\n') {
+            contents.push('\n');
+        }
+        if !contents.trim().is_empty() {
+            contents.push('\n');
+        }
+        contents.push_str(&format!("{}:\n{}", section, indent(entry, "  ")));
+        return Some(contents);
+    };
+
+    // An empty section, like `requests: {}`, is made a block.
+    let value = section_value(&lines[header], section).unwrap_or_default();
+    let (value, comment) = match value.find('#') {
+        Some(i) => (value[..i].trim(), &value[i..]),
+        None => (value.trim(), ""),
+    };
+    match value {
+        "" => {}
+        "{}" | "~" | "null" => {
+            lines[header] = format!("{}: {}", section, comment).trim_end().into()
+        }
+        _ => return None,
+    }
+
+    // The section is the indented lines after it. Comments that aren't
+    // indented belong to what follows.
+    let end = lines[header + 1..]
+        .iter()
+        .position(|l| !l.is_empty() && !l.starts_with([' ', '\t']))
+        .map_or(lines.len(), |i| header + 1 + i);
+    let entries = &lines[header + 1..end];
+    let last = entries
+        .iter()
+        .rposition(|l| !l.trim().is_empty())
+        .map_or(header, |i| header + 1 + i);
+    let prefix = entries
+        .iter()
+        .find(|l| !l.trim().is_empty() && !l.trim_start().starts_with('#'))
+        .map_or("  ", |l| &l[..l.len() - l.trim_start().len()])
+        .to_string();
+    // Entries separated by blank lines get one before the new entry too.
+    let spaced = lines[header + 1..=last].iter().any(|l| l.trim().is_empty());
+
+    let mut added = indent(entry, &prefix).trim_end().to_string();
+    if spaced {
+        added.insert(0, '\n');
+    }
+    lines.insert(last + 1, added);
+    Some(lines.join("\n") + "\n")
+}
+
+/// The value after the key when the line is the top-level key of the
+/// section.
+fn section_value<'a>(line: &'a str, section: &str) -> Option<&'a str> {
+    let value = line.strip_prefix(section)?.strip_prefix(':')?;
+    (value.is_empty() || value.starts_with([' ', '\t'])).then_some(value)
+}
+
+/// Indent each line of the text that isn't empty.
+fn indent(text: &str, prefix: &str) -> String {
+    text.lines()
+        .map(|l| match l.is_empty() {
+            true => "\n".to_string(),
+            false => format!("{}{}\n", prefix, l),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    #[test]
+    fn starter_parses() {
+        let cfg: Config = serde_yaml::from_str(STARTER).unwrap();
+        assert_eq!(cfg.contexts.len(), 1);
+        assert_eq!(cfg.requests.len(), 3);
+        assert_eq!(cfg.tests.len(), 1);
+    }
+
+    #[test]
+    fn add_entries() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let path = dir.join("config.yaml");
+        new(&path, false).unwrap();
+        assert!(new(&path, false).is_err());
+
+        let cfg = Config::new(path.to_str().unwrap()).unwrap();
+        let request = cfg.requests.get("get-posts").unwrap().clone();
+        add_request(&path, "get-posts-again", &request).unwrap();
+        assert!(add_request(&path, "get-posts", &request).is_err());
+
+        let test = cfg.tests.get("create-post").unwrap().clone();
+        add_test(&path, "create-post-again", &test).unwrap();
+
+        let cfg = Config::new(path.to_str().unwrap()).unwrap();
+        assert_eq!(cfg.requests.len(), 4);
+        assert_eq!(cfg.tests.len(), 2);
+
+        // The file is added to, not rewritten.
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(contents.starts_with(&STARTER[..STARTER.find("\ntests:").unwrap()]));
+        assert!(contents.contains("\n\n  get-posts-again:\n    description: get all"));
+
+        std::fs::write(&path, "# mine\ncontexts: {}\nrequests: {} # none yet\n").unwrap();
+        add_request(&path, "get-posts", &request).unwrap();
+        add_test(&path, "create-post", &test).unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(contents.starts_with("# mine\ncontexts: {}\nrequests: # none yet\n  get-posts:\n"));
+        assert!(contents.contains("\n\ntests:\n  create-post:\n"));
+        let cfg = Config::new(path.to_str().unwrap()).unwrap();
+        assert_eq!(cfg.requests.len(), 1);
+        assert_eq!(cfg.tests.len(), 1);
+    }
+}
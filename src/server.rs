@@ -0,0 +1,102 @@
+/// Server is a small HTTP server shared by the commands that listen for
+/// requests.
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+
+use http_body_util::{BodyExt, Full};
+use hyper::body::{Bytes, Incoming};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper_util::rt::TokioIo;
+use tokio::net::TcpListener;
+
+/// Received is a request with its body read.
+pub(crate) struct Received {
+    pub method: String,
+    pub uri: hyper::Uri,
+    pub headers: HashMap<String, String>,
+    pub body: Bytes,
+}
+
+impl Received {
+    pub async fn from(req: hyper::Request<Incoming>) -> Self {
+        let method = req.method().to_string();
+        let uri = req.uri().clone();
+        let headers = req
+            .headers()
+            .iter()
+            .map(|(k, v)| {
+                (
+                    k.to_string(),
+                    String::from_utf8_lossy(v.as_bytes()).to_string(),
+                )
+            })
+            .collect();
+        let body = match req.into_body().collect().await {
+            Ok(b) => b.to_bytes(),
+            Err(_) => Bytes::new(),
+        };
+        Self {
+            method,
+            uri,
+            headers,
+            body,
+        }
+    }
+
+    /// The query parameters of the request.
+    pub fn query(&self) -> HashMap<String, String> {
+        self.uri
+            .query()
+            .map(|q| {
+                q.split('&')
+                    .filter_map(|p| p.split_once('='))
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Build a plain text response.
+pub(crate) fn text(status: u16, body: String) -> hyper::Response<Full<Bytes>> {
+    hyper::Response::builder()
+        .status(status)
+        .body(Full::new(Bytes::from(body)))
+        .unwrap()
+}
+
+/// Serve requests on the given address with the handler until the
+/// process exits.
+pub(crate) async fn serve<F, Fut>(addr: SocketAddr, handler: F) -> std::io::Result<()>
+where
+    F: Fn(Received) -> Fut + Clone + Send + Sync + 'static,
+    Fut: Future<Output = hyper::Response<Full<Bytes>>> + Send + 'static,
+{
+    serve_listener(TcpListener::bind(addr).await?, handler).await
+}
+
+/// Serve requests from the listener with the handler until the process
+/// exits.
+pub(crate) async fn serve_listener<F, Fut>(listener: TcpListener, handler: F) -> std::io::Result<()>
+where
+    F: Fn(Received) -> Fut + Clone + Send + Sync + 'static,
+    Fut: Future<Output = hyper::Response<Full<Bytes>>> + Send + 'static,
+{
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let handler = handler.clone();
+        tokio::spawn(async move {
+            let service = service_fn(move |req| {
+                let handler = handler.clone();
+                async move { Ok::<_, Infallible>(handler(Received::from(req).await).await) }
+            });
+            // Connection errors only affect that client.
+            let _ = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await;
+        });
+    }
+}
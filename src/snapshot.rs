@@ -0,0 +1,180 @@
+/// Snapshot is used to compare response bodies against stored golden
+/// copies.
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+use thiserror::Error;
+
+/// SnapshotError is the error type for snapshots.
+#[derive(Error, Debug)]
+pub enum SnapshotError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("snapshot '{name}' differs at line {line}: got '{got}', want '{want}'")]
+    Mismatch {
+        name: String,
+        line: usize,
+        got: String,
+        want: String,
+    },
+
+    #[error("invalid snapshot name: {0}")]
+    Name(String),
+}
+
+/// Result is the result type for snapshots.
+pub type Result<T> = std::result::Result<T, SnapshotError>;
+
+/// The path in the cache where the named snapshot is stored. Path
+/// separators in the name are replaced so it stays in the snapshot
+/// folder, and names with `..` components are an error.
+pub fn snapshot_path(cache_dir: &Path, name: &str) -> Result<PathBuf> {
+    if name.split(['/', '\\']).any(|c| c == "..") {
+        return Err(SnapshotError::Name(name.to_string()));
+    }
+    let name = name.replace(['/', '\\'], "_");
+    Ok(cache_dir.join("snapshots").join(format!("{}.snap", name)))
+}
+
+/// Normalize the body so that it can be compared. JSON bodies are
+/// pretty printed with sorted keys and the ignored paths removed. A
+/// `*` in a path matches every key or index. Other bodies are used as
+/// is.
+pub fn normalize(body: &str, ignore: &[String]) -> String {
+    let mut value: Value = match serde_json::from_str(body) {
+        Ok(v) => v,
+        Err(_) => return body.to_string(),
+    };
+    for path in ignore {
+        let tokens = path.split('.').collect::<Vec<_>>();
+        remove(&mut value, &tokens);
+    }
+    serde_json::to_string_pretty(&value).unwrap_or_else(|_| body.to_string())
+}
+
+pub(crate) fn remove(value: &mut Value, tokens: &[&str]) {
+    let (first, rest) = match tokens.split_first() {
+        Some(t) => t,
+        None => return,
+    };
+    match value {
+        Value::Object(o) => {
+            if rest.is_empty() {
+                match *first {
+                    "*" => o.clear(),
+                    k => {
+                        o.remove(k);
+                    }
+                }
+                return;
+            }
+            for (k, v) in o.iter_mut() {
+                if *first == "*" || k == first {
+                    remove(v, rest);
+                }
+            }
+        }
+        Value::Array(a) => {
+            if rest.is_empty() {
+                match *first {
+                    "*" => a.clear(),
+                    i => {
+                        if let Ok(i) = i.parse::<usize>() {
+                            if i < a.len() {
+                                a.remove(i);
+                            }
+                        }
+                    }
+                }
+                return;
+            }
+            for (i, v) in a.iter_mut().enumerate() {
+                if *first == "*" || first.parse::<usize>() == Ok(i) {
+                    remove(v, rest);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Compare the normalized body against the stored snapshot. The
+/// snapshot is written if it doesn't exist yet or update is set.
+pub fn check(cache_dir: &Path, name: &str, normalized: &str, update: bool) -> Result<()> {
+    let path = snapshot_path(cache_dir, name)?;
+    if update || !path.exists() {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, normalized)?;
+        return Ok(());
+    }
+
+    let want = std::fs::read_to_string(&path)?;
+    if want == normalized {
+        return Ok(());
+    }
+    let mut got_lines = normalized.lines();
+    let mut want_lines = want.lines();
+    let mut line = 1;
+    loop {
+        match (got_lines.next(), want_lines.next()) {
+            (Some(g), Some(w)) if g == w => line += 1,
+            (g, w) => {
+                return Err(SnapshotError::Mismatch {
+                    name: name.to_string(),
+                    line,
+                    got: g.unwrap_or_default().trim().to_string(),
+                    want: w.unwrap_or_default().trim().to_string(),
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_ignores_paths() {
+        let body = r#"{"b": 1, "a": {"id": 2, "keep": 3}, "items": [{"ts": 1, "v": 1}, {"ts": 2, "v": 2}]}"#;
+        let normalized = normalize(body, &["a.id".into(), "items.*.ts".into()]);
+        let expected = serde_json::to_string_pretty(&serde_json::json!({
+            "a": {"keep": 3},
+            "b": 1,
+            "items": [{"v": 1}, {"v": 2}],
+        }))
+        .unwrap();
+        assert_eq!(normalized, expected);
+        assert_eq!(normalize("not json", &["a".into()]), "not json");
+    }
+
+    #[test]
+    fn check_snapshot() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        check(dir, "s", "a\nb", false).unwrap();
+        check(dir, "s", "a\nb", false).unwrap();
+        match check(dir, "s", "a\nc", false) {
+            Err(SnapshotError::Mismatch {
+                line, got, want, ..
+            }) => {
+                assert_eq!((line, got.as_str(), want.as_str()), (2, "c", "b"));
+            }
+            r => panic!("expected mismatch, got {:?}", r),
+        }
+        check(dir, "s", "a\nc", true).unwrap();
+        check(dir, "s", "a\nc", false).unwrap();
+
+        assert_eq!(
+            snapshot_path(dir, "users/list").unwrap(),
+            dir.join("snapshots").join("users_list.snap")
+        );
+        assert!(matches!(
+            check(dir, "../../escape", "a", false),
+            Err(SnapshotError::Name(_))
+        ));
+    }
+}
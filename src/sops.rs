@@ -0,0 +1,391 @@
+/// Sops decrypts configuration files encrypted with SOPS and age keys,
+/// so contexts with secrets can be kept in git. Files are decrypted
+/// when they're loaded and never written back.
+use std::io::Read;
+
+use aes_gcm::aead::consts::U32;
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::aes::Aes256;
+use aes_gcm::{AesGcm, KeyInit, Nonce};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde_yaml::{Mapping, Value};
+use sha2::{Digest, Sha512};
+use thiserror::Error;
+
+/// SopsError is the error type for encrypted files.
+#[derive(Error, Debug)]
+pub enum SopsError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("age error: {0}")]
+    Age(String),
+
+    #[error("no age identity: set SOPS_AGE_KEY_FILE or SOPS_AGE_KEY, or add one to ~/.config/sops/age/keys.txt")]
+    NoIdentity,
+
+    #[error("no age identity for any of the recipients: {0}")]
+    NoMatchingIdentity(String),
+
+    #[error("invalid metadata: {0}")]
+    Metadata(String),
+
+    #[error("invalid value at {0}: {1}")]
+    Value(String, String),
+
+    #[error("mac mismatch: the file was changed after it was encrypted")]
+    Mac,
+}
+
+/// Result is the result type for encrypted files.
+pub type Result<T> = std::result::Result<T, SopsError>;
+
+/// Nonces are 32 bytes, which SOPS uses instead of AES-GCM's usual 12.
+type Cipher = AesGcm<Aes256, U32>;
+
+/// Whether SOPS encrypted the document, which adds its metadata under
+/// the `sops` key.
+pub fn is_encrypted(document: &Value) -> bool {
+    document.get("sops").is_some_and(Value::is_mapping)
+}
+
+/// Decrypt the document in place and remove its metadata. The data key
+/// is decrypted with the age identities in `SOPS_AGE_KEY`, the file in
+/// `SOPS_AGE_KEY_FILE` or `~/.config/sops/age/keys.txt`, like `sops`
+/// does.
+pub fn decrypt(document: &mut Value) -> Result<()> {
+    decrypt_with(document, &identities()?)
+}
+
+fn decrypt_with(document: &mut Value, identities: &[Box<dyn age::Identity>]) -> Result<()> {
+    let Some(mapping) = document.as_mapping_mut() else {
+        return Ok(());
+    };
+    let Some(metadata) = mapping.remove("sops") else {
+        return Ok(());
+    };
+    let key = data_key(&metadata, identities)?;
+
+    // The MAC is a hash of the values in the order they're written, so
+    // values can't be removed or moved without the key.
+    let only_encrypted = metadata
+        .get("mac_only_encrypted")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    let mut hash = Sha512::new();
+    decrypt_mapping(mapping, &key, &mut vec![], &mut hash, only_encrypted)?;
+
+    let field = |name: &str| {
+        metadata
+            .get(name)
+            .and_then(Value::as_str)
+            .ok_or_else(|| SopsError::Metadata(format!("missing {}", name)))
+    };
+    let (mac, _) = decrypt_value(&key, field("mac")?, field("lastmodified")?)
+        .map_err(|e| SopsError::Value("sops.mac".into(), e))?;
+    let expected = hash
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<String>();
+    match mac == expected.as_bytes() {
+        true => Ok(()),
+        false => Err(SopsError::Mac),
+    }
+}
+
+/// The age identities the data key can be decrypted with.
+fn identities() -> Result<Vec<Box<dyn age::Identity>>> {
+    let mut keys = std::env::var("SOPS_AGE_KEY").unwrap_or_default();
+    match std::env::var_os("SOPS_AGE_KEY_FILE") {
+        Some(file) => keys.push_str(&format!("\n{}", std::fs::read_to_string(file)?)),
+        None => {
+            let file = crate::profile::config_dir()
+                .unwrap_or_default()
+                .join("sops")
+                .join("age")
+                .join("keys.txt");
+            match std::fs::read_to_string(file) {
+                Ok(contents) => keys.push_str(&format!("\n{}", contents)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+    if keys.trim().is_empty() {
+        return Err(SopsError::NoIdentity);
+    }
+    age::IdentityFile::from_buffer(keys.as_bytes())
+        .map_err(|e| SopsError::Age(e.to_string()))?
+        .into_identities()
+        .map_err(|e| SopsError::Age(e.to_string()))
+}
+
+/// Decrypt the key the values were encrypted with. Each age recipient
+/// has its own copy of it.
+fn data_key(metadata: &Value, identities: &[Box<dyn age::Identity>]) -> Result<Vec<u8>> {
+    let recipients = metadata
+        .get("age")
+        .and_then(Value::as_sequence)
+        .filter(|r| !r.is_empty())
+        .ok_or_else(|| {
+            SopsError::Metadata("no age recipients; only age keys are supported".into())
+        })?;
+    for recipient in recipients {
+        let Some(enc) = recipient.get("enc").and_then(Value::as_str) else {
+            continue;
+        };
+        let decryptor = age::Decryptor::new(age::armor::ArmoredReader::new(enc.as_bytes()))
+            .map_err(|e| SopsError::Age(e.to_string()))?;
+        match decryptor.decrypt(identities.iter().map(|i| i.as_ref())) {
+            Ok(mut reader) => {
+                let mut key = vec![];
+                reader.read_to_end(&mut key)?;
+                return Ok(key);
+            }
+            Err(age::DecryptError::NoMatchingKeys) => continue,
+            Err(e) => return Err(SopsError::Age(e.to_string())),
+        }
+    }
+    let names = recipients
+        .iter()
+        .filter_map(|r| r.get("recipient").and_then(Value::as_str))
+        .collect::<Vec<_>>();
+    Err(SopsError::NoMatchingIdentity(names.join(", ")))
+}
+
+fn decrypt_mapping(
+    mapping: &mut Mapping,
+    key: &[u8],
+    path: &mut Vec<String>,
+    hash: &mut Sha512,
+    only_encrypted: bool,
+) -> Result<()> {
+    for (k, v) in mapping.iter_mut() {
+        path.push(match k {
+            Value::String(s) => s.clone(),
+            Value::Number(n) => n.to_string(),
+            Value::Bool(b) => b.to_string(),
+            _ => String::new(),
+        });
+        decrypt_tree(v, key, path, hash, only_encrypted)?;
+        path.pop();
+    }
+    Ok(())
+}
+
+/// Decrypt the values in the tree. Each is encrypted with its path,
+/// like `contexts:prod:token:`, so it can't be moved to another key.
+/// Items in lists have the path of the list.
+fn decrypt_tree(
+    value: &mut Value,
+    key: &[u8],
+    path: &mut Vec<String>,
+    hash: &mut Sha512,
+    only_encrypted: bool,
+) -> Result<()> {
+    match value {
+        Value::Mapping(m) => decrypt_mapping(m, key, path, hash, only_encrypted),
+        Value::Sequence(s) => s
+            .iter_mut()
+            .try_for_each(|v| decrypt_tree(v, key, path, hash, only_encrypted)),
+        Value::Tagged(t) => decrypt_tree(&mut t.value, key, path, hash, only_encrypted),
+        Value::String(s) if s.starts_with("ENC[") => {
+            let aad = format!("{}:", path.join(":"));
+            let (plaintext, kind) =
+                decrypt_value(key, s, &aad).map_err(|e| SopsError::Value(path.join("."), e))?;
+            hash.update(&plaintext);
+            *value = typed(plaintext, &kind).map_err(|e| SopsError::Value(path.join("."), e))?;
+            Ok(())
+        }
+        _ if only_encrypted => Ok(()),
+        Value::String(s) => {
+            hash.update(s.as_bytes());
+            Ok(())
+        }
+        Value::Number(n) => {
+            hash.update(n.to_string().as_bytes());
+            Ok(())
+        }
+        Value::Bool(b) => {
+            hash.update(if *b { "True" } else { "False" });
+            Ok(())
+        }
+        Value::Null => Ok(()),
+    }
+}
+
+/// Decrypt a value written like
+/// `ENC[AES256_GCM,data:...,iv:...,tag:...,type:str]` and return it
+/// with its type.
+fn decrypt_value(
+    key: &[u8],
+    value: &str,
+    aad: &str,
+) -> std::result::Result<(Vec<u8>, String), String> {
+    let fields = value
+        .strip_prefix("ENC[AES256_GCM,")
+        .and_then(|v| v.strip_suffix(']'))
+        .ok_or("not an AES256_GCM value")?;
+    let (mut data, mut iv, mut tag, mut kind) = (None, None, None, None);
+    for field in fields.split(',') {
+        match field.split_once(':') {
+            Some(("data", v)) => data = Some(v),
+            Some(("iv", v)) => iv = Some(v),
+            Some(("tag", v)) => tag = Some(v),
+            Some(("type", v)) => kind = Some(v),
+            _ => return Err(format!("unknown field: {}", field)),
+        }
+    }
+    let decode = |v: Option<&str>, name: &str| {
+        let v = v.ok_or_else(|| format!("missing {}", name))?;
+        STANDARD
+            .decode(v)
+            .map_err(|e| format!("invalid {}: {}", name, e))
+    };
+    let mut ciphertext = decode(data, "data")?;
+    ciphertext.extend(decode(tag, "tag")?);
+    let iv = decode(iv, "iv")?;
+    if iv.len() != 32 {
+        return Err(format!("iv is {} bytes, not 32", iv.len()));
+    }
+
+    let cipher = Cipher::new_from_slice(key).map_err(|_| "data key isn't 32 bytes")?;
+    let payload = Payload {
+        msg: &ciphertext,
+        aad: aad.as_bytes(),
+    };
+    let plaintext = cipher
+        .decrypt(Nonce::<U32>::from_slice(&iv), payload)
+        .map_err(|_| "can't be decrypted with the data key")?;
+    Ok((plaintext, kind.unwrap_or("str").to_string()))
+}
+
+/// The YAML value of the decrypted text with the type it was written as.
+fn typed(plaintext: Vec<u8>, kind: &str) -> std::result::Result<Value, String> {
+    let text = String::from_utf8(plaintext).map_err(|e| e.to_string())?;
+    match kind {
+        "str" | "bytes" | "comment" => Ok(Value::String(text)),
+        "int" => text
+            .parse::<i64>()
+            .map(Value::from)
+            .map_err(|e| e.to_string()),
+        "float" => text
+            .parse::<f64>()
+            .map(Value::from)
+            .map_err(|e| e.to_string()),
+        "bool" => match text.to_lowercase().as_str() {
+            "true" => Ok(Value::Bool(true)),
+            "false" => Ok(Value::Bool(false)),
+            _ => Err(format!("invalid bool: {}", text)),
+        },
+        _ => Err(format!("unknown type: {}", kind)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aes_gcm::aead::Aead;
+
+    fn encrypt(key: &[u8], plaintext: &str, aad: &str, kind: &str) -> String {
+        let iv: [u8; 32] = rand::random();
+        let payload = Payload {
+            msg: plaintext.as_bytes(),
+            aad: aad.as_bytes(),
+        };
+        let out = Cipher::new_from_slice(key)
+            .unwrap()
+            .encrypt(Nonce::<U32>::from_slice(&iv), payload)
+            .unwrap();
+        let (data, tag) = out.split_at(out.len() - 16);
+        format!(
+            "ENC[AES256_GCM,data:{},iv:{},tag:{},type:{}]",
+            STANDARD.encode(data),
+            STANDARD.encode(iv),
+            STANDARD.encode(tag),
+            kind
+        )
+    }
+
+    #[test]
+    fn decrypt() {
+        let identity = age::x25519::Identity::generate();
+        let key: [u8; 32] = rand::random();
+        let lastmodified = "2026-10-16T00:00:00Z";
+        let mac = Sha512::digest("https://api.example.comabc123443True")
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect::<String>();
+        let mut encrypted: Value = serde_yaml::from_str(&format!(
+            r#"contexts:
+  prod:
+    base_url_unencrypted: https://api.example.com
+    token: {}
+    port: {}
+    verbose: {}
+sops:
+  age:
+    - recipient: {}
+      enc: ""
+  lastmodified: "{}"
+  mac: {}
+  unencrypted_suffix: _unencrypted
+  version: 3.9.0
+"#,
+            encrypt(&key, "abc123", "contexts:prod:token:", "str"),
+            encrypt(&key, "443", "contexts:prod:port:", "int"),
+            encrypt(&key, "True", "contexts:prod:verbose:", "bool"),
+            identity.to_public(),
+            lastmodified,
+            encrypt(&key, &mac, lastmodified, "str"),
+        ))
+        .unwrap();
+        encrypted["sops"]["age"][0]["enc"] =
+            Value::String(age::encrypt_and_armor(&identity.to_public(), &key).unwrap());
+        assert!(is_encrypted(&encrypted));
+
+        let identities: Vec<Box<dyn age::Identity>> = vec![Box::new(identity)];
+        let mut document = encrypted.clone();
+        decrypt_with(&mut document, &identities).unwrap();
+        let expected: Value = serde_yaml::from_str(
+            r#"contexts:
+  prod:
+    base_url_unencrypted: https://api.example.com
+    token: abc123
+    port: 443
+    verbose: true
+"#,
+        )
+        .unwrap();
+        assert_eq!(document, expected);
+
+        // Removing a value breaks the MAC.
+        let mut document = encrypted.clone();
+        document["contexts"]["prod"]
+            .as_mapping_mut()
+            .unwrap()
+            .remove("port");
+        assert!(matches!(
+            decrypt_with(&mut document, &identities),
+            Err(SopsError::Mac)
+        ));
+
+        // Values can't be moved to other keys.
+        let mut document = encrypted.clone();
+        let token = document["contexts"]["prod"]["token"].clone();
+        document["contexts"]["prod"]["port"] = token;
+        assert!(matches!(
+            decrypt_with(&mut document, &identities),
+            Err(SopsError::Value(path, _)) if path == "contexts.prod.port"
+        ));
+
+        let other: Vec<Box<dyn age::Identity>> = vec![Box::new(age::x25519::Identity::generate())];
+        let mut document = encrypted.clone();
+        assert!(matches!(
+            decrypt_with(&mut document, &other),
+            Err(SopsError::NoMatchingIdentity(_))
+        ));
+    }
+}
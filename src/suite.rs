@@ -0,0 +1,127 @@
+/// Suite is used to group tests that run together, like a nightly run,
+/// with the contexts and settings they share.
+use std::collections::HashMap;
+
+use crate::List;
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Implement List for suites.
+impl List for HashMap<String, Suite> {
+    fn headers(&self) -> Vec<String> {
+        vec!["Name".into(), "Tests".into(), "Description".into()]
+    }
+
+    fn values(&self) -> Vec<Vec<String>> {
+        self.iter()
+            .map(|(n, s)| vec![n.clone(), s.tests.len().to_string(), s.description.clone()])
+            .collect()
+    }
+}
+
+/// Order is the order a suite's tests run in.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Order {
+    /// The order the tests are listed in.
+    #[default]
+    Listed,
+    /// Sorted by name.
+    Name,
+    /// A random order each run, to find tests that depend on each
+    /// other.
+    Shuffle,
+}
+
+/// Suite is a named group of tests.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
+pub struct Suite {
+    #[serde(default)]
+    pub description: String,
+    pub tests: Vec<String>,
+    /// The contexts the tests run in. Contexts given when running the
+    /// suite are used after them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub contexts: Vec<String>,
+    #[serde(default)]
+    pub order: Order,
+    /// How many tests run at once. When more than one runs at a time,
+    /// the results are printed once they all finish.
+    #[serde(default = "default_parallel")]
+    pub parallel: usize,
+    /// Requests made once before the tests, like creating a tenant.
+    /// Their responses can be used by every test.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub before: Vec<String>,
+    /// Requests made once after the tests, even when they fail, like
+    /// deleting the tenant.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub after: Vec<String>,
+}
+
+impl Default for Suite {
+    fn default() -> Self {
+        Self {
+            description: String::new(),
+            tests: vec![],
+            contexts: vec![],
+            order: Order::default(),
+            parallel: default_parallel(),
+            before: vec![],
+            after: vec![],
+        }
+    }
+}
+
+fn default_parallel() -> usize {
+    1
+}
+
+impl Suite {
+    /// A suite of the tests, run in order, for running tests that
+    /// aren't in a suite.
+    pub fn new(tests: Vec<String>) -> Self {
+        Self {
+            tests,
+            ..Default::default()
+        }
+    }
+
+    /// The names of the tests in the order they start. With a seed,
+    /// they are shuffled, and the same seed gives the same order.
+    pub fn ordered_tests(&self, seed: Option<u64>) -> Vec<String> {
+        let mut tests = self.tests.clone();
+        match (seed, self.order) {
+            (Some(seed), _) => tests.shuffle(&mut StdRng::seed_from_u64(seed)),
+            (None, Order::Name) => tests.sort(),
+            (None, _) => {}
+        }
+        tests
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ordered_tests() {
+        let mut suite: Suite = serde_yaml::from_str("{tests: [b, c, a], before: [setup]}").unwrap();
+        assert_eq!(suite.parallel, 1);
+        assert_eq!(suite.before, vec!["setup"]);
+        assert!(suite.after.is_empty());
+        assert_eq!(suite.ordered_tests(None), vec!["b", "c", "a"]);
+        suite.order = Order::Name;
+        assert_eq!(suite.ordered_tests(None), vec!["a", "b", "c"]);
+
+        suite.tests = (0..20).map(|i| i.to_string()).collect();
+        let shuffled = suite.ordered_tests(Some(7));
+        assert_eq!(shuffled, suite.ordered_tests(Some(7)));
+        assert_ne!(shuffled, suite.tests);
+        assert_ne!(shuffled, suite.ordered_tests(Some(8)));
+    }
+}
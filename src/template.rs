@@ -0,0 +1,391 @@
+/// Template is used to split strings with `${name}` variables,
+/// `${name(args)}` function calls and `${ ${a} + 1 }` expressions into
+/// segments once so they can be rendered many times cheaply.
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock, RwLock};
+
+use crate::{expression, function};
+
+use regex::Regex;
+
+static VARIABLE: OnceLock<Regex> = OnceLock::new();
+static CALL: OnceLock<Regex> = OnceLock::new();
+
+fn variable_regex() -> &'static Regex {
+    VARIABLE.get_or_init(|| Regex::new(r"^[-.\w\[\]*]+$").unwrap())
+}
+
+fn call_regex() -> &'static Regex {
+    CALL.get_or_init(|| Regex::new(r"(?s)^([A-Za-z_][\w.]*)\s*\((.*)\)$").unwrap())
+}
+
+/// Segment is a part of a template.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Segment {
+    /// Text that is output as is.
+    Literal(String),
+    /// The name of a variable that is replaced when rendering.
+    Variable(String),
+    /// A function called with its arguments when rendering. The
+    /// arguments are templates themselves, so they can use variables.
+    Call(String, Vec<Template>),
+    /// Arithmetic evaluated once the templates in it are rendered.
+    Expression(Template),
+}
+
+/// Template is a string split into literal, variable and call segments.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    /// Split the string into its segments. Text in `${...}` that isn't a
+    /// variable name or a function call is kept as a literal.
+    pub fn parse(s: &str) -> Self {
+        let mut segments = vec![];
+        let mut literal = String::new();
+        let mut rest = s;
+        while let Some(start) = rest.find("${") {
+            literal.push_str(&rest[..start]);
+            let after = &rest[start + 2..];
+            let end = match closing_brace(after) {
+                Some(end) => end,
+                None => {
+                    rest = &rest[start..];
+                    break;
+                }
+            };
+            match segment(after[..end].trim()) {
+                Some(segment) => {
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(segment);
+                }
+                None => literal.push_str(&rest[start..start + end + 3]),
+            }
+            rest = &after[end + 1..];
+        }
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Self { segments }
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// The names of the variables in the template, including those in
+    /// the arguments of calls.
+    pub fn variables(&self) -> Vec<&str> {
+        self.segments
+            .iter()
+            .flat_map(|segment| match segment {
+                Segment::Literal(_) => vec![],
+                Segment::Variable(name) => vec![name.as_str()],
+                Segment::Call(_, args) => args.iter().flat_map(|a| a.variables()).collect(),
+                Segment::Expression(t) => t.variables(),
+            })
+            .collect()
+    }
+
+    /// Join the segments, replacing variables with the value from
+    /// lookup and calls with their result, or nothing if there is none.
+    pub fn render<F>(&self, lookup: F) -> String
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        self.render_with(&lookup)
+    }
+
+    fn render_with(&self, lookup: &dyn Fn(&str) -> Option<String>) -> String {
+        let mut output = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(s) => output.push_str(s),
+                Segment::Variable(name) => output.push_str(&lookup(name).unwrap_or_default()),
+                Segment::Call(name, args) => {
+                    let args = args
+                        .iter()
+                        .map(|a| a.render_with(lookup))
+                        .collect::<Vec<_>>();
+                    match function::call(name, &args) {
+                        Ok(value) => output.push_str(&value),
+                        Err(e) => {
+                            tracing::warn!(function = %name, error = %e, "template function failed")
+                        }
+                    }
+                }
+                Segment::Expression(t) => match expression::evaluate(&t.render_with(lookup)) {
+                    Ok(value) => output.push_str(&value),
+                    Err(e) => tracing::warn!(error = %e, "template expression failed"),
+                },
+            }
+        }
+        output
+    }
+}
+
+impl std::fmt::Display for Segment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Segment::Literal(s) => write!(f, "{}", s),
+            Segment::Variable(name) => write!(f, "${{{}}}", name),
+            Segment::Call(name, args) => {
+                let args = args
+                    .iter()
+                    .map(|a| match a.to_string() {
+                        a if a.contains(',') && a.contains('"') => format!("'{}'", a),
+                        a if a.contains(',') => format!("\"{}\"", a),
+                        a => a,
+                    })
+                    .collect::<Vec<_>>();
+                write!(f, "${{{}({})}}", name, args.join(", "))
+            }
+            Segment::Expression(t) => write!(f, "${{ {} }}", t),
+        }
+    }
+}
+
+impl std::fmt::Display for Template {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.segments.iter().try_for_each(|s| write!(f, "{}", s))
+    }
+}
+
+/// The index of the `}` that closes a `${`, given the text after it.
+/// Braces in between, like those of nested templates or JSON arguments,
+/// must be balanced.
+fn closing_brace(s: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' if depth == 0 => return Some(i),
+            '}' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Parse the text inside `${...}` as a variable or a call.
+fn segment(inner: &str) -> Option<Segment> {
+    if variable_regex().is_match(inner) {
+        return Some(Segment::Variable(inner.to_string()));
+    }
+    let c = match call_regex().captures(inner) {
+        Some(c) => c,
+        None => {
+            // Check the expression with its templates as numbers.
+            let template = Template::parse(inner);
+            let numbers = template
+                .segments()
+                .iter()
+                .map(|s| match s {
+                    Segment::Literal(s) => s.clone(),
+                    _ => "0".into(),
+                })
+                .collect::<String>();
+            return expression::is_expression(&numbers).then_some(Segment::Expression(template));
+        }
+    };
+    let args = split_args(&c[2])?
+        .iter()
+        .map(|a| Template::parse(&unquote(a)))
+        .collect();
+    Some(Segment::Call(c[1].to_string(), args))
+}
+
+/// Split function arguments on the commas that aren't in quotes,
+/// brackets or nested templates. Unbalanced arguments aren't a call.
+fn split_args(s: &str) -> Option<Vec<String>> {
+    if s.trim().is_empty() {
+        return Some(vec![]);
+    }
+    let mut args = vec![];
+    let mut depth = 0i32;
+    let mut quote = None;
+    let mut current = String::new();
+    for c in s.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '(' | '[' | '{') => depth += 1,
+            (None, ')' | ']' | '}') => depth -= 1,
+            (None, ',') if depth == 0 => {
+                args.push(current.trim().to_string());
+                current.clear();
+                continue;
+            }
+            _ => {}
+        }
+        if depth < 0 {
+            return None;
+        }
+        current.push(c);
+    }
+    if depth != 0 || quote.is_some() {
+        return None;
+    }
+    args.push(current.trim().to_string());
+    Some(args)
+}
+
+/// Remove the quotes around a quoted argument.
+fn unquote(s: &str) -> String {
+    let quoted = s.len() >= 2
+        && (s.starts_with('"') && s.ends_with('"') || s.starts_with('\'') && s.ends_with('\''));
+    match quoted {
+        true => s[1..s.len() - 1].to_string(),
+        false => s.to_string(),
+    }
+}
+
+/// The most parsed templates kept. Data-driven strings, like URLs with
+/// ids in them, would otherwise grow the cache for as long as a monitor
+/// or benchmark runs.
+const MAX_TEMPLATES: usize = 10_000;
+
+/// Templates is a cache of parsed templates shared by clones, so each
+/// distinct string is only parsed once. The cache is emptied when it's
+/// full.
+#[derive(Clone, Debug, Default)]
+pub struct Templates {
+    cache: Arc<RwLock<HashMap<String, Arc<Template>>>>,
+}
+
+impl Templates {
+    /// Get the parsed template for the string, parsing it if it hasn't
+    /// been seen before.
+    pub fn get(&self, s: &str) -> Arc<Template> {
+        if let Some(template) = self.cache.read().unwrap().get(s) {
+            return template.clone();
+        }
+        let mut cache = self.cache.write().unwrap();
+        if cache.len() >= MAX_TEMPLATES && !cache.contains_key(s) {
+            cache.clear();
+        }
+        cache
+            .entry(s.to_string())
+            .or_insert_with(|| Arc::new(Template::parse(s)))
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn variable_regex() {
+        let tests = vec![
+            ("Hello, ${name}", vec!["name"]),
+            ("Hello, ${   name  }! how are you?", vec!["name"]),
+            (
+                "Hello, ${ name }! How are you, ${    name}?",
+                vec!["name", "name"],
+            ),
+            (
+                "Hello, ${name }! How are you, ${name    }?",
+                vec!["name", "name"],
+            ),
+            (
+                "Hello, ${ cheese_and_toast }${toast_and_cheese}",
+                vec!["cheese_and_toast", "toast_and_cheese"],
+            ),
+            ("howdy, ${ responses.get.name }", vec!["responses.get.name"]),
+            (
+                "${response.list.items[*].id}",
+                vec!["response.list.items[*].id"],
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let actual = Template::parse(input)
+                .segments()
+                .iter()
+                .filter_map(|s| match s {
+                    Segment::Variable(name) => Some(name.clone()),
+                    Segment::Literal(_) | Segment::Call(..) | Segment::Expression(_) => None,
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(actual, expected);
+        }
+    }
+
+    #[test]
+    fn calls() {
+        let template = Template::parse(r#"a${ f(${x}, "b, c", {"k": 1}) }${g()}${h(}"#);
+        assert_eq!(
+            template.segments(),
+            &[
+                Segment::Literal("a".into()),
+                Segment::Call(
+                    "f".into(),
+                    vec![
+                        Template::parse("${x}"),
+                        Template::parse("b, c"),
+                        Template::parse(r#"{"k": 1}"#),
+                    ]
+                ),
+                Segment::Call("g".into(), vec![]),
+                Segment::Literal("${h(}".into()),
+            ]
+        );
+        assert_eq!(template.variables(), vec!["x"]);
+        assert_eq!(
+            template.to_string(),
+            r#"a${f(${x}, "b, c", {"k": 1})}${g()}${h(}"#
+        );
+    }
+
+    #[test]
+    fn expressions() {
+        let lookup = |n: &str| match n {
+            "count" => Some("4".to_string()),
+            "name" => Some("bob".to_string()),
+            _ => None,
+        };
+        let render = |s: &str| Template::parse(s).render(lookup);
+        assert_eq!(render("${ ${count} + 1 }"), "5");
+        assert_eq!(render("${ (${count} - 1) * 2 }"), "6");
+        assert_eq!(render("${upper(${name})}"), "BOB");
+        assert_eq!(render("${substr(${upper(${name})}, 1)}"), "OB");
+        assert_eq!(render("${ ${name} + 1 }"), "");
+        assert_eq!(render("${ a + b }"), "${ a + b }");
+    }
+
+    #[test]
+    fn render() {
+        let template = Template::parse("Hello, ${ name }! ${missing}${$}");
+        assert_eq!(
+            template.segments(),
+            &[
+                Segment::Literal("Hello, ".into()),
+                Segment::Variable("name".into()),
+                Segment::Literal("! ".into()),
+                Segment::Variable("missing".into()),
+                Segment::Literal("${$}".into()),
+            ]
+        );
+        assert_eq!(
+            template.render(|n| (n == "name").then(|| "World".to_string())),
+            "Hello, World! ${$}"
+        );
+
+        let templates = Templates::default();
+        assert!(Arc::ptr_eq(
+            &templates.get("${a}"),
+            &templates.clone().get("${a}")
+        ));
+        for i in 0..MAX_TEMPLATES {
+            templates.get(&format!("${{a}}/{}", i));
+        }
+        assert_eq!(templates.cache.read().unwrap().len(), 1);
+    }
+}
@@ -1,9 +1,16 @@
 use std::collections::HashMap;
-use std::io::Stdout;
-use std::time::Instant;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use crate::{Applicator, Config, List, Response, Results, State};
+use crate::events::millis;
+use crate::{
+    condition, diff, snapshot, transform, Applicator, Certificate, Config, Correlation, Event,
+    Events, List, Redactor, Request, Response, Results, State, Transform, WriteBehind,
+};
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
@@ -46,63 +53,260 @@ pub enum TestError {
 
     #[error("results error: {0}")]
     ResultsErrro(#[from] crate::ResultsError),
+
+    #[error("response not cached: {0}")]
+    ResponseNotCached(String),
+
+    #[error("condition error: {0}")]
+    Condition(#[from] crate::ConditionError),
+
+    #[error("foreach did not resolve to an array: {0}")]
+    Foreach(String),
+
+    #[error("transform error: {0}")]
+    Transform(#[from] crate::TransformError),
 }
 
 /// Result is the result type for tests.
 pub type Result<T> = std::result::Result<T, TestError>;
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+/// TestOptions changes how tests are executed.
+#[derive(Clone, Debug, Default)]
+pub struct TestOptions {
+    /// Answer each step from the cached responses instead of making
+    /// the request.
+    pub offline: bool,
+    /// The folder where snapshots and other test data are stored.
+    pub cache_dir: PathBuf,
+    /// Replace stored snapshots with the current responses.
+    pub update_snapshots: bool,
+    /// How long to wait between steps.
+    pub delay: Option<Duration>,
+    /// Masks secrets in the results.
+    pub redactor: Redactor,
+    /// When set, the last response of each request is kept here with
+    /// its secrets masked.
+    pub responses: Option<Arc<WriteBehind>>,
+    /// The correlation header sent with every request, checked by the
+    /// `correlation_id_echoed` assert.
+    pub correlation: Option<Correlation>,
+    /// When set, what happens during the run is written here.
+    pub events: Option<Arc<Events>>,
+    /// Redraw the results while a request is running, for when they're
+    /// written to a terminal.
+    pub live: bool,
+}
+
+impl TestOptions {
+    /// Write the event when events are being written.
+    pub fn send(&self, event: Event) {
+        if let Some(events) = &self.events {
+            events.send(event);
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 pub struct Test {
     pub description: String,
     pub steps: Vec<Step>,
+    /// Flaky tests are run again when they fail, up to `retries` more
+    /// times, and pass as flaky if a later run passes.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub flaky: bool,
+    #[serde(
+        default = "default_retries",
+        skip_serializing_if = "is_default_retries"
+    )]
+    pub retries: usize,
+    /// The test fails when it takes longer than this (e.g. 2s).
+    #[serde(
+        default,
+        with = "humantime_serde",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schemars(with = "Option<String>")]
+    pub max_duration: Option<Duration>,
+}
+
+impl Default for Test {
+    fn default() -> Self {
+        Self {
+            description: String::new(),
+            steps: vec![],
+            flaky: false,
+            retries: default_retries(),
+            max_duration: None,
+        }
+    }
+}
+
+fn default_retries() -> usize {
+    2
+}
+
+fn is_default_retries(retries: &usize) -> bool {
+    *retries == default_retries()
 }
 
 impl Test {
+    /// The test's definition for tools to read. When requests are
+    /// given, each step also has the definition of its request as
+    /// `request_definition`.
+    pub fn definition(
+        &self,
+        requests: Option<&HashMap<String, Request>>,
+    ) -> Result<serde_yaml::Value> {
+        let mut definition = serde_yaml::to_value(self)?;
+        let (requests, steps) = match (requests, definition.get_mut("steps")) {
+            (Some(r), Some(serde_yaml::Value::Sequence(steps))) => (r, steps),
+            _ => return Ok(definition),
+        };
+        for (step, value) in self.steps.iter().zip(steps.iter_mut()) {
+            let request = requests
+                .get(&step.request)
+                .ok_or_else(|| TestError::RequestNotFound(step.request.clone()))?;
+            if let serde_yaml::Value::Mapping(m) = value {
+                m.insert("request_definition".into(), serde_yaml::to_value(request)?);
+            }
+        }
+        Ok(definition)
+    }
+
+    /// Run the test, adding its results. Flaky tests that fail are run
+    /// again with fresh results until they pass or run out of retries.
     pub async fn execute(
         &self,
         name: String,
         cfg: &Config,
         context: &HashMap<String, String>,
         results: &mut Results,
-        stdout: &mut Stdout,
+        stdout: &mut impl Write,
+        options: &TestOptions,
+    ) -> Result<()> {
+        let names = vec![results.name.clone(), name.clone()];
+        let attempts = match self.flaky {
+            true => self.retries + 1,
+            false => 1,
+        };
+        let now = Instant::now();
+        for attempt in 1..=attempts {
+            if attempt > 1 {
+                tracing::warn!(test = %name, attempt, "retrying flaky test");
+                results.clear(stdout)?;
+                results.children.retain(|c| c.name != name);
+            }
+            let result = self
+                .attempt(name.clone(), cfg, context, results, stdout, options)
+                .await;
+            let failed = result.is_err()
+                || results
+                    .find_mut(&names)
+                    .is_some_and(|r| !r.failures().is_empty());
+            if failed && attempt < attempts {
+                continue;
+            }
+            match &result {
+                Err(e) => {
+                    let message = options.redactor.text(&e.to_string());
+                    results.update(&names, State::Failed(message), now)?;
+                    results.output(stdout, "")?;
+                }
+                Ok(_) if attempt > 1 && !failed => {
+                    results.update(&names, State::FlakyPass(attempt), now)?;
+                    results.output(stdout, "")?;
+                }
+                Ok(_) => {}
+            }
+            if let Some(test) = results.find_mut(&names) {
+                options.send(Event::TestFinished {
+                    test: name,
+                    state: test.state.clone(),
+                    duration_ms: millis(test.duration),
+                });
+            }
+            return result;
+        }
+        Ok(())
+    }
+
+    async fn attempt(
+        &self,
+        name: String,
+        cfg: &Config,
+        context: &HashMap<String, String>,
+        results: &mut Results,
+        stdout: &mut impl Write,
+        options: &TestOptions,
     ) -> Result<()> {
         results.add_results(Results::from_test(&name, self));
-        results.print(stdout, "")?;
         let mut names = vec![results.name.clone(), name];
         let test_now = Instant::now();
-        let mut app = Applicator::new(context.clone(), cfg.responses.clone());
-        for step in &self.steps {
+        results.update(&names, State::Running, test_now)?;
+        results.print(stdout, "")?;
+        let mut app = cfg.applicator(context.clone());
+        for (i, step) in self.steps.iter().enumerate() {
+            if let (Some(delay), true) = (options.delay, i > 0) {
+                tokio::time::sleep(delay).await;
+            }
             let step_now = Instant::now();
             names.push(step.name.clone());
-            let mut request = match cfg.requests.get(&step.request) {
-                Some(r) => r.clone(),
+            tracing::info!(test = %names[1], step = %step.name, "running step");
+            if let Some(when) = &step.when {
+                if !condition::evaluate(&app.apply(when))? {
+                    results.update(
+                        &names,
+                        State::Skipped(format!("condition '{}' was false", when)),
+                        step_now,
+                    )?;
+                    results.output(stdout, "")?;
+                    names.pop();
+                    continue;
+                }
+            }
+            results.update(&names, State::Running, step_now)?;
+            results.output(stdout, "")?;
+            match &step.foreach {
                 None => {
-                    // TODO (?) return a test result here as well?
-                    return Err(TestError::RequestNotFound(step.request.clone()));
+                    step.run(cfg, &mut app, results, &mut names, stdout, options)
+                        .await?
                 }
-            };
-            request.apply(&app);
-
-            let resp: Response = request.request().await.map_err(TestError::RequestError)?;
-            // Save the response incase it is used by a later request.
-            app.add_response(step.request.clone(), resp.clone());
-
-            for assert in &step.asserts {
-                let assert_now = Instant::now();
-                names.push(format!("{}", assert));
-                match assert.execute(&resp) {
-                    Ok(_) => results.update(&names, State::Passed, assert_now),
-                    Err(e) => results.update(&names, State::Failed(e.to_string()), assert_now),
-                };
+                Some(foreach) => {
+                    let items = foreach_items(&app.apply(foreach))?;
 
-                results.output(stdout, "")?;
-                names.pop();
+                    // The tree grows, so clear it before adding the iterations.
+                    results.clear(stdout)?;
+                    if let Some(node) = results.find_mut(&names) {
+                        node.children = (0..items.len())
+                            .map(|i| {
+                                let mut iteration = Results::new(&format!("{}[{}]", step.name, i));
+                                for assert in &step.asserts {
+                                    iteration.add(&format!("{}", assert));
+                                }
+                                iteration
+                            })
+                            .collect();
+                    }
+                    results.print(stdout, "")?;
+
+                    for (i, item) in items.into_iter().enumerate() {
+                        let iteration_now = Instant::now();
+                        app.set("item", item);
+                        app.set("index", i.to_string());
+                        names.push(format!("{}[{}]", step.name, i));
+                        step.run(cfg, &mut app, results, &mut names, stdout, options)
+                            .await?;
+                        results.update(&names, State::Passed, iteration_now)?;
+                        results.output(stdout, "")?;
+                        names.pop();
+                    }
+                }
             }
-            results.update(&names, State::Passed, step_now);
+            results.update(&names, within(step.max_duration, step_now), step_now)?;
             results.output(stdout, "")?;
             names.pop();
         }
-        results.update(&names, State::Passed, test_now);
+        results.update(&names, within(self.max_duration, test_now), test_now)?;
         results.output(stdout, "")?;
         Ok(())
     }
@@ -125,11 +329,211 @@ impl std::fmt::Display for Test {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Step {
     pub name: String,
     pub request: String,
+    /// The step only runs if the condition is true once templates are
+    /// applied. Otherwise it is skipped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub when: Option<String>,
+    /// When set, the step runs once for each element of the array the
+    /// template resolves to. The element is available as `${item}` and
+    /// its position as `${index}`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub foreach: Option<String>,
+    /// Transforms applied in order to the response body before the
+    /// asserts run, like `sort_array($.items, id)`. Later steps still see
+    /// the original response.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub transforms: Vec<Transform>,
     pub asserts: Vec<Assert>,
+    /// The step fails when it takes longer than this, including every
+    /// iteration of a foreach.
+    #[serde(
+        default,
+        with = "humantime_serde",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schemars(with = "Option<String>")]
+    pub max_duration: Option<Duration>,
+}
+
+/// Passed, or failed if it's been longer than the max duration since the
+/// start.
+fn within(max_duration: Option<Duration>, start: Instant) -> State {
+    let elapsed = start.elapsed();
+    match max_duration {
+        Some(max) if elapsed > max => State::Failed(format!(
+            "took {:?}, longer than the max duration of {:?}",
+            elapsed, max
+        )),
+        _ => State::Passed,
+    }
+}
+
+/// How often a running step is redrawn.
+const TICK: Duration = Duration::from_millis(100);
+
+impl Step {
+    /// Make the step's request and run its asserts against the response.
+    async fn run(
+        &self,
+        cfg: &Config,
+        app: &mut Applicator,
+        results: &mut Results,
+        names: &mut Vec<String>,
+        stdout: &mut impl Write,
+        options: &TestOptions,
+    ) -> Result<()> {
+        let mut request = match cfg.requests.get(&self.request) {
+            Some(r) => r.clone(),
+            None => {
+                // TODO (?) return a test result here as well?
+                return Err(TestError::RequestNotFound(self.request.clone()));
+            }
+        };
+        request.try_apply(app).map_err(TestError::RequestError)?;
+
+        let resp: Response = match options.offline {
+            true => cfg
+                .responses
+                .get(&self.request)
+                .cloned()
+                .ok_or_else(|| TestError::ResponseNotCached(self.request.clone()))?,
+            false => {
+                options.send(Event::RequestSent {
+                    request: self.request.clone(),
+                    method: request.method.clone(),
+                    url: options.redactor.text(&request.url),
+                    test: Some(names[1].clone()),
+                });
+                let request_now = Instant::now();
+
+                // Redraw while waiting so a slow request shows it's running.
+                let response = request.request();
+                tokio::pin!(response);
+                let mut ticks = tokio::time::interval(TICK);
+                let response = loop {
+                    tokio::select! {
+                        response = &mut response => break response.map_err(TestError::RequestError)?,
+                        _ = ticks.tick(), if options.live => results.output(stdout, "")?,
+                    }
+                };
+                options.send(Event::ResponseReceived {
+                    request: self.request.clone(),
+                    status_code: response.status_code,
+                    duration_ms: millis(request_now.elapsed()),
+                    test: Some(names[1].clone()),
+                });
+                response
+            }
+        };
+        // Save the response incase it is used by a later request.
+        app.add_response(self.request.clone(), resp.clone());
+        if let Some(responses) = &options.responses {
+            responses.insert(&self.request, options.redactor.response(&resp));
+        }
+        let resp = match self.transforms.is_empty() {
+            true => resp,
+            false => {
+                let mut resp = resp;
+                resp.set_body(transform::apply_all(&self.transforms, &resp.body)?);
+                resp
+            }
+        };
+
+        // Snapshots are compared with the sanitized response, like the
+        // cached one, so volatile fields don't change them.
+        let sanitized = match request.sanitize.is_empty() {
+            true => None,
+            false => Some(resp.sanitize(&request.sanitize)),
+        };
+
+        // Asserts see the request's scopes and defaults, like the request.
+        let own = request.applicator(app);
+        let assert_app = own.as_ref().unwrap_or(app);
+        for assert in &self.asserts {
+            let assert_now = Instant::now();
+            names.push(format!("{}", assert));
+            let mut assert = assert.clone();
+            assert.apply(assert_app);
+            let resp = match (&assert, &sanitized) {
+                (Assert::MatchesSnapshot { .. }, Some(sanitized)) => sanitized,
+                _ => &resp,
+            };
+            let (test, step, name) = (
+                Some(names[1].clone()),
+                Some(names[names.len() - 2].clone()),
+                names[names.len() - 1].clone(),
+            );
+            match assert.execute(resp, options) {
+                Ok(_) => {
+                    options.send(Event::AssertPassed {
+                        request: self.request.clone(),
+                        assert: name,
+                        test,
+                        step,
+                    });
+                    results.update(names, State::Passed, assert_now)?
+                }
+                Err(e) => {
+                    let error = options.redactor.text(&e.to_string());
+                    options.send(Event::AssertFailed {
+                        request: self.request.clone(),
+                        assert: name,
+                        error: error.clone(),
+                        test,
+                        step,
+                    });
+                    results.update(names, State::Failed(error), assert_now)?
+                }
+            };
+
+            results.output(stdout, "")?;
+            names.pop();
+        }
+        Ok(())
+    }
+}
+
+/// The certificate of the response or an error if it didn't use TLS.
+fn certificate(response: &Response) -> Result<&Certificate> {
+    response
+        .certificate
+        .as_ref()
+        .ok_or_else(|| TestError::AssertError("response has no tls certificate".into()))
+}
+
+/// The error for a key that isn't in the body, noting when the body was
+/// truncated since the key may be in the part that wasn't kept.
+fn key_not_found(key: &str, response: &Response) -> TestError {
+    match &response.body_file {
+        Some(file) => TestError::AssertError(format!(
+            "key '{}' not found in request, the body was truncated (the whole body is in {})",
+            key,
+            file.path.display()
+        )),
+        None => TestError::AssertError(format!("key '{}' not found in request", key)),
+    }
+}
+
+/// Parse the items of a foreach. The value should be a JSON array.
+/// String elements are used as is and other elements as JSON.
+fn foreach_items(value: &str) -> Result<Vec<String>> {
+    if value.trim().is_empty() {
+        return Ok(vec![]);
+    }
+    match serde_json::from_str::<serde_json::Value>(value) {
+        Ok(serde_json::Value::Array(items)) => Ok(items
+            .into_iter()
+            .map(|i| match i {
+                serde_json::Value::String(s) => s,
+                i => i.to_string(),
+            })
+            .collect()),
+        _ => Err(TestError::Foreach(value.to_string())),
+    }
 }
 
 impl std::fmt::Display for Step {
@@ -150,22 +554,116 @@ impl std::fmt::Display for Step {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum Assert {
-    StatusCode { value: u16 },
-    HeaderContains { key: String, value: String },
-    HeaderEquals { key: String, value: String },
-    Contains { key: String, value: String },
-    Equals { key: String, value: String },
-    NotEquals { key: String, value: String },
-    HasPrefix { key: String, value: String },
-    HasSuffix { key: String, value: String },
-    Regex { key: String, value: String },
+    StatusCode {
+        value: u16,
+    },
+    HeaderContains {
+        key: String,
+        value: String,
+    },
+    HeaderEquals {
+        key: String,
+        value: String,
+    },
+    Contains {
+        key: String,
+        value: String,
+    },
+    Equals {
+        key: String,
+        value: String,
+    },
+    NotEquals {
+        key: String,
+        value: String,
+    },
+    HasPrefix {
+        key: String,
+        value: String,
+    },
+    HasSuffix {
+        key: String,
+        value: String,
+    },
+    Regex {
+        key: String,
+        value: String,
+    },
+    /// Compare the value at the key with the value as JSON, ignoring
+    /// formatting and the order of object keys.
+    JsonEquals {
+        key: String,
+        value: String,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        ignore_array_order: bool,
+    },
+    MessageReceived {
+        value: String,
+    },
+    MessageCount {
+        value: usize,
+    },
+    /// The raw body contains the value, whatever its format.
+    BodyContains {
+        value: String,
+    },
+    /// The number of non-empty lines in the body, like the records in
+    /// newline delimited JSON.
+    LineCount {
+        value: usize,
+    },
+    MatchesSnapshot {
+        name: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        ignore: Vec<String>,
+    },
+    /// The server's certificate is valid for at least this many more
+    /// days.
+    CertificateExpiresAfter {
+        days: u64,
+    },
+    /// The server's certificate is valid for the host name.
+    CertificateHasName {
+        value: String,
+    },
+    /// The response has the correlation header with the ID the request
+    /// was sent with.
+    CorrelationIdEchoed,
 }
 
 impl Assert {
-    pub fn execute(&self, response: &Response) -> Result<()> {
+    /// Apply the templates in the keys and values of the assert, which
+    /// lets them refer to context variables and other responses.
+    pub fn apply(&mut self, app: &Applicator) {
+        match self {
+            Assert::HeaderContains { key, value }
+            | Assert::HeaderEquals { key, value }
+            | Assert::Contains { key, value }
+            | Assert::Equals { key, value }
+            | Assert::NotEquals { key, value }
+            | Assert::HasPrefix { key, value }
+            | Assert::HasSuffix { key, value }
+            | Assert::Regex { key, value }
+            | Assert::JsonEquals { key, value, .. } => {
+                *key = app.apply(key);
+                *value = app.apply(value);
+            }
+            Assert::MessageReceived { value }
+            | Assert::BodyContains { value }
+            | Assert::CertificateHasName { value } => *value = app.apply(value),
+            Assert::StatusCode { .. }
+            | Assert::CertificateExpiresAfter { .. }
+            | Assert::MessageCount { .. }
+            | Assert::LineCount { .. }
+            | Assert::MatchesSnapshot { .. }
+            | Assert::CorrelationIdEchoed => {}
+        }
+    }
+
+    pub fn execute(&self, response: &Response, options: &TestOptions) -> Result<()> {
         match self {
             Assert::StatusCode { value } => {
                 if response.status_code != *value {
@@ -202,100 +700,192 @@ impl Assert {
             Assert::Contains { key, value } => {
                 let result = response
                     .find_path_in_body(key)
-                    .ok_or(TestError::AssertError(format!(
-                        "key '{}' not found in request",
-                        key
-                    )))?;
+                    .ok_or_else(|| key_not_found(key, response))?;
                 if !result.contains(value) {
                     return Err(TestError::AssertError(format!(
                         "body '{}' got '{}', does not contain '{}'",
                         key.clone(),
-                        result,
-                        value.clone(),
+                        diff::truncate(&result),
+                        diff::truncate(value),
                     )));
                 }
             }
             Assert::Equals { key, value } => {
                 let result = response
                     .find_path_in_body(key)
-                    .ok_or(TestError::AssertError(format!(
-                        "key '{}' not found in request",
-                        key
-                    )))?;
+                    .ok_or_else(|| key_not_found(key, response))?;
                 if result != *value {
                     return Err(TestError::AssertError(format!(
-                        "body '{}' got '{}', want '{}'",
-                        key.clone(),
-                        result,
-                        value.clone(),
+                        "body '{}' {}",
+                        key,
+                        diff::describe(value, &result),
                     )));
                 }
             }
             Assert::NotEquals { key, value } => {
                 let result = response
                     .find_path_in_body(key)
-                    .ok_or(TestError::AssertError(format!(
-                        "key '{}' not found in request",
-                        key
-                    )))?;
+                    .ok_or_else(|| key_not_found(key, response))?;
                 if result == *value {
                     return Err(TestError::AssertError(format!(
                         "body '{}' got '{}', did not want '{}'",
                         key.clone(),
-                        result,
-                        value.clone(),
+                        diff::truncate(&result),
+                        diff::truncate(value),
                     )));
                 }
             }
             Assert::HasPrefix { key, value } => {
                 let result = response
                     .find_path_in_body(key)
-                    .ok_or(TestError::AssertError(format!(
-                        "key '{}' not found in request",
-                        key
-                    )))?;
+                    .ok_or_else(|| key_not_found(key, response))?;
                 if !result.starts_with(value) {
                     return Err(TestError::AssertError(format!(
                         "body '{}' got '{}', does not have prefix '{}'",
                         key.clone(),
-                        result,
-                        value.clone(),
+                        diff::truncate(&result),
+                        diff::truncate(value),
                     )));
                 }
             }
             Assert::HasSuffix { key, value } => {
                 let result = response
                     .find_path_in_body(key)
-                    .ok_or(TestError::AssertError(format!(
-                        "key '{}' not found in request",
-                        key
-                    )))?;
+                    .ok_or_else(|| key_not_found(key, response))?;
                 if !result.ends_with(value) {
                     return Err(TestError::AssertError(format!(
                         "body '{}' got '{}', does not have suffix '{}'",
                         key.clone(),
-                        result,
-                        value.clone(),
+                        diff::truncate(&result),
+                        diff::truncate(value),
                     )));
                 }
             }
             Assert::Regex { key, value } => {
                 let result = response
                     .find_path_in_body(key)
-                    .ok_or(TestError::AssertError(format!(
-                        "key '{}' not found in request",
-                        key
-                    )))?;
+                    .ok_or_else(|| key_not_found(key, response))?;
                 let re = regex::Regex::new(value).map_err(TestError::RegexError)?;
                 if !re.is_match(&result) {
                     return Err(TestError::AssertError(format!(
                         "body '{}' got '{}', does not match regex '{}'",
                         key.clone(),
-                        result,
-                        value.clone(),
+                        diff::truncate(&result),
+                        diff::truncate(value),
+                    )));
+                }
+            }
+            Assert::MessageReceived { value } => {
+                if !response.messages.iter().any(|m| m.contains(value)) {
+                    return Err(TestError::AssertError(format!(
+                        "no message received containing '{}'",
+                        value
+                    )));
+                }
+            }
+            Assert::MessageCount { value } => {
+                if response.messages.len() != *value {
+                    return Err(TestError::AssertError(format!(
+                        "got {} messages, want {}",
+                        response.messages.len(),
+                        value
+                    )));
+                }
+            }
+            Assert::BodyContains { value } => {
+                if !response.body.contains(value) {
+                    return Err(TestError::AssertError(format!(
+                        "body '{}' does not contain '{}'",
+                        diff::truncate(&response.body),
+                        diff::truncate(value),
+                    )));
+                }
+            }
+            Assert::LineCount { value } => {
+                let count = response.lines().len();
+                if count != *value {
+                    return Err(TestError::AssertError(format!(
+                        "got {} lines, want {}",
+                        count, value
+                    )));
+                }
+            }
+            Assert::CertificateExpiresAfter { days } => {
+                let certificate = certificate(response)?;
+                let left = certificate.expires_in().as_secs() / 86400;
+                if left < *days {
+                    return Err(TestError::AssertError(format!(
+                        "certificate expires in {} days ({}), want at least {}",
+                        left,
+                        humantime::format_rfc3339_seconds(certificate.not_after),
+                        days
+                    )));
+                }
+            }
+            Assert::CertificateHasName { value } => {
+                let certificate = certificate(response)?;
+                if !certificate.has_name(value) {
+                    return Err(TestError::AssertError(format!(
+                        "certificate is for {}, not '{}'",
+                        certificate.names.join(", "),
+                        value
                     )));
                 }
             }
+            Assert::CorrelationIdEchoed => {
+                let correlation = options.correlation.as_ref().ok_or_else(|| {
+                    TestError::AssertError("no correlation header was sent".into())
+                })?;
+                let echoed = response
+                    .headers
+                    .iter()
+                    .find(|(k, _)| k.eq_ignore_ascii_case(&correlation.header))
+                    .map(|(_, v)| v);
+                match echoed {
+                    Some(id) if *id == correlation.id => {}
+                    Some(id) => {
+                        return Err(TestError::AssertError(format!(
+                            "header '{}' got '{}', want '{}'",
+                            correlation.header, id, correlation.id
+                        )))
+                    }
+                    None => {
+                        return Err(TestError::AssertError(format!(
+                            "header not echoed: {}",
+                            correlation.header
+                        )))
+                    }
+                }
+            }
+            Assert::JsonEquals {
+                key,
+                value,
+                ignore_array_order,
+            } => {
+                let result = response
+                    .find_value_in_body(key)
+                    .ok_or_else(|| key_not_found(key, response))?;
+                let want: serde_json::Value = serde_json::from_str(value)
+                    .map_err(|e| TestError::AssertError(format!("value is not json: {}", e)))?;
+                let differences = diff::json(&want, &result, *ignore_array_order);
+                if !differences.is_empty() {
+                    return Err(TestError::AssertError(format!(
+                        "body '{}' differs from the expected json:\n{}",
+                        key,
+                        diff::limit(differences)
+                    )));
+                }
+            }
+            Assert::MatchesSnapshot { name, ignore } => {
+                let normalized = snapshot::normalize(&response.body, ignore);
+                snapshot::check(
+                    &options.cache_dir,
+                    name,
+                    &normalized,
+                    options.update_snapshots,
+                )
+                .map_err(|e| TestError::AssertError(e.to_string()))?;
+            }
         }
         Ok(())
     }
@@ -315,6 +905,181 @@ impl std::fmt::Display for Assert {
             Assert::HasPrefix { key, value } => write!(f, "has_prefix({}, {})", key, value),
             Assert::HasSuffix { key, value } => write!(f, "has_suffix({}, {})", key, value),
             Assert::Regex { key, value } => write!(f, "regex({}, {})", key, value),
+            Assert::JsonEquals { key, value, .. } => write!(f, "json_equals({}, {})", key, value),
+            Assert::MessageReceived { value } => write!(f, "message_received({})", value),
+            Assert::MessageCount { value } => write!(f, "message_count == {}", value),
+            Assert::BodyContains { value } => write!(f, "body_contains({})", value),
+            Assert::LineCount { value } => write!(f, "line_count == {}", value),
+            Assert::MatchesSnapshot { name, .. } => write!(f, "matches_snapshot({})", name),
+            Assert::CertificateExpiresAfter { days } => {
+                write!(f, "certificate_expires_after({} days)", days)
+            }
+            Assert::CertificateHasName { value } => write!(f, "certificate_has_name({})", value),
+            Assert::CorrelationIdEchoed => write!(f, "correlation_id_echoed"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn definition() {
+        let test: Test = serde_yaml::from_str(
+            "description: d\nsteps:\n  - {name: s, request: get, asserts: []}",
+        )
+        .unwrap();
+        let requests: HashMap<String, Request> =
+            serde_yaml::from_str("get: {description: '', tags: [], url: /users}").unwrap();
+
+        let definition = test.definition(None).unwrap();
+        assert!(definition["steps"][0].get("request_definition").is_none());
+        let definition = test.definition(Some(&requests)).unwrap();
+        assert_eq!(
+            definition["steps"][0]["request_definition"]["url"],
+            serde_yaml::Value::from("/users")
+        );
+        assert!(matches!(
+            test.definition(Some(&HashMap::new())),
+            Err(TestError::RequestNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn max_duration() {
+        let test: Test = serde_yaml::from_str(
+            "description: d\nmax_duration: 2s\nsteps:\n  - {name: s, request: get, asserts: [], max_duration: 50ms}",
+        )
+        .unwrap();
+        assert_eq!(test.max_duration, Some(Duration::from_secs(2)));
+        assert_eq!(test.steps[0].max_duration, Some(Duration::from_millis(50)));
+        let start = Instant::now() - Duration::from_millis(100);
+        assert_eq!(within(test.max_duration, start), State::Passed);
+        assert!(matches!(
+            within(test.steps[0].max_duration, start),
+            State::Failed(m) if m.contains("longer than the max duration of 50ms")
+        ));
+    }
+
+    #[test]
+    fn foreach() {
+        assert_eq!(
+            foreach_items(r#"[1, "two", {"three": 3}]"#).unwrap(),
+            vec!["1", "two", r#"{"three":3}"#]
+        );
+        assert!(foreach_items("").unwrap().is_empty());
+        assert!(foreach_items("one").is_err());
+    }
+
+    #[test]
+    fn apply_assert() {
+        let baseline = Response {
+            body: r#"{"id": 42}"#.into(),
+            ..Default::default()
+        };
+        let cached: HashMap<_, _> = vec![("baseline".to_string(), baseline)]
+            .into_iter()
+            .collect();
+        let app = Applicator::new(HashMap::new(), HashMap::new()).with_cached(cached);
+
+        let mut assert = Assert::Equals {
+            key: "id".into(),
+            value: "${cached.baseline.body.id}".into(),
+        };
+        assert.apply(&app);
+        let response = Response {
+            body: r#"{"id": 42}"#.into(),
+            ..Default::default()
+        };
+        assert!(assert.execute(&response, &TestOptions::default()).is_ok());
+
+        let context = vec![
+            ("field".to_string(), "user.name".to_string()),
+            ("expected".to_string(), "bob".to_string()),
+        ]
+        .into_iter()
+        .collect();
+        let mut assert = Assert::Equals {
+            key: "${field}".into(),
+            value: "${expected}".into(),
+        };
+        assert.apply(&Applicator::new(context, HashMap::new()));
+        let response = Response {
+            body: r#"{"user": {"name": "bob"}}"#.into(),
+            ..Default::default()
+        };
+        assert!(assert.execute(&response, &TestOptions::default()).is_ok());
+    }
+
+    #[test]
+    fn json_equals() {
+        let response = Response {
+            body: r#"{"user": {"name": "bob", "roles": ["admin", "dev"]}, "id": 1}"#.into(),
+            ..Default::default()
+        };
+        let assert = Assert::JsonEquals {
+            key: "user".into(),
+            value: r#"{"roles": ["admin", "dev"], "name": "bob"}"#.into(),
+            ignore_array_order: false,
+        };
+        assert!(assert.execute(&response, &TestOptions::default()).is_ok());
+
+        let assert = Assert::JsonEquals {
+            key: "".into(),
+            value: r#"{"id": 1.0, "user": {"name": "bob", "roles": ["dev", "admin"]}}"#.into(),
+            ignore_array_order: false,
+        };
+        match assert.execute(&response, &TestOptions::default()) {
+            Err(TestError::AssertError(msg)) => assert!(msg.contains("$.user.roles[0]")),
+            r => panic!("unexpected result: {:?}", r),
         }
+
+        let assert = Assert::JsonEquals {
+            key: "".into(),
+            value: r#"{"id": 1.0, "user": {"name": "bob", "roles": ["dev", "admin"]}}"#.into(),
+            ignore_array_order: true,
+        };
+        assert!(assert.execute(&response, &TestOptions::default()).is_ok());
+
+        // The raw body is searched, whatever its formatting.
+        let contains = |value: &str| Assert::BodyContains {
+            value: value.into(),
+        };
+        let options = TestOptions::default();
+        assert!(contains(r#""roles": ["#)
+            .execute(&response, &options)
+            .is_ok());
+        assert!(contains(r#""roles":["#)
+            .execute(&response, &options)
+            .is_err());
+    }
+
+    #[test]
+    fn correlation_id_echoed() {
+        let mut cfg: Config = serde_yaml::from_str(
+            "requests: {get: {description: '', tags: [], url: /users, headers: {x-request-id: mine}}, list: {description: '', tags: [], url: /users}}",
+        )
+        .unwrap();
+        let correlation = Correlation {
+            header: "X-Request-Id".into(),
+            id: "run-1".into(),
+        };
+        cfg.add_correlation(correlation.clone());
+        assert_eq!(cfg.requests["list"].headers["X-Request-Id"], "run-1");
+        assert_eq!(cfg.requests["get"].headers.len(), 1);
+
+        let assert = Assert::CorrelationIdEchoed;
+        let mut response = Response::default();
+        assert!(assert.execute(&response, &TestOptions::default()).is_err());
+        let options = TestOptions {
+            correlation: Some(correlation),
+            ..Default::default()
+        };
+        assert!(assert.execute(&response, &options).is_err());
+        response
+            .headers
+            .insert("x-request-id".into(), "run-1".into());
+        assert!(assert.execute(&response, &options).is_ok());
     }
 }
@@ -0,0 +1,120 @@
+/// Tls is used to describe the certificate a server presented and how
+/// servers' certificates are verified.
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use x509_parser::extensions::GeneralName;
+
+/// Tls changes how the certificates of HTTPS servers are verified.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Tls {
+    /// A PEM file with more certificates to trust, like a company's own
+    /// certificate authority.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca_cert: Option<PathBuf>,
+    /// Accept any certificate, even an expired or self-signed one. Only
+    /// use it for servers you control.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub insecure: bool,
+}
+
+/// Certificate is the part of a server's certificate that is useful to
+/// check. The negotiated TLS version and cipher aren't available from
+/// the TLS backend, so only the certificate is kept.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Certificate {
+    pub subject: String,
+    pub issuer: String,
+    #[serde(with = "humantime_serde")]
+    pub not_before: SystemTime,
+    #[serde(with = "humantime_serde")]
+    pub not_after: SystemTime,
+    /// The DNS names and IP addresses the certificate is valid for.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub names: Vec<String>,
+}
+
+impl Certificate {
+    /// Parse a DER encoded certificate, returning None if it's invalid.
+    pub fn from_der(der: &[u8]) -> Option<Self> {
+        let (_, cert) = x509_parser::parse_x509_certificate(der).ok()?;
+        let time = |t: x509_parser::time::ASN1Time| {
+            let secs = t.timestamp();
+            match secs >= 0 {
+                true => SystemTime::UNIX_EPOCH + Duration::from_secs(secs as u64),
+                false => SystemTime::UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs()),
+            }
+        };
+        let names = match cert.subject_alternative_name() {
+            Ok(Some(san)) => san
+                .value
+                .general_names
+                .iter()
+                .filter_map(|n| match n {
+                    GeneralName::DNSName(name) => Some(name.to_string()),
+                    GeneralName::IPAddress(ip) => match ip.len() {
+                        4 => Some(
+                            std::net::Ipv4Addr::from(<[u8; 4]>::try_from(*ip).ok()?).to_string(),
+                        ),
+                        16 => Some(
+                            std::net::Ipv6Addr::from(<[u8; 16]>::try_from(*ip).ok()?).to_string(),
+                        ),
+                        _ => None,
+                    },
+                    _ => None,
+                })
+                .collect(),
+            _ => vec![],
+        };
+        Some(Self {
+            subject: cert.subject().to_string(),
+            issuer: cert.issuer().to_string(),
+            not_before: time(cert.validity().not_before),
+            not_after: time(cert.validity().not_after),
+            names,
+        })
+    }
+
+    /// The time left until the certificate expires, or zero if it has.
+    pub fn expires_in(&self) -> Duration {
+        self.not_after
+            .duration_since(SystemTime::now())
+            .unwrap_or_default()
+    }
+
+    /// Whether the certificate is valid for the name. Wildcards match a
+    /// single label.
+    pub fn has_name(&self, name: &str) -> bool {
+        let name = name.to_lowercase();
+        self.names.iter().any(|n| {
+            let n = n.to_lowercase();
+            match n.strip_prefix("*.") {
+                Some(suffix) => name.split_once('.').is_some_and(|(_, rest)| rest == suffix),
+                None => n == name,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn has_name() {
+        let cert = Certificate {
+            subject: "CN=example.com".into(),
+            issuer: "CN=ca".into(),
+            not_before: SystemTime::UNIX_EPOCH,
+            not_after: SystemTime::now() + Duration::from_secs(86400 * 10),
+            names: vec!["example.com".into(), "*.api.example.com".into()],
+        };
+        assert!(cert.has_name("EXAMPLE.com"));
+        assert!(cert.has_name("v1.api.example.com"));
+        assert!(!cert.has_name("a.v1.api.example.com"));
+        assert!(!cert.has_name("other.com"));
+        assert!(cert.expires_in() > Duration::from_secs(86400 * 9));
+    }
+}
@@ -0,0 +1,320 @@
+/// Transform is used to normalize response bodies before asserts run,
+/// like sorting arrays whose order isn't stable or removing timestamps.
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+/// TransformError is the error type for transforms.
+#[derive(Error, Debug)]
+pub enum TransformError {
+    #[error("unknown transform: {0}")]
+    Unknown(String),
+
+    #[error("transform '{name}' takes {want} arguments, got {got}")]
+    Arguments {
+        name: String,
+        want: String,
+        got: usize,
+    },
+
+    #[error("transform '{0}' needs a JSON body")]
+    NotJson(String),
+}
+
+/// Result is the result type for transforms.
+pub type Result<T> = std::result::Result<T, TransformError>;
+
+/// The function that applies a transform to the body with the arguments.
+type Apply = fn(&str, &[String]) -> Result<String>;
+
+/// A registered transform and the number of arguments it takes.
+struct Registered {
+    min: usize,
+    max: usize,
+    apply: Apply,
+}
+
+static REGISTRY: OnceLock<HashMap<&'static str, Registered>> = OnceLock::new();
+
+fn registry() -> &'static HashMap<&'static str, Registered> {
+    REGISTRY.get_or_init(|| {
+        let mut registry = HashMap::new();
+        let mut register = |name, min, max, apply| {
+            registry.insert(name, Registered { min, max, apply });
+        };
+        register("json_flatten", 0, 0, json_flatten as Apply);
+        register("lowercase", 0, 0, lowercase);
+        register("remove", 1, usize::MAX, remove);
+        register("sort_array", 1, 2, sort_array);
+        registry
+    })
+}
+
+/// Transform is a named transform and its arguments. It's written like a
+/// function call, `sort_array($.items, id)`, or just the name when it
+/// takes no arguments.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(try_from = "String", into = "String")]
+pub struct Transform {
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+impl Transform {
+    /// Parse the transform, checking that it exists and has the right
+    /// number of arguments.
+    pub fn parse(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let (name, args) = match s.split_once('(') {
+            Some((name, args)) => (
+                name.trim(),
+                args.trim_end()
+                    .trim_end_matches(')')
+                    .split(',')
+                    .map(|a| a.trim().to_string())
+                    .filter(|a| !a.is_empty())
+                    .collect::<Vec<_>>(),
+            ),
+            None => (s, vec![]),
+        };
+        let registered = registry()
+            .get(name)
+            .ok_or_else(|| TransformError::Unknown(name.to_string()))?;
+        if args.len() < registered.min || args.len() > registered.max {
+            let want = match (registered.min, registered.max) {
+                (min, max) if min == max => min.to_string(),
+                (min, usize::MAX) => format!("at least {}", min),
+                (min, max) => format!("{} to {}", min, max),
+            };
+            return Err(TransformError::Arguments {
+                name: name.to_string(),
+                want,
+                got: args.len(),
+            });
+        }
+        Ok(Self {
+            name: name.to_string(),
+            args,
+        })
+    }
+
+    /// Apply the transform to the body.
+    pub fn apply(&self, body: &str) -> Result<String> {
+        let registered = registry()
+            .get(self.name.as_str())
+            .ok_or_else(|| TransformError::Unknown(self.name.clone()))?;
+        (registered.apply)(body, &self.args)
+    }
+}
+
+impl TryFrom<String> for Transform {
+    type Error = TransformError;
+
+    fn try_from(s: String) -> Result<Self> {
+        Self::parse(&s)
+    }
+}
+
+impl From<Transform> for String {
+    fn from(t: Transform) -> Self {
+        t.to_string()
+    }
+}
+
+impl std::fmt::Display for Transform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.args.is_empty() {
+            true => write!(f, "{}", self.name),
+            false => write!(f, "{}({})", self.name, self.args.join(", ")),
+        }
+    }
+}
+
+/// Apply each of the transforms to the body in order.
+pub fn apply_all(transforms: &[Transform], body: &str) -> Result<String> {
+    transforms
+        .iter()
+        .try_fold(body.to_string(), |body, t| t.apply(&body))
+}
+
+/// Parse the body as JSON, change it and serialize it again.
+fn with_json<F>(name: &str, body: &str, f: F) -> Result<String>
+where
+    F: FnOnce(&mut Value),
+{
+    let mut value: Value =
+        serde_json::from_str(body).map_err(|_| TransformError::NotJson(name.to_string()))?;
+    f(&mut value);
+    Ok(value.to_string())
+}
+
+/// Split a path like `$.items[0].id` into its tokens. The leading `$`
+/// is optional.
+fn tokens(path: &str) -> Vec<String> {
+    path.trim_start_matches('$')
+        .replace('[', ".")
+        .replace(']', "")
+        .split('.')
+        .filter(|t| !t.is_empty())
+        .map(|t| t.to_string())
+        .collect()
+}
+
+/// Call f with each value at the path. A `*` matches every key or index.
+fn each_mut<F>(value: &mut Value, tokens: &[String], f: &mut F)
+where
+    F: FnMut(&mut Value),
+{
+    let (first, rest) = match tokens.split_first() {
+        Some(t) => t,
+        None => return f(value),
+    };
+    match value {
+        Value::Object(o) => {
+            for (k, v) in o.iter_mut() {
+                if first == "*" || k == first {
+                    each_mut(v, rest, f);
+                }
+            }
+        }
+        Value::Array(a) => {
+            for (i, v) in a.iter_mut().enumerate() {
+                if first == "*" || first.parse::<usize>() == Ok(i) {
+                    each_mut(v, rest, f);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Replace each value at the path with the replacement.
+pub(crate) fn replace(value: &mut Value, path: &str, replacement: &Value) {
+    each_mut(value, &tokens(path), &mut |v| *v = replacement.clone());
+}
+
+/// Replace nested objects and arrays with a single object whose keys are
+/// the dot separated paths to each value.
+fn json_flatten(body: &str, _: &[String]) -> Result<String> {
+    fn flatten(prefix: &str, value: Value, flat: &mut Map<String, Value>) {
+        let key = |k: &str| match prefix.is_empty() {
+            true => k.to_string(),
+            false => format!("{}.{}", prefix, k),
+        };
+        match value {
+            Value::Object(o) if !o.is_empty() => {
+                for (k, v) in o {
+                    flatten(&key(&k), v, flat);
+                }
+            }
+            Value::Array(a) if !a.is_empty() => {
+                for (i, v) in a.into_iter().enumerate() {
+                    flatten(&key(&i.to_string()), v, flat);
+                }
+            }
+            v => {
+                flat.insert(prefix.to_string(), v);
+            }
+        }
+    }
+    with_json("json_flatten", body, |value| {
+        let mut flat = Map::new();
+        flatten("", value.take(), &mut flat);
+        *value = Value::Object(flat);
+    })
+}
+
+/// Lowercase the whole body.
+fn lowercase(body: &str, _: &[String]) -> Result<String> {
+    Ok(body.to_lowercase())
+}
+
+/// Remove the values at each of the paths.
+fn remove(body: &str, paths: &[String]) -> Result<String> {
+    with_json("remove", body, |value| {
+        for path in paths {
+            let tokens = tokens(path);
+            let tokens = tokens.iter().map(|t| t.as_str()).collect::<Vec<_>>();
+            crate::snapshot::remove(value, &tokens);
+        }
+    })
+}
+
+/// Sort the array at the path, by the value of the key in each element
+/// when given. Numbers sort by value and everything else by its text.
+fn sort_array(body: &str, args: &[String]) -> Result<String> {
+    let key = args.get(1).map(|k| tokens(k));
+    let sort_key = |v: &Value| match &key {
+        Some(key) => key.iter().try_fold(v, |v, t| v.get(t.as_str())).cloned(),
+        None => Some(v.clone()),
+    };
+    with_json("sort_array", body, |value| {
+        each_mut(value, &tokens(&args[0]), &mut |v| {
+            if let Value::Array(a) = v {
+                a.sort_by(|x, y| compare(&sort_key(x), &sort_key(y)));
+            }
+        })
+    })
+}
+
+fn compare(a: &Option<Value>, b: &Option<Value>) -> Ordering {
+    match (a, b) {
+        (Some(Value::Number(a)), Some(Value::Number(b))) => a
+            .as_f64()
+            .partial_cmp(&b.as_f64())
+            .unwrap_or(Ordering::Equal),
+        (Some(Value::String(a)), Some(Value::String(b))) => a.cmp(b),
+        (Some(a), Some(b)) => a.to_string().cmp(&b.to_string()),
+        (a, b) => a.is_some().cmp(&b.is_some()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parse() {
+        let t: Vec<Transform> =
+            serde_yaml::from_str("[json_flatten, 'sort_array($.items, id)']").unwrap();
+        assert_eq!(t[1].args, vec!["$.items", "id"]);
+        assert_eq!(t[1].to_string(), "sort_array($.items, id)");
+        assert!(matches!(
+            Transform::parse("nope"),
+            Err(TransformError::Unknown(_))
+        ));
+        assert_eq!(
+            Transform::parse("sort_array").unwrap_err().to_string(),
+            "transform 'sort_array' takes 1 to 2 arguments, got 0"
+        );
+    }
+
+    #[test]
+    fn apply() {
+        let body = r#"{"Items": [{"id": 10, "ts": 1}, {"id": 9, "ts": 2}], "meta": {"Page": 1}}"#;
+        let transforms = [
+            "lowercase",
+            "sort_array($.items, id)",
+            "remove($.items[*].ts)",
+            "json_flatten",
+        ]
+        .iter()
+        .map(|t| Transform::parse(t).unwrap())
+        .collect::<Vec<_>>();
+        let got: Value = serde_json::from_str(&apply_all(&transforms, body).unwrap()).unwrap();
+        assert_eq!(
+            got,
+            json!({"items.0.id": 9, "items.1.id": 10, "meta.page": 1})
+        );
+        assert!(matches!(
+            apply_all(&transforms[1..2], "not json"),
+            Err(TransformError::NotJson(_))
+        ));
+    }
+}
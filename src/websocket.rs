@@ -0,0 +1,179 @@
+/// WebSocket is used to make requests against websocket endpoints.
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::{Applicator, Response};
+
+use futures_util::{SinkExt, StreamExt};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::{HeaderName, HeaderValue};
+use tokio_tungstenite::tungstenite::Message;
+
+/// WebSocketError is the error type for websocket requests.
+#[derive(Error, Debug)]
+pub enum WebSocketError {
+    #[error("websocket error: {0}")]
+    WebSocket(#[from] tokio_tungstenite::tungstenite::Error),
+
+    #[error("invalid url: {0}")]
+    Url(String),
+
+    #[error("invalid header: {0}")]
+    Header(String),
+
+    #[error("regex error: {0}")]
+    Regex(#[from] regex::Error),
+}
+
+/// Result is the result type for websocket requests.
+pub type Result<T> = std::result::Result<T, WebSocketError>;
+
+/// WebSocket turns a request into a websocket session. The messages
+/// are sent in order once connected and then received messages are
+/// collected until the duration elapses or a message matches until.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
+pub struct WebSocket {
+    #[serde(default)]
+    pub messages: Vec<String>,
+    #[serde(default = "default_duration", with = "humantime_serde")]
+    #[schemars(with = "String")]
+    pub duration: Duration,
+    #[serde(default)]
+    pub until: Option<String>,
+}
+
+fn default_duration() -> Duration {
+    Duration::from_secs(5)
+}
+
+impl WebSocket {
+    /// Apply the context to the messages and matcher.
+    pub fn apply(&mut self, app: &Applicator) {
+        for message in self.messages.iter_mut() {
+            *message = app.apply(message);
+        }
+        if let Some(until) = &mut self.until {
+            *until = app.apply(until);
+        }
+    }
+
+    /// Connect to the given url, send the messages, and collect the
+    /// received messages into a response. The response body is a JSON
+    /// array of the received messages.
+    pub async fn request(
+        &self,
+        url: &str,
+        headers: &HashMap<String, String>,
+        query_parameters: &HashMap<String, String>,
+    ) -> Result<Response> {
+        let url = reqwest::Url::parse_with_params(url, query_parameters)
+            .map_err(|e| WebSocketError::Url(e.to_string()))?;
+        let mut request = url.as_str().into_client_request()?;
+        for (key, value) in headers.iter() {
+            request.headers_mut().insert(
+                HeaderName::try_from(key.as_str())
+                    .map_err(|e| WebSocketError::Header(e.to_string()))?,
+                HeaderValue::try_from(value.as_str())
+                    .map_err(|e| WebSocketError::Header(e.to_string()))?,
+            );
+        }
+        let until = match &self.until {
+            Some(u) => Some(regex::Regex::new(u)?),
+            None => None,
+        };
+
+        let (mut stream, handshake) = tokio_tungstenite::connect_async(request).await?;
+        for message in &self.messages {
+            stream.send(Message::text(message.clone())).await?;
+        }
+
+        let mut messages = vec![];
+        let deadline = tokio::time::sleep(self.duration);
+        tokio::pin!(deadline);
+        loop {
+            tokio::select! {
+                _ = &mut deadline => break,
+                message = stream.next() => {
+                    let text = match message {
+                        Some(Ok(Message::Text(t))) => t.to_string(),
+                        Some(Ok(Message::Binary(b))) => String::from_utf8_lossy(&b).to_string(),
+                        Some(Ok(Message::Close(_))) | None => break,
+                        Some(Ok(_)) => continue,
+                        Some(Err(e)) => return Err(e.into()),
+                    };
+                    let done = until.as_ref().is_some_and(|u| u.is_match(&text));
+                    messages.push(text);
+                    if done {
+                        break;
+                    }
+                }
+            }
+        }
+        // The server may have already gone away, so a failed close isn't
+        // an error for the request.
+        let _ = stream.close(None).await;
+
+        let body = serde_json::Value::Array(
+            messages
+                .iter()
+                .map(|m| {
+                    serde_json::from_str(m).unwrap_or_else(|_| serde_json::Value::String(m.clone()))
+                })
+                .collect(),
+        );
+        Ok(Response {
+            status_code: handshake.status().as_u16(),
+            version: format!("{:?}", handshake.version()),
+            headers: handshake
+                .headers()
+                .iter()
+                .map(|(k, v)| {
+                    (
+                        k.to_string(),
+                        String::from_utf8_lossy(v.as_bytes()).to_string(),
+                    )
+                })
+                .collect(),
+            body: body.to_string(),
+            messages,
+            json: Some(body).into(),
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn echo() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            while let Some(Ok(message)) = ws.next().await {
+                if message.is_text() {
+                    ws.send(message).await.unwrap();
+                }
+            }
+        });
+
+        let ws = WebSocket {
+            messages: vec!["hello".into(), r#"{"done": true}"#.into()],
+            duration: Duration::from_secs(5),
+            until: Some("done".into()),
+        };
+        let response = ws
+            .request(&format!("ws://{}", addr), &HashMap::new(), &HashMap::new())
+            .await
+            .unwrap();
+        assert_eq!(response.status_code, 101);
+        assert_eq!(response.messages, vec!["hello", r#"{"done": true}"#]);
+        assert_eq!(response.find_path_in_body("1.done"), Some("true".into()));
+    }
+}